@@ -0,0 +1,18 @@
+// build.rs
+// Generates the gRPC service of src/grpc.rs from proto/s3.proto when the
+// `grpc` feature is on, with a vendored protoc so builds need no system one.
+
+fn main() {
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    let protoc =
+        protoc_bin_vendored::protoc_bin_path().expect("no vendored protoc for this platform");
+    // SAFETY: the build script is single-threaded
+    unsafe { std::env::set_var("PROTOC", protoc) };
+    println!("cargo:rerun-if-changed=proto/s3.proto");
+    tonic_build::compile_protos("proto/s3.proto").expect("failed to compile proto/s3.proto");
+}
@@ -0,0 +1,143 @@
+// access.rs
+// Per-object access statistics. Reads are counted in memory and flushed to
+// the objects table in batches, so a hot object costs one UPDATE per flush
+// interval rather than one write per GET.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::Mutex;
+use tokio::time;
+use tracing::{debug, error};
+use utoipa::ToSchema;
+
+use crate::storage::{Storage, StorageError};
+
+/// Access counters accumulated for one object since the last flush.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingAccess {
+    pub count: u64,
+    pub last_accessed: i64,
+}
+
+/// Access statistics of a single object.
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct ObjectAccess {
+    pub key: String,
+    pub access_count: u64,
+    pub last_accessed: Option<i64>,
+}
+
+/// Hot and never-read objects of a bucket.
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct AccessReport {
+    pub hot: Vec<ObjectAccess>,
+    pub never_read: Vec<String>,
+}
+
+/// In-memory buffer of object reads waiting to be flushed to storage.
+#[derive(Debug, Default)]
+pub struct AccessTracker {
+    pending: std::sync::Mutex<HashMap<(String, String), PendingAccess>>,
+}
+
+impl AccessTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a read of `key` in `bucket`.
+    pub fn record(&self, bucket: &str, key: &str) {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = pending
+            .entry((bucket.to_string(), key.to_string()))
+            .or_insert(PendingAccess {
+                count: 0,
+                last_accessed: now,
+            });
+        entry.count += 1;
+        entry.last_accessed = entry.last_accessed.max(now);
+    }
+
+    /// Takes all buffered reads, leaving the buffer empty.
+    pub fn drain(&self) -> HashMap<(String, String), PendingAccess> {
+        let mut pending = self.pending.lock().unwrap_or_else(|e| e.into_inner());
+        std::mem::take(&mut *pending)
+    }
+}
+
+/// Background task that periodically writes buffered reads to storage
+pub struct AccessStatsFlusher {
+    storage: Arc<Mutex<Storage>>,
+    tracker: Arc<AccessTracker>,
+    flush_interval: Duration,
+}
+
+impl AccessStatsFlusher {
+    /// Create a new AccessStatsFlusher
+    pub fn new(
+        storage: Arc<Mutex<Storage>>,
+        tracker: Arc<AccessTracker>,
+        flush_interval: Duration,
+    ) -> Self {
+        Self {
+            storage,
+            tracker,
+            flush_interval,
+        }
+    }
+
+    /// Start the background flusher
+    pub fn start(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = time::interval(self.flush_interval);
+
+            loop {
+                interval.tick().await;
+
+                match self.flush().await {
+                    Ok(0) => {}
+                    Ok(n) => debug!("Flushed access statistics for {} objects", n),
+                    Err(e) => error!("Failed to flush access statistics: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Write all buffered reads in a single transaction
+    async fn flush(&self) -> Result<usize, StorageError> {
+        let pending = self.tracker.drain();
+        if pending.is_empty() {
+            return Ok(0);
+        }
+        let mut storage = self.storage.lock().await;
+        storage.record_accesses(&pending)?;
+        Ok(pending.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tracker_batches_reads_until_drained() {
+        let tracker = AccessTracker::new();
+        tracker.record("bucket", "a");
+        tracker.record("bucket", "a");
+        tracker.record("bucket", "b");
+
+        let pending = tracker.drain();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[&("bucket".to_string(), "a".to_string())].count, 2);
+        assert_eq!(pending[&("bucket".to_string(), "b".to_string())].count, 1);
+
+        // Draining empties the buffer
+        assert!(tracker.drain().is_empty());
+    }
+}
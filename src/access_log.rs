@@ -0,0 +1,264 @@
+// access_log.rs
+// Access log: one line per request, separate from the application log, in
+// the Common or Combined Log Format of web servers or as JSON, so standard
+// log tooling can ingest it. Each line carries the request's method, path,
+// status, response size, latency, request ID and principal.
+
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{REFERER, USER_AGENT};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, web};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::error;
+use tracing_actix_web::RequestId;
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+
+use crate::config::{AccessLogConfig, AccessLogFormat};
+use crate::log_file::{self, UtcTime, now_secs};
+use crate::throttle::{ANONYMOUS, access_key};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// One request as recorded in the access log.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessEntry {
+    pub time: u64,
+    pub remote: String,
+    pub principal: String,
+    pub method: String,
+    /// Path and query of the request.
+    pub target: String,
+    pub version: String,
+    pub status: u16,
+    /// Size of the response body, if known up front.
+    pub bytes: Option<u64>,
+    pub latency_ms: f64,
+    pub request_id: Option<String>,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl AccessEntry {
+    /// The entry in the Common Log Format, with the principal as user.
+    pub fn common(&self) -> String {
+        let t = UtcTime::from_secs(self.time);
+        let user = match self.principal.as_str() {
+            ANONYMOUS => "-",
+            principal => principal,
+        };
+        format!(
+            "{} - {} [{:02}/{}/{}:{:02}:{:02}:{:02} +0000] \"{} {} {}\" {} {}",
+            self.remote,
+            user,
+            t.day,
+            MONTHS[t.month as usize - 1],
+            t.year,
+            t.hour,
+            t.minute,
+            t.second,
+            self.method,
+            self.target,
+            self.version,
+            self.status,
+            self.bytes.map_or("-".to_string(), |b| b.to_string())
+        )
+    }
+
+    /// The entry in the Combined Log Format, followed by the latency in
+    /// milliseconds and the request ID, as many servers append.
+    pub fn combined(&self) -> String {
+        let quoted = |value: &Option<String>| value.as_deref().unwrap_or("-").replace('"', "\\\"");
+        format!(
+            "{} \"{}\" \"{}\" {:.3} {}",
+            self.common(),
+            quoted(&self.referer),
+            quoted(&self.user_agent),
+            self.latency_ms,
+            self.request_id.as_deref().unwrap_or("-")
+        )
+    }
+
+    pub fn format(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Common => self.common(),
+            AccessLogFormat::Combined => self.combined(),
+            AccessLogFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Output {
+    Stdout,
+    /// Written by a worker thread; the guard flushes it when dropped.
+    File {
+        writer: NonBlocking,
+        _guard: WorkerGuard,
+    },
+}
+
+/// Where access log lines go, if anywhere.
+#[derive(Debug)]
+pub struct AccessLog {
+    format: AccessLogFormat,
+    output: Option<Output>,
+}
+
+impl AccessLog {
+    /// Opens the access log, to its file or stdout, if it is enabled.
+    pub fn new(config: &AccessLogConfig) -> io::Result<Self> {
+        let output = match (config.enabled, &config.file) {
+            (false, _) => None,
+            (true, None) => Some(Output::Stdout),
+            (true, Some(path)) => {
+                let (writer, guard) = log_file::open_non_blocking(
+                    path,
+                    config.rotation,
+                    config.max_bytes,
+                    config.max_files,
+                )?;
+                Some(Output::File {
+                    writer,
+                    _guard: guard,
+                })
+            }
+        };
+        Ok(Self {
+            format: config.format,
+            output,
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.output.is_some()
+    }
+
+    /// Writes one entry.
+    pub fn record(&self, entry: &AccessEntry) {
+        let line = format!("{}\n", entry.format(self.format));
+        let result = match &self.output {
+            None => Ok(()),
+            Some(Output::Stdout) => io::stdout().lock().write_all(line.as_bytes()),
+            Some(Output::File { writer, .. }) => writer.clone().write_all(line.as_bytes()),
+        };
+        if let Err(e) = result {
+            error!("Failed to write access log: {}", e);
+        }
+    }
+}
+
+/// Middleware recording every request in the access log. Registered inside
+/// the tracing middleware, which assigns the request ID, and outside the
+/// others, so the final status and body are recorded.
+pub async fn log_access(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(access_log) = req
+        .app_data::<web::Data<Arc<AccessLog>>>()
+        .filter(|log| log.is_enabled())
+        .cloned()
+    else {
+        return next.call(req).await;
+    };
+
+    let started = Instant::now();
+    let header = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    };
+    let mut entry = AccessEntry {
+        time: now_secs(),
+        remote: req
+            .peer_addr()
+            .map_or("-".to_string(), |addr| addr.ip().to_string()),
+        principal: access_key(&req),
+        method: req.method().to_string(),
+        target: req.uri().path_and_query().map_or_else(
+            || req.path().to_string(),
+            |target| target.as_str().to_string(),
+        ),
+        version: format!("{:?}", req.version()),
+        status: 0,
+        bytes: None,
+        latency_ms: 0.0,
+        request_id: req.extensions().get::<RequestId>().map(|id| id.to_string()),
+        referer: header(REFERER),
+        user_agent: header(USER_AGENT),
+    };
+
+    let result = next.call(req).await;
+    entry.latency_ms = millis(started.elapsed());
+    match &result {
+        Ok(res) => {
+            entry.status = res.status().as_u16();
+            entry.bytes = match res.response().body().size() {
+                BodySize::Sized(size) => Some(size),
+                BodySize::None => Some(0),
+                BodySize::Stream => None,
+            };
+        }
+        Err(e) => entry.status = e.as_response_error().status_code().as_u16(),
+    }
+    access_log.record(&entry);
+    result
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> AccessEntry {
+        AccessEntry {
+            time: 1_792_109_633,
+            remote: "10.0.0.7".to_string(),
+            principal: ANONYMOUS.to_string(),
+            method: "GET".to_string(),
+            target: "/buckets/b/objects/a?versionId=1".to_string(),
+            version: "HTTP/1.1".to_string(),
+            status: 200,
+            bytes: Some(512),
+            latency_ms: 1.5,
+            request_id: Some("req-1".to_string()),
+            referer: None,
+            user_agent: Some("curl/8.5.0".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_common_and_combined() {
+        assert_eq!(
+            entry().common(),
+            "10.0.0.7 - - [16/Oct/2026:00:13:53 +0000] \"GET /buckets/b/objects/a?versionId=1 HTTP/1.1\" 200 512"
+        );
+
+        let mut signed = entry();
+        signed.principal = "AKID".to_string();
+        signed.bytes = None;
+        assert_eq!(
+            signed.combined(),
+            "10.0.0.7 - AKID [16/Oct/2026:00:13:53 +0000] \"GET /buckets/b/objects/a?versionId=1 HTTP/1.1\" 200 - \"-\" \"curl/8.5.0\" 1.500 req-1"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let line: serde_json::Value =
+            serde_json::from_str(&entry().format(AccessLogFormat::Json)).unwrap();
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["principal"], ANONYMOUS);
+        assert_eq!(line["request_id"], "req-1");
+    }
+}
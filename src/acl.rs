@@ -0,0 +1,89 @@
+// acl.rs
+// Access control list grants, with which a bucket's admin shares a bucket
+// with principals of other teams: PUT /buckets/{bucket}?acl replaces its
+// grants of read or write access. Grants are merged with the bucket's roles
+// (see roles.rs) when a request is authorized; either one granting the
+// access lets the request through, and a bucket with grants is closed to
+// principals holding neither.
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use utoipa::ToSchema;
+
+use crate::jwt::Access;
+
+/// The access a grant gives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Read,
+    Write,
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::Read => "read",
+            Permission::Write => "write",
+        }
+    }
+
+    /// Whether the permission grants `access`; writing includes reading.
+    pub fn grants(&self, access: Access) -> bool {
+        let granted = match self {
+            Permission::Read => Access::Read,
+            Permission::Write => Access::Write,
+        };
+        granted >= access
+    }
+}
+
+impl FromStr for Permission {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Permission::Read),
+            "write" => Ok(Permission::Write),
+            other => Err(format!("Unknown permission '{}'", other)),
+        }
+    }
+}
+
+/// A permission given to a principal on a bucket.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+pub struct Grant {
+    pub grantee: String,
+    pub permission: Permission,
+}
+
+/// Whether any of `grants` gives `principal` the `access`.
+pub fn grants(grants: &[Grant], principal: &str, access: Access) -> bool {
+    grants
+        .iter()
+        .any(|grant| grant.grantee == principal && grant.permission.grants(access))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grants() {
+        let acl = vec![
+            Grant {
+                grantee: "bob".to_string(),
+                permission: Permission::Read,
+            },
+            Grant {
+                grantee: "carol".to_string(),
+                permission: Permission::Write,
+            },
+        ];
+        assert!(grants(&acl, "bob", Access::Read));
+        assert!(!grants(&acl, "bob", Access::Write));
+        assert!(grants(&acl, "carol", Access::Read));
+        assert!(!grants(&acl, "carol", Access::Admin));
+        assert!(!grants(&acl, "dave", Access::Read));
+    }
+}
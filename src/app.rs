@@ -0,0 +1,591 @@
+// app.rs
+// The HTTP application: the state shared by the handlers, the middleware
+// and the routes. Assembled here rather than in main.rs so the server binary
+// and the embedded test server (`testing`) serve the very same app; main.rs
+// adds the background workers, the listeners and the `Expect` handling.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceFactory, ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::from_fn;
+use actix_web::{App, Error, HttpResponse, web};
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing_actix_web::TracingLogger;
+use utoipa::OpenApi;
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::access::AccessTracker;
+use crate::access_log::{AccessLog, log_access};
+use crate::backpressure::{Backpressure, shed_load};
+use crate::backup::DbBackup;
+use crate::bandwidth::Bandwidth;
+use crate::bucket_logging::{VerboseBuckets, log_bucket_activity};
+use crate::cache::ObjectCache;
+use crate::circuit::{CircuitBreaker, FAILURE_THRESHOLD, fail_fast_when_storage_down};
+use crate::config::{Config, Credentials, ServerConfig, SimulationConfig, TimeoutConfig};
+use crate::copy::CopyJobs;
+use crate::disk::{DiskState, reject_writes_when_full};
+use crate::error_code::complete_error_bodies;
+use crate::expect::ExpectCheck;
+use crate::graphql::{self, GRAPHQL_PATH};
+use crate::guards::query_param;
+use crate::handlers::{
+    cache_stats_handler, compose_object_handler, copy_status_handler, create_bucket_handler,
+    create_folder_handler, create_share_handler, create_upload_handler, db_backup_status_handler,
+    delete_bucket_alias_handler, delete_bucket_handler, delete_cache_pin_handler,
+    delete_object_handler, delete_prefix_handler, delete_upload_handler,
+    get_bucket_access_report_handler, get_bucket_acl_handler, get_bucket_content_type_handler,
+    get_bucket_lifecycle_handler, get_bucket_logging_handler, get_bucket_metadata_handler,
+    get_bucket_metrics_handler, get_bucket_replication_handler, get_bucket_roles_handler,
+    get_bucket_versioning_handler, get_bucket_worm_handler, get_log_level_handler,
+    get_maintenance_handler, get_object_handler, get_object_legal_hold_handler,
+    get_read_only_handler, get_share_handler, graphql_handler, head_bucket_handler,
+    head_object_handler, head_upload_handler, list_bucket_aliases_handler, list_buckets_handler,
+    list_cache_pins_handler, list_folder_handler, list_objects_handler, list_shares_handler,
+    list_upload_parts_handler, list_uploads_handler, metrics_handler, patch_object_handler,
+    patch_upload_handler, post_object_handler, put_bucket_acl_handler, put_bucket_alias_handler,
+    put_bucket_content_type_handler, put_bucket_lifecycle_handler, put_bucket_logging_handler,
+    put_bucket_metadata_handler, put_bucket_replication_handler, put_bucket_roles_handler,
+    put_bucket_versioning_handler, put_bucket_worm_handler, put_cache_pin_handler,
+    put_object_handler, put_object_legal_hold_handler, rehash_status_handler,
+    reload_config_handler, restore_bucket_handler, revoke_share_handler, set_log_level_handler,
+    set_maintenance_handler, set_read_only_handler, start_copy_handler, start_db_backup_handler,
+    start_rehash_handler, tus_options_handler, upload_progress_handler, usage_stats_handler,
+    verify_object_handler, warm_cache_handler,
+};
+use crate::jwt::{JwtVerifier, authenticate_bearer_tokens};
+use crate::log_control::LogControl;
+use crate::maintenance::{MaintenanceMode, reject_requests_during_maintenance};
+use crate::memory::MemoryBudget;
+use crate::metrics::{Metrics, track_bucket_requests};
+use crate::namespace::strip_namespace_prefix;
+use crate::negotiation::encode_binary_responses;
+use crate::notifications::Notifier;
+use crate::openapi::{ApiDoc, OPENAPI_PATH, SWAGGER_UI_PATH};
+use crate::read_only::{ReadOnlyMode, reject_mutations_when_read_only};
+use crate::rehash::RehashJob;
+use crate::reload::ConfigReloader;
+use crate::roles::{BucketRoles, enforce_bucket_roles};
+use crate::s3_service::{S3Error, S3Service};
+use crate::secrets::SecretStore;
+use crate::simulation::simulate_flaky_storage;
+use crate::storage::Storage;
+use crate::throttle::{Throttle, throttle_requests};
+use crate::timeout::limit_request_time;
+use crate::upload_progress::UploadProgress;
+use crate::upload_slots::UploadSlots;
+
+/// Everything the handlers and middleware share, one instance per server.
+#[derive(Clone)]
+pub struct AppState {
+    pub s3_service: Arc<Mutex<S3Service>>,
+    pub metrics: Arc<Metrics>,
+    pub access_tracker: Arc<AccessTracker>,
+    pub throttle: Arc<Throttle>,
+    pub bandwidth: Arc<Bandwidth>,
+    pub credentials: Arc<Credentials>,
+    pub secrets: Arc<SecretStore>,
+    pub jwt: Arc<JwtVerifier>,
+    pub cache: Arc<ObjectCache>,
+    pub memory: Arc<MemoryBudget>,
+    pub disk_state: Arc<DiskState>,
+    pub read_only: Arc<ReadOnlyMode>,
+    pub maintenance: Arc<MaintenanceMode>,
+    pub db_backup: Arc<DbBackup>,
+    pub rehash_job: Arc<RehashJob>,
+    pub copy_jobs: Arc<CopyJobs>,
+    pub backpressure: Arc<Backpressure>,
+    pub circuit_breaker: Arc<CircuitBreaker>,
+    pub upload_slots: Arc<UploadSlots>,
+    pub upload_progress: Arc<UploadProgress>,
+    pub reloader: Arc<ConfigReloader>,
+    /// Absent where another subscriber owns the process' logging, as in
+    /// tests; the log level endpoints then fail.
+    pub log_control: Option<Arc<LogControl>>,
+    pub access_log: Arc<AccessLog>,
+    pub verbose_buckets: Arc<VerboseBuckets>,
+    pub bucket_roles: Arc<BucketRoles>,
+    pub expect_check: Arc<ExpectCheck>,
+    pub server: ServerConfig,
+    pub timeouts: TimeoutConfig,
+    pub simulation: SimulationConfig,
+}
+
+impl AppState {
+    /// Creates the shared state of a server from its configuration.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The loaded configuration.
+    /// * `storage` - The opened storage.
+    /// * `db_path` - The path of the metadata database, for online backups.
+    /// * `log_control` - The control of the log filter, if this process owns it.
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<Self>` - The state, or the error opening the access log
+    ///   or loading the bucket logging settings.
+    pub async fn new(
+        config: &Config,
+        storage: Arc<Mutex<Storage>>,
+        db_path: impl Into<PathBuf>,
+        log_control: Option<Arc<LogControl>>,
+    ) -> io::Result<Self> {
+        // Secrets kept in a secret manager rather than the config file
+        let secrets = SecretStore::load(&config.secrets)
+            .await
+            .map(Arc::new)
+            .map_err(|e| io::Error::other(format!("Failed to fetch secrets: {}", e)))?;
+
+        // Bearer tokens issued by an identity provider
+        let jwt = JwtVerifier::load(config.jwt.clone(), secrets.clone())
+            .await
+            .map(Arc::new)
+            .map_err(|e| io::Error::other(format!("Failed to load JWT keys: {}", e)))?;
+
+        // Limits on stalled clients and on waiting for the storage
+        let timeouts = config.timeouts.clone();
+
+        // Writes are shed while the storage layer is saturated
+        let backpressure = Arc::new(Backpressure::new(config.backpressure.clone()));
+
+        // Requests fail fast while the storage keeps failing, until a probe
+        // finds it working again
+        let circuit_breaker = Arc::new(CircuitBreaker::new(FAILURE_THRESHOLD));
+
+        // Refuse writes while the data volume is nearly full
+        let disk_state = Arc::new(DiskState::new(config.disk.clone()));
+
+        // Operator switches refusing every mutating or data path request
+        let read_only = Arc::new(ReadOnlyMode::new(config.read_only));
+        let maintenance = Arc::new(MaintenanceMode::default());
+
+        // Per-access-key request and bandwidth budgets, and upload and
+        // download rate limits shared by all workers
+        let throttle = Arc::new(Throttle::new(config.throttle.clone()));
+        let bandwidth = Arc::new(Bandwidth::new(config.bandwidth.clone()));
+
+        // One line per request, apart from the application log
+        let access_log = AccessLog::new(&config.access_log)
+            .map(Arc::new)
+            .map_err(|e| io::Error::other(format!("Failed to open access log: {}", e)))?;
+
+        // Settings applied again on SIGHUP or POST /admin/reload
+        let mut reloader = ConfigReloader::new(throttle.clone(), bandwidth.clone());
+        if let Some(log_control) = &log_control {
+            reloader = reloader.with_log_control(log_control.clone());
+        }
+
+        // Memory shared by upload bodies and the cache, and recently read
+        // objects kept in it
+        let memory = Arc::new(MemoryBudget::new(config.memory.max_bytes));
+        let cache =
+            Arc::new(ObjectCache::new(config.cache.clone()).with_memory_budget(memory.clone()));
+
+        // Uploads announcing their body with `Expect: 100-continue` are
+        // checked before the client sends it
+        let expect_check = Arc::new(ExpectCheck::new(
+            storage.clone(),
+            memory.clone(),
+            disk_state.clone(),
+            read_only.clone(),
+            maintenance.clone(),
+        ));
+
+        // Buckets logged in detail, looked up on every request
+        let verbose_buckets = storage
+            .lock()
+            .await
+            .list_verbose_logging_buckets()
+            .map(|buckets| Arc::new(VerboseBuckets::new(buckets)))
+            .map_err(|e| {
+                io::Error::other(format!("Failed to load bucket logging settings: {}", e))
+            })?;
+
+        // Roles and ACL grants of principals on buckets, checked on every
+        // request
+        let bucket_roles = {
+            let storage = storage.lock().await;
+            storage
+                .list_bucket_roles()
+                .and_then(|roles| Ok(BucketRoles::new(roles, storage.list_bucket_acls()?)))
+                .map(Arc::new)
+                .map_err(|e| io::Error::other(format!("Failed to load bucket roles: {}", e)))?
+        };
+
+        let metrics = Arc::new(Metrics::new());
+        let s3_service = Arc::new(Mutex::new(
+            S3Service::new(storage.clone())
+                .with_cache(cache.clone())
+                .with_content_type_inference(config.content_type)
+                .with_notifier(Arc::new(Notifier::start(
+                    config.notifications.webhooks.clone(),
+                    secrets.clone(),
+                    metrics.clone(),
+                )))
+                .with_storage_timeout(timeouts.storage()),
+        ));
+
+        Ok(Self {
+            s3_service,
+            metrics,
+            // Buffers object reads in memory until they are flushed
+            access_tracker: Arc::new(AccessTracker::new()),
+            throttle,
+            bandwidth,
+            // Secret keys for verifying signed browser uploads
+            credentials: Arc::new(config.credentials.clone().with_secrets(secrets.clone())),
+            secrets,
+            jwt,
+            cache: cache.clone(),
+            memory,
+            disk_state,
+            read_only,
+            maintenance,
+            // Online snapshots of the metadata database
+            db_backup: Arc::new(DbBackup::new(db_path)),
+            // Converts checksums after the hash algorithm changed
+            rehash_job: Arc::new(RehashJob::new(storage.clone())),
+            // Server-side copies of key prefixes between buckets
+            copy_jobs: Arc::new(CopyJobs::new(storage, cache)),
+            backpressure,
+            circuit_breaker,
+            // Uploads whose bodies are read at the same time
+            upload_slots: Arc::new(UploadSlots::new(&config.uploads)),
+            // Progress of the resumable uploads being received
+            upload_progress: Arc::new(UploadProgress::default()),
+            reloader: Arc::new(reloader),
+            log_control,
+            access_log,
+            verbose_buckets,
+            bucket_roles,
+            expect_check,
+            server: config.server.clone(),
+            timeouts,
+            simulation: config.simulation,
+        })
+    }
+}
+
+/// Builds the app serving the S3-like API and the admin endpoints.
+///
+/// # Arguments
+///
+/// * `state` - The state shared by the handlers.
+///
+/// # Returns
+///
+/// * `App` - The app, with its middleware and routes.
+pub fn build_app(
+    state: &AppState,
+) -> App<
+    impl ServiceFactory<
+        ServiceRequest,
+        Config = (),
+        Response = ServiceResponse<impl MessageBody + use<>>,
+        Error = Error,
+        InitError = (),
+    > + use<>,
+> {
+    let mut app = App::new();
+    if let Some(log_control) = &state.log_control {
+        app = app.app_data(web::Data::new(log_control.clone()));
+    }
+
+    app.wrap(from_fn(strip_namespace_prefix))
+        .wrap(from_fn(shed_load))
+        .wrap(from_fn(fail_fast_when_storage_down))
+        .wrap(from_fn(reject_writes_when_full))
+        .wrap(from_fn(reject_mutations_when_read_only))
+        .wrap(from_fn(reject_requests_during_maintenance))
+        .wrap(from_fn(throttle_requests))
+        .wrap(from_fn(enforce_bucket_roles))
+        .wrap(from_fn(authenticate_bearer_tokens))
+        .wrap(from_fn(track_bucket_requests))
+        .wrap(from_fn(log_bucket_activity))
+        .wrap(from_fn(simulate_flaky_storage))
+        .wrap(from_fn(limit_request_time))
+        .wrap(from_fn(complete_error_bodies))
+        .wrap(from_fn(encode_binary_responses))
+        .wrap(from_fn(log_access))
+        .wrap(TracingLogger::default())
+        // Handlers will interact with S3Service, which internally manages Storage.
+        .app_data(web::Data::new(state.s3_service.clone()))
+        .app_data(web::Data::new(state.metrics.clone()))
+        .app_data(web::Data::new(state.access_tracker.clone()))
+        .app_data(web::Data::new(state.throttle.clone()))
+        .app_data(web::Data::new(state.bandwidth.clone()))
+        .app_data(web::Data::new(state.credentials.clone()))
+        .app_data(web::Data::new(state.jwt.clone()))
+        .app_data(web::Data::new(state.cache.clone()))
+        .app_data(web::Data::new(state.memory.clone()))
+        .app_data(web::Data::new(state.disk_state.clone()))
+        .app_data(web::Data::new(state.read_only.clone()))
+        .app_data(web::Data::new(state.maintenance.clone()))
+        .app_data(web::Data::new(state.db_backup.clone()))
+        .app_data(web::Data::new(state.rehash_job.clone()))
+        .app_data(web::Data::new(state.copy_jobs.clone()))
+        .app_data(web::Data::new(state.backpressure.clone()))
+        .app_data(web::Data::new(state.circuit_breaker.clone()))
+        .app_data(web::Data::new(state.timeouts.clone()))
+        .app_data(web::Data::new(state.simulation))
+        .app_data(web::Data::new(state.upload_slots.clone()))
+        .app_data(web::Data::new(state.upload_progress.clone()))
+        .app_data(web::Data::new(state.server.clone()))
+        .app_data(web::Data::new(state.reloader.clone()))
+        .app_data(web::Data::new(state.access_log.clone()))
+        .app_data(web::Data::new(state.verbose_buckets.clone()))
+        .app_data(web::Data::new(state.bucket_roles.clone()))
+        .app_data(web::Data::new(graphql::schema(
+            state.s3_service.clone(),
+            state.bucket_roles.clone(),
+        )))
+        // Malformed query strings and JSON bodies get the usual error body
+        .app_data(
+            web::QueryConfig::default()
+                .error_handler(|e, _| S3Error::InvalidRequest(e.to_string()).into()),
+        )
+        .app_data(
+            web::JsonConfig::default()
+                .limit(state.server.max_json_bytes)
+                .error_handler(|e, _| S3Error::InvalidRequest(e.to_string()).into()),
+        )
+        .service(
+            web::resource("/buckets/{bucket_name}")
+                .route(
+                    web::get()
+                        .guard(query_param("versioning"))
+                        .to(get_bucket_versioning_handler),
+                )
+                .route(
+                    web::put()
+                        .guard(query_param("versioning"))
+                        .to(put_bucket_versioning_handler),
+                )
+                .route(
+                    web::get()
+                        .guard(query_param("worm"))
+                        .to(get_bucket_worm_handler),
+                )
+                .route(
+                    web::put()
+                        .guard(query_param("worm"))
+                        .to(put_bucket_worm_handler),
+                )
+                .route(
+                    web::get()
+                        .guard(query_param("logging"))
+                        .to(get_bucket_logging_handler),
+                )
+                .route(
+                    web::put()
+                        .guard(query_param("logging"))
+                        .to(put_bucket_logging_handler),
+                )
+                .route(
+                    web::get()
+                        .guard(query_param("content-type"))
+                        .to(get_bucket_content_type_handler),
+                )
+                .route(
+                    web::put()
+                        .guard(query_param("content-type"))
+                        .to(put_bucket_content_type_handler),
+                )
+                .route(
+                    web::get()
+                        .guard(query_param("metadata"))
+                        .to(get_bucket_metadata_handler),
+                )
+                .route(
+                    web::put()
+                        .guard(query_param("metadata"))
+                        .to(put_bucket_metadata_handler),
+                )
+                .route(
+                    web::get()
+                        .guard(query_param("access-stats"))
+                        .to(get_bucket_access_report_handler),
+                )
+                .route(
+                    web::get()
+                        .guard(query_param("metrics"))
+                        .to(get_bucket_metrics_handler),
+                )
+                .route(
+                    web::get()
+                        .guard(query_param("lifecycle"))
+                        .to(get_bucket_lifecycle_handler),
+                )
+                .route(
+                    web::put()
+                        .guard(query_param("lifecycle"))
+                        .to(put_bucket_lifecycle_handler),
+                )
+                .route(
+                    web::get()
+                        .guard(query_param("replication"))
+                        .to(get_bucket_replication_handler),
+                )
+                .route(
+                    web::put()
+                        .guard(query_param("replication"))
+                        .to(put_bucket_replication_handler),
+                )
+                .route(
+                    web::get()
+                        .guard(query_param("roles"))
+                        .to(get_bucket_roles_handler),
+                )
+                .route(
+                    web::put()
+                        .guard(query_param("roles"))
+                        .to(put_bucket_roles_handler),
+                )
+                .route(
+                    web::get()
+                        .guard(query_param("acl"))
+                        .to(get_bucket_acl_handler),
+                )
+                .route(
+                    web::put()
+                        .guard(query_param("acl"))
+                        .to(put_bucket_acl_handler),
+                )
+                .route(
+                    web::post()
+                        .guard(query_param("copy-from"))
+                        .to(start_copy_handler),
+                )
+                .head(head_bucket_handler)
+                .put(create_bucket_handler)
+                .post(post_object_handler)
+                .delete(delete_bucket_handler),
+        )
+        .service(web::resource("/buckets/{bucket_name}/aliases").get(list_bucket_aliases_handler))
+        .service(web::resource("/buckets/{bucket_name}/shares").get(list_shares_handler))
+        .service(
+            web::resource("/buckets/{bucket_name}/shares/{share_id}").delete(revoke_share_handler),
+        )
+        .service(
+            web::resource("/buckets/{bucket_name}/aliases/{alias}")
+                .put(put_bucket_alias_handler)
+                .delete(delete_bucket_alias_handler),
+        )
+        .service(web::resource("/buckets").get(list_buckets_handler))
+        .service(web::resource(GRAPHQL_PATH).post(graphql_handler))
+        .service(
+            SwaggerUi::new(format!("{}/{{_:.*}}", SWAGGER_UI_PATH))
+                .url(OPENAPI_PATH, ApiDoc::openapi()),
+        )
+        .service(web::resource("/share/{token}").get(get_share_handler))
+        .service(web::resource("/metrics").get(metrics_handler))
+        .service(web::resource("/admin/cache/warm").post(warm_cache_handler))
+        .service(web::resource("/admin/cache/stats").get(cache_stats_handler))
+        .service(web::resource("/admin/stats").get(usage_stats_handler))
+        .service(
+            web::resource("/admin/db/backup")
+                .get(db_backup_status_handler)
+                .post(start_db_backup_handler),
+        )
+        .service(
+            web::resource("/admin/rehash")
+                .get(rehash_status_handler)
+                .post(start_rehash_handler),
+        )
+        .service(web::resource("/admin/restore").post(restore_bucket_handler))
+        .service(web::resource("/admin/reload").post(reload_config_handler))
+        .service(
+            web::resource("/admin/log-level")
+                .get(get_log_level_handler)
+                .put(set_log_level_handler),
+        )
+        .service(web::resource("/admin/copy/{id}").get(copy_status_handler))
+        .service(
+            web::resource("/admin/read-only")
+                .get(get_read_only_handler)
+                .post(set_read_only_handler),
+        )
+        .service(
+            web::resource("/admin/maintenance")
+                .get(get_maintenance_handler)
+                .post(set_maintenance_handler),
+        )
+        .service(
+            web::resource("/admin/cache/pins")
+                .get(list_cache_pins_handler)
+                .put(put_cache_pin_handler)
+                .delete(delete_cache_pin_handler),
+        )
+        .service(
+            web::resource("/buckets/{bucket_name}/objects/{object_key}")
+                .route(
+                    web::get()
+                        .guard(query_param("legal-hold"))
+                        .to(get_object_legal_hold_handler),
+                )
+                .route(
+                    web::put()
+                        .guard(query_param("legal-hold"))
+                        .to(put_object_legal_hold_handler),
+                )
+                .route(
+                    web::post()
+                        .guard(query_param("verify"))
+                        .to(verify_object_handler),
+                )
+                .route(
+                    web::post()
+                        .guard(query_param("share"))
+                        .to(create_share_handler),
+                )
+                .route(
+                    web::post()
+                        .guard(query_param("compose"))
+                        .to(compose_object_handler),
+                )
+                .put(put_object_handler)
+                .patch(patch_object_handler)
+                .get(get_object_handler)
+                .head(head_object_handler)
+                .delete(delete_object_handler),
+        )
+        .service(
+            web::resource("/buckets/{bucket_name}/objects")
+                .route(
+                    web::delete()
+                        .guard(query_param("prefix"))
+                        .to(delete_prefix_handler),
+                )
+                .get(list_objects_handler),
+        )
+        .service(
+            web::resource("/buckets/{bucket_name}/folders/{prefix:.*}")
+                .put(create_folder_handler)
+                .get(list_folder_handler),
+        )
+        .service(
+            web::resource("/buckets/{bucket_name}/uploads")
+                .route(web::method(Method::OPTIONS).to(tus_options_handler))
+                .post(create_upload_handler)
+                .get(list_uploads_handler),
+        )
+        .service(
+            web::resource("/buckets/{bucket_name}/uploads/{upload_id}")
+                .head(head_upload_handler)
+                .route(web::patch().to(patch_upload_handler))
+                .delete(delete_upload_handler),
+        )
+        .service(
+            web::resource("/buckets/{bucket_name}/uploads/{upload_id}/progress")
+                .get(upload_progress_handler),
+        )
+        .service(
+            web::resource("/buckets/{bucket_name}/uploads/{upload_id}/parts")
+                .get(list_upload_parts_handler),
+        )
+        .default_service(web::to(|| async { HttpResponse::NotFound().finish() }))
+}
@@ -1,10 +1,16 @@
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::Mutex;
 use tokio::time;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
+use crate::backpressure::Backpressure;
+use crate::circuit::CircuitBreaker;
+use crate::disk::{self, DiskState};
+use crate::metrics::Metrics;
 use crate::storage::{Storage, StorageError};
+use crate::upload_progress::UploadProgress;
 
 /// Background task that periodically checks storage consistency
 pub struct ConsistencyChecker {
@@ -46,6 +52,264 @@ impl ConsistencyChecker {
     }
 }
 
+/// Background task that applies lifecycle rules, moving aged objects to
+/// their target storage class
+pub struct TransitionWorker {
+    storage: Arc<Mutex<Storage>>,
+    metrics: Arc<Metrics>,
+    run_interval: Duration,
+}
+
+impl TransitionWorker {
+    /// Create a new TransitionWorker
+    pub fn new(
+        storage: Arc<Mutex<Storage>>,
+        metrics: Arc<Metrics>,
+        run_interval: Duration,
+    ) -> Self {
+        Self {
+            storage,
+            metrics,
+            run_interval,
+        }
+    }
+
+    /// Start the background transition worker
+    pub fn start(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = time::interval(self.run_interval);
+
+            loop {
+                interval.tick().await;
+
+                match self.run_transitions().await {
+                    Ok((0, _)) => {}
+                    Ok((objects, bytes)) => {
+                        info!(objects, bytes, "Lifecycle transitions completed");
+                        self.metrics.record_transition(objects, bytes);
+                    }
+                    Err(e) => error!("Lifecycle transitions failed: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Run a single pass over the lifecycle rules
+    async fn run_transitions(&self) -> Result<(u64, u64), StorageError> {
+        let mut storage = self.storage.lock().await;
+        let now = storage.clock().unix_secs()?;
+        storage.transition_objects(now)
+    }
+}
+
+/// Background task that watches the free space of the data volume and
+/// switches the server in and out of read-only mode
+pub struct DiskMonitor {
+    path: PathBuf,
+    state: Arc<DiskState>,
+    metrics: Arc<Metrics>,
+    check_interval: Duration,
+}
+
+impl DiskMonitor {
+    /// Create a new DiskMonitor
+    pub fn new(
+        path: PathBuf,
+        state: Arc<DiskState>,
+        metrics: Arc<Metrics>,
+        check_interval: Duration,
+    ) -> Self {
+        Self {
+            path,
+            state,
+            metrics,
+            check_interval,
+        }
+    }
+
+    /// Start the background disk monitor
+    pub fn start(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = time::interval(self.check_interval);
+
+            loop {
+                interval.tick().await;
+                self.run_check();
+            }
+        })
+    }
+
+    /// Take a single reading of the data volume
+    fn run_check(&self) {
+        let usage = match disk::disk_usage(&self.path) {
+            Ok(usage) => usage,
+            Err(e) => {
+                error!(
+                    "Failed to read free space of {}: {}",
+                    self.path.display(),
+                    e
+                );
+                return;
+            }
+        };
+        let used_percent = usage.used_percent();
+        match self.state.update(usage) {
+            Some(true) => warn!(
+                used_percent,
+                available_bytes = usage.available_bytes,
+                "Data volume above high watermark, refusing writes"
+            ),
+            Some(false) => info!(
+                used_percent,
+                available_bytes = usage.available_bytes,
+                "Data volume below low watermark, accepting writes again"
+            ),
+            None => {}
+        }
+        self.metrics
+            .record_disk_usage(usage, self.state.is_read_only());
+    }
+}
+
+/// Background task that samples how long the storage lock takes to acquire,
+/// the lock contention writes are shed on
+pub struct StorageLockProbe {
+    storage: Arc<Mutex<Storage>>,
+    backpressure: Arc<Backpressure>,
+    metrics: Arc<Metrics>,
+    interval: Duration,
+}
+
+impl StorageLockProbe {
+    /// Create a new StorageLockProbe
+    pub fn new(
+        storage: Arc<Mutex<Storage>>,
+        backpressure: Arc<Backpressure>,
+        metrics: Arc<Metrics>,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            storage,
+            backpressure,
+            metrics,
+            interval,
+        }
+    }
+
+    /// Start the background probe
+    pub fn start(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = time::interval(self.interval);
+            interval.set_missed_tick_behavior(time::MissedTickBehavior::Delay);
+
+            loop {
+                interval.tick().await;
+                let started = Instant::now();
+                drop(self.storage.lock().await);
+                self.backpressure.record_lock_wait(started.elapsed());
+                self.metrics.record_pressure(self.backpressure.pressure());
+            }
+        })
+    }
+}
+
+/// Background task that tests the storage while the circuit breaker is
+/// open and closes it once the storage answers again
+pub struct StorageHealthProbe {
+    storage: Arc<Mutex<Storage>>,
+    breaker: Arc<CircuitBreaker>,
+    interval: Duration,
+}
+
+impl StorageHealthProbe {
+    /// Create a new StorageHealthProbe
+    pub fn new(
+        storage: Arc<Mutex<Storage>>,
+        breaker: Arc<CircuitBreaker>,
+        interval: Duration,
+    ) -> Self {
+        Self {
+            storage,
+            breaker,
+            interval,
+        }
+    }
+
+    /// Start the background probe
+    pub fn start(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = time::interval(self.interval);
+
+            loop {
+                interval.tick().await;
+                if !self.breaker.is_open() {
+                    continue;
+                }
+                match self.storage.lock().await.probe() {
+                    Ok(()) => self.breaker.close(),
+                    Err(e) => warn!("Storage still failing: {}", e),
+                }
+            }
+        })
+    }
+}
+
+/// Background task that aborts resumable uploads left idle for too long, so
+/// abandoned uploads don't keep their bytes on disk forever
+pub struct UploadJanitor {
+    storage: Arc<Mutex<Storage>>,
+    progress: Arc<UploadProgress>,
+    stale_after: Duration,
+    run_interval: Duration,
+}
+
+impl UploadJanitor {
+    /// Create a new UploadJanitor
+    pub fn new(
+        storage: Arc<Mutex<Storage>>,
+        progress: Arc<UploadProgress>,
+        stale_after: Duration,
+        run_interval: Duration,
+    ) -> Self {
+        Self {
+            storage,
+            progress,
+            stale_after,
+            run_interval,
+        }
+    }
+
+    /// Start the background upload janitor
+    pub fn start(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = time::interval(self.run_interval);
+
+            loop {
+                interval.tick().await;
+
+                match self.run_cleanup().await {
+                    Ok((0, _)) => {}
+                    Ok((uploads, bytes)) => info!(uploads, bytes, "Aborted stale uploads"),
+                    Err(e) => error!("Stale upload cleanup failed: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Run a single pass over the uploads
+    async fn run_cleanup(&self) -> Result<(usize, u64), StorageError> {
+        let (ids, bytes) = {
+            let mut storage = self.storage.lock().await;
+            let cutoff = storage.clock().unix_secs()? - self.stale_after.as_secs() as i64;
+            storage.abort_stale_uploads(cutoff)?
+        };
+        for id in &ids {
+            self.progress.remove(id);
+        }
+        Ok((ids.len(), bytes))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -60,7 +324,7 @@ mod tests {
         let db_path_str = db_path.to_str().unwrap();
 
         // Create storage and checker
-        let storage = Storage::new(db_path_str).unwrap();
+        let storage = Storage::open(db_path_str, dir.path().join("data")).unwrap();
         let checker =
             ConsistencyChecker::new(Arc::new(Mutex::new(storage)), Duration::from_millis(100));
 
@@ -0,0 +1,173 @@
+// backpressure.rs
+// Load shedding in front of the storage layer. Every write goes through the
+// single storage lock, so under a burst they pile up behind it without
+// bound. Writes are counted while in flight and a probe samples how long
+// the lock takes to acquire; above the configured thresholds new writes are
+// answered with 503 SlowDown and Retry-After instead of joining the queue.
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, web};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::config::BackpressureConfig;
+use crate::error_code::{ErrorCode, error_response};
+use crate::metrics::Metrics;
+use crate::read_only::is_mutation;
+
+/// The load on the storage layer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Pressure {
+    /// Writes admitted and not yet answered.
+    pub queued_writes: u64,
+    /// Time the latest probe waited for the storage lock.
+    pub lock_wait: Duration,
+}
+
+/// Admission of writes against the configured thresholds.
+#[derive(Debug, Default)]
+pub struct Backpressure {
+    config: BackpressureConfig,
+    queued_writes: AtomicU64,
+    lock_wait_micros: AtomicU64,
+}
+
+impl Backpressure {
+    pub fn new(config: BackpressureConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// The current load.
+    pub fn pressure(&self) -> Pressure {
+        Pressure {
+            queued_writes: self.queued_writes.load(Ordering::Relaxed),
+            lock_wait: Duration::from_micros(self.lock_wait_micros.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Records how long the storage lock took to acquire.
+    pub fn record_lock_wait(&self, wait: Duration) {
+        self.lock_wait_micros
+            .store(wait.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Admits a write, or returns `None` if the storage layer is over a
+    /// threshold. The returned ticket counts the write as queued until it is
+    /// dropped.
+    pub fn admit(self: &Arc<Self>) -> Option<WriteTicket> {
+        let pressure = self.pressure();
+        let max_queued = self.config.max_queued_writes;
+        let max_wait = self.config.max_lock_wait_ms;
+        if (max_queued > 0 && pressure.queued_writes >= max_queued)
+            || (max_wait > 0 && pressure.lock_wait > Duration::from_millis(max_wait))
+        {
+            return None;
+        }
+        self.queued_writes.fetch_add(1, Ordering::Relaxed);
+        Some(WriteTicket {
+            backpressure: self.clone(),
+        })
+    }
+
+    /// The response refusing a write under pressure.
+    pub fn refusal(&self) -> HttpResponse {
+        let mut response = error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::SlowDown,
+            "SlowDown: Please reduce your request rate.",
+        );
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(self.config.retry_after_secs));
+        response
+    }
+}
+
+/// A write counted as queued.
+#[derive(Debug)]
+pub struct WriteTicket {
+    backpressure: Arc<Backpressure>,
+}
+
+impl Drop for WriteTicket {
+    fn drop(&mut self) {
+        self.backpressure
+            .queued_writes
+            .fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Middleware refusing writes while the storage layer is over a threshold.
+pub async fn shed_load(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(backpressure) = req.app_data::<web::Data<Arc<Backpressure>>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    if !is_mutation(req.method(), req.path()) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    let Some(_ticket) = backpressure.admit() else {
+        let pressure = backpressure.pressure();
+        warn!(
+            queued_writes = pressure.queued_writes,
+            lock_wait_ms = pressure.lock_wait.as_millis() as u64,
+            "Write shed under storage pressure"
+        );
+        if let Some(metrics) = req.app_data::<web::Data<Arc<Metrics>>>() {
+            metrics.record_shed_request();
+        }
+        let response = backpressure.refusal();
+        return Ok(req.into_response(response).map_into_right_body());
+    };
+
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_admit() {
+        let backpressure = Arc::new(Backpressure::new(BackpressureConfig {
+            max_queued_writes: 2,
+            max_lock_wait_ms: 100,
+            retry_after_secs: 1,
+        }));
+
+        let first = backpressure.admit().unwrap();
+        let _second = backpressure.admit().unwrap();
+        assert!(backpressure.admit().is_none());
+        drop(first);
+        assert_eq!(backpressure.pressure().queued_writes, 1);
+
+        backpressure.record_lock_wait(Duration::from_millis(150));
+        assert!(backpressure.admit().is_none());
+        backpressure.record_lock_wait(Duration::from_millis(5));
+        assert!(backpressure.admit().is_some());
+    }
+
+    #[test]
+    fn test_zero_thresholds_are_unlimited() {
+        let backpressure = Arc::new(Backpressure::new(BackpressureConfig {
+            max_queued_writes: 0,
+            max_lock_wait_ms: 0,
+            retry_after_secs: 1,
+        }));
+        backpressure.record_lock_wait(Duration::from_secs(10));
+        let tickets: Vec<_> = (0..1000).map(|_| backpressure.admit()).collect();
+        assert!(tickets.iter().all(Option::is_some));
+    }
+}
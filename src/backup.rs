@@ -0,0 +1,184 @@
+// backup.rs
+// Online snapshots of the metadata database using SQLite's backup API. The
+// copy runs on a separate connection in small steps, so the server keeps
+// serving while it progresses; writes made in the meantime make SQLite
+// restart the copy rather than produce an inconsistent snapshot.
+
+use rusqlite::backup::{Backup, StepResult};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+/// Pages copied per backup step.
+const PAGES_PER_STEP: i32 = 256;
+/// Pause between steps, leaving the database to the request handlers.
+const STEP_PAUSE: Duration = Duration::from_millis(10);
+
+/// Custom error type for starting a backup.
+#[derive(Debug, Error)]
+pub enum BackupError {
+    #[error("A backup to '{0}' is already running")]
+    InProgress(String),
+    #[error("Backup destination '{0}' already exists")]
+    DestinationExists(String),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BackupState {
+    #[default]
+    Idle,
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Progress of the most recent backup.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct BackupStatus {
+    pub state: BackupState,
+    pub dest: Option<String>,
+    pub pages_copied: u64,
+    pub pages_total: u64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// Runs at most one backup of the database at a time and tracks its progress.
+#[derive(Debug)]
+pub struct DbBackup {
+    db_path: PathBuf,
+    status: std::sync::Mutex<BackupStatus>,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+impl DbBackup {
+    pub fn new(db_path: impl Into<PathBuf>) -> Self {
+        Self {
+            db_path: db_path.into(),
+            status: std::sync::Mutex::new(BackupStatus::default()),
+        }
+    }
+
+    /// Returns the progress of the running or most recent backup.
+    pub fn status(&self) -> BackupStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut BackupStatus)) {
+        f(&mut self.status.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    /// Starts copying the database to `dest` in the background and returns
+    /// the initial status.
+    pub fn start(self: &Arc<Self>, dest: &str) -> Result<BackupStatus, BackupError> {
+        {
+            let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+            if status.state == BackupState::Running {
+                return Err(BackupError::InProgress(
+                    status.dest.clone().unwrap_or_default(),
+                ));
+            }
+            if Path::new(dest).exists() {
+                return Err(BackupError::DestinationExists(dest.to_string()));
+            }
+            *status = BackupStatus {
+                state: BackupState::Running,
+                dest: Some(dest.to_string()),
+                started_at: Some(now()),
+                ..BackupStatus::default()
+            };
+        }
+
+        let backup = self.clone();
+        let dest = dest.to_string();
+        tokio::task::spawn_blocking(move || {
+            let result = backup.run(Path::new(&dest));
+            backup.update(|status| {
+                status.finished_at = Some(now());
+                match &result {
+                    Ok(()) => status.state = BackupState::Completed,
+                    Err(e) => {
+                        status.state = BackupState::Failed;
+                        status.error = Some(e.to_string());
+                    }
+                }
+            });
+            match result {
+                Ok(()) => info!("Backed up database to '{}'", dest),
+                Err(e) => error!(error = %e, "Failed to back up database to '{}'", dest),
+            }
+        });
+        Ok(self.status())
+    }
+
+    fn run(&self, dest: &Path) -> Result<(), rusqlite::Error> {
+        let source = Connection::open_with_flags(&self.db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let mut target = Connection::open(dest)?;
+        let backup = Backup::new(&source, &mut target)?;
+        loop {
+            let result = backup.step(PAGES_PER_STEP)?;
+            let progress = backup.progress();
+            self.update(|status| {
+                status.pages_total = progress.pagecount as u64;
+                status.pages_copied = (progress.pagecount - progress.remaining) as u64;
+            });
+            if let StepResult::Done = result {
+                return Ok(());
+            }
+            // More pages, or the source is busy with a write; retry shortly
+            std::thread::sleep(STEP_PAUSE);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_backup_copies_database() {
+        let dir = tempdir().unwrap();
+        let db_path = dir.path().join("source.db");
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch("CREATE TABLE t (v TEXT); INSERT INTO t VALUES ('a'), ('b');")
+            .unwrap();
+
+        let backup = Arc::new(DbBackup::new(&db_path));
+        let dest = dir.path().join("copy.db");
+        let dest = dest.to_str().unwrap();
+        backup.start(dest).unwrap();
+        while backup.status().state == BackupState::Running {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(backup.status().state, BackupState::Completed);
+
+        let copy = Connection::open(dest).unwrap();
+        let count: i64 = copy
+            .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 2);
+
+        // The destination is never overwritten
+        assert!(matches!(
+            backup.start(dest),
+            Err(BackupError::DestinationExists(_))
+        ));
+    }
+}
@@ -0,0 +1,151 @@
+// bandwidth.rs
+// Byte-rate limiting for object bodies. Limiters hand out bytes at a fixed
+// rate and make callers sleep once they have drawn ahead of it, so a stream
+// paced through a limiter never exceeds the configured throughput.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::config::BandwidthConfig;
+
+/// An async token bucket measured in bytes. A rate of 0 means unlimited.
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_second: AtomicU64,
+    state: std::sync::Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_second: u64) -> Self {
+        Self {
+            bytes_per_second: AtomicU64::new(bytes_per_second),
+            state: std::sync::Mutex::new((bytes_per_second as f64, Instant::now())),
+        }
+    }
+
+    /// Changes the rate, also for bodies already being paced.
+    pub fn set_rate(&self, bytes_per_second: u64) {
+        self.bytes_per_second
+            .store(bytes_per_second, Ordering::Relaxed);
+    }
+
+    /// Reserves `bytes` and waits until the reservation is covered by the rate.
+    pub async fn acquire(&self, bytes: u64) {
+        let wait = self.reserve(bytes, Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Takes `bytes` from the bucket, returning how long the caller must wait
+    /// for the balance to be repaid.
+    fn reserve(&self, bytes: u64, now: Instant) -> Duration {
+        let rate = self.bytes_per_second.load(Ordering::Relaxed);
+        if rate == 0 {
+            return Duration::ZERO;
+        }
+        let rate = rate as f64;
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (tokens, updated) = &mut *state;
+        let elapsed = now.saturating_duration_since(*updated).as_secs_f64();
+        // Allow bursts of at most one second's worth of bytes
+        *tokens = (*tokens + elapsed * rate).min(rate) - bytes as f64;
+        *updated = now;
+        if *tokens < 0.0 {
+            Duration::from_secs_f64(-*tokens / rate)
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+/// Upload and download rate limits shared by all request handlers.
+#[derive(Debug)]
+pub struct Bandwidth {
+    config: RwLock<BandwidthConfig>,
+    global_upload: Arc<RateLimiter>,
+    global_download: Arc<RateLimiter>,
+}
+
+impl Bandwidth {
+    pub fn new(config: BandwidthConfig) -> Self {
+        Self {
+            global_upload: Arc::new(RateLimiter::new(config.upload_bytes_per_second)),
+            global_download: Arc::new(RateLimiter::new(config.download_bytes_per_second)),
+            config: RwLock::new(config),
+        }
+    }
+
+    /// Replaces the limits. The global limits apply to bodies in flight at
+    /// once, per-connection limits to bodies started afterwards.
+    pub fn reload(&self, config: BandwidthConfig) {
+        self.global_upload.set_rate(config.upload_bytes_per_second);
+        self.global_download
+            .set_rate(config.download_bytes_per_second);
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    fn config(&self) -> std::sync::RwLockReadGuard<'_, BandwidthConfig> {
+        self.config.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns a pacer for one upload body, combining the global limit with a
+    /// fresh per-connection limit. HTTP/1.1 connections carry one request body
+    /// at a time, so limiting each body limits its connection.
+    pub fn upload_pacer(&self) -> Pacer {
+        Pacer {
+            global: self.global_upload.clone(),
+            connection: RateLimiter::new(self.config().upload_bytes_per_second_per_connection),
+        }
+    }
+
+    /// Returns a pacer for one response body served from `bucket`, using the
+    /// bucket's per-connection override when one is configured.
+    pub fn download_pacer(&self, bucket: &str) -> Pacer {
+        let config = self.config();
+        let per_connection = config
+            .buckets
+            .get(bucket)
+            .and_then(|o| o.download_bytes_per_second_per_connection)
+            .unwrap_or(config.download_bytes_per_second_per_connection);
+        Pacer {
+            global: self.global_download.clone(),
+            connection: RateLimiter::new(per_connection),
+        }
+    }
+}
+
+/// Paces the chunks of a single request or response body.
+#[derive(Debug)]
+pub struct Pacer {
+    global: Arc<RateLimiter>,
+    connection: RateLimiter,
+}
+
+impl Pacer {
+    /// Waits until `bytes` more may be transferred under both the global and
+    /// the per-connection limit.
+    pub async fn pace(&self, bytes: u64) {
+        self.global.acquire(bytes).await;
+        self.connection.acquire(bytes).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter_waits_for_debt() {
+        let limiter = RateLimiter::new(1000);
+        let now = Instant::now();
+
+        // The first second's worth passes without waiting
+        assert_eq!(limiter.reserve(1000, now), Duration::ZERO);
+        // Drawing ahead of the rate costs proportional time
+        assert_eq!(limiter.reserve(500, now), Duration::from_millis(500));
+        // Unlimited limiters never wait
+        assert_eq!(RateLimiter::new(0).reserve(u64::MAX, now), Duration::ZERO);
+    }
+}
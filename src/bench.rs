@@ -0,0 +1,433 @@
+// bench.rs
+// The `bench` subcommand, for measuring the effect of storage changes:
+// writes, reads and deletes a number of objects of a given size with a
+// given number of operations in flight, and reports the throughput and
+// latency percentiles of each phase. It drives either a running server over
+// HTTP (`--url`) or, in-process, an `S3Service` over a store in a temporary
+// directory, which leaves the HTTP layer out of the measurement.
+
+use futures::future::join_all;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::config::{BenchOptions, Config};
+use crate::http_client;
+use crate::object::Object;
+use crate::s3_service::{S3Error, S3Service};
+use crate::storage::{Storage, StorageError};
+
+/// Custom error type for setting up a benchmark
+#[derive(Debug, Error)]
+pub enum BenchError {
+    #[error("Invalid URL '{0}', expected http://host:port[/prefix]")]
+    InvalidUrl(String),
+    #[error("Failed to set up the store: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Failed to create the bucket: {0}")]
+    Setup(String),
+}
+
+/// The operations of the phases, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Put,
+    Get,
+    Delete,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Put => "put",
+            Operation::Get => "get",
+            Operation::Delete => "delete",
+        })
+    }
+}
+
+/// The results of one phase.
+#[derive(Debug, Clone)]
+pub struct PhaseReport {
+    pub operation: Operation,
+    pub errors: usize,
+    /// The first error, to tell what went wrong.
+    pub first_error: Option<String>,
+    /// Bytes moved by the successful operations.
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// Latencies of the successful operations, in ascending order.
+    latencies: Vec<Duration>,
+}
+
+impl PhaseReport {
+    fn new(operation: Operation, elapsed: Duration, results: Vec<TaskResults>) -> Self {
+        let mut report = Self {
+            operation,
+            errors: 0,
+            first_error: None,
+            bytes: 0,
+            elapsed,
+            latencies: Vec::new(),
+        };
+        for results in results {
+            report.errors += results.errors;
+            report.bytes += results.bytes;
+            report.latencies.extend(results.latencies);
+            if report.first_error.is_none() {
+                report.first_error = results.first_error;
+            }
+        }
+        report.latencies.sort_unstable();
+        report
+    }
+
+    /// Successful operations.
+    pub fn operations(&self) -> usize {
+        self.latencies.len()
+    }
+
+    pub fn ops_per_sec(&self) -> f64 {
+        self.operations() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn mib_per_sec(&self) -> f64 {
+        self.bytes as f64 / (1024.0 * 1024.0) / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// The latency `percentile` percent of the operations stayed within.
+    pub fn percentile(&self, percentile: f64) -> Duration {
+        if self.latencies.is_empty() {
+            return Duration::ZERO;
+        }
+        let rank = (percentile / 100.0 * (self.latencies.len() - 1) as f64).round() as usize;
+        self.latencies[rank.min(self.latencies.len() - 1)]
+    }
+}
+
+impl fmt::Display for PhaseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{}: {} ops, {} errors in {:.2} s, {:.1} ops/s, {:.1} MiB/s, latency p50 {:.2} ms, p90 {:.2} ms, p99 {:.2} ms, max {:.2} ms",
+            self.operation,
+            self.operations(),
+            self.errors,
+            self.elapsed.as_secs_f64(),
+            self.ops_per_sec(),
+            self.mib_per_sec(),
+            ms(self.percentile(50.0)),
+            ms(self.percentile(90.0)),
+            ms(self.percentile(99.0)),
+            ms(self.percentile(100.0))
+        )
+    }
+}
+
+/// What one of the concurrent tasks of a phase measured.
+#[derive(Debug, Default)]
+struct TaskResults {
+    latencies: Vec<Duration>,
+    bytes: u64,
+    errors: usize,
+    first_error: Option<String>,
+}
+
+/// What the benchmark drives.
+enum Target {
+    InProcess {
+        s3_service: Arc<Mutex<S3Service>>,
+        /// Removed when the benchmark is done.
+        dir: PathBuf,
+    },
+    Http {
+        authority: String,
+        /// Path before `/buckets`, e.g. a namespace.
+        prefix: String,
+    },
+}
+
+impl Target {
+    fn in_process(config: &Config) -> Result<Self, BenchError> {
+        let dir = std::env::temp_dir().join(format!("s3-bench-{}", Uuid::new_v4().simple()));
+        let db_path = dir.join("s3_storage.db");
+        std::fs::create_dir_all(&dir).map_err(StorageError::from)?;
+        let storage = Storage::open(&db_path.to_string_lossy(), dir.join("data"))?
+            .with_busy(config.storage.busy)?
+            .with_hash_algorithm(config.storage.hash_algorithm)
+            .with_slow_ops(config.storage.slow_ops);
+        let s3_service = S3Service::new(Arc::new(Mutex::new(storage)))
+            .with_storage_timeout(config.timeouts.storage());
+        Ok(Target::InProcess {
+            s3_service: Arc::new(Mutex::new(s3_service)),
+            dir,
+        })
+    }
+
+    fn http(url: &str) -> Result<Self, BenchError> {
+        let invalid = || BenchError::InvalidUrl(url.to_string());
+        let rest = url.strip_prefix("http://").ok_or_else(invalid)?;
+        let (authority, prefix) = match rest.find('/') {
+            Some(i) => rest.split_at(i),
+            None => (rest, ""),
+        };
+        if authority.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Target::Http {
+            authority: authority.to_string(),
+            prefix: prefix.trim_end_matches('/').to_string(),
+        })
+    }
+
+    async fn create_bucket(&self, bucket: &str) -> Result<(), String> {
+        match self {
+            Target::InProcess { s3_service, .. } => s3_service
+                .lock()
+                .await
+                .create_bucket(bucket)
+                .await
+                .map_err(|e| e.to_string()),
+            Target::Http { .. } => self.http_request("PUT", bucket, None, &[]).await.map(drop),
+        }
+    }
+
+    /// Removes the bucket, or the whole store of an in-process target.
+    async fn clean_up(&self, bucket: &str) -> Result<(), String> {
+        match self {
+            Target::InProcess { dir, .. } => {
+                std::fs::remove_dir_all(dir).map_err(|e| e.to_string())
+            }
+            Target::Http { .. } => self
+                .http_request("DELETE", bucket, None, &[])
+                .await
+                .map(drop),
+        }
+    }
+
+    /// Runs one operation, returning the bytes it moved.
+    async fn run(
+        &self,
+        operation: Operation,
+        bucket: &str,
+        key: &str,
+        data: &[u8],
+    ) -> Result<usize, String> {
+        match self {
+            Target::InProcess { s3_service, .. } => {
+                let mut s3 = s3_service.lock().await;
+                let error = |e: S3Error| e.to_string();
+                match operation {
+                    Operation::Put => s3
+                        .put_object(bucket, object(key, data))
+                        .await
+                        .map_err(error)
+                        .map(drop)?,
+                    Operation::Get => s3
+                        .get_object(bucket, key)
+                        .await
+                        .map_err(error)
+                        .and_then(|object| check_length(object.data.len(), data.len()))?,
+                    Operation::Delete => {
+                        s3.delete_object(bucket, key, None).await.map_err(error)?
+                    }
+                }
+            }
+            Target::Http { .. } => {
+                let body = match operation {
+                    Operation::Put => data,
+                    _ => &[][..],
+                };
+                let method = match operation {
+                    Operation::Put => "PUT",
+                    Operation::Get => "GET",
+                    Operation::Delete => "DELETE",
+                };
+                let received = self.http_request(method, bucket, Some(key), body).await?;
+                if operation == Operation::Get {
+                    check_length(received, data.len())?;
+                }
+            }
+        }
+        Ok(match operation {
+            Operation::Put | Operation::Get => data.len(),
+            Operation::Delete => 0,
+        })
+    }
+
+    /// Sends a request for a bucket or one of its objects, returning the
+    /// length of the response body.
+    async fn http_request(
+        &self,
+        method: &str,
+        bucket: &str,
+        key: Option<&str>,
+        body: &[u8],
+    ) -> Result<usize, String> {
+        let Target::Http { authority, prefix } = self else {
+            unreachable!("HTTP request to an in-process target");
+        };
+        let path = match key {
+            Some(key) => format!("{}/buckets/{}/objects/{}", prefix, bucket, key),
+            None => format!("{}/buckets/{}", prefix, bucket),
+        };
+        let response = http_client::send(authority, method, &path, &[], body)
+            .await
+            .map_err(|e| e.to_string())?;
+        if !(200..300).contains(&response.status) {
+            return Err(format!(
+                "{} {}: {}",
+                method,
+                response.status,
+                response.text()
+            ));
+        }
+        Ok(response.body.len())
+    }
+}
+
+fn object(key: &str, data: &[u8]) -> Object {
+    Object {
+        key: key.to_string(),
+        data: data.to_vec(),
+        content_type: Some("application/octet-stream".to_string()),
+        etag: None,
+        last_modified: 0,
+        user_metadata: None,
+        version_id: None,
+        immutable: false,
+    }
+}
+
+fn check_length(received: usize, expected: usize) -> Result<(), String> {
+    match received == expected {
+        true => Ok(()),
+        false => Err(format!("Read {} bytes, expected {}", received, expected)),
+    }
+}
+
+/// Runs the put, get and delete phases.
+///
+/// # Arguments
+///
+/// * `options` - What to run and against which target.
+/// * `config` - The configuration of the in-process store.
+///
+/// # Returns
+///
+/// * `Result<Vec<PhaseReport>, BenchError>` - The report of each phase, or
+///   the error setting the benchmark up.
+pub async fn run(options: &BenchOptions, config: &Config) -> Result<Vec<PhaseReport>, BenchError> {
+    let target = match &options.url {
+        Some(url) => Target::http(url)?,
+        None => Target::in_process(config)?,
+    };
+    let bucket = format!("bench-{}", &Uuid::new_v4().simple().to_string()[..8]);
+    target
+        .create_bucket(&bucket)
+        .await
+        .map_err(BenchError::Setup)?;
+
+    // Not all zeroes, so compression along the way cannot flatter the numbers
+    let data: Vec<u8> = (0..options.size).map(|i| (i % 251) as u8).collect();
+    let mut reports = Vec::new();
+    for operation in [Operation::Put, Operation::Get, Operation::Delete] {
+        reports.push(run_phase(&target, operation, &bucket, &data, options).await);
+    }
+
+    // The bucket is empty unless deletes failed, which the report shows
+    if let Err(e) = target.clean_up(&bucket).await {
+        tracing::warn!(bucket = %bucket, error = %e, "Failed to clean up after the benchmark");
+    }
+    Ok(reports)
+}
+
+async fn run_phase(
+    target: &Target,
+    operation: Operation,
+    bucket: &str,
+    data: &[u8],
+    options: &BenchOptions,
+) -> PhaseReport {
+    let next = AtomicUsize::new(0);
+    let started = Instant::now();
+    let tasks = (0..options.concurrency.max(1)).map(|_| async {
+        let mut results = TaskResults::default();
+        loop {
+            let index = next.fetch_add(1, Ordering::Relaxed);
+            if index >= options.objects {
+                return results;
+            }
+            let key = format!("object-{:08}", index);
+            let op_started = Instant::now();
+            match target.run(operation, bucket, &key, data).await {
+                Ok(bytes) => {
+                    results.latencies.push(op_started.elapsed());
+                    results.bytes += bytes as u64;
+                }
+                Err(e) => {
+                    results.errors += 1;
+                    results.first_error.get_or_insert(e);
+                }
+            }
+        }
+    });
+    let results = join_all(tasks).await;
+    PhaseReport::new(operation, started.elapsed(), results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let results = TaskResults {
+            latencies: (1..=100).rev().map(Duration::from_millis).collect(),
+            bytes: 100 * 1024 * 1024,
+            errors: 1,
+            first_error: Some("boom".to_string()),
+        };
+        let report = PhaseReport::new(Operation::Get, Duration::from_secs(2), vec![results]);
+        assert_eq!(report.operations(), 100);
+        assert_eq!(report.percentile(50.0), Duration::from_millis(51));
+        assert_eq!(report.percentile(99.0), Duration::from_millis(99));
+        assert_eq!(report.percentile(100.0), Duration::from_millis(100));
+        assert_eq!(report.ops_per_sec(), 50.0);
+        assert_eq!(report.mib_per_sec(), 50.0);
+        assert_eq!(report.first_error.as_deref(), Some("boom"));
+    }
+
+    #[test]
+    fn test_http_target() {
+        assert!(matches!(
+            Target::http("http://127.0.0.1:8080/ns/team/").unwrap(),
+            Target::Http { authority, prefix } if authority == "127.0.0.1:8080" && prefix == "/ns/team"
+        ));
+        assert!(Target::http("https://example.com").is_err());
+        assert!(Target::http("http:///buckets").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_in_process() {
+        let options = BenchOptions {
+            objects: 20,
+            size: 1024,
+            concurrency: 4,
+            url: None,
+        };
+        let reports = run(&options, &Config::default()).await.unwrap();
+        assert_eq!(reports.len(), 3);
+        for report in &reports {
+            assert_eq!(report.errors, 0, "{:?}", report.first_error);
+            assert_eq!(report.operations(), 20);
+        }
+        assert_eq!(reports[0].bytes, 20 * 1024);
+    }
+}
@@ -1,9 +1,14 @@
 // bucket.rs
-use crate::object::{Object, ObjectError}; // Ensure Object and ObjectError are accessible
-use crate::storage::{Storage, StorageError}; // Import Storage and StorageError
+use crate::object::{Object, ObjectError, ObjectInfo, StorageClass}; // Ensure Object and ObjectError are accessible
+use crate::range::ContentRange;
+use crate::storage::{ObjectReader, Storage, StorageError, lock_storage}; // Import Storage and StorageError
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, MutexGuard};
+use utoipa::ToSchema;
 
 #[derive(Debug, Error)]
 pub enum BucketError {
@@ -13,11 +18,53 @@ pub enum BucketError {
     ObjectDataError(#[from] ObjectError),
 }
 
+/// Versioning state of a bucket. A bucket that never had versioning enabled
+/// has no status at all, mirroring S3.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub enum VersioningStatus {
+    Enabled,
+    Suspended,
+}
+
+impl VersioningStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VersioningStatus::Enabled => "Enabled",
+            VersioningStatus::Suspended => "Suspended",
+        }
+    }
+}
+
+impl FromStr for VersioningStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Enabled" => Ok(VersioningStatus::Enabled),
+            "Suspended" => Ok(VersioningStatus::Suspended),
+            other => Err(format!("Unknown versioning status: {}", other)),
+        }
+    }
+}
+
+/// Moves objects whose key starts with `prefix` into `storage_class` once
+/// they are `days` old. Of several rules due for an object, the one with the
+/// longest prefix applies, then the one with the fewest days.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct LifecycleRule {
+    #[serde(default)]
+    pub prefix: String,
+    pub days: u32,
+    pub storage_class: StorageClass,
+}
+
 pub struct Bucket {
     pub name: String,
     // The bucket no longer holds objects directly in a HashMap.
     // Instead, it holds a reference to the shared Storage.
     storage: Arc<Mutex<Storage>>,
+    /// How long to wait for the storage before failing, if not indefinitely.
+    lock_timeout: Option<Duration>,
 }
 
 impl Bucket {
@@ -35,9 +82,21 @@ impl Bucket {
         Bucket {
             name,
             storage, // Store the clone of the Arc
+            lock_timeout: None,
         }
     }
 
+    /// Fails operations that wait longer than `timeout` for the storage.
+    pub fn with_lock_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    /// Locks the storage within the bucket's lock timeout.
+    async fn lock_storage(&self) -> Result<MutexGuard<'_, Storage>, StorageError> {
+        lock_storage(&self.storage, self.lock_timeout).await
+    }
+
     /// Puts an object into the bucket.
     ///
     /// # Arguments
@@ -51,7 +110,7 @@ impl Bucket {
         // Return the created Object (from get_object)
         // First, create the Object struct. This part is in-memory.
         let result = {
-            let mut storage_lock = self.storage.lock().await;
+            let mut storage_lock = self.lock_storage().await?;
             storage_lock.put_object(&self.name, object.clone())
         };
 
@@ -81,29 +140,130 @@ impl Bucket {
     /// * `Result<Object, BucketError>` - The object that was retrieved, or an error.
     pub async fn get_object(&self, key: &str) -> Result<Object, BucketError> {
         let object = {
-            let lock = self.storage.lock().await;
+            let lock = self.lock_storage().await?;
             lock.get_object(&self.name, key)
         };
         Ok(object?)
     }
 
+    /// Opens an object in the bucket for reading its data piece by piece.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the object to open.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectReader, BucketError>` - The object's metadata and file, or an error.
+    pub async fn open_object(&self, key: &str) -> Result<ObjectReader, BucketError> {
+        let reader = {
+            let lock = self.lock_storage().await?;
+            lock.open_object(&self.name, key)
+        };
+        Ok(reader?)
+    }
+
+    /// Gets the metadata of an object in the bucket without its data.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the object.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectInfo, BucketError>` - The object's metadata, or an error.
+    pub async fn head_object(&self, key: &str) -> Result<ObjectInfo, BucketError> {
+        let info = {
+            let lock = self.lock_storage().await?;
+            lock.head_object(&self.name, key)
+        };
+        Ok(info?)
+    }
+
+    /// Rewrites a byte span of an object in the bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the object to patch.
+    /// * `range` - The span to rewrite.
+    /// * `data` - The new bytes of the span.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectInfo, BucketError>` - The object's new metadata, or an error.
+    pub async fn patch_object(
+        &mut self,
+        key: &str,
+        range: &ContentRange,
+        data: &[u8],
+    ) -> Result<ObjectInfo, BucketError> {
+        let info = {
+            let mut lock = self.lock_storage().await?;
+            lock.patch_object(&self.name, key, range, data)
+        };
+        Ok(info?)
+    }
+
     /// Deletes an object from the bucket.
     ///
     /// # Arguments
     ///
     /// * `key` - The key of the object to delete.
+    /// * `if_match` - The `If-Match` header value the object's ETag must match, if any.
     ///
     /// # Returns
     ///
     /// * `Result<bool, BucketError>` - Whether the object was deleted, or an error.
-    pub async fn delete_object(&mut self, key: &str) -> Result<bool, BucketError> {
+    pub async fn delete_object(
+        &mut self,
+        key: &str,
+        if_match: Option<&str>,
+    ) -> Result<bool, BucketError> {
         let object = {
-            let mut lock = self.storage.lock().await;
-            lock.delete_object(&self.name, key)
+            let mut lock = self.lock_storage().await?;
+            match if_match {
+                Some(if_match) => lock.delete_object_if_match(&self.name, key, if_match),
+                None => lock.delete_object(&self.name, key),
+            }
         };
         Ok(object?)
     }
 
+    /// Gets the legal hold flag of an object in the bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the object.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, BucketError>` - Whether the object is under legal hold, or an error.
+    pub async fn get_legal_hold(&self, key: &str) -> Result<bool, BucketError> {
+        let legal_hold = {
+            let lock = self.lock_storage().await?;
+            lock.get_object_legal_hold(&self.name, key)
+        };
+        Ok(legal_hold?)
+    }
+
+    /// Places or releases a legal hold on an object in the bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - The key of the object.
+    /// * `legal_hold` - Whether the object should be under legal hold.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), BucketError>` - An empty result, or an error.
+    pub async fn set_legal_hold(&mut self, key: &str, legal_hold: bool) -> Result<(), BucketError> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.set_object_legal_hold(&self.name, key, legal_hold)
+        };
+        Ok(result?)
+    }
+
     /// Lists all objects in the bucket.
     ///
     /// # Returns
@@ -111,9 +271,22 @@ impl Bucket {
     /// * `Result<Vec<String>, BucketError>` - A vector of object keys in the bucket, or an error.
     pub async fn list_objects(&self) -> Result<Vec<String>, BucketError> {
         let object = {
-            let lock = self.storage.lock().await;
+            let lock = self.lock_storage().await?;
             lock.list_objects(&self.name)
         };
         Ok(object?)
     }
+
+    /// Lists the objects in the bucket with their metadata.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<ObjectInfo>, BucketError>` - The metadata of the objects, or an error.
+    pub async fn list_object_infos(&self) -> Result<Vec<ObjectInfo>, BucketError> {
+        let infos = {
+            let lock = self.lock_storage().await?;
+            lock.list_object_infos(&self.name)
+        };
+        Ok(infos?)
+    }
 }
@@ -0,0 +1,157 @@
+// bucket_logging.rs
+// Detailed logging of the operations on selected buckets, turned on per
+// bucket with PUT /buckets/{bucket}?logging. Each operation on such a bucket
+// is logged with its key, query, content type, user metadata, principal,
+// status, size and latency, under the `bucket_activity` target at info
+// level, which the default log filter lets through while keeping everything
+// else at error. Busy buckets stay quiet while one of them is debugged.
+//
+// Requests addressing a bucket by one of its aliases are not covered.
+
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use actix_web::middleware::Next;
+use actix_web::{Error, web};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+use tracing::info;
+
+use crate::metrics::bucket_operation;
+use crate::namespace::split_path;
+use crate::throttle::access_key;
+
+/// Log target of the detailed records, for use in filter directives.
+pub const TARGET: &str = "bucket_activity";
+
+/// Storage names of the buckets logged in detail, kept in memory so
+/// requests need no database lookup.
+#[derive(Debug, Default)]
+pub struct VerboseBuckets(RwLock<HashSet<String>>);
+
+impl VerboseBuckets {
+    pub fn new(buckets: impl IntoIterator<Item = String>) -> Self {
+        Self(RwLock::new(buckets.into_iter().collect()))
+    }
+
+    pub fn is_verbose(&self, bucket: &str) -> bool {
+        self.0
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(bucket)
+    }
+
+    pub fn set(&self, bucket: &str, verbose: bool) {
+        let mut buckets = self.0.write().unwrap_or_else(|e| e.into_inner());
+        if verbose {
+            buckets.insert(bucket.to_string());
+        } else {
+            buckets.remove(bucket);
+        }
+    }
+}
+
+/// The decoded object key of a request under `/buckets/{bucket}/objects/`.
+fn object_key(path: &str) -> Option<String> {
+    let path = split_path(path).map_or(path, |(_, rest)| rest);
+    let mut segments = path.trim_start_matches('/').splitn(4, '/');
+    match (segments.next(), segments.next(), segments.next()) {
+        (Some("buckets"), Some(_), Some("objects")) => segments.next().map(|key| {
+            percent_encoding::percent_decode_str(key)
+                .decode_utf8_lossy()
+                .into_owned()
+        }),
+        _ => None,
+    }
+}
+
+/// Middleware logging the operations on buckets with verbose logging on.
+pub async fn log_bucket_activity(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(verbose) = req.app_data::<web::Data<Arc<VerboseBuckets>>>().cloned() else {
+        return next.call(req).await;
+    };
+    let Some((bucket, operation)) =
+        bucket_operation(req.method().as_str(), req.path(), req.query_string())
+            .filter(|(bucket, _)| verbose.is_verbose(bucket))
+    else {
+        return next.call(req).await;
+    };
+
+    let started = Instant::now();
+    let header = |name| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("-")
+            .to_string()
+    };
+    let key = object_key(req.path()).unwrap_or_default();
+    let query = req.query_string().to_string();
+    let principal = access_key(&req);
+    let content_length = header(CONTENT_LENGTH);
+    let content_type = header(CONTENT_TYPE);
+    let metadata: BTreeMap<_, _> = req
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| {
+            let name = name.as_str().strip_prefix("x-amz-meta-")?;
+            Some((name.to_string(), value.to_str().unwrap_or("?").to_string()))
+        })
+        .collect();
+
+    let res = next.call(req).await?;
+    let bytes = match res.response().body().size() {
+        BodySize::Sized(size) => size.to_string(),
+        BodySize::None => "0".to_string(),
+        BodySize::Stream => "-".to_string(),
+    };
+    info!(
+        target: TARGET,
+        bucket,
+        operation,
+        key,
+        query,
+        principal,
+        content_length,
+        content_type,
+        metadata = ?metadata,
+        status = res.status().as_u16(),
+        bytes,
+        latency_ms = started.elapsed().as_secs_f64() * 1000.0,
+        "Bucket operation"
+    );
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_object_key() {
+        assert_eq!(
+            object_key("/buckets/b/objects/dir/a%20b.txt").as_deref(),
+            Some("dir/a b.txt")
+        );
+        assert_eq!(
+            object_key("/ns/team/buckets/b/objects/k").as_deref(),
+            Some("k")
+        );
+        assert_eq!(object_key("/buckets/b/objects"), None);
+        assert_eq!(object_key("/buckets/b"), None);
+    }
+
+    #[test]
+    fn test_verbose_buckets() {
+        let verbose = VerboseBuckets::new(["a".to_string()]);
+        assert!(verbose.is_verbose("a"));
+        verbose.set("a", false);
+        verbose.set("team/b", true);
+        assert!(!verbose.is_verbose("a"));
+        assert!(verbose.is_verbose("team/b"));
+    }
+}
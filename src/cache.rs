@@ -0,0 +1,481 @@
+// cache.rs
+// In-memory cache of object contents bounded by total size. Reads of hot
+// objects are served without touching SQLite or the disk; writes and deletes
+// made through S3Service invalidate the affected entries. When full, entries
+// are evicted by the configured policy (LRU, LFU or TTL), optionally within a
+// per-bucket budget; pinned objects are never evicted to make room for others.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
+
+use crate::config::{CacheConfig, EvictionPolicy};
+use crate::memory::MemoryBudget;
+use crate::object::Object;
+
+type CacheKey = (String, String);
+
+/// Counters describing the cache since the server started.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CacheStats {
+    pub policy: EvictionPolicy,
+    pub hits: u64,
+    pub misses: u64,
+    /// Share of lookups served from the cache, 0 before the first lookup.
+    pub hit_ratio: f64,
+    pub evictions: u64,
+    /// Entries dropped because they outlived the TTL.
+    pub expirations: u64,
+    pub entries: usize,
+    pub bytes: u64,
+    pub pinned_entries: usize,
+    pub pinned_bytes: u64,
+    pub max_bytes: u64,
+    /// Bytes cached per bucket.
+    pub bucket_bytes: BTreeMap<String, u64>,
+}
+
+/// An object key, or all keys starting with a prefix, kept in the cache.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct CachePin {
+    pub bucket: String,
+    /// The key, or the key prefix if `prefix` is set.
+    pub key: String,
+    #[serde(default)]
+    pub prefix: bool,
+}
+
+impl CachePin {
+    pub fn matches(&self, bucket: &str, key: &str) -> bool {
+        self.bucket == bucket
+            && if self.prefix {
+                key.starts_with(&self.key)
+            } else {
+                key == self.key
+            }
+    }
+}
+
+/// Objects loaded by a cache warm-up.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct CacheWarmReport {
+    pub objects: u64,
+    pub bytes: u64,
+    /// Matching objects left out because they are too large or the cache is full.
+    pub skipped: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    object: Object,
+    size: u64,
+    last_used: u64,
+    reads: u64,
+    inserted: Instant,
+    pinned: bool,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    objects: HashMap<CacheKey, Entry>,
+    /// Keys ordered from least to most recently used.
+    recency: BTreeMap<u64, CacheKey>,
+    bucket_bytes: HashMap<String, u64>,
+    bytes: u64,
+    pinned_bytes: u64,
+    clock: u64,
+}
+
+impl Entries {
+    fn remove(&mut self, key: &CacheKey) -> Option<Entry> {
+        let entry = self.objects.remove(key)?;
+        self.recency.remove(&entry.last_used);
+        self.bytes -= entry.size;
+        if entry.pinned {
+            self.pinned_bytes -= entry.size;
+        }
+        if let Some(bytes) = self.bucket_bytes.get_mut(&key.0) {
+            *bytes -= entry.size;
+            if *bytes == 0 {
+                self.bucket_bytes.remove(&key.0);
+            }
+        }
+        Some(entry)
+    }
+
+    fn bucket_bytes(&self, bucket: &str) -> u64 {
+        self.bucket_bytes.get(bucket).copied().unwrap_or(0)
+    }
+
+    fn bucket_pinned_bytes(&self, bucket: &str) -> u64 {
+        self.objects
+            .iter()
+            .filter(|((b, _), entry)| b == bucket && entry.pinned)
+            .map(|(_, entry)| entry.size)
+            .sum()
+    }
+
+    /// The unpinned entry to evict first under `policy`, optionally only
+    /// among the entries of one bucket.
+    fn eviction_candidate(&self, policy: EvictionPolicy, bucket: Option<&str>) -> Option<CacheKey> {
+        let evictable =
+            |key: &CacheKey| !self.objects[key].pinned && bucket.is_none_or(|b| key.0 == b);
+        let unpinned = || self.objects.iter().filter(|(key, _)| evictable(key));
+        match policy {
+            EvictionPolicy::Lru => self.recency.values().find(|key| evictable(key)).cloned(),
+            EvictionPolicy::Lfu => unpinned()
+                .min_by_key(|(_, entry)| (entry.reads, entry.last_used))
+                .map(|(key, _)| key.clone()),
+            EvictionPolicy::Ttl => unpinned()
+                .min_by_key(|(_, entry)| entry.inserted)
+                .map(|(key, _)| key.clone()),
+        }
+    }
+
+    fn touch(&mut self, key: &CacheKey) -> Option<&Entry> {
+        self.clock += 1;
+        let clock = self.clock;
+        let entry = self.objects.get_mut(key)?;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(clock, key.clone());
+        entry.last_used = clock;
+        entry.reads += 1;
+        Some(entry)
+    }
+}
+
+/// Size-bounded cache of objects keyed by bucket and key, evicting entries
+/// according to the configured policy.
+#[derive(Debug)]
+pub struct ObjectCache {
+    config: CacheConfig,
+    entries: std::sync::Mutex<Entries>,
+    pins: std::sync::RwLock<Vec<CachePin>>,
+    memory: Option<Arc<MemoryBudget>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+    expirations: AtomicU64,
+}
+
+impl ObjectCache {
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            entries: std::sync::Mutex::new(Entries::default()),
+            pins: std::sync::RwLock::new(Vec::new()),
+            memory: None,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+            expirations: AtomicU64::new(0),
+        }
+    }
+
+    /// A cache that stores nothing.
+    pub fn disabled() -> Self {
+        Self::new(CacheConfig {
+            max_bytes: 0,
+            ..CacheConfig::default()
+        })
+    }
+
+    /// Counts the cached bytes against a global memory budget, which the
+    /// cache also stays within.
+    pub fn with_memory_budget(mut self, memory: Arc<MemoryBudget>) -> Self {
+        self.memory = Some(memory);
+        self
+    }
+
+    fn memory_allows(&self, cached: u64, size: u64) -> bool {
+        self.memory
+            .as_ref()
+            .is_none_or(|memory| memory.allows_cache(cached, size))
+    }
+
+    fn sync_memory(&self, entries: &Entries) {
+        if let Some(memory) = &self.memory {
+            memory.set_cache_bytes(entries.bytes);
+        }
+    }
+
+    fn bucket_budget(&self, bucket: &str) -> Option<u64> {
+        self.config.buckets.get(bucket).map(|b| b.max_bytes)
+    }
+
+    /// Whether an object of `size` bytes from `bucket` may be cached at all.
+    pub fn accepts(&self, bucket: &str, size: u64) -> bool {
+        size <= self.config.max_object_bytes
+            && size <= self.config.max_bytes
+            && self
+                .bucket_budget(bucket)
+                .is_none_or(|budget| size <= budget)
+    }
+
+    /// Whether an object of `size` bytes fits without evicting anything it
+    /// may not evict: nothing for ordinary objects, unpinned entries for
+    /// pinned ones.
+    pub fn has_room(&self, bucket: &str, key: &str, size: u64) -> bool {
+        let pinned = self.is_pinned(bucket, key);
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let (used, bucket_used) = if pinned {
+            (entries.pinned_bytes, entries.bucket_pinned_bytes(bucket))
+        } else {
+            (entries.bytes, entries.bucket_bytes(bucket))
+        };
+        size <= self.config.max_bytes.saturating_sub(used)
+            && self
+                .bucket_budget(bucket)
+                .is_none_or(|budget| size <= budget.saturating_sub(bucket_used))
+    }
+
+    pub fn is_pinned(&self, bucket: &str, key: &str) -> bool {
+        let pins = self.pins.read().unwrap_or_else(|e| e.into_inner());
+        pins.iter().any(|pin| pin.matches(bucket, key))
+    }
+
+    /// Replaces the pinned keys and prefixes, re-evaluating cached entries.
+    pub fn set_pins(&self, pins: Vec<CachePin>) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let mut pinned_bytes = 0;
+        for ((bucket, key), entry) in entries.objects.iter_mut() {
+            entry.pinned = pins.iter().any(|pin| pin.matches(bucket, key));
+            if entry.pinned {
+                pinned_bytes += entry.size;
+            }
+        }
+        entries.pinned_bytes = pinned_bytes;
+        *self.pins.write().unwrap_or_else(|e| e.into_inner()) = pins;
+    }
+
+    /// Looks up an object, counting the lookup as a hit or a miss. Under the
+    /// TTL policy, unpinned entries older than the TTL are dropped instead.
+    pub fn get(&self, bucket: &str, key: &str) -> Option<Object> {
+        let cache_key = (bucket.to_string(), key.to_string());
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let expired = self.config.policy == EvictionPolicy::Ttl
+            && entries.objects.get(&cache_key).is_some_and(|entry| {
+                !entry.pinned
+                    && entry.inserted.elapsed() >= Duration::from_secs(self.config.ttl_secs)
+            });
+        if expired {
+            entries.remove(&cache_key);
+            self.expirations.fetch_add(1, Ordering::Relaxed);
+            self.sync_memory(&entries);
+        }
+        match entries.touch(&cache_key) {
+            Some(entry) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(entry.object.clone())
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Caches an object, evicting unpinned entries chosen by the eviction
+    /// policy to make room within the cache and the bucket's budget. Returns
+    /// false if the object is too large to be cached or pinned objects leave
+    /// no room for it.
+    pub fn insert(&self, bucket: &str, object: &Object) -> bool {
+        let size = object.data.len() as u64;
+        if !self.accepts(bucket, size) {
+            return false;
+        }
+        let pinned = self.is_pinned(bucket, &object.key);
+        let key = (bucket.to_string(), object.key.clone());
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.remove(&key).is_some() {
+            self.sync_memory(&entries);
+        }
+        if entries.pinned_bytes + size > self.config.max_bytes {
+            return false;
+        }
+        if let Some(budget) = self.bucket_budget(bucket) {
+            if entries.bucket_pinned_bytes(bucket) + size > budget {
+                return false;
+            }
+            while entries.bucket_bytes(bucket) + size > budget {
+                let Some(victim) = entries.eviction_candidate(self.config.policy, Some(bucket))
+                else {
+                    break;
+                };
+                entries.remove(&victim);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        while entries.bytes + size > self.config.max_bytes
+            || !self.memory_allows(entries.bytes, size)
+        {
+            let Some(victim) = entries.eviction_candidate(self.config.policy, None) else {
+                break;
+            };
+            entries.remove(&victim);
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+        }
+        if !self.memory_allows(entries.bytes, size) {
+            self.sync_memory(&entries);
+            return false;
+        }
+
+        entries.clock += 1;
+        let last_used = entries.clock;
+        entries.recency.insert(last_used, key.clone());
+        entries.bytes += size;
+        if pinned {
+            entries.pinned_bytes += size;
+        }
+        *entries.bucket_bytes.entry(bucket.to_string()).or_default() += size;
+        entries.objects.insert(
+            key,
+            Entry {
+                object: object.clone(),
+                size,
+                last_used,
+                reads: 0,
+                inserted: Instant::now(),
+                pinned,
+            },
+        );
+        self.sync_memory(&entries);
+        true
+    }
+
+    /// Drops the cached copy of an object, if any.
+    pub fn invalidate(&self, bucket: &str, key: &str) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.remove(&(bucket.to_string(), key.to_string()));
+        self.sync_memory(&entries);
+    }
+
+    /// Drops every cached object of a bucket.
+    pub fn invalidate_bucket(&self, bucket: &str) {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let keys: Vec<CacheKey> = entries
+            .objects
+            .keys()
+            .filter(|(b, _)| b == bucket)
+            .cloned()
+            .collect();
+        for key in keys {
+            entries.remove(&key);
+        }
+        self.sync_memory(&entries);
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        CacheStats {
+            policy: self.config.policy,
+            hits,
+            misses,
+            hit_ratio: if lookups == 0 {
+                0.0
+            } else {
+                hits as f64 / lookups as f64
+            },
+            evictions: self.evictions.load(Ordering::Relaxed),
+            expirations: self.expirations.load(Ordering::Relaxed),
+            entries: entries.objects.len(),
+            bytes: entries.bytes,
+            pinned_entries: entries.objects.values().filter(|e| e.pinned).count(),
+            pinned_bytes: entries.pinned_bytes,
+            max_bytes: self.config.max_bytes,
+            bucket_bytes: entries
+                .bucket_bytes
+                .iter()
+                .map(|(bucket, bytes)| (bucket.clone(), *bytes))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::BucketCacheConfig;
+
+    fn object(key: &str, size: usize) -> Object {
+        Object::new(key.to_string(), vec![0; size], None, None).unwrap()
+    }
+
+    #[test]
+    fn test_least_recently_used_object_is_evicted() {
+        let cache = ObjectCache::new(CacheConfig {
+            max_bytes: 30,
+            max_object_bytes: 20,
+            ..CacheConfig::default()
+        });
+        assert!(cache.insert("b", &object("a", 10)));
+        assert!(cache.insert("b", &object("b", 10)));
+        assert!(cache.insert("b", &object("c", 10)));
+        assert!(!cache.insert("b", &object("huge", 25)));
+
+        // Reading "a" makes "b" the least recently used entry
+        assert!(cache.get("b", "a").is_some());
+        assert!(cache.insert("b", &object("d", 10)));
+        assert!(cache.get("b", "b").is_none());
+        assert!(cache.get("b", "a").is_some());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (2, 1, 1));
+        assert_eq!((stats.entries, stats.bytes), (3, 30));
+    }
+
+    #[test]
+    fn test_pinned_objects_are_not_evicted() {
+        let cache = ObjectCache::new(CacheConfig {
+            max_bytes: 20,
+            max_object_bytes: 20,
+            ..CacheConfig::default()
+        });
+        cache.set_pins(vec![CachePin {
+            bucket: "b".to_string(),
+            key: "logo".to_string(),
+            prefix: false,
+        }]);
+        assert!(cache.insert("b", &object("logo", 10)));
+        assert!(cache.insert("b", &object("a", 10)));
+        assert!(cache.insert("b", &object("c", 10)));
+
+        // "logo" is the least recently used entry but "a" is evicted instead
+        assert!(cache.get("b", "a").is_none());
+        assert!(cache.get("b", "logo").is_some());
+        // Pinned bytes plus a new object may not exceed the budget
+        assert!(!cache.insert("b", &object("big", 15)));
+        assert_eq!(cache.stats().pinned_bytes, 10);
+    }
+
+    #[test]
+    fn test_lfu_policy_within_bucket_budget() {
+        let cache = ObjectCache::new(CacheConfig {
+            max_bytes: 100,
+            max_object_bytes: 100,
+            policy: EvictionPolicy::Lfu,
+            buckets: HashMap::from([("small".to_string(), BucketCacheConfig { max_bytes: 20 })]),
+            ..CacheConfig::default()
+        });
+        assert!(cache.insert("small", &object("a", 10)));
+        assert!(cache.insert("small", &object("b", 10)));
+        assert!(cache.insert("other", &object("c", 10)));
+        assert!(cache.get("small", "a").is_some());
+        assert!(cache.get("small", "a").is_some());
+        assert!(cache.get("small", "b").is_some());
+
+        // The bucket is at its budget, so its least frequently read entry is
+        // evicted even though "a" was read less recently; "other" is untouched
+        assert!(cache.insert("small", &object("d", 10)));
+        assert!(cache.get("small", "b").is_none());
+        assert!(cache.get("small", "a").is_some());
+        assert!(cache.get("other", "c").is_some());
+        assert!(!cache.insert("small", &object("e", 25)));
+    }
+}
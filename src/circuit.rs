@@ -0,0 +1,139 @@
+// circuit.rs
+// Circuit breaker around the storage layer. Requests failing with database
+// or I/O errors are counted; after `failure_threshold` of them in a row the
+// circuit opens and requests are refused right away with 503 instead of each
+// waiting on a broken disk. A background probe (see background.rs) keeps
+// testing the storage and closes the circuit once it answers again.
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, web};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use tracing::{error, info};
+
+use crate::error_code::{ErrorCode, error_response};
+
+/// Consecutive storage failures opening the circuit.
+pub const FAILURE_THRESHOLD: u32 = 5;
+
+/// Marker put into the extensions of error responses caused by a database
+/// or I/O failure of the storage layer.
+#[derive(Debug, Clone, Copy)]
+pub struct StorageFailure;
+
+/// State of the circuit.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    consecutive_failures: AtomicU32,
+    open: AtomicBool,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            consecutive_failures: AtomicU32::new(0),
+            open: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether requests are currently refused.
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    /// Records a request the storage failed, opening the circuit once
+    /// `failure_threshold` failed in a row.
+    pub fn record_failure(&self) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= self.failure_threshold && !self.open.swap(true, Ordering::Relaxed) {
+            error!(failures, "Storage failing repeatedly, opening circuit");
+        }
+    }
+
+    /// Records a request the storage served.
+    pub fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+    }
+
+    /// Closes the circuit after the storage answered a probe.
+    pub fn close(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        if self.open.swap(false, Ordering::Relaxed) {
+            info!("Storage recovered, closing circuit");
+        }
+    }
+}
+
+/// The response refusing a request while the circuit is open.
+pub fn refusal() -> HttpResponse {
+    let mut response = error_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::ServiceUnavailable,
+        "ServiceUnavailable: Storage is failing, retry later.",
+    );
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from_static("5"));
+    response
+}
+
+/// Whether a request is served without the storage layer. Admin requests
+/// and metrics stay available so operators can see what is going on.
+fn bypasses_storage(path: &str) -> bool {
+    path == "/metrics" || path.starts_with("/admin/")
+}
+
+/// Middleware refusing requests while the circuit is open and recording the
+/// outcome of the others.
+pub async fn fail_fast_when_storage_down(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(breaker) = req.app_data::<web::Data<Arc<CircuitBreaker>>>().cloned() else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    if bypasses_storage(req.path()) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+    if breaker.is_open() {
+        return Ok(req.into_response(refusal()).map_into_right_body());
+    }
+
+    let res = next.call(req).await?;
+    if res.response().extensions().contains::<StorageFailure>() {
+        breaker.record_failure();
+    } else if !res.status().is_server_error() {
+        breaker.record_success();
+    }
+    Ok(res.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(3);
+        breaker.record_failure();
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(!breaker.is_open());
+
+        breaker.record_failure();
+        assert!(breaker.is_open());
+
+        breaker.close();
+        assert!(!breaker.is_open());
+        breaker.record_failure();
+        assert!(!breaker.is_open());
+    }
+}
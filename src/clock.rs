@@ -0,0 +1,125 @@
+// clock.rs
+// The source of the current time for timestamps and time-dependent logic:
+// object modification times, upload creation times and lifecycle
+// transitions. The storage owns the clock, so everything stamped or compared
+// against stored times reads the same one; `SystemClock` is the default, and
+// tests inject a `ManualClock` to move time forward at will instead of
+// waiting.
+
+use std::fmt;
+use std::time::{SystemTime, SystemTimeError};
+
+/// A source of the current time.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    /// The current time in seconds since the Unix epoch, as stored.
+    fn unix_secs(&self) -> Result<i64, SystemTimeError> {
+        Ok(self.now().duration_since(SystemTime::UNIX_EPOCH)?.as_secs() as i64)
+    }
+}
+
+/// The clock of the operating system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock standing still until set or advanced, for tests.
+#[cfg(any(test, feature = "testing"))]
+#[allow(dead_code)] // Used by tests, through the library
+#[derive(Debug)]
+pub struct ManualClock(std::sync::Mutex<SystemTime>);
+
+#[cfg(any(test, feature = "testing"))]
+#[allow(dead_code)]
+impl ManualClock {
+    pub fn new(now: SystemTime) -> Self {
+        Self(std::sync::Mutex::new(now))
+    }
+
+    /// A clock showing `secs` seconds since the Unix epoch.
+    pub fn at_unix_secs(secs: u64) -> Self {
+        Self::new(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+    }
+
+    pub fn set(&self, now: SystemTime) {
+        *self.lock() = now;
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: std::time::Duration) {
+        *self.lock() += duration;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SystemTime> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl Clock for ManualClock {
+    fn now(&self) -> SystemTime {
+        *self.lock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_manual_clock() {
+        let clock = ManualClock::at_unix_secs(1_000);
+        assert_eq!(clock.unix_secs().unwrap(), 1_000);
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.unix_secs().unwrap(), 1_090);
+        clock.set(SystemTime::UNIX_EPOCH);
+        assert_eq!(clock.unix_secs().unwrap(), 0);
+        assert!(SystemClock.unix_secs().unwrap() > 1_000);
+    }
+
+    #[test]
+    fn test_storage_clock() {
+        use crate::bucket::LifecycleRule;
+        use crate::object::{Object, StorageClass};
+        use crate::storage::Storage;
+        use std::sync::Arc;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let clock = Arc::new(ManualClock::at_unix_secs(1_000_000));
+        let mut storage = Storage::open(&db_path.to_string_lossy(), dir.path().join("data"))
+            .unwrap()
+            .with_clock(clock.clone());
+        storage.create_bucket("b").unwrap();
+        storage
+            .set_bucket_lifecycle(
+                "b",
+                &[LifecycleRule {
+                    prefix: String::new(),
+                    days: 30,
+                    storage_class: StorageClass::Cold,
+                }],
+            )
+            .unwrap();
+        let object = Object::new("k".to_string(), b"hello".to_vec(), None, None).unwrap();
+        storage.put_object("b", object).unwrap();
+        assert_eq!(
+            storage.get_object("b", "k").unwrap().last_modified,
+            1_000_000
+        );
+
+        clock.advance(Duration::from_secs(29 * 86400));
+        let now = storage.clock().unix_secs().unwrap();
+        assert_eq!(storage.transition_objects(now).unwrap(), (0, 0));
+        clock.advance(Duration::from_secs(86400));
+        let now = storage.clock().unix_secs().unwrap();
+        assert_eq!(storage.transition_objects(now).unwrap(), (1, 5));
+    }
+}
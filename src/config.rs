@@ -0,0 +1,1130 @@
+// config.rs
+// Server configuration loaded from a TOML file. Every setting has a default,
+// so the file and any of its sections may be omitted.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::placement::Placement;
+use crate::secrets::SecretStore;
+use crate::storage::{HashAlgorithm, VerifyMode};
+
+/// Environment variable naming the configuration file.
+pub const CONFIG_PATH_ENV: &str = "S3_CONFIG";
+/// Configuration file read when `S3_CONFIG` is not set, if it exists.
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+/// Environment variables overriding the `[server]` section.
+pub const BIND_ENV: &str = "S3_BIND";
+pub const PORT_ENV: &str = "S3_PORT";
+pub const WORKERS_ENV: &str = "S3_WORKERS";
+
+/// Custom error type for loading the configuration.
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Failed to read config file '{0}': {1}")]
+    Io(String, std::io::Error),
+    #[error("Failed to parse config file '{0}': {1}")]
+    Parse(String, toml::de::Error),
+    #[error("Invalid command line argument '{0}'")]
+    InvalidArgument(String),
+    #[error("Invalid value '{1}' of environment variable {0}")]
+    InvalidEnv(&'static str, String),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub grpc: GrpcConfig,
+    pub logging: LoggingConfig,
+    pub access_log: AccessLogConfig,
+    pub credentials: Credentials,
+    pub throttle: ThrottleConfig,
+    pub bandwidth: BandwidthConfig,
+    pub cache: CacheConfig,
+    pub memory: MemoryConfig,
+    pub disk: DiskConfig,
+    pub storage: StorageConfig,
+    pub content_type: ContentTypeConfig,
+    pub notifications: NotificationConfig,
+    pub secrets: SecretsConfig,
+    pub jwt: JwtConfig,
+    pub backpressure: BackpressureConfig,
+    pub timeouts: TimeoutConfig,
+    pub uploads: UploadConfig,
+    pub simulation: SimulationConfig,
+    /// Start with mutating requests refused; see POST /admin/read-only.
+    pub read_only: bool,
+}
+
+/// Where the HTTP server listens, how many workers serve it and how it
+/// treats connections. The listener settings may be overridden from the
+/// environment (`S3_BIND`, `S3_PORT`, `S3_WORKERS`), so containers can run
+/// the server without a config file. The time allowed for a request head is
+/// `[timeouts] header_read_secs`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    /// Address to listen on, e.g. `0.0.0.0` to accept outside connections.
+    pub bind: String,
+    pub port: u16,
+    /// Worker threads; 0 starts one per CPU core.
+    pub workers: usize,
+    /// Listeners served instead of `bind` and `port`, e.g. one for internal
+    /// traffic and a Unix socket for a local proxy. `S3_BIND` and `S3_PORT`
+    /// do not apply to them.
+    pub listeners: Vec<ListenerConfig>,
+    /// Time an idle connection is kept open for further requests; 0 closes
+    /// connections after each response.
+    pub keep_alive_secs: u64,
+    /// Time a client has to close its side once a connection is shut down;
+    /// 0 waits indefinitely.
+    pub client_disconnect_ms: u64,
+    /// Connections each worker serves at once; further ones wait in the
+    /// listen backlog.
+    pub max_connections_per_worker: usize,
+    /// Connections waiting to be accepted.
+    pub backlog: u32,
+    /// Largest JSON request body, e.g. of a batch delete.
+    pub max_json_bytes: usize,
+    /// Largest object body accepted, refused with 413 EntityTooLarge above;
+    /// 0 means unlimited.
+    pub max_body_bytes: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1".to_string(),
+            port: 8080,
+            workers: 5,
+            listeners: Vec::new(),
+            keep_alive_secs: 5,
+            client_disconnect_ms: 1000,
+            max_connections_per_worker: 25_000,
+            backlog: 2048,
+            max_json_bytes: 1024 * 1024,
+            max_body_bytes: 0,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Applies the overrides found by `var`, which looks up an environment
+    /// variable by name.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), ConfigError> {
+        if let Some(bind) = var(BIND_ENV) {
+            self.bind = bind;
+        }
+        if let Some(port) = var(PORT_ENV) {
+            self.port = port
+                .parse()
+                .map_err(|_| ConfigError::InvalidEnv(PORT_ENV, port))?;
+        }
+        if let Some(workers) = var(WORKERS_ENV) {
+            self.workers = workers
+                .parse()
+                .map_err(|_| ConfigError::InvalidEnv(WORKERS_ENV, workers))?;
+        }
+        Ok(())
+    }
+
+    pub fn keep_alive(&self) -> Duration {
+        Duration::from_secs(self.keep_alive_secs)
+    }
+
+    pub fn client_disconnect(&self) -> Duration {
+        Duration::from_millis(self.client_disconnect_ms)
+    }
+
+    pub fn max_body(&self) -> Option<u64> {
+        (self.max_body_bytes > 0).then_some(self.max_body_bytes)
+    }
+
+    /// The listeners to serve: the configured ones, or `bind` and `port`.
+    pub fn listeners(&self) -> Vec<ListenerConfig> {
+        if self.listeners.is_empty() {
+            vec![ListenerConfig::Tcp {
+                bind: self.bind.clone(),
+                port: self.port,
+            }]
+        } else {
+            self.listeners.clone()
+        }
+    }
+
+    /// The number of worker threads to start.
+    pub fn worker_count(&self) -> usize {
+        if self.workers > 0 {
+            self.workers
+        } else {
+            std::thread::available_parallelism().map_or(1, |n| n.get())
+        }
+    }
+}
+
+/// How log records are filtered, formatted and where they are written.
+/// Without a `file` they go to stdout.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Filter directives, e.g. `info` or `warn,s3_learning_project=debug`;
+    /// `RUST_LOG` takes precedence. The `bucket_activity` target carries the
+    /// detailed records of buckets with verbose logging on, `slow_ops` the
+    /// warnings about slow storage operations.
+    pub level: String,
+    pub format: LogFormat,
+    /// Log file, rotated as configured below.
+    pub file: Option<PathBuf>,
+    pub rotation: Rotation,
+    /// Size at which the file is rotated; 0 means no limit, and leaves
+    /// rotation to tracing-appender's dated files, see `log_file.rs`.
+    pub max_bytes: u64,
+    /// Rotated files kept; 0 keeps all of them.
+    pub max_files: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: "error,bucket_activity=info,slow_ops=warn".to_string(),
+            format: LogFormat::Json,
+            file: None,
+            rotation: Rotation::Daily,
+            max_bytes: 100 * 1024 * 1024,
+            max_files: 7,
+        }
+    }
+}
+
+/// The access log, one line per request, apart from the application log.
+/// Without a `file` lines go to stdout.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AccessLogConfig {
+    pub enabled: bool,
+    pub format: AccessLogFormat,
+    pub file: Option<PathBuf>,
+    pub rotation: Rotation,
+    /// Size at which the file is rotated; 0 means no limit.
+    pub max_bytes: u64,
+    /// Rotated files kept; 0 keeps all of them.
+    pub max_files: usize,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            format: AccessLogFormat::Combined,
+            file: None,
+            rotation: Rotation::Daily,
+            max_bytes: 100 * 1024 * 1024,
+            max_files: 7,
+        }
+    }
+}
+
+/// Line format of the access log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    /// Common Log Format.
+    Common,
+    /// Combined Log Format, with latency and request ID appended.
+    Combined,
+    Json,
+}
+
+/// How log records are formatted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// One JSON object per record, for log collectors.
+    Json,
+    /// Multi-line text, for reading on a terminal.
+    Pretty,
+}
+
+/// When the log file is rotated regardless of its size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+/// The gRPC service next to the HTTP API, served by builds with the `grpc`
+/// feature. It is off unless a port is set, and meant for trusted internal
+/// consumers: bearer tokens are checked like over HTTP, but bucket roles,
+/// ACLs and the load shedding middleware are not.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct GrpcConfig {
+    pub bind: String,
+    /// Port to listen on; 0 leaves the service off.
+    pub port: u16,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1".to_string(),
+            port: 0,
+        }
+    }
+}
+
+impl GrpcConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.port > 0
+    }
+}
+
+/// A socket the server accepts connections on, given in the config as
+/// `{ bind = "0.0.0.0", port = 8080 }` or `{ unix = "/run/s3.sock" }`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum ListenerConfig {
+    Tcp {
+        bind: String,
+        port: u16,
+    },
+    /// A Unix domain socket; a stale socket file is replaced.
+    Unix {
+        unix: PathBuf,
+    },
+}
+
+impl fmt::Display for ListenerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenerConfig::Tcp { bind, port } => write!(f, "http://{}:{}", bind, port),
+            ListenerConfig::Unix { unix } => write!(f, "unix:{}", unix.display()),
+        }
+    }
+}
+
+/// Secret access keys by access key id, used to verify signed requests.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Credentials(
+    HashMap<String, String>,
+    #[serde(skip)] Option<Arc<SecretStore>>,
+);
+
+impl Credentials {
+    /// Also looks up access keys in `secrets`, which take precedence.
+    pub fn with_secrets(mut self, secrets: Arc<SecretStore>) -> Self {
+        self.1 = Some(secrets);
+        self
+    }
+
+    /// Returns the secret key of an access key id, if it is known.
+    pub fn secret_key(&self, access_key: &str) -> Option<String> {
+        self.1
+            .as_ref()
+            .and_then(|secrets| secrets.credential(access_key))
+            .or_else(|| self.0.get(access_key).cloned())
+    }
+}
+
+/// Request and bandwidth budgets per client: the subject of a verified
+/// bearer token, or the peer address of any other request. A limit of 0
+/// means unlimited.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ThrottleConfig {
+    /// Limits for principals without an entry in `keys`, and for every
+    /// client without a token, each address having its own budget.
+    pub default: ThrottleLimits,
+    /// Limits per token subject.
+    pub keys: HashMap<String, ThrottleLimits>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct ThrottleLimits {
+    pub requests_per_second: u32,
+    pub bytes_per_second: u64,
+}
+
+/// Artificial latency and failures on the data path, for testing clients
+/// against a slow or flaky object store locally. Off unless set.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct SimulationConfig {
+    /// Delay added to every data path request.
+    pub latency_ms: u64,
+    /// Up to this much more delay, chosen at random per request.
+    pub jitter_ms: u64,
+    /// Fraction of data path requests failed with 500, from 0.0 to 1.0.
+    pub error_rate: f64,
+}
+
+/// Byte-rate limits on object bodies. A limit of 0 means unlimited.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BandwidthConfig {
+    /// Combined rate of all uploads.
+    pub upload_bytes_per_second: u64,
+    /// Rate of each individual upload connection.
+    pub upload_bytes_per_second_per_connection: u64,
+    /// Combined rate of all downloads.
+    pub download_bytes_per_second: u64,
+    /// Rate of each individual download connection.
+    pub download_bytes_per_second_per_connection: u64,
+    /// Per-bucket overrides, keyed by bucket name.
+    pub buckets: HashMap<String, BucketBandwidthConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BucketBandwidthConfig {
+    /// Replaces `download_bytes_per_second_per_connection` for the bucket.
+    pub download_bytes_per_second_per_connection: Option<u64>,
+}
+
+/// Size limits and eviction policy of the in-memory object cache. A
+/// `max_bytes` of 0 disables it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Total size of the cached objects.
+    pub max_bytes: u64,
+    /// Objects larger than this are never cached.
+    pub max_object_bytes: u64,
+    pub policy: EvictionPolicy,
+    /// Lifetime of cached objects under the `ttl` policy.
+    pub ttl_secs: u64,
+    /// Size budgets of individual buckets, keyed by bucket name.
+    pub buckets: HashMap<String, BucketCacheConfig>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: 64 * 1024 * 1024,
+            max_object_bytes: 8 * 1024 * 1024,
+            policy: EvictionPolicy::default(),
+            ttl_secs: 300,
+            buckets: HashMap::new(),
+        }
+    }
+}
+
+/// Which cached objects make room for new ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum EvictionPolicy {
+    /// Least recently read first.
+    #[default]
+    Lru,
+    /// Least often read first, ties broken by recency.
+    Lfu,
+    /// Oldest first; objects also expire after `ttl_secs`.
+    Ttl,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct BucketCacheConfig {
+    /// Total size of the bucket's cached objects.
+    pub max_bytes: u64,
+}
+
+/// How objects are stored.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    /// Directory of the object files, relative to the working directory
+    /// unless absolute; `--data-dir` overrides it. It must stay the one the
+    /// database was created with.
+    pub data_dir: PathBuf,
+    /// Further directories, e.g. on other disks, new object files are spread
+    /// over besides `data_dir`. Directories may be added later, but not
+    /// removed while objects live in them.
+    pub extra_data_dirs: Vec<PathBuf>,
+    /// How new object files are spread over the data directories:
+    /// `round-robin` or `most-free`.
+    pub placement: Placement,
+    /// Algorithm of the ETags of new objects. After changing it, existing
+    /// objects are converted with POST /admin/rehash.
+    pub hash_algorithm: HashAlgorithm,
+    pub slow_ops: SlowOpConfig,
+    pub busy: BusyConfig,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            data_dir: PathBuf::from("data"),
+            extra_data_dirs: Vec::new(),
+            placement: Placement::default(),
+            hash_algorithm: HashAlgorithm::default(),
+            slow_ops: SlowOpConfig::default(),
+            busy: BusyConfig::default(),
+        }
+    }
+}
+
+/// Durations above which a storage operation is logged as slow, under the
+/// `slow_ops` target. SQL and file I/O time are compared separately; 0
+/// turns the check off.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct SlowOpConfig {
+    pub sql_ms: u64,
+    pub file_ms: u64,
+}
+
+impl Default for SlowOpConfig {
+    fn default() -> Self {
+        Self {
+            sql_ms: 100,
+            file_ms: 500,
+        }
+    }
+}
+
+/// Waiting for the database while another connection, e.g. a backup or the
+/// CLI, holds its write lock. A write still locked out after `timeout_ms`
+/// is retried `retries` times after a random pause of up to `backoff_ms`,
+/// doubled on each retry, before it fails with 503 SlowDown. The waiting
+/// holds up every other storage request, so the pauses add up to at most a
+/// quarter of `timeout_ms`, and never more than 250ms.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct BusyConfig {
+    pub timeout_ms: u64,
+    pub retries: u32,
+    pub backoff_ms: u64,
+}
+
+impl Default for BusyConfig {
+    fn default() -> Self {
+        Self {
+            timeout_ms: 5000,
+            retries: 3,
+            backoff_ms: 50,
+        }
+    }
+}
+
+/// Inference of the content type of objects uploaded without one. Both
+/// methods are off by default, leaving such objects without a type.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct ContentTypeConfig {
+    /// Recognize common formats by the signature at the start of the data.
+    pub from_content: bool,
+    /// Look up the extension of the object's key.
+    pub from_extension: bool,
+}
+
+/// Webhooks notified of object events.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookConfig {
+    /// Endpoint the events are POSTed to, as `http://host:port/path`.
+    pub url: String,
+    /// Key of the HMAC signature sent in `X-Signature`.
+    #[serde(default)]
+    pub secret: String,
+    /// Name of a secret from `[secrets]` used as the key instead of `secret`.
+    #[serde(default)]
+    pub secret_name: Option<String>,
+}
+
+/// Secrets fetched from a secret manager at startup and refreshed
+/// periodically, instead of being written into this file. Access keys are
+/// read from secrets named `credentials.<access key id>`, ahead of the
+/// `[credentials]` section; webhooks name theirs with `secret_name`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SecretsConfig {
+    pub source: SecretSource,
+    /// The secret to read, as `http://host:port/path` on the loopback
+    /// interface, e.g. `http://127.0.0.1:8200/v1/secret/data/s3` for a Vault
+    /// KV v2 secret served by a local Vault Agent.
+    pub url: String,
+    /// Environment variable holding the token sent with requests.
+    pub token_env: String,
+    /// Time between refreshes; 0 fetches the secrets only at startup.
+    pub refresh_secs: u64,
+}
+
+impl Default for SecretsConfig {
+    fn default() -> Self {
+        Self {
+            source: SecretSource::None,
+            url: String::new(),
+            token_env: "VAULT_TOKEN".to_string(),
+            refresh_secs: 300,
+        }
+    }
+}
+
+/// The secret manager secrets are fetched from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SecretSource {
+    /// No secret manager; secrets are only read from this file.
+    #[default]
+    None,
+    /// A HashiCorp Vault KV v2 secret, read with the token in `X-Vault-Token`.
+    Vault,
+    /// Any endpoint answering with a JSON object of secret names to values,
+    /// read with the token, if set, as a bearer token.
+    Http,
+}
+
+/// Authentication with JSON Web Tokens sent as `Authorization: Bearer`,
+/// e.g. by an OIDC identity provider. Tokens must be signed with HMAC
+/// (HS256, HS384 or HS512) under `secret` or the secret named `secret_name`,
+/// with RS256 or ES256 under `public_key`, or with any of these under a key
+/// of the JWKS document at `jwks_url`. Without any key, bearer tokens are
+/// not checked.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct JwtConfig {
+    /// Required `iss` claim, if set.
+    pub issuer: Option<String>,
+    /// Required `aud` claim, if set.
+    pub audience: Option<String>,
+    pub secret: Option<String>,
+    /// Name of the secret holding the key, see `[secrets]`.
+    pub secret_name: Option<String>,
+    /// PEM-encoded RSA or P-256 public key of the issuer.
+    pub public_key: Option<String>,
+    /// JWKS document, as `http://host:port/path`.
+    pub jwks_url: Option<String>,
+    /// Time between fetches of the JWKS document; 0 fetches it only at startup.
+    pub jwks_refresh_secs: u64,
+    /// Clock skew allowed when checking `exp` and `nbf`.
+    pub leeway_secs: u64,
+    /// Refuse requests without a bearer token with 401, except downloads of
+    /// share links. Requests to the admin endpoints need a token either way.
+    pub required: bool,
+}
+
+impl Default for JwtConfig {
+    fn default() -> Self {
+        Self {
+            issuer: None,
+            audience: None,
+            secret: None,
+            secret_name: None,
+            public_key: None,
+            jwks_url: None,
+            jwks_refresh_secs: 300,
+            leeway_secs: 60,
+            required: false,
+        }
+    }
+}
+
+/// Memory held by in-flight request bodies and the object cache.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MemoryConfig {
+    /// Uploads beyond this are refused with 503 SlowDown; 0 means unlimited.
+    pub max_bytes: u64,
+}
+
+/// Load shedding of writes while the storage layer is saturated. A
+/// threshold of 0 disables it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BackpressureConfig {
+    /// Writes in flight beyond which new ones are refused with 503 SlowDown.
+    pub max_queued_writes: u64,
+    /// Wait for the storage lock beyond which writes are refused.
+    pub max_lock_wait_ms: u64,
+    /// Sent in the Retry-After header of refused writes.
+    pub retry_after_secs: u64,
+}
+
+impl Default for BackpressureConfig {
+    fn default() -> Self {
+        Self {
+            max_queued_writes: 128,
+            max_lock_wait_ms: 2000,
+            retry_after_secs: 1,
+        }
+    }
+}
+
+/// Limit on the uploads whose bodies are read at the same time, and on how
+/// long resumable uploads may sit idle.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UploadConfig {
+    /// Uploads read at once; 0 means unlimited.
+    pub max_concurrent: usize,
+    /// How long further uploads wait for a slot before they are refused
+    /// with 503 SlowDown; 0 refuses them right away.
+    pub queue_timeout_ms: u64,
+    /// Resumable uploads not appended to for this long are aborted and
+    /// their bytes removed; 0 keeps them forever.
+    pub stale_after_secs: u64,
+    /// How often stale uploads are looked for.
+    pub cleanup_interval_secs: u64,
+}
+
+impl Default for UploadConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 64,
+            queue_timeout_ms: 1000,
+            stale_after_secs: 7 * 24 * 3600,
+            cleanup_interval_secs: 3600,
+        }
+    }
+}
+
+/// Time limits on requests, in seconds. A limit of 0 disables it.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// Time for a client to send the request head.
+    pub header_read_secs: u64,
+    /// Longest pause between two parts of a request body.
+    pub body_read_secs: u64,
+    /// Time to answer a request, reading its body included. Off by default,
+    /// as large uploads under bandwidth limits may legitimately take long.
+    pub request_secs: u64,
+    /// Wait for the storage before a request fails with 503.
+    pub storage_secs: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            header_read_secs: 5,
+            body_read_secs: 30,
+            request_secs: 0,
+            storage_secs: 30,
+        }
+    }
+}
+
+impl TimeoutConfig {
+    pub fn header_read(&self) -> Option<Duration> {
+        limit(self.header_read_secs)
+    }
+
+    pub fn body_read(&self) -> Option<Duration> {
+        limit(self.body_read_secs)
+    }
+
+    pub fn request(&self) -> Option<Duration> {
+        limit(self.request_secs)
+    }
+
+    pub fn storage(&self) -> Option<Duration> {
+        limit(self.storage_secs)
+    }
+}
+
+fn limit(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+/// Free space monitoring of the data volume. Watermarks are percentages of
+/// the volume in use.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DiskConfig {
+    /// Writes are refused with 507 Insufficient Storage above this usage.
+    pub high_watermark_percent: f64,
+    /// Writes are accepted again once usage drops below this.
+    pub low_watermark_percent: f64,
+    pub check_interval_secs: u64,
+}
+
+impl Default for DiskConfig {
+    fn default() -> Self {
+        Self {
+            high_watermark_percent: 95.0,
+            low_watermark_percent: 90.0,
+            check_interval_secs: 30,
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the file named by `S3_CONFIG`, falling back
+    /// to `config.toml` in the working directory and then to the defaults.
+    /// Server settings given in the environment take precedence over both.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut config = match std::env::var(CONFIG_PATH_ENV) {
+            Ok(path) => Self::from_file(&path)?,
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(DEFAULT_CONFIG_PATH)?
+            }
+            Err(_) => Self::default(),
+        };
+        config.server.apply_env(|name| std::env::var(name).ok())?;
+        Ok(config)
+    }
+
+    /// Loads the configuration from a TOML file.
+    pub fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let contents =
+            fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_string(), e))?;
+        toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_string(), e))
+    }
+}
+
+/// What the binary was asked to do on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Run the HTTP server (no subcommand).
+    Serve(StartupOptions),
+    /// Check the store offline (`fsck [--repair] [DIR]`).
+    Fsck(FsckOptions),
+    /// Measure throughput and latency (`bench [--objects N] [--size SIZE]
+    /// [--concurrency N] [--url URL]`).
+    Bench(BenchOptions),
+}
+
+impl Command {
+    /// Parses the command line arguments, without the program name.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut args = args.into_iter().peekable();
+        if args.peek().map(String::as_str) == Some("fsck") {
+            args.next();
+            return FsckOptions::from_args(args).map(Command::Fsck);
+        }
+        if args.peek().map(String::as_str) == Some("bench") {
+            args.next();
+            return BenchOptions::from_args(args).map(Command::Bench);
+        }
+        StartupOptions::from_args(args).map(Command::Serve)
+    }
+}
+
+/// Options of the `fsck` subcommand.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FsckOptions {
+    /// Drop records of missing files and move orphaned files to `lost+found`.
+    pub repair: bool,
+    /// Directory holding the database; defaults to the working directory.
+    pub dir: Option<String>,
+    /// Data directory, relative to `dir` unless absolute (`--data-dir
+    /// <dir>`); defaults to `[storage] data_dir`.
+    pub data_dir: Option<PathBuf>,
+}
+
+impl FsckOptions {
+    fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--repair" => options.repair = true,
+                "--data-dir" => {
+                    let dir = args.next().ok_or(ConfigError::InvalidArgument(arg))?;
+                    options.data_dir = Some(PathBuf::from(dir));
+                }
+                _ if arg.starts_with("--data-dir=") => {
+                    options.data_dir = Some(PathBuf::from(&arg["--data-dir=".len()..]))
+                }
+                _ if !arg.starts_with('-') && options.dir.is_none() => options.dir = Some(arg),
+                _ => return Err(ConfigError::InvalidArgument(arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Options of the `bench` subcommand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchOptions {
+    /// Objects written, read and deleted.
+    pub objects: usize,
+    /// Size of each object in bytes; given e.g. as `1MB` or `64KB`.
+    pub size: usize,
+    /// Operations in flight at a time.
+    pub concurrency: usize,
+    /// Base URL of a running server, e.g. `http://127.0.0.1:8080`, to
+    /// measure over HTTP; without one, a store in a temporary directory is
+    /// measured in-process.
+    pub url: Option<String>,
+}
+
+impl Default for BenchOptions {
+    fn default() -> Self {
+        Self {
+            objects: 1000,
+            size: 64 * 1024,
+            concurrency: 16,
+            url: None,
+        }
+    }
+}
+
+impl BenchOptions {
+    fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let (name, value) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), value.to_string()),
+                None => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| ConfigError::InvalidArgument(arg.clone()))?;
+                    (arg.clone(), value)
+                }
+            };
+            let invalid = || ConfigError::InvalidArgument(format!("{} {}", name, value));
+            match name.as_str() {
+                "--objects" => options.objects = value.parse().map_err(|_| invalid())?,
+                "--size" => options.size = parse_size(&value).ok_or_else(invalid)?,
+                "--concurrency" => {
+                    options.concurrency =
+                        value.parse().ok().filter(|n| *n > 0).ok_or_else(invalid)?
+                }
+                "--url" => options.url = Some(value),
+                _ => return Err(ConfigError::InvalidArgument(arg)),
+            }
+        }
+        Ok(options)
+    }
+}
+
+/// Parses a size in bytes with an optional unit, e.g. `512`, `64KB` or
+/// `1MB`. Units are powers of 1024.
+pub fn parse_size(size: &str) -> Option<usize> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1024,
+        "M" | "MB" | "MIB" => 1024 * 1024,
+        "G" | "GB" | "GIB" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    number.parse::<usize>().ok()?.checked_mul(multiplier)
+}
+
+/// Options of the HTTP server given on the command line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StartupOptions {
+    /// Check the stored objects before serving (`--verify-on-start[=quick|full]`).
+    pub verify_on_start: Option<VerifyMode>,
+    /// Serve even if the startup check finds problems (`--force`).
+    pub force: bool,
+    /// Create buckets and objects from a fixtures directory before serving
+    /// (`--seed <dir>`).
+    pub seed: Option<PathBuf>,
+    /// Keep the object files in this directory instead of `[storage]
+    /// data_dir` (`--data-dir <dir>`).
+    pub data_dir: Option<PathBuf>,
+}
+
+impl StartupOptions {
+    fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, ConfigError> {
+        let mut options = Self::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            options.verify_on_start = match arg.as_str() {
+                "--verify-on-start" | "--verify-on-start=quick" => Some(VerifyMode::Quick),
+                "--verify-on-start=full" => Some(VerifyMode::Full),
+                "--force" => {
+                    options.force = true;
+                    continue;
+                }
+                "--seed" => {
+                    let dir = args.next().ok_or(ConfigError::InvalidArgument(arg))?;
+                    options.seed = Some(PathBuf::from(dir));
+                    continue;
+                }
+                "--data-dir" => {
+                    let dir = args.next().ok_or(ConfigError::InvalidArgument(arg))?;
+                    options.data_dir = Some(PathBuf::from(dir));
+                    continue;
+                }
+                _ => {
+                    if let Some(dir) = arg.strip_prefix("--seed=") {
+                        options.seed = Some(PathBuf::from(dir));
+                    } else if let Some(dir) = arg.strip_prefix("--data-dir=") {
+                        options.data_dir = Some(PathBuf::from(dir));
+                    } else {
+                        return Err(ConfigError::InvalidArgument(arg));
+                    }
+                    continue;
+                }
+            };
+        }
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_line() {
+        let parse = |args: &[&str]| Command::from_args(args.iter().map(|a| a.to_string()));
+
+        assert_eq!(
+            parse(&[]).unwrap(),
+            Command::Serve(StartupOptions::default())
+        );
+        assert_eq!(
+            parse(&["--verify-on-start=full", "--force"]).unwrap(),
+            Command::Serve(StartupOptions {
+                verify_on_start: Some(VerifyMode::Full),
+                force: true,
+                seed: None,
+                data_dir: None,
+            })
+        );
+        assert_eq!(
+            parse(&["--verify-on-start"]).unwrap(),
+            Command::Serve(StartupOptions {
+                verify_on_start: Some(VerifyMode::Quick),
+                force: false,
+                seed: None,
+                data_dir: None,
+            })
+        );
+        assert!(parse(&["--verify-on-start=deep"]).is_err());
+        assert_eq!(
+            parse(&["--seed", "fixtures"]).unwrap(),
+            parse(&["--seed=fixtures"]).unwrap()
+        );
+        assert!(matches!(
+            parse(&["--seed", "fixtures"]).unwrap(),
+            Command::Serve(StartupOptions { seed: Some(dir), .. }) if dir == Path::new("fixtures")
+        ));
+        assert!(parse(&["--seed"]).is_err());
+        assert!(matches!(
+            parse(&["--data-dir=/srv/s3/data"]).unwrap(),
+            Command::Serve(StartupOptions { data_dir: Some(dir), .. }) if dir == Path::new("/srv/s3/data")
+        ));
+        assert!(parse(&["--data-dir"]).is_err());
+
+        assert_eq!(
+            parse(&["fsck", "--repair", "/srv/s3"]).unwrap(),
+            Command::Fsck(FsckOptions {
+                repair: true,
+                dir: Some("/srv/s3".to_string()),
+                data_dir: None,
+            })
+        );
+        assert_eq!(
+            parse(&["fsck", "--data-dir", "/mnt/objects", "/srv/s3"]).unwrap(),
+            Command::Fsck(FsckOptions {
+                repair: false,
+                dir: Some("/srv/s3".to_string()),
+                data_dir: Some(PathBuf::from("/mnt/objects")),
+            })
+        );
+        assert!(parse(&["fsck", "--force"]).is_err());
+
+        assert_eq!(
+            parse(&[
+                "bench",
+                "--objects",
+                "10000",
+                "--size=1MB",
+                "--concurrency",
+                "32"
+            ])
+            .unwrap(),
+            Command::Bench(BenchOptions {
+                objects: 10000,
+                size: 1024 * 1024,
+                concurrency: 32,
+                url: None,
+            })
+        );
+        assert!(parse(&["bench", "--concurrency", "0"]).is_err());
+        assert!(parse(&["bench", "--size"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Some(512));
+        assert_eq!(parse_size("64KB"), Some(64 * 1024));
+        assert_eq!(parse_size("1 MiB"), Some(1024 * 1024));
+        assert_eq!(parse_size("1TB"), None);
+        assert_eq!(parse_size("MB"), None);
+    }
+
+    #[test]
+    fn test_server_env_overrides() {
+        let env = |vars: &'static [(&str, &str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(var, _)| *var == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+
+        let mut server = ServerConfig::default();
+        server
+            .apply_env(env(&[(BIND_ENV, "0.0.0.0"), (WORKERS_ENV, "2")]))
+            .unwrap();
+        assert_eq!(server.bind, "0.0.0.0");
+        assert_eq!(server.port, 8080);
+        assert_eq!(server.worker_count(), 2);
+
+        assert!(matches!(
+            server.apply_env(env(&[(PORT_ENV, "http")])),
+            Err(ConfigError::InvalidEnv(PORT_ENV, _))
+        ));
+    }
+
+    #[test]
+    fn test_listeners() {
+        let config: Config = toml::from_str(
+            r#"
+            [[server.listeners]]
+            bind = "10.0.0.1"
+            port = 8080
+
+            [[server.listeners]]
+            unix = "/run/s3.sock"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.server.listeners(),
+            vec![
+                ListenerConfig::Tcp {
+                    bind: "10.0.0.1".to_string(),
+                    port: 8080,
+                },
+                ListenerConfig::Unix {
+                    unix: PathBuf::from("/run/s3.sock"),
+                },
+            ]
+        );
+        assert_eq!(
+            Config::default().server.listeners()[0].to_string(),
+            "http://127.0.0.1:8080"
+        );
+    }
+}
@@ -0,0 +1,216 @@
+// content_type.rs
+// Inference of the content type of objects uploaded without one, from the
+// leading "magic" bytes of their data or the extension of their key, so they
+// are served with a usable Content-Type later. A bucket's default content
+// type, when it has one, is used instead.
+
+use crate::config::ContentTypeConfig;
+
+/// Leading bytes of the data `from_content` looks at.
+pub const SNIFF_LEN: usize = 16;
+
+/// Content types recognized by the signature at the start of the data.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"\0asm", "application/wasm"),
+    (b"ID3", "audio/mpeg"),
+    (b"OggS", "audio/ogg"),
+    (b"\x1aE\xdf\xa3", "video/webm"),
+];
+
+/// Content types by lowercase key extension.
+const EXTENSIONS: &[(&str, &str)] = &[
+    ("html", "text/html"),
+    ("htm", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("json", "application/json"),
+    ("xml", "application/xml"),
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("csv", "text/csv"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("wasm", "application/wasm"),
+    ("mp3", "audio/mpeg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+];
+
+/// Infers a content type from the data's leading bytes.
+pub fn from_content(data: &[u8]) -> Option<&'static str> {
+    // RIFF containers and ISO media carry their type after a length field
+    match data {
+        [
+            b'R',
+            b'I',
+            b'F',
+            b'F',
+            _,
+            _,
+            _,
+            _,
+            b'W',
+            b'E',
+            b'B',
+            b'P',
+            ..,
+        ] => {
+            return Some("image/webp");
+        }
+        [
+            b'R',
+            b'I',
+            b'F',
+            b'F',
+            _,
+            _,
+            _,
+            _,
+            b'W',
+            b'A',
+            b'V',
+            b'E',
+            ..,
+        ] => {
+            return Some("audio/wav");
+        }
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => return Some("video/mp4"),
+        _ => {}
+    }
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| data.starts_with(signature))
+        .map(|(_, content_type)| *content_type)
+}
+
+/// Infers a content type from the extension of an object's key.
+pub fn from_extension(key: &str) -> Option<&'static str> {
+    let name = key.rsplit('/').next().unwrap_or(key);
+    let (_, extension) = name.rsplit_once('.')?;
+    let extension = extension.to_ascii_lowercase();
+    EXTENSIONS
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, content_type)| *content_type)
+}
+
+/// Infers the content type of an object uploaded without one, trying the
+/// methods enabled in `config`: the data's leading bytes first, as they are
+/// more reliable, then the key's extension.
+pub fn infer(config: &ContentTypeConfig, key: &str, data: &[u8]) -> Option<&'static str> {
+    config
+        .from_content
+        .then(|| from_content(data))
+        .flatten()
+        .or_else(|| config.from_extension.then(|| from_extension(key)).flatten())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_content() {
+        assert_eq!(from_content(b"\x89PNG\r\n\x1a\n...."), Some("image/png"));
+        assert_eq!(from_content(b"%PDF-1.7\n"), Some("application/pdf"));
+        assert_eq!(from_content(b"RIFF\0\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(from_content(b"\0\0\0\x20ftypisom"), Some("video/mp4"));
+        assert_eq!(from_content(b"hello"), None);
+        assert_eq!(from_content(b""), None);
+    }
+
+    #[test]
+    fn test_from_extension() {
+        assert_eq!(from_extension("photos/cat.JPG"), Some("image/jpeg"));
+        assert_eq!(from_extension("data.json"), Some("application/json"));
+        assert_eq!(from_extension("archive.tar.gz"), Some("application/gzip"));
+        assert_eq!(from_extension("v1.2/README"), None);
+        assert_eq!(from_extension("notes.unknown"), None);
+    }
+
+    #[test]
+    fn test_infer() {
+        let png = b"\x89PNG\r\n\x1a\n....";
+        let both = ContentTypeConfig {
+            from_content: true,
+            from_extension: true,
+        };
+        assert_eq!(infer(&both, "image.txt", png), Some("image/png"));
+        assert_eq!(infer(&both, "notes.txt", b"hello"), Some("text/plain"));
+
+        let extension_only = ContentTypeConfig {
+            from_content: false,
+            from_extension: true,
+        };
+        assert_eq!(infer(&extension_only, "image.txt", png), Some("text/plain"));
+        assert_eq!(infer(&ContentTypeConfig::default(), "image.png", png), None);
+    }
+
+    #[tokio::test]
+    async fn test_bucket_default() {
+        use crate::object::Object;
+        use crate::s3_service::S3Service;
+        use crate::storage::Storage;
+        use std::sync::Arc;
+        use tokio::sync::Mutex;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let storage = Storage::open(&db_path.to_string_lossy(), dir.path().join("data")).unwrap();
+        let mut s3 = S3Service::new(Arc::new(Mutex::new(storage))).with_content_type_inference(
+            ContentTypeConfig {
+                from_content: true,
+                from_extension: true,
+            },
+        );
+        s3.create_bucket("b").await.unwrap();
+        s3.put_bucket_default_content_type("b", Some("application/json"))
+            .await
+            .unwrap();
+        assert!(
+            s3.put_bucket_default_content_type("b", Some("json"))
+                .await
+                .is_err()
+        );
+
+        let put = |key: &str, content_type: Option<&str>| {
+            Object::new(
+                key.to_string(),
+                b"{}".to_vec(),
+                content_type.map(str::to_string),
+                None,
+            )
+            .unwrap()
+        };
+        let stored = s3.put_object("b", put("a.txt", None)).await.unwrap();
+        assert_eq!(stored.content_type.as_deref(), Some("application/json"));
+        let stored = s3
+            .put_object("b", put("b.txt", Some("text/csv")))
+            .await
+            .unwrap();
+        assert_eq!(stored.content_type.as_deref(), Some("text/csv"));
+
+        // Without a default, the content type is inferred again
+        s3.put_bucket_default_content_type("b", None).await.unwrap();
+        let stored = s3.put_object("b", put("c.txt", None)).await.unwrap();
+        assert_eq!(stored.content_type.as_deref(), Some("text/plain"));
+    }
+}
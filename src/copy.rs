@@ -0,0 +1,219 @@
+// copy.rs
+// Server-side copies of every object under a key prefix from one bucket to
+// another, e.g. to promote a dataset from staging to production. Copies run
+// in the background in small batches, releasing the storage lock in between;
+// their progress is polled with GET /admin/copy/{id}.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tokio::time;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+use crate::cache::ObjectCache;
+use crate::storage::{Storage, StorageError};
+
+/// Objects copied per batch.
+const BATCH_SIZE: usize = 64;
+/// Pause between batches, leaving the storage to the request handlers.
+const BATCH_PAUSE: Duration = Duration::from_millis(10);
+
+/// Custom error type for starting a copy job.
+#[derive(Debug, Error)]
+pub enum CopyError {
+    #[error("Bucket '{0}' not found")]
+    BucketNotFound(String),
+    #[error("Cannot copy bucket '{0}' onto itself")]
+    SameBucket(String),
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CopyState {
+    #[default]
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Progress of a copy job.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct CopyStatus {
+    pub id: String,
+    pub state: CopyState,
+    pub source: String,
+    pub destination: String,
+    pub prefix: String,
+    /// Objects and bytes under the prefix when the job started.
+    pub total_objects: u64,
+    pub total_bytes: u64,
+    pub copied_objects: u64,
+    pub copied_bytes: u64,
+    /// Keys not copied because the destination object is under legal hold
+    /// or write-once.
+    pub skipped: Vec<String>,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub error: Option<String>,
+}
+
+/// Runs prefix copies in the background and keeps their progress.
+pub struct CopyJobs {
+    storage: Arc<Mutex<Storage>>,
+    cache: Arc<ObjectCache>,
+    jobs: std::sync::Mutex<HashMap<String, CopyStatus>>,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+impl CopyJobs {
+    pub fn new(storage: Arc<Mutex<Storage>>, cache: Arc<ObjectCache>) -> Self {
+        Self {
+            storage,
+            cache,
+            jobs: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the progress of a job, if it exists.
+    pub fn status(&self, id: &str) -> Option<CopyStatus> {
+        self.jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .cloned()
+    }
+
+    fn update(&self, id: &str, f: impl FnOnce(&mut CopyStatus)) {
+        if let Some(status) = self
+            .jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get_mut(id)
+        {
+            f(status);
+        }
+    }
+
+    /// Starts copying the objects under `prefix` from `source` to
+    /// `destination` and returns the initial status. Either bucket may be
+    /// given by an alias.
+    pub async fn start(
+        self: &Arc<Self>,
+        source: &str,
+        destination: &str,
+        prefix: &str,
+    ) -> Result<CopyStatus, CopyError> {
+        let (source, destination, (total_objects, total_bytes)) = {
+            let storage = self.storage.lock().await;
+            let resolve = |name: &str| {
+                storage
+                    .resolve_bucket(name)?
+                    .ok_or_else(|| CopyError::BucketNotFound(name.to_string()))
+            };
+            let source = resolve(source)?;
+            let destination = resolve(destination)?;
+            if source == destination {
+                return Err(CopyError::SameBucket(source));
+            }
+            let totals = storage.count_prefix(&source, prefix)?;
+            (source, destination, totals)
+        };
+
+        let status = CopyStatus {
+            id: uuid::Uuid::new_v4().to_string(),
+            state: CopyState::Running,
+            source,
+            destination,
+            prefix: prefix.to_string(),
+            total_objects,
+            total_bytes,
+            started_at: now(),
+            ..CopyStatus::default()
+        };
+        self.jobs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(status.id.clone(), status.clone());
+
+        let jobs = self.clone();
+        let id = status.id.clone();
+        tokio::spawn(async move {
+            let result = jobs.run(&id).await;
+            jobs.update(&id, |status| {
+                status.finished_at = Some(now());
+                match &result {
+                    Ok(()) => status.state = CopyState::Completed,
+                    Err(e) => {
+                        status.state = CopyState::Failed;
+                        status.error = Some(e.clone());
+                    }
+                }
+            });
+            let Some(status) = jobs.status(&id) else {
+                return;
+            };
+            match result {
+                Ok(()) if status.skipped.is_empty() => info!(
+                    copied = status.copied_objects,
+                    bytes = status.copied_bytes,
+                    "Copy job '{}' completed",
+                    id
+                ),
+                Ok(()) => warn!(
+                    copied = status.copied_objects,
+                    skipped = status.skipped.len(),
+                    "Copy job '{}' completed with objects left uncopied",
+                    id
+                ),
+                Err(e) => error!(error = %e, "Copy job '{}' failed", id),
+            }
+        });
+        Ok(status)
+    }
+
+    async fn run(&self, id: &str) -> Result<(), String> {
+        let Some(job) = self.status(id) else {
+            return Ok(());
+        };
+        let mut after = String::new();
+        loop {
+            let batch = {
+                let mut storage = self.storage.lock().await;
+                storage
+                    .copy_prefix_batch(
+                        &job.source,
+                        &job.destination,
+                        &job.prefix,
+                        &after,
+                        BATCH_SIZE,
+                    )
+                    .map_err(|e| e.to_string())?
+            };
+            for key in &batch.copied {
+                self.cache.invalidate(&job.destination, key);
+            }
+            self.update(id, |status| {
+                status.copied_objects += batch.copied.len() as u64;
+                status.copied_bytes += batch.bytes;
+                status.skipped.extend(batch.skipped);
+            });
+            match batch.cursor {
+                Some(next) => after = next,
+                None => return Ok(()),
+            }
+            time::sleep(BATCH_PAUSE).await;
+        }
+    }
+}
@@ -0,0 +1,146 @@
+// disk.rs
+// Free space of the data volume. When usage crosses the high watermark the
+// server turns read-only: writes are refused with 507 Insufficient Storage
+// while reads and deletes keep working, until usage drops below the low
+// watermark again.
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, web};
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::config::DiskConfig;
+use crate::error_code::{ErrorCode, error_response};
+
+/// Size and free space of a volume.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub total_bytes: u64,
+    /// Bytes available to unprivileged processes.
+    pub available_bytes: u64,
+}
+
+impl DiskUsage {
+    /// Percentage of the volume not available for new data.
+    pub fn used_percent(&self) -> f64 {
+        if self.total_bytes == 0 {
+            return 0.0;
+        }
+        100.0 * (self.total_bytes - self.available_bytes.min(self.total_bytes)) as f64
+            / self.total_bytes as f64
+    }
+}
+
+/// Returns the usage of the volume holding `path`.
+pub fn disk_usage(path: &Path) -> io::Result<DiskUsage> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is NUL-terminated and `stat` is a valid out-pointer.
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let block_size = stat.f_frsize as u64;
+    Ok(DiskUsage {
+        total_bytes: stat.f_blocks as u64 * block_size,
+        available_bytes: stat.f_bavail as u64 * block_size,
+    })
+}
+
+/// Whether the server accepts writes, driven by the watermarks in `[disk]`.
+#[derive(Debug)]
+pub struct DiskState {
+    config: DiskConfig,
+    read_only: AtomicBool,
+}
+
+impl DiskState {
+    pub fn new(config: DiskConfig) -> Self {
+        Self {
+            config,
+            read_only: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::Relaxed)
+    }
+
+    /// Applies a new usage reading, returning the new read-only state if it
+    /// changed.
+    pub fn update(&self, usage: DiskUsage) -> Option<bool> {
+        let used = usage.used_percent();
+        let read_only = self.is_read_only();
+        let next = if read_only {
+            used >= self.config.low_watermark_percent
+        } else {
+            used >= self.config.high_watermark_percent
+        };
+        (next != read_only).then(|| {
+            self.read_only.store(next, Ordering::Relaxed);
+            next
+        })
+    }
+}
+
+/// Whether a request writes data and is refused while the volume is full.
+pub fn is_write(method: &Method, path: &str) -> bool {
+    [Method::PUT, Method::POST, Method::PATCH].contains(method) && !path.starts_with("/admin/")
+}
+
+/// The response refusing a write while the volume is full.
+pub fn refusal() -> HttpResponse {
+    error_response(
+        StatusCode::INSUFFICIENT_STORAGE,
+        ErrorCode::InsufficientStorage,
+        "InsufficientStorage: The data volume is almost full; the server is read-only.",
+    )
+}
+
+/// Middleware refusing object writes while the data volume is above its
+/// high watermark. Deletes and admin requests are let through so space can
+/// be freed.
+pub async fn reject_writes_when_full(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let read_only = req
+        .app_data::<web::Data<Arc<DiskState>>>()
+        .is_some_and(|state| state.is_read_only());
+
+    if read_only && is_write(req.method(), req.path()) {
+        return Ok(req.into_response(refusal()).map_into_right_body());
+    }
+
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watermarks_have_hysteresis() {
+        let state = DiskState::new(DiskConfig::default());
+        let usage = |used: u64| DiskUsage {
+            total_bytes: 100,
+            available_bytes: 100 - used,
+        };
+
+        assert_eq!(state.update(usage(80)), None);
+        assert_eq!(state.update(usage(96)), Some(true));
+        assert!(state.is_read_only());
+
+        // Stays read-only between the watermarks
+        assert_eq!(state.update(usage(92)), None);
+        assert_eq!(state.update(usage(85)), Some(false));
+        assert!(!state.is_read_only());
+    }
+}
@@ -0,0 +1,288 @@
+// error_code.rs
+// Stable machine-readable codes of error responses, named after those of S3
+// where it has one, and the envelope every error body is sent in:
+// `{code, message, resource, request_id}`. Clients branch on the code, also
+// sent in the `x-error-code` header, rather than on the English messages,
+// which may change. Clients accepting `application/problem+json` get the
+// same error as RFC 7807 problem details instead. Every error also carries
+// the request's ID, in the body, the `x-amz-request-id` header and the log
+// record of the failure, so a failure a client reports can be found in the
+// server's logs.
+
+use actix_web::body::{BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{ACCEPT, CONTENT_TYPE, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, HttpResponse};
+use serde::Serialize;
+use std::fmt;
+use tracing::{error, warn};
+use tracing_actix_web::RequestId;
+use utoipa::ToSchema;
+
+/// Header carrying the code of an error response.
+pub const ERROR_CODE_HEADER: &str = "x-error-code";
+/// Header carrying the ID of a failed request, also for HEAD requests,
+/// whose error bodies are never sent.
+pub const REQUEST_ID_HEADER: &str = "x-amz-request-id";
+/// Media type of RFC 7807 problem details.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub enum ErrorCode {
+    NoSuchBucket,
+    NoSuchKey,
+    NoSuchAlias,
+    NoSuchUpload,
+    NoSuchCopyJob,
+    NoSuchShare,
+    /// The share link ran out of downloads or expired.
+    ShareExpired,
+    BucketAlreadyOwnedByYou,
+    InvalidRequest,
+    InvalidRange,
+    IncompleteBody,
+    EntityTooLarge,
+    RequestTimeout,
+    MetadataTooLarge,
+    AccessDenied,
+    ObjectLocked,
+    ObjectImmutable,
+    WriteOnceConflict,
+    UploadConflict,
+    PreconditionFailed,
+    /// Another job of the same kind is already running.
+    OperationAborted,
+    SlowDown,
+    ServiceUnavailable,
+    InsufficientStorage,
+    InternalError,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::NoSuchBucket => "NoSuchBucket",
+            ErrorCode::NoSuchKey => "NoSuchKey",
+            ErrorCode::NoSuchAlias => "NoSuchAlias",
+            ErrorCode::NoSuchUpload => "NoSuchUpload",
+            ErrorCode::NoSuchCopyJob => "NoSuchCopyJob",
+            ErrorCode::NoSuchShare => "NoSuchShare",
+            ErrorCode::ShareExpired => "ShareExpired",
+            ErrorCode::BucketAlreadyOwnedByYou => "BucketAlreadyOwnedByYou",
+            ErrorCode::InvalidRequest => "InvalidRequest",
+            ErrorCode::InvalidRange => "InvalidRange",
+            ErrorCode::IncompleteBody => "IncompleteBody",
+            ErrorCode::EntityTooLarge => "EntityTooLarge",
+            ErrorCode::RequestTimeout => "RequestTimeout",
+            ErrorCode::MetadataTooLarge => "MetadataTooLarge",
+            ErrorCode::AccessDenied => "AccessDenied",
+            ErrorCode::ObjectLocked => "ObjectLocked",
+            ErrorCode::ObjectImmutable => "ObjectImmutable",
+            ErrorCode::WriteOnceConflict => "WriteOnceConflict",
+            ErrorCode::UploadConflict => "UploadConflict",
+            ErrorCode::PreconditionFailed => "PreconditionFailed",
+            ErrorCode::OperationAborted => "OperationAborted",
+            ErrorCode::SlowDown => "SlowDown",
+            ErrorCode::ServiceUnavailable => "ServiceUnavailable",
+            ErrorCode::InsufficientStorage => "InsufficientStorage",
+            ErrorCode::InternalError => "InternalError",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The body of every error response.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub code: ErrorCode,
+    pub message: String,
+    /// Path of the request that failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource: Option<String>,
+    /// ID of the request in the server's logs.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl ErrorBody {
+    /// The error as RFC 7807 problem details, keeping the code and request ID
+    /// as extension members.
+    pub fn to_problem(&self, status: StatusCode) -> serde_json::Value {
+        let mut problem = serde_json::json!({
+            "type": format!("urn:s3:error:{}", self.code),
+            "title": self.code,
+            "status": status.as_u16(),
+            "detail": self.message,
+            "code": self.code,
+        });
+        if let Some(resource) = &self.resource {
+            problem["instance"] = resource.as_str().into();
+        }
+        if let Some(request_id) = &self.request_id {
+            problem["request_id"] = request_id.as_str().into();
+        }
+        problem
+    }
+}
+
+/// Builds an error response with its code in the body and the header. The
+/// request's details are filled in by `complete_error_bodies`.
+///
+/// # Arguments
+///
+/// * `status` - The HTTP status of the response.
+/// * `code` - The machine-readable code of the error.
+/// * `message` - The human-readable description of the error.
+///
+/// # Returns
+///
+/// * `HttpResponse` - The JSON error response.
+pub fn error_response(status: StatusCode, code: ErrorCode, message: &str) -> HttpResponse {
+    let body = ErrorBody {
+        code,
+        message: message.to_string(),
+        resource: None,
+        request_id: None,
+    };
+    let mut response = HttpResponse::build(status)
+        .insert_header((ERROR_CODE_HEADER, code.as_str()))
+        .json(&body);
+    response.extensions_mut().insert(body);
+    response
+}
+
+/// Whether an `Accept` header asks for problem details.
+fn accepts_problem_json(accept: &str) -> bool {
+    accept.split(',').any(|range| {
+        range
+            .split(';')
+            .next()
+            .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case(PROBLEM_JSON))
+    })
+}
+
+/// Middleware adding the request's path and ID to error responses,
+/// wherever they were produced, rendering them as problem details if the
+/// client asks for those, and logging the failure under the same ID.
+/// Registered inside the tracing middleware, which assigns the ID and
+/// records it in the request's span.
+pub async fn complete_error_bodies(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let method = req.method().clone();
+    let resource = req.path().to_string();
+    let request_id = req.extensions().get::<RequestId>().map(|id| id.to_string());
+    let problem = req
+        .headers()
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(accepts_problem_json);
+
+    let mut res = next.call(req).await?;
+    let Some(mut body) = res.response().extensions().get::<ErrorBody>().cloned() else {
+        return Ok(res.map_into_left_body());
+    };
+    let (status, id) = (res.status(), request_id.as_deref().unwrap_or("-"));
+    if status.is_server_error() {
+        error!(
+            request_id = id,
+            %method,
+            resource,
+            status = status.as_u16(),
+            code = %body.code,
+            error = body.message,
+            "Request failed"
+        );
+    } else {
+        warn!(
+            request_id = id,
+            %method,
+            resource,
+            status = status.as_u16(),
+            code = %body.code,
+            error = body.message,
+            "Request failed"
+        );
+    }
+    if let Some(id) = request_id
+        .as_deref()
+        .and_then(|id| HeaderValue::from_str(id).ok())
+    {
+        res.response_mut()
+            .headers_mut()
+            .insert(HeaderName::from_static(REQUEST_ID_HEADER), id);
+    }
+    body.resource = Some(resource);
+    body.request_id = request_id;
+    // Kept for the binary encodings, which serialize the completed body
+    res.response_mut().extensions_mut().insert(body.clone());
+    let json = if problem {
+        res.response_mut()
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+        body.to_problem(res.status()).to_string()
+    } else {
+        serde_json::to_string(&body).unwrap_or_default()
+    };
+    Ok(res
+        .map_body(|_, _| BoxBody::new(json))
+        .map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_serialize_as_their_names() {
+        for code in [
+            ErrorCode::NoSuchKey,
+            ErrorCode::BucketAlreadyOwnedByYou,
+            ErrorCode::InternalError,
+        ] {
+            assert_eq!(
+                serde_json::to_value(code).unwrap(),
+                serde_json::Value::from(code.as_str())
+            );
+        }
+    }
+
+    #[test]
+    fn test_accepts_problem_json() {
+        assert!(accepts_problem_json("application/problem+json"));
+        assert!(accepts_problem_json(
+            "application/json;q=0.9, Application/Problem+JSON;q=1"
+        ));
+        assert!(!accepts_problem_json("application/json"));
+        assert!(!accepts_problem_json("*/*"));
+    }
+
+    #[test]
+    fn test_to_problem() {
+        let body = ErrorBody {
+            code: ErrorCode::NoSuchKey,
+            message: "Object 'a' not found in bucket 'b'".to_string(),
+            resource: Some("/buckets/b/objects/a".to_string()),
+            request_id: None,
+        };
+        assert_eq!(
+            body.to_problem(StatusCode::NOT_FOUND),
+            serde_json::json!({
+                "type": "urn:s3:error:NoSuchKey",
+                "title": "NoSuchKey",
+                "status": 404,
+                "detail": "Object 'a' not found in bucket 'b'",
+                "code": "NoSuchKey",
+                "instance": "/buckets/b/objects/a",
+            })
+        );
+    }
+}
@@ -0,0 +1,146 @@
+// expect.rs
+// Handling of `Expect: 100-continue`. Clients sending it wait for
+// "100 Continue" before they transmit the body, so uploads that would be
+// refused anyway are answered with their final status right away and the
+// body is never sent. The checks run before routing, so they repeat those of
+// the middlewares and handlers the request would reach: maintenance and
+// read-only mode, a full data volume, the memory budget and the existence of
+// the bucket.
+
+use actix_http::{HttpMessage, Request};
+use actix_web::HttpResponse;
+use actix_web::http::StatusCode;
+use actix_web::http::header::{CONTENT_LENGTH, HeaderValue, RETRY_AFTER};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::warn;
+
+use crate::disk::{self, DiskState};
+use crate::error_code::{ErrorCode, error_response};
+use crate::maintenance::MaintenanceMode;
+use crate::memory::MemoryBudget;
+use crate::namespace::split_path;
+use crate::read_only::{self, ReadOnlyMode};
+use crate::storage::Storage;
+
+/// Decides whether a client announcing its body may send it.
+pub struct ExpectCheck {
+    storage: Arc<Mutex<Storage>>,
+    memory: Arc<MemoryBudget>,
+    disk: Arc<DiskState>,
+    read_only: Arc<ReadOnlyMode>,
+    maintenance: Arc<MaintenanceMode>,
+}
+
+impl ExpectCheck {
+    pub fn new(
+        storage: Arc<Mutex<Storage>>,
+        memory: Arc<MemoryBudget>,
+        disk: Arc<DiskState>,
+        read_only: Arc<ReadOnlyMode>,
+        maintenance: Arc<MaintenanceMode>,
+    ) -> Self {
+        Self {
+            storage,
+            memory,
+            disk,
+            read_only,
+            maintenance,
+        }
+    }
+
+    /// Returns the response refusing the request, or `None` if the client
+    /// may go on and send its body.
+    pub async fn check(&self, req: &Request) -> Option<HttpResponse> {
+        let (method, path) = (req.method(), req.path());
+        if let Some(refusal) = self.maintenance.refusal_for(path) {
+            return Some(refusal);
+        }
+        if self.read_only.is_enabled() && read_only::is_mutation(method, path) {
+            return Some(read_only::refusal());
+        }
+        if self.disk.is_read_only() && disk::is_write(method, path) {
+            return Some(disk::refusal());
+        }
+
+        let declared = req
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(0);
+        if !self.memory.has_room(declared) {
+            warn!(declared, "Upload refused by memory budget before its body");
+            let mut response = error_response(
+                StatusCode::SERVICE_UNAVAILABLE,
+                ErrorCode::SlowDown,
+                "SlowDown: Please reduce your request rate.",
+            );
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from_static("1"));
+            return Some(response);
+        }
+
+        let (bucket, storage_name) = upload_bucket(method.as_str(), path)?;
+        match self.storage.lock().await.resolve_bucket(&storage_name) {
+            Ok(Some(_)) => None,
+            Ok(None) => Some(error_response(
+                StatusCode::NOT_FOUND,
+                ErrorCode::NoSuchBucket,
+                &format!("Bucket '{}' not found", bucket),
+            )),
+            // Left to the handler, which reports the error properly
+            Err(_) => None,
+        }
+    }
+}
+
+/// Returns the bucket an upload goes to, as clients name it and as it is
+/// stored, for object and upload paths and form uploads to the bucket.
+fn upload_bucket(method: &str, path: &str) -> Option<(String, String)> {
+    let (namespace, path) = match split_path(path) {
+        Some((namespace, rest)) => (Some(namespace), rest),
+        None => (None, path),
+    };
+    let mut segments = path.trim_start_matches('/').split('/');
+    if segments.next() != Some("buckets") {
+        return None;
+    }
+    let bucket = segments.next().filter(|b| !b.is_empty())?;
+    match (method, segments.next()) {
+        ("POST", None) => {}
+        (_, Some("objects" | "uploads" | "folders")) => {}
+        _ => return None,
+    }
+    let storage_name = match namespace {
+        Some(namespace) => format!("{}/{}", namespace, bucket),
+        None => bucket.to_string(),
+    };
+    Some((bucket.to_string(), storage_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_bucket() {
+        let bucket = |method, path| upload_bucket(method, path).map(|(_, name)| name);
+        assert_eq!(
+            bucket("PUT", "/buckets/photos/objects/cat.jpg"),
+            Some("photos".to_string())
+        );
+        assert_eq!(
+            bucket("PATCH", "/ns/team-a/buckets/photos/uploads/1"),
+            Some("team-a/photos".to_string())
+        );
+        assert_eq!(
+            bucket("POST", "/buckets/photos"),
+            Some("photos".to_string())
+        );
+        // Creating a bucket needs no existing one
+        assert_eq!(bucket("PUT", "/buckets/photos"), None);
+        assert_eq!(bucket("POST", "/admin/restore"), None);
+    }
+}
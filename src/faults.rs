@@ -0,0 +1,209 @@
+// faults.rs
+// Fault injection into the storage, for tests of the error paths, the
+// circuit breaker and crash recovery (feature `faults`). A `FaultSchedule`
+// is a list of rules, each naming a fault, the operations it hits and when;
+// `Storage::with_faults` makes the storage consult it at the start of its
+// operations and the health probe. The schedule is shared, so a test can
+// keep programming it while the server runs.
+//
+// Operations are named as in the storage spans and slow operation warnings,
+// e.g. `put_object`, `get_object` or `probe`.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// What an injected fault does.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// The operation fails with an I/O error before doing anything.
+    IoError,
+    /// The operation is delayed, then runs normally.
+    Latency(Duration),
+    /// An object write stores only its first bytes, then fails, as if the
+    /// process crashed mid-write. Other operations run normally.
+    PartialWrite(usize),
+}
+
+/// When a fault is injected.
+#[derive(Debug, Clone)]
+pub struct FaultRule {
+    fault: Fault,
+    operation: Option<String>,
+    skip: u32,
+    times: Option<u32>,
+}
+
+impl FaultRule {
+    /// A rule injecting `fault` into every operation, from now on.
+    pub fn new(fault: Fault) -> Self {
+        Self {
+            fault,
+            operation: None,
+            skip: 0,
+            times: None,
+        }
+    }
+
+    /// Restricts the rule to `operation`.
+    pub fn with_operation(mut self, operation: &str) -> Self {
+        self.operation = Some(operation.to_string());
+        self
+    }
+
+    /// Lets the first `skip` matching operations through untouched.
+    pub fn with_skip(mut self, skip: u32) -> Self {
+        self.skip = skip;
+        self
+    }
+
+    /// Injects the fault `times` times, then drops the rule.
+    pub fn with_times(mut self, times: u32) -> Self {
+        self.times = Some(times);
+        self
+    }
+
+    fn matches(&self, operation: &str) -> bool {
+        self.operation.as_deref().is_none_or(|op| op == operation)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Schedule {
+    rules: Vec<FaultRule>,
+    injected: u64,
+}
+
+/// The programmable schedule of faults, shared by its clones.
+#[derive(Debug, Clone, Default)]
+pub struct FaultSchedule(Arc<Mutex<Schedule>>);
+
+impl FaultSchedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rule, consulted after the ones added before.
+    pub fn add(&self, rule: FaultRule) {
+        self.lock().rules.push(rule);
+    }
+
+    /// Removes every rule, so the storage behaves normally again.
+    pub fn clear(&self) {
+        self.lock().rules.clear();
+    }
+
+    /// How many faults were injected so far.
+    pub fn injected(&self) -> u64 {
+        self.lock().injected
+    }
+
+    /// The fault to inject into `operation`, if any. Every matching rule
+    /// counts the operation towards its skip; the first one past it fires.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The name of the storage operation about to run.
+    ///
+    /// # Returns
+    ///
+    /// * `Option<Fault>` - The fault, or `None` to run normally.
+    pub fn next(&self, operation: &str) -> Option<Fault> {
+        let mut schedule = self.lock();
+        let mut fault = None;
+        for rule in schedule.rules.iter_mut().filter(|r| r.matches(operation)) {
+            if rule.skip > 0 {
+                rule.skip -= 1;
+                continue;
+            }
+            if fault.is_none() {
+                fault = Some(rule.fault.clone());
+                if let Some(times) = &mut rule.times {
+                    *times -= 1;
+                }
+            }
+        }
+        schedule.rules.retain(|rule| rule.times != Some(0));
+        if fault.is_some() {
+            schedule.injected += 1;
+        }
+        fault
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Schedule> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Object;
+    use crate::storage::Storage;
+
+    fn object(key: &str, data: &[u8]) -> Object {
+        Object {
+            key: key.to_string(),
+            data: data.to_vec(),
+            content_type: None,
+            etag: None,
+            last_modified: 0,
+            user_metadata: None,
+            version_id: None,
+            immutable: false,
+        }
+    }
+
+    #[test]
+    fn test_schedule() {
+        let schedule = FaultSchedule::new();
+        schedule.add(
+            FaultRule::new(Fault::IoError)
+                .with_operation("put_object")
+                .with_skip(1)
+                .with_times(2),
+        );
+        schedule.add(FaultRule::new(Fault::Latency(Duration::from_millis(5))));
+
+        assert_eq!(
+            schedule.next("put_object"),
+            Some(Fault::Latency(Duration::from_millis(5)))
+        );
+        assert_eq!(schedule.next("put_object"), Some(Fault::IoError));
+        assert_eq!(schedule.next("put_object"), Some(Fault::IoError));
+        assert_eq!(
+            schedule.next("put_object"),
+            Some(Fault::Latency(Duration::from_millis(5)))
+        );
+        assert_eq!(schedule.injected(), 4);
+
+        schedule.clear();
+        assert_eq!(schedule.next("get_object"), None);
+    }
+
+    #[test]
+    fn test_storage_faults() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let faults = FaultSchedule::new();
+        let mut storage = Storage::open(&db_path.to_string_lossy(), dir.path().join("data"))
+            .unwrap()
+            .with_faults(faults.clone());
+        storage.create_bucket("b").unwrap();
+
+        faults.add(
+            FaultRule::new(Fault::PartialWrite(2))
+                .with_operation("put_object")
+                .with_times(1),
+        );
+        assert!(storage.put_object("b", object("k", b"hello")).is_err());
+        assert!(storage.get_object("b", "k").is_err());
+        storage.put_object("b", object("k", b"hello")).unwrap();
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"hello");
+
+        faults.add(FaultRule::new(Fault::IoError).with_operation("probe"));
+        assert!(storage.probe().is_err());
+        faults.clear();
+        storage.probe().unwrap();
+        assert_eq!(faults.injected(), 2);
+    }
+}
@@ -0,0 +1,100 @@
+// folder.rs
+// Folder placeholders as S3 consoles represent them: a zero-byte object
+// whose key ends with `/` marks a folder, so empty folders survive without
+// any objects below them. Listing a folder groups the keys below it into
+// its direct objects and subfolders.
+
+use serde::Serialize;
+use std::collections::BTreeSet;
+use utoipa::ToSchema;
+
+/// Content type of folder marker objects.
+pub const FOLDER_CONTENT_TYPE: &str = "application/x-directory";
+
+/// The direct contents of a folder.
+#[derive(Debug, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct FolderListing {
+    /// The folder listed, ending with `/`; empty for the bucket's root.
+    pub prefix: String,
+    /// Subfolders, as full keys ending with `/`.
+    pub folders: Vec<String>,
+    /// Objects directly in the folder, as full keys.
+    pub objects: Vec<String>,
+}
+
+/// Returns the key of the marker object of a folder, e.g. `logs/2024/` for
+/// `logs/2024`. Empty paths and empty path segments are refused.
+pub fn marker_key(path: &str) -> Option<String> {
+    let path = path.strip_suffix('/').unwrap_or(path);
+    if path.is_empty() || path.split('/').any(str::is_empty) {
+        return None;
+    }
+    Some(format!("{}/", path))
+}
+
+/// Groups the keys below `prefix` into the folder's direct objects and
+/// subfolders. A key with more path segments below the prefix shows up as
+/// the subfolder it is in, whether or not that subfolder has a marker.
+pub fn list_folder<'a>(keys: impl IntoIterator<Item = &'a str>, prefix: &str) -> FolderListing {
+    let mut folders = BTreeSet::new();
+    let mut objects = BTreeSet::new();
+    for key in keys {
+        let Some(rest) = key.strip_prefix(prefix) else {
+            continue;
+        };
+        match rest.find('/') {
+            // The folder's own marker
+            _ if rest.is_empty() => {}
+            Some(end) => {
+                folders.insert(format!("{}{}", prefix, &rest[..=end]));
+            }
+            None => {
+                objects.insert(key.to_string());
+            }
+        }
+    }
+    FolderListing {
+        prefix: prefix.to_string(),
+        folders: folders.into_iter().collect(),
+        objects: objects.into_iter().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marker_keys() {
+        assert_eq!(marker_key("logs"), Some("logs/".to_string()));
+        assert_eq!(marker_key("logs/2024/"), Some("logs/2024/".to_string()));
+        assert_eq!(marker_key(""), None);
+        assert_eq!(marker_key("/"), None);
+        assert_eq!(marker_key("logs//2024"), None);
+    }
+
+    #[test]
+    fn test_list_folder() {
+        let keys = [
+            "readme.txt",
+            "logs/",
+            "logs/a.log",
+            "logs/2024/",
+            "logs/2024/b.log",
+            "logs/2025/c.log",
+            "images/x.png",
+        ];
+
+        let root = list_folder(keys, "");
+        assert_eq!(root.folders, vec!["images/", "logs/"]);
+        assert_eq!(root.objects, vec!["readme.txt"]);
+
+        let logs = list_folder(keys, "logs/");
+        assert_eq!(logs.folders, vec!["logs/2024/", "logs/2025/"]);
+        assert_eq!(logs.objects, vec!["logs/a.log"]);
+
+        let empty = list_folder(keys, "logs/2024/");
+        assert!(empty.folders.is_empty());
+        assert_eq!(empty.objects, vec!["logs/2024/b.log"]);
+    }
+}
@@ -0,0 +1,185 @@
+// generators.rs
+// Generators of the identifiers the storage hands out: the ETags of object
+// data, the internal IDs naming object files and the IDs of versions, delete
+// markers and resumable uploads. The
+// storage owns one of each; the built-in ETag algorithms are the
+// `HashAlgorithm`s of `[storage] hash_algorithm`, and IDs are random UUIDs.
+// Deployments can plug in other algorithms, and tests `SequentialIds` to get
+// predictable IDs.
+
+use std::fmt;
+
+/// Computes the ETags of object data.
+pub trait ETagGenerator: fmt::Debug + Send + Sync {
+    /// Name of the algorithm, recorded with every object so its ETag can be
+    /// verified after the generator changed.
+    fn algorithm(&self) -> &str;
+
+    fn etag(&self, data: &[u8]) -> String;
+}
+
+/// Generates the IDs of stored objects, object versions and resumable uploads.
+pub trait IdGenerator: fmt::Debug + Send + Sync {
+    /// The internal ID of newly written object data, naming its file.
+    fn object_id(&self) -> String;
+
+    /// The ID of a new object version or delete marker.
+    fn version_id(&self) -> String;
+
+    /// The ID of a new resumable upload.
+    fn upload_id(&self) -> String;
+}
+
+/// Random UUIDs (v4) as IDs.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UuidGenerator;
+
+impl IdGenerator for UuidGenerator {
+    fn object_id(&self) -> String {
+        uuid::Uuid::new_v4().to_string()
+    }
+
+    fn version_id(&self) -> String {
+        uuid::Uuid::new_v4().to_string()
+    }
+
+    fn upload_id(&self) -> String {
+        uuid::Uuid::new_v4().to_string()
+    }
+}
+
+/// Numbered IDs, `o00000001`, `v00000002` and so on, counted across all
+/// kinds, for tests.
+#[cfg(any(test, feature = "testing"))]
+#[allow(dead_code)] // Used by tests, through the library
+#[derive(Debug, Default)]
+pub struct SequentialIds(std::sync::atomic::AtomicU64);
+
+#[cfg(any(test, feature = "testing"))]
+#[allow(dead_code)]
+impl SequentialIds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn next(&self, prefix: char) -> String {
+        let n = self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+        format!("{}{:08}", prefix, n)
+    }
+}
+
+#[cfg(any(test, feature = "testing"))]
+impl IdGenerator for SequentialIds {
+    fn object_id(&self) -> String {
+        self.next('o')
+    }
+
+    fn version_id(&self) -> String {
+        self.next('v')
+    }
+
+    fn upload_id(&self) -> String {
+        self.next('u')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bucket::VersioningStatus;
+    use crate::object::Object;
+    use crate::storage::Storage;
+    use std::sync::Arc;
+
+    /// Length of the data as its ETag.
+    #[derive(Debug)]
+    struct LengthETag;
+
+    impl ETagGenerator for LengthETag {
+        fn algorithm(&self) -> &str {
+            "length"
+        }
+
+        fn etag(&self, data: &[u8]) -> String {
+            data.len().to_string()
+        }
+    }
+
+    fn open(dir: &tempfile::TempDir) -> Storage {
+        let db_path = dir.path().join("s3_storage.db");
+        Storage::open(&db_path.to_string_lossy(), dir.path().join("data")).unwrap()
+    }
+
+    fn object(key: &str, data: &[u8]) -> Object {
+        Object::new(key.to_string(), data.to_vec(), None, None).unwrap()
+    }
+
+    #[test]
+    fn test_sequential_ids() {
+        let ids = SequentialIds::new();
+        assert_eq!(ids.object_id(), "o00000001");
+        assert_eq!(ids.version_id(), "v00000002");
+        assert_eq!(ids.upload_id(), "u00000003");
+        assert_ne!(UuidGenerator.version_id(), UuidGenerator.version_id());
+    }
+
+    #[test]
+    fn test_storage_generators() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = open(&dir);
+        storage.create_bucket("b").unwrap();
+        storage.put_object("b", object("md5", b"hi")).unwrap();
+
+        let mut storage = open(&dir)
+            .with_etag_generator(Arc::new(LengthETag))
+            .with_id_generator(Arc::new(SequentialIds::new()));
+        storage
+            .set_bucket_versioning("b", VersioningStatus::Enabled)
+            .unwrap();
+        storage.put_object("b", object("k", b"hello")).unwrap();
+        let stored = storage.get_object("b", "k").unwrap();
+        assert_eq!(stored.etag.as_deref(), Some("5"));
+        assert_eq!(stored.version_id.as_deref(), Some("v00000002"));
+        assert!(storage.verify_object("b", "k", false).unwrap().valid);
+        storage.put_object("b", object("k", b"world")).unwrap();
+        storage.delete_object("b", "k").unwrap();
+        assert!(storage.fsck(false).unwrap().is_clean());
+
+        // Objects written with another algorithm still verify
+        assert_eq!(storage.get_object("b", "md5").unwrap().data, b"hi");
+        assert!(storage.verify_object("b", "md5", false).unwrap().valid);
+        assert_eq!(storage.count_rehash_pending().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_object_files_named_by_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = open(&dir).with_id_generator(Arc::new(SequentialIds::new()));
+        storage.create_bucket("b").unwrap();
+
+        // Keys that collide or escape as paths are plain metadata
+        for key in ["a", "a/", "a/b", "../escape", "x:y?*"] {
+            storage
+                .put_object("b", object(key, key.as_bytes()))
+                .unwrap();
+        }
+        for key in ["a", "a/", "a/b", "../escape", "x:y?*"] {
+            assert_eq!(storage.get_object("b", key).unwrap().data, key.as_bytes());
+        }
+        let bucket_dir = dir.path().join("data").join("buckets").join("b");
+        assert!(bucket_dir.join("o0").join("o00000001").exists());
+        assert!(
+            !dir.path()
+                .join("data")
+                .join("buckets")
+                .join("escape")
+                .exists()
+        );
+
+        // An overwrite gets a new file and removes the old one
+        storage.put_object("b", object("a", b"new")).unwrap();
+        assert!(!bucket_dir.join("o0").join("o00000001").exists());
+        assert!(bucket_dir.join("o0").join("o00000006").exists());
+        assert!(storage.fsck(false).unwrap().is_clean());
+    }
+}
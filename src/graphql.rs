@@ -0,0 +1,495 @@
+// graphql.rs
+// Read-only queries over the metadata at POST /graphql: buckets, their
+// objects with the objects' tags and versions, and the space they use. There
+// are no mutations; writes keep going through the REST routes. Buckets whose
+// roles don't let the caller read them are left out of every result, the same
+// check enforce_bucket_roles makes for REST requests. Lists are connections
+// paginated with `first`/`after` and `last`/`before`, nested lists included.
+// An object's tags are its user metadata, the key-value pairs this store
+// keeps per object.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_graphql::connection::{Connection, Edge, query};
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Error, ErrorExtensions, InputObject, Object,
+    OutputType, Result, Schema, SimpleObject,
+};
+use tokio::sync::Mutex;
+
+use crate::jwt::{Access, Principal};
+use crate::object::{ObjectInfo, ObjectVersion};
+use crate::roles::BucketRoles;
+use crate::s3_service::{S3Error, S3Service};
+use crate::usage::{BucketUsage, UsageReport};
+
+pub const GRAPHQL_PATH: &str = "/graphql";
+
+/// Items in a page when the query asks for neither `first` nor `last`.
+const DEFAULT_PAGE_SIZE: usize = 100;
+/// The most items a page holds, whatever the query asks for.
+const MAX_PAGE_SIZE: usize = 1000;
+/// How deeply queries may nest fields.
+const MAX_DEPTH: usize = 16;
+
+pub type MetadataSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// Builds the schema the /graphql handler executes queries against. The
+/// principal of each request, if its token named one, is added to the query
+/// by the handler.
+pub fn schema(s3_service: Arc<Mutex<S3Service>>, roles: Arc<BucketRoles>) -> MetadataSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(s3_service)
+        .data(roles)
+        .limit_depth(MAX_DEPTH)
+        .finish()
+}
+
+fn s3_service<'a>(ctx: &Context<'a>) -> &'a Arc<Mutex<S3Service>> {
+    ctx.data_unchecked::<Arc<Mutex<S3Service>>>()
+}
+
+/// Whether the caller may read `bucket` under its roles.
+fn may_read(ctx: &Context<'_>, bucket: &str) -> bool {
+    ctx.data_unchecked::<Arc<BucketRoles>>().allows(
+        bucket,
+        ctx.data_opt::<Principal>(),
+        Access::Read,
+    )
+}
+
+/// Reports an S3 error with its error code in the `code` extension.
+fn error(e: S3Error) -> Error {
+    let code = e.error_code().as_str();
+    Error::new(e.to_string()).extend_with(|_, extensions| extensions.set("code", code))
+}
+
+/// Pages through `items`, using their positions as cursors.
+async fn paginate<T: OutputType>(
+    items: Vec<T>,
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<i32>,
+    last: Option<i32>,
+) -> Result<Connection<usize, T>> {
+    query(
+        after,
+        before,
+        first,
+        last,
+        |after: Option<usize>, before: Option<usize>, first, last| async move {
+            let len = items.len();
+            let mut end = before.unwrap_or(len).min(len);
+            let mut start = after.map_or(0, |after| after + 1).min(end);
+            match (first, last) {
+                (None, None) => end = end.min(start + DEFAULT_PAGE_SIZE),
+                (first, last) => {
+                    if let Some(first) = first {
+                        end = end.min(start + first.min(MAX_PAGE_SIZE));
+                    }
+                    if let Some(last) = last {
+                        start = start.max(end.saturating_sub(last.min(MAX_PAGE_SIZE)));
+                    }
+                }
+            }
+
+            let mut connection = Connection::new(start > 0, end < len);
+            connection.edges.extend(
+                items
+                    .into_iter()
+                    .enumerate()
+                    .skip(start)
+                    .take(end - start)
+                    .map(|(cursor, item)| Edge::new(cursor, item)),
+            );
+            Ok::<_, Error>(connection)
+        },
+    )
+    .await
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// The buckets the caller may read, by name.
+    async fn buckets(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<BucketFilter>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<usize, BucketNode>> {
+        let filter = filter.unwrap_or_default();
+        let names = s3_service(ctx)
+            .lock()
+            .await
+            .list_buckets(filter.namespace.as_deref())
+            .await
+            .map_err(error)?;
+        let buckets = names
+            .into_iter()
+            .filter(|name| filter.prefix.as_deref().is_none_or(|p| name.starts_with(p)))
+            .filter(|name| may_read(ctx, name))
+            .map(|name| BucketNode { name })
+            .collect();
+        paginate(buckets, after, before, first, last).await
+    }
+
+    /// A bucket by name or alias, or null if there is no such bucket.
+    async fn bucket(&self, ctx: &Context<'_>, name: String) -> Result<Option<BucketNode>> {
+        let resolved = s3_service(ctx)
+            .lock()
+            .await
+            .resolve_bucket(&name)
+            .await
+            .map_err(error)?;
+        let Some(name) = resolved else {
+            return Ok(None);
+        };
+        if !may_read(ctx, &name) {
+            return Err(error(S3Error::AccessDenied(format!(
+                "Not allowed to read bucket {}",
+                name
+            ))));
+        }
+        Ok(Some(BucketNode { name }))
+    }
+
+    /// Space used by all the buckets the caller may read.
+    async fn stats(&self, ctx: &Context<'_>) -> Result<Stats> {
+        let report = s3_service(ctx)
+            .lock()
+            .await
+            .get_usage_report()
+            .await
+            .map_err(error)?;
+        let buckets = report
+            .buckets
+            .into_iter()
+            .filter(|usage| may_read(ctx, &usage.bucket))
+            .collect();
+        Ok(UsageReport::new(buckets).total.into())
+    }
+}
+
+/// Space used by a bucket, or by several.
+#[derive(SimpleObject)]
+pub struct Stats {
+    /// Current objects.
+    objects: u64,
+    object_bytes: u64,
+    /// Noncurrent versions and delete markers.
+    versions: u64,
+    version_bytes: u64,
+    /// Resumable uploads in progress, with the bytes received so far.
+    uploads: u64,
+    upload_bytes: u64,
+    total_bytes: u64,
+}
+
+impl From<BucketUsage> for Stats {
+    fn from(usage: BucketUsage) -> Self {
+        Stats {
+            objects: usage.objects,
+            object_bytes: usage.object_bytes,
+            versions: usage.versions,
+            version_bytes: usage.version_bytes,
+            uploads: usage.uploads,
+            upload_bytes: usage.upload_bytes,
+            total_bytes: usage.total_bytes,
+        }
+    }
+}
+
+/// Which buckets the `buckets` field returns.
+#[derive(InputObject, Default)]
+pub struct BucketFilter {
+    namespace: Option<String>,
+    prefix: Option<String>,
+}
+
+/// Which objects a bucket's `objects` field returns. Every condition given
+/// must hold.
+#[derive(InputObject, Default)]
+pub struct ObjectFilter {
+    prefix: Option<String>,
+    content_type: Option<String>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    storage_class: Option<String>,
+    /// Unix time in seconds, inclusive.
+    modified_after: Option<i64>,
+    /// Unix time in seconds, exclusive.
+    modified_before: Option<i64>,
+    tag: Option<TagFilter>,
+}
+
+/// Objects with a tag, with any value unless one is given.
+#[derive(InputObject)]
+pub struct TagFilter {
+    key: String,
+    value: Option<String>,
+}
+
+impl ObjectFilter {
+    fn matches(&self, info: &ObjectInfo, tags: &HashMap<String, String>) -> bool {
+        self.prefix
+            .as_deref()
+            .is_none_or(|p| info.key.starts_with(p))
+            && self
+                .content_type
+                .as_deref()
+                .is_none_or(|t| info.content_type.as_deref() == Some(t))
+            && self.min_size.is_none_or(|min| info.size >= min)
+            && self.max_size.is_none_or(|max| info.size <= max)
+            && self
+                .storage_class
+                .as_deref()
+                .is_none_or(|c| info.storage_class.as_str().eq_ignore_ascii_case(c))
+            && self.modified_after.is_none_or(|t| info.last_modified >= t)
+            && self.modified_before.is_none_or(|t| info.last_modified < t)
+            && self.tag.as_ref().is_none_or(|tag| {
+                tags.get(&tag.key)
+                    .is_some_and(|value| tag.value.as_ref().is_none_or(|v| v == value))
+            })
+    }
+}
+
+/// A bucket the caller may read.
+pub struct BucketNode {
+    name: String,
+}
+
+#[Object(name = "Bucket")]
+impl BucketNode {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// `Enabled` or `Suspended`, or null if versioning was never configured.
+    async fn versioning(&self, ctx: &Context<'_>) -> Result<Option<String>> {
+        let status = s3_service(ctx)
+            .lock()
+            .await
+            .get_bucket_versioning(&self.name)
+            .await
+            .map_err(error)?;
+        Ok(status.map(|status| status.as_str().to_string()))
+    }
+
+    async fn stats(&self, ctx: &Context<'_>) -> Result<Stats> {
+        let usage = s3_service(ctx)
+            .lock()
+            .await
+            .get_bucket_usage(&self.name)
+            .await
+            .map_err(error)?;
+        Ok(usage.into())
+    }
+
+    /// The bucket's current objects, by key.
+    async fn objects(
+        &self,
+        ctx: &Context<'_>,
+        filter: Option<ObjectFilter>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<usize, ObjectNode>> {
+        let filter = filter.unwrap_or_default();
+        // Tags are read for the whole bucket at once, and only when needed
+        let look_ahead = ctx.look_ahead();
+        let wants_tags = filter.tag.is_some()
+            || look_ahead
+                .field("edges")
+                .field("node")
+                .field("tags")
+                .exists()
+            || look_ahead.field("nodes").field("tags").exists();
+
+        let (infos, mut tags) = {
+            let s3_service = s3_service(ctx).lock().await;
+            let infos = s3_service
+                .list_objects_detailed(&self.name)
+                .await
+                .map_err(error)?;
+            let tags = if wants_tags {
+                s3_service
+                    .list_object_metadata(&self.name)
+                    .await
+                    .map_err(error)?
+            } else {
+                HashMap::new()
+            };
+            (infos, tags)
+        };
+
+        let objects = infos
+            .into_iter()
+            .map(|info| ObjectNode {
+                bucket: self.name.clone(),
+                tags: tags.remove(&info.key).unwrap_or_default(),
+                info,
+            })
+            .filter(|object| filter.matches(&object.info, &object.tags))
+            .collect();
+        paginate(objects, after, before, first, last).await
+    }
+
+    /// The bucket's object versions and delete markers, by key and then
+    /// newest first, optionally only those of one key.
+    async fn versions(
+        &self,
+        ctx: &Context<'_>,
+        key: Option<String>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<usize, VersionNode>> {
+        let versions = s3_service(ctx)
+            .lock()
+            .await
+            .list_object_versions(&self.name)
+            .await
+            .map_err(error)?;
+        let versions = versions
+            .into_iter()
+            .filter(|version| key.as_ref().is_none_or(|key| &version.key == key))
+            .map(VersionNode)
+            .collect();
+        paginate(versions, after, before, first, last).await
+    }
+}
+
+/// A current object of a bucket.
+pub struct ObjectNode {
+    bucket: String,
+    info: ObjectInfo,
+    tags: HashMap<String, String>,
+}
+
+#[Object(name = "Object")]
+impl ObjectNode {
+    async fn key(&self) -> &str {
+        &self.info.key
+    }
+
+    async fn size(&self) -> u64 {
+        self.info.size
+    }
+
+    async fn content_type(&self) -> Option<&str> {
+        self.info.content_type.as_deref()
+    }
+
+    async fn etag(&self) -> Option<&str> {
+        self.info.etag.as_deref()
+    }
+
+    /// Unix time in seconds.
+    async fn last_modified(&self) -> i64 {
+        self.info.last_modified
+    }
+
+    async fn version_id(&self) -> Option<&str> {
+        self.info.version_id.as_deref()
+    }
+
+    async fn storage_class(&self) -> &str {
+        self.info.storage_class.as_str()
+    }
+
+    /// Whether the object was stored immutable.
+    async fn immutable(&self) -> bool {
+        self.info.immutable
+    }
+
+    /// The object's user metadata, by key.
+    async fn tags(&self) -> Vec<Tag> {
+        let mut tags: Vec<Tag> = self
+            .tags
+            .iter()
+            .map(|(key, value)| Tag {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .collect();
+        tags.sort_by(|a, b| a.key.cmp(&b.key));
+        tags
+    }
+
+    /// The object's versions and delete markers, newest first.
+    async fn versions(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<usize, VersionNode>> {
+        let versions = s3_service(ctx)
+            .lock()
+            .await
+            .list_object_versions(&self.bucket)
+            .await
+            .map_err(error)?;
+        let versions = versions
+            .into_iter()
+            .filter(|version| version.key == self.info.key)
+            .map(VersionNode)
+            .collect();
+        paginate(versions, after, before, first, last).await
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Tag {
+    key: String,
+    value: String,
+}
+
+/// A version of an object, or a delete marker.
+pub struct VersionNode(ObjectVersion);
+
+#[Object(name = "Version")]
+impl VersionNode {
+    async fn key(&self) -> &str {
+        &self.0.key
+    }
+
+    /// Null for an object written before versioning was enabled.
+    async fn version_id(&self) -> Option<&str> {
+        self.0.version_id.as_deref()
+    }
+
+    async fn size(&self) -> u64 {
+        self.0.size
+    }
+
+    async fn content_type(&self) -> Option<&str> {
+        self.0.content_type.as_deref()
+    }
+
+    async fn etag(&self) -> Option<&str> {
+        self.0.etag.as_deref()
+    }
+
+    /// Unix time in seconds.
+    async fn last_modified(&self) -> i64 {
+        self.0.last_modified
+    }
+
+    async fn is_latest(&self) -> bool {
+        self.0.is_latest
+    }
+
+    async fn is_delete_marker(&self) -> bool {
+        self.0.is_delete_marker
+    }
+}
@@ -0,0 +1,450 @@
+// grpc.rs
+// The bucket and object operations as a gRPC service, for internal
+// RPC-first consumers who don't want HTTP semantics. It shares `S3Service`
+// with the HTTP handlers and runs on its own port (`[grpc]`), in builds
+// with the `grpc` feature. Put streams an object in as chunks, written to a
+// resumable upload as they arrive; Get streams it out the same way, so
+// neither holds a whole object in memory. Errors carry the S3 error code in
+// the `x-error-code` metadata, next to the closest gRPC status code.
+
+// `Status` is the error type tonic's generated traits require
+#![allow(clippy::result_large_err)]
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::{Stream, StreamExt, stream};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpListener;
+use tokio::sync::Mutex;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::metadata::{MetadataMap, MetadataValue};
+use tonic::transport::Server;
+use tonic::{Code, Request, Response, Status, Streaming};
+use tracing::{error, warn};
+
+use crate::error_code::{ERROR_CODE_HEADER, ErrorCode};
+use crate::jwt::{Access, JwtVerifier};
+use crate::object::Object;
+use crate::read_only::ReadOnlyMode;
+use crate::s3_service::{ObjectDownload, S3Error, S3Service};
+
+pub mod proto {
+    tonic::include_proto!("s3");
+}
+
+use proto::object_store_server::{ObjectStore, ObjectStoreServer};
+use proto::{
+    BucketRequest, DeleteObjectRequest, Empty, GetObjectResponse, ListBucketsRequest,
+    ListBucketsResponse, ListObjectsResponse, ObjectInfo, ObjectRequest, PutObjectRequest,
+    get_object_response, put_object_request,
+};
+
+/// Size of the data chunks of a streamed object.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The gRPC service over `S3Service`.
+pub struct GrpcService {
+    s3_service: Arc<Mutex<S3Service>>,
+    jwt: Arc<JwtVerifier>,
+    read_only: Arc<ReadOnlyMode>,
+    /// Largest object accepted, like `[server] max_body_bytes`.
+    max_body: Option<u64>,
+}
+
+impl GrpcService {
+    pub fn new(
+        s3_service: Arc<Mutex<S3Service>>,
+        jwt: Arc<JwtVerifier>,
+        read_only: Arc<ReadOnlyMode>,
+        max_body: Option<u64>,
+    ) -> Self {
+        Self {
+            s3_service,
+            jwt,
+            read_only,
+            max_body,
+        }
+    }
+
+    /// Checks the bearer token in the `authorization` metadata, as the HTTP
+    /// middleware does, and refuses mutations in read-only mode.
+    fn authorize(&self, metadata: &MetadataMap, access: Access) -> Result<(), Status> {
+        if access >= Access::Write && self.read_only.is_enabled() {
+            return Err(Status::unavailable(
+                "The server is in read-only mode; writes are refused.",
+            ));
+        }
+        if !self.jwt.is_enabled() {
+            return Ok(());
+        }
+        let token = metadata
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|auth| auth.strip_prefix("Bearer "));
+        let Some(token) = token else {
+            if self.jwt.is_required() {
+                return Err(Status::unauthenticated("A bearer token is required."));
+            }
+            return Ok(());
+        };
+        let now = self.jwt.now();
+        let principal = self.jwt.verify(token.trim(), now).map_err(|e| {
+            warn!(error = %e, "Refused bearer token");
+            Status::unauthenticated(e.to_string())
+        })?;
+        if !principal.may(access) {
+            return Err(Status::permission_denied(
+                "The token's scopes do not grant this request.",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Streams the data messages of a Put into the upload `id`, which
+    /// stores the object once `size` bytes arrived.
+    async fn stream_upload(
+        &self,
+        bucket: &str,
+        id: &str,
+        size: u64,
+        parts: &mut Streaming<PutObjectRequest>,
+    ) -> Result<ObjectInfo, Status> {
+        let mut offset = 0;
+        if size == 0 {
+            return self
+                .append(bucket, id, 0, &[])
+                .await?
+                .ok_or_else(|| Status::internal("The upload of an empty object did not complete"));
+        }
+        while let Some(message) = parts.message().await? {
+            let Some(put_object_request::Part::Data(data)) = message.part else {
+                return Err(Status::invalid_argument(
+                    "Only the first message of a Put may be a header",
+                ));
+            };
+            if offset + data.len() as u64 > size {
+                return Err(Status::invalid_argument(format!(
+                    "The data exceeds the declared size of {} bytes",
+                    size
+                )));
+            }
+            if let Some(info) = self.append(bucket, id, offset, &data).await? {
+                return Ok(info);
+            }
+            offset += data.len() as u64;
+        }
+        Err(status(S3Error::IncompleteBody(size, offset)))
+    }
+
+    /// Appends a chunk to an upload, returning the object it completed.
+    async fn append(
+        &self,
+        bucket: &str,
+        id: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<Option<ObjectInfo>, Status> {
+        let mut s3 = self.s3_service.lock().await;
+        let (_, info) = s3
+            .append_upload(bucket, id, offset, data)
+            .await
+            .map_err(status)?;
+        Ok(info.map(|info| object_info(&info)))
+    }
+}
+
+#[tonic::async_trait]
+impl ObjectStore for GrpcService {
+    type GetObjectStream = Pin<Box<dyn Stream<Item = Result<GetObjectResponse, Status>> + Send>>;
+
+    async fn create_bucket(
+        &self,
+        request: Request<BucketRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        self.authorize(request.metadata(), Access::Write)?;
+        let request = request.into_inner();
+        let mut s3 = self.s3_service.lock().await;
+        s3.create_bucket(&request.bucket).await.map_err(status)?;
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn delete_bucket(
+        &self,
+        request: Request<BucketRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        self.authorize(request.metadata(), Access::Write)?;
+        let request = request.into_inner();
+        let mut s3 = self.s3_service.lock().await;
+        s3.delete_bucket(&request.bucket).await.map_err(status)?;
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn list_buckets(
+        &self,
+        request: Request<ListBucketsRequest>,
+    ) -> Result<Response<ListBucketsResponse>, Status> {
+        self.authorize(request.metadata(), Access::Read)?;
+        let request = request.into_inner();
+        let namespace = Some(request.namespace.as_str()).filter(|ns| !ns.is_empty());
+        let s3 = self.s3_service.lock().await;
+        let buckets = s3.list_buckets(namespace).await.map_err(status)?;
+        Ok(Response::new(ListBucketsResponse { buckets }))
+    }
+
+    async fn list_objects(
+        &self,
+        request: Request<BucketRequest>,
+    ) -> Result<Response<ListObjectsResponse>, Status> {
+        self.authorize(request.metadata(), Access::Read)?;
+        let request = request.into_inner();
+        let s3 = self.s3_service.lock().await;
+        let objects = s3
+            .list_objects_detailed(&request.bucket)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(ListObjectsResponse {
+            objects: objects.iter().map(object_info).collect(),
+        }))
+    }
+
+    async fn head_object(
+        &self,
+        request: Request<ObjectRequest>,
+    ) -> Result<Response<ObjectInfo>, Status> {
+        self.authorize(request.metadata(), Access::Read)?;
+        let request = request.into_inner();
+        let s3 = self.s3_service.lock().await;
+        let info = s3
+            .head_object(&request.bucket, &request.key)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(object_info(&info)))
+    }
+
+    async fn delete_object(
+        &self,
+        request: Request<DeleteObjectRequest>,
+    ) -> Result<Response<Empty>, Status> {
+        self.authorize(request.metadata(), Access::Write)?;
+        let request = request.into_inner();
+        let if_match = Some(request.if_match.as_str()).filter(|etag| !etag.is_empty());
+        let mut s3 = self.s3_service.lock().await;
+        s3.delete_object(&request.bucket, &request.key, if_match)
+            .await
+            .map_err(status)?;
+        Ok(Response::new(Empty {}))
+    }
+
+    async fn put_object(
+        &self,
+        request: Request<Streaming<PutObjectRequest>>,
+    ) -> Result<Response<ObjectInfo>, Status> {
+        self.authorize(request.metadata(), Access::Write)?;
+        let mut parts = request.into_inner();
+        let header = match parts.message().await? {
+            Some(PutObjectRequest {
+                part: Some(put_object_request::Part::Header(header)),
+            }) => header,
+            _ => {
+                return Err(Status::invalid_argument(
+                    "The first message of a Put must be its header",
+                ));
+            }
+        };
+        if let Some(limit) = self.max_body.filter(|limit| header.size > *limit) {
+            return Err(status(S3Error::EntityTooLarge(limit)));
+        }
+
+        let content_type = Some(header.content_type).filter(|ct| !ct.is_empty());
+        let upload = self
+            .s3_service
+            .lock()
+            .await
+            .create_upload(
+                &header.bucket,
+                &header.key,
+                header.size,
+                content_type,
+                header.metadata.into_iter().collect::<HashMap<_, _>>(),
+            )
+            .await
+            .map_err(status)?;
+        let result = self
+            .stream_upload(&upload.bucket, &upload.id, header.size, &mut parts)
+            .await;
+        if result.is_err() {
+            // The staged data of a failed Put is of no use to anyone
+            let mut s3 = self.s3_service.lock().await;
+            if let Err(e) = s3.delete_upload(&upload.bucket, &upload.id).await
+                && !matches!(e, S3Error::UploadNotFound(_))
+            {
+                warn!(error = %e, upload = %upload.id, "Failed to remove the upload of a failed Put");
+            }
+        }
+        result.map(Response::new)
+    }
+
+    async fn get_object(
+        &self,
+        request: Request<ObjectRequest>,
+    ) -> Result<Response<Self::GetObjectStream>, Status> {
+        self.authorize(request.metadata(), Access::Read)?;
+        let request = request.into_inner();
+        let download = self
+            .s3_service
+            .lock()
+            .await
+            .download_object(&request.bucket, &request.key)
+            .await
+            .map_err(status)?;
+        let stream: Self::GetObjectStream = match download {
+            ObjectDownload::Buffered(object) => {
+                let info = message(get_object_response::Part::Info(buffered_info(&object)));
+                let chunks = object
+                    .data
+                    .chunks(CHUNK_SIZE)
+                    .map(|chunk| message(get_object_response::Part::Data(chunk.to_vec())))
+                    .collect::<Vec<_>>();
+                Box::pin(stream::iter(std::iter::once(info).chain(chunks)))
+            }
+            ObjectDownload::Streamed(reader) => {
+                let info = message(get_object_response::Part::Info(object_info(&reader.info)));
+                let object = format!("{}/{}", request.bucket, reader.info.key);
+                let file = tokio::fs::File::from_std(reader.file);
+                let state = (file, reader.check, reader.info.size, object);
+                let chunks = stream::unfold(Some(state), |state| async move {
+                    let (mut file, mut check, remaining, object) = state?;
+                    if remaining == 0 {
+                        return None;
+                    }
+                    let mut chunk = vec![0; remaining.min(CHUNK_SIZE as u64) as usize];
+                    let read = match file.read(&mut chunk).await {
+                        Ok(0) => Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof)),
+                        read => read,
+                    };
+                    let read = match read {
+                        Ok(read) => read,
+                        Err(e) => {
+                            error!(error = %e, object = %object, "Failed to read object while sending it");
+                            return Some((Err(Status::internal(e.to_string())), None));
+                        }
+                    };
+                    chunk.truncate(read);
+                    if let Some(check) = &mut check {
+                        check.update(&chunk);
+                    }
+                    let remaining = remaining - read as u64;
+                    if remaining == 0
+                        && let Some(check) = check.take()
+                        && !check.matches()
+                    {
+                        error!(object = %object, "ETag mismatch while sending object - possible data corruption");
+                        return Some((
+                            Err(Status::data_loss(
+                                "ETag mismatch - possible data corruption",
+                            )),
+                            None,
+                        ));
+                    }
+                    let state = (file, check, remaining, object);
+                    Some((message(get_object_response::Part::Data(chunk)), Some(state)))
+                });
+                Box::pin(stream::once(async { info }).chain(chunks))
+            }
+        };
+        Ok(Response::new(stream))
+    }
+}
+
+/// Serves the gRPC service on `listener` until `shutdown` completes.
+///
+/// # Arguments
+///
+/// * `service` - The service to serve.
+/// * `listener` - The bound listener to accept connections on.
+/// * `shutdown` - Completes when the server should stop.
+///
+/// # Returns
+///
+/// * `Result<(), tonic::transport::Error>` - An error if the server failed.
+pub async fn serve(
+    service: GrpcService,
+    listener: TcpListener,
+    shutdown: impl Future<Output = ()>,
+) -> Result<(), tonic::transport::Error> {
+    Server::builder()
+        .add_service(ObjectStoreServer::new(service))
+        .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown)
+        .await
+}
+
+/// The gRPC status of an error: the closest status code, with the S3 error
+/// code in the metadata.
+fn status(e: S3Error) -> Status {
+    let error_code = e.error_code();
+    let code = match error_code {
+        ErrorCode::NoSuchBucket
+        | ErrorCode::NoSuchKey
+        | ErrorCode::NoSuchAlias
+        | ErrorCode::NoSuchUpload
+        | ErrorCode::NoSuchCopyJob
+        | ErrorCode::NoSuchShare => Code::NotFound,
+        ErrorCode::BucketAlreadyOwnedByYou => Code::AlreadyExists,
+        ErrorCode::InvalidRequest
+        | ErrorCode::InvalidRange
+        | ErrorCode::IncompleteBody
+        | ErrorCode::MetadataTooLarge => Code::InvalidArgument,
+        ErrorCode::AccessDenied => Code::PermissionDenied,
+        ErrorCode::ObjectLocked
+        | ErrorCode::ObjectImmutable
+        | ErrorCode::WriteOnceConflict
+        | ErrorCode::UploadConflict
+        | ErrorCode::PreconditionFailed
+        | ErrorCode::ShareExpired => Code::FailedPrecondition,
+        ErrorCode::OperationAborted => Code::Aborted,
+        ErrorCode::EntityTooLarge | ErrorCode::SlowDown | ErrorCode::InsufficientStorage => {
+            Code::ResourceExhausted
+        }
+        ErrorCode::RequestTimeout => Code::DeadlineExceeded,
+        ErrorCode::ServiceUnavailable => Code::Unavailable,
+        ErrorCode::InternalError => Code::Internal,
+    };
+    let mut status = Status::new(code, e.to_string());
+    status.metadata_mut().insert(
+        ERROR_CODE_HEADER,
+        MetadataValue::from_static(error_code.as_str()),
+    );
+    status
+}
+
+fn object_info(info: &crate::object::ObjectInfo) -> ObjectInfo {
+    ObjectInfo {
+        key: info.key.clone(),
+        size: info.size,
+        content_type: info.content_type.clone().unwrap_or_default(),
+        etag: info.etag.clone().unwrap_or_default(),
+        last_modified: info.last_modified,
+        version_id: info.version_id.clone().unwrap_or_default(),
+        immutable: info.immutable,
+    }
+}
+
+/// A message of a Get's stream.
+fn message(part: get_object_response::Part) -> Result<GetObjectResponse, Status> {
+    Ok(GetObjectResponse { part: Some(part) })
+}
+
+/// The metadata of an object read whole, e.g. from the cache.
+fn buffered_info(object: &Object) -> ObjectInfo {
+    ObjectInfo {
+        key: object.key.clone(),
+        size: object.data.len() as u64,
+        content_type: object.content_type.clone().unwrap_or_default(),
+        etag: object.etag.clone().unwrap_or_default(),
+        last_modified: object.last_modified,
+        version_id: object.version_id.clone().unwrap_or_default(),
+        immutable: object.immutable,
+    }
+}
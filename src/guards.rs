@@ -0,0 +1,16 @@
+// guards.rs
+// Route guards for S3-style sub-resources selected by a query parameter,
+// e.g. `GET /buckets/{bucket}?versioning`.
+
+use actix_web::guard::{self, Guard};
+
+/// Matches requests whose query string contains `name`, with or without a value.
+pub fn query_param(name: &'static str) -> impl Guard {
+    guard::fn_guard(move |ctx| {
+        ctx.head().uri.query().is_some_and(|query| {
+            query
+                .split('&')
+                .any(|pair| pair.split('=').next() == Some(name))
+        })
+    })
+}
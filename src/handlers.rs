@@ -1,20 +1,246 @@
+use actix_multipart::Multipart;
+use actix_web::HttpMessage;
 use actix_web::HttpRequest;
 use actix_web::HttpResponse;
-use actix_web::http::header::CONTENT_TYPE;
+use actix_web::body::SizedStream;
+use actix_web::error::PayloadError;
+use actix_web::http::StatusCode;
+use actix_web::http::header::HttpDate;
+use actix_web::http::header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, LAST_MODIFIED, LOCATION};
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
 use actix_web::web;
 use actix_web::web::Bytes;
+use futures::stream::{self, Empty};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use tokio::io::AsyncReadExt;
 use tokio::sync::Mutex;
-use tracing::{error, info};
+use tracing::{Span, error, info, warn};
 
 use crate::S3Error;
 use crate::S3Service;
+use crate::access::AccessTracker;
+use crate::aws_chunked::{self, ChunkDecoder, ChunkError, ChunkVerifier, SignatureError};
+use crate::backup::DbBackup;
+use crate::bandwidth::Bandwidth;
+use crate::bucket_logging::VerboseBuckets;
+use crate::cache::{CachePin, ObjectCache};
+use crate::config::{Credentials, ServerConfig};
+use crate::copy::{CopyError, CopyJobs};
+use crate::folder::marker_key;
+use crate::graphql::MetadataSchema;
+use crate::jwt::{Access, Principal};
+use crate::log_control::LogControl;
+use crate::maintenance::MaintenanceMode;
+use crate::memory::{MemoryBudget, Reservation};
+use crate::metadata;
+use crate::metrics::Metrics;
+use crate::namespace::Namespace;
+use crate::negotiation::Negotiate;
 use crate::object::Object;
+use crate::openapi::{
+    AliasPath, BucketPath, CopyJobPath, FolderPath, ObjectPath, SharePath, ShareTokenPath,
+    UploadPath,
+};
+use crate::post_policy;
+use crate::range::ContentRange;
+use crate::read_only::ReadOnlyMode;
+use crate::rehash::RehashJob;
+use crate::reload::ConfigReloader;
+use crate::roles::BucketRoles;
+use crate::s3_service::{ObjectDownload, storage_error};
+use crate::storage::ObjectReader;
 use crate::structs::{
-    BucketCreatedResponse, BucketDeletedResponse, ListResponse, ObjectCreatedResponse,
-    ObjectDeletedResponse, ObjectListResponse,
+    AccessReportQuery, BackupQuery, BucketAccessReportResponse, BucketAclConfiguration,
+    BucketAclResponse, BucketAliasesResponse, BucketContentTypeConfiguration,
+    BucketContentTypeResponse, BucketCreatedResponse, BucketDeletedResponse,
+    BucketLoggingConfiguration, BucketLoggingResponse, BucketMetadataConfiguration,
+    BucketMetadataResponse, BucketMetricsResponse, BucketReplicationResponse,
+    BucketRestoreResponse, BucketRolesConfiguration, BucketRolesResponse, BucketSharesResponse,
+    BucketUploadsResponse, BucketVersioningResponse, BucketWormResponse, CacheWarmRequest,
+    ComposeConfiguration, CopyQuery, FolderListResponse, LegalHoldConfiguration,
+    LifecycleConfiguration, ListDetail, ListObjectsQuery, ListResponse, LogLevel,
+    MaintenanceStatus, ObjectChecksumEntry, ObjectChecksumListResponse, ObjectComposedResponse,
+    ObjectCreatedResponse, ObjectDeletedResponse, ObjectDetailListResponse,
+    ObjectLegalHoldResponse, ObjectListResponse, ObjectVerificationResponse, PrefixDeletedResponse,
+    PrefixQuery, ReadOnlyStatus, ReplicationConfiguration, RestoreQuery, ShareConfiguration,
+    ShareCreatedResponse, StatsQuery, UploadPartsResponse, VerifyQuery, VersioningConfiguration,
+    WormConfiguration,
+};
+use crate::timeout;
+use crate::tus::{
+    OFFSET_CONTENT_TYPE, TUS_EXTENSION_HEADER, TUS_EXTENSIONS, TUS_RESUMABLE_HEADER, TUS_VERSION,
+    TUS_VERSION_HEADER, UPLOAD_LENGTH_HEADER, UPLOAD_METADATA_HEADER, UPLOAD_OFFSET_HEADER,
+    parse_metadata,
 };
+use crate::upload_progress::UploadProgress;
+use crate::upload_slots::{UploadSlot, UploadSlots};
+use crate::usage::NamedUsage;
+
+const VERSION_ID_HEADER: &str = "x-amz-version-id";
+const REPLICATION_STATUS_HEADER: &str = "x-amz-replication-status";
+const STORAGE_CLASS_HEADER: &str = "x-amz-storage-class";
+const TOTAL_COUNT_HEADER: &str = "x-total-count";
+const BUCKET_OBJECT_COUNT_HEADER: &str = "x-bucket-object-count";
+const BUCKET_BYTES_USED_HEADER: &str = "x-bucket-bytes-used";
+const IMMUTABLE_HEADER: &str = "x-immutable";
+const DEFAULT_ACCESS_REPORT_LIMIT: usize = 100;
+/// Largest accepted non-file field of a POST upload form.
+const MAX_FORM_FIELD_SIZE: usize = 20 * 1024;
+/// Size of the chunks response bodies are paced in.
+const DOWNLOAD_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Returns the body length declared in the Content-Length header, if any.
+fn declared_length(req: &HttpRequest) -> Option<u64> {
+    req.headers()
+        .get(actix_web::http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+}
+
+/// The largest object body the server accepts, if limited.
+fn body_limit(req: &HttpRequest) -> Option<u64> {
+    req.app_data::<web::Data<ServerConfig>>()
+        .and_then(|config| config.max_body())
+}
+
+/// Whether the `x-immutable` header asks for the object to be stored as
+/// immutable; it must be `true` or `false` if present.
+fn immutable_flag(req: &HttpRequest) -> Result<bool, S3Error> {
+    let Some(value) = req.headers().get(IMMUTABLE_HEADER) else {
+        return Ok(false);
+    };
+    match value.to_str().map(str::trim) {
+        Ok(v) if v.eq_ignore_ascii_case("true") => Ok(true),
+        Ok(v) if v.eq_ignore_ascii_case("false") => Ok(false),
+        _ => Err(S3Error::InvalidRequest(format!(
+            "The {} header must be 'true' or 'false'",
+            IMMUTABLE_HEADER
+        ))),
+    }
+}
+
+/// Reads an object body chunk by chunk, so uploads stay within the
+/// bandwidth limits and the memory reservation. A body that ends before or
+/// runs past its declared Content-Length fails with `IncompleteBody`, so a
+/// truncated upload is never stored as a valid object. Bodies in the
+/// aws-chunked framing of AWS SDKs are decoded to their payload, verifying
+/// the signature of every chunk in the signed modes.
+async fn read_body(
+    req: &HttpRequest,
+    payload: &mut web::Payload,
+    bandwidth: &Bandwidth,
+    reservation: &mut Reservation,
+) -> Result<Vec<u8>, S3Error> {
+    let declared = declared_length(req);
+    let mut decoder = aws_chunked::is_aws_chunked(req.headers()).then(ChunkDecoder::new);
+    let denied = |e: SignatureError| S3Error::AccessDenied(e.to_string());
+    let mut verifier = match aws_chunked::has_signed_chunks(req.headers()) {
+        true => {
+            let no_credentials = Arc::new(Credentials::default());
+            let credentials = req
+                .app_data::<web::Data<Arc<Credentials>>>()
+                .map_or(&no_credentials, |data| data.get_ref());
+            let verifier = ChunkVerifier::from_request(
+                req.method().as_str(),
+                req.uri().path(),
+                req.query_string(),
+                req.headers(),
+                credentials,
+            );
+            Some(verifier.map_err(denied)?)
+        }
+        false => None,
+    };
+    let malformed =
+        |e: ChunkError| S3Error::InvalidRequest(format!("Malformed aws-chunked body: {}", e));
+    let pacer = bandwidth.upload_pacer();
+    let idle = timeout::body_idle_limit(req);
+    let limit = body_limit(req);
+    let mut received = 0;
+    let mut body = Vec::new();
+    while let Some(chunk) = timeout::next_within(payload, idle).await? {
+        let chunk = chunk.map_err(|e| match e {
+            PayloadError::Incomplete(_) => {
+                S3Error::IncompleteBody(declared.unwrap_or_default(), received)
+            }
+            e => S3Error::InvalidRequest(format!("Failed to read request body: {}", e)),
+        })?;
+        pacer.pace(chunk.len() as u64).await;
+        received += chunk.len() as u64;
+        // Bodies without a declared length are only caught here
+        if let Some(limit) = limit
+            && received > limit
+        {
+            return Err(S3Error::EntityTooLarge(limit));
+        }
+        match &mut decoder {
+            Some(decoder) => {
+                for decoded in decoder.feed(&chunk).map_err(malformed)? {
+                    // A tampered chunk is refused before the rest is read
+                    if let Some(verifier) = &mut verifier {
+                        verifier.verify(&decoded).map_err(denied)?;
+                    }
+                    body.extend_from_slice(&decoded.data);
+                }
+            }
+            None => body.extend_from_slice(&chunk),
+        }
+        reservation.grow_to(body.len() as u64);
+    }
+    if let Some(declared) = declared
+        && declared != received
+    {
+        return Err(S3Error::IncompleteBody(declared, received));
+    }
+    if let Some(decoder) = decoder {
+        decoder.finish().map_err(malformed)?;
+        if let Some(declared) = aws_chunked::decoded_length(req.headers())
+            && declared != body.len() as u64
+        {
+            return Err(S3Error::IncompleteBody(declared, body.len() as u64));
+        }
+    }
+    Ok(body)
+}
+
+/// Admits a request body into the memory budget using its declared length,
+/// failing with EntityTooLarge above the body limit and with SlowDown when
+/// the server is short of memory.
+fn admit_body(req: &HttpRequest, memory: &Arc<MemoryBudget>) -> Result<Reservation, S3Error> {
+    let declared = declared_length(req).unwrap_or(0);
+    if let Some(limit) = body_limit(req)
+        && declared > limit
+    {
+        return Err(S3Error::EntityTooLarge(limit));
+    }
+    memory.admit(declared).ok_or_else(|| {
+        warn!(
+            declared,
+            used = memory.used(),
+            "Upload refused by memory budget"
+        );
+        S3Error::SlowDown("Please reduce your request rate.".to_string())
+    })
+}
+
+/// Takes an upload slot for the request's body, failing with SlowDown when
+/// too many uploads are being read already.
+async fn admit_upload(req: &HttpRequest) -> Result<Option<UploadSlot>, S3Error> {
+    let Some(slots) = req.app_data::<web::Data<Arc<UploadSlots>>>() else {
+        return Ok(None);
+    };
+    match slots.acquire().await {
+        Some(slot) => Ok(Some(slot)),
+        None => {
+            warn!("Upload refused, no upload slot free");
+            Err(S3Error::SlowDown(
+                "Please reduce your request rate.".to_string(),
+            ))
+        }
+    }
+}
 
 // --- Bucket handlers ---
 
@@ -25,22 +251,34 @@ use crate::structs::{
 ///
 /// * `s3_service` - A reference to the S3Service instance.
 /// * `path` - The path to the bucket to create.
+/// * `namespace` - The namespace the bucket belongs to.
 ///
 /// # Returns
 ///
 /// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 201, description = "The bucket was created.", body = BucketCreatedResponse),
+    )
+)]
 pub async fn create_bucket_handler(
     s3_service: web::Data<Arc<Mutex<S3Service>>>,
     // storage: web::Data<Arc<Mutex<Storage>>>, // REMOVE THIS ARGUMENT - S3Service now manages Storage
     path: web::Path<String>,
+    namespace: Namespace,
 ) -> Result<HttpResponse, S3Error> {
     let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
     let mut s3 = s3_service.lock().await;
     // Call create_bucket without the storage argument
-    match s3.create_bucket(&bucket_name).await {
+    match s3.create_bucket(&bucket).await {
         Ok(_) => {
             info!("Bucket '{}' created.", bucket_name);
-            Ok(HttpResponse::Created().json(BucketCreatedResponse {
+            Ok(HttpResponse::Created().negotiated(BucketCreatedResponse {
                 name: bucket_name,
                 message: "Bucket created successfully".to_string(),
             }))
@@ -59,20 +297,38 @@ pub async fn create_bucket_handler(
 ///
 /// * `s3_service` - A reference to the S3Service instance.
 /// * `path` - The path to the bucket to delete.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `verbose_buckets` - The buckets logged in detail.
+/// * `bucket_roles` - The roles and grants of the buckets that have any.
 ///
 /// # Returns
 ///
 /// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    delete,
+    path = "/buckets/{bucket_name}",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 204, description = "The bucket was deleted."),
+    )
+)]
 pub async fn delete_bucket_handler(
     s3_service: web::Data<Arc<Mutex<S3Service>>>,
     path: web::Path<String>,
+    namespace: Namespace,
+    verbose_buckets: web::Data<Arc<VerboseBuckets>>,
+    bucket_roles: web::Data<Arc<BucketRoles>>,
 ) -> Result<HttpResponse, S3Error> {
     let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
     let mut s3 = s3_service.lock().await;
-    match s3.delete_bucket(&bucket_name).await {
+    match s3.delete_bucket(&bucket).await {
         Ok(_) => {
+            verbose_buckets.set(&bucket, false);
+            bucket_roles.remove(&bucket);
             info!("Bucket '{}' deleted.", bucket_name);
-            Ok(HttpResponse::NoContent().json(BucketDeletedResponse {
+            Ok(HttpResponse::NoContent().negotiated(BucketDeletedResponse {
                 message: "Bucket deleted successfully".to_string(),
                 bucket: bucket_name,
             }))
@@ -84,246 +340,3899 @@ pub async fn delete_bucket_handler(
     }
 }
 
-/// Handles GET /buckets
-/// Lists all existing buckets.
+/// Handles GET /buckets/{bucket_name}/aliases
+/// Lists the alternate names of a bucket.
 ///
 /// # Arguments
 ///
 /// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
 ///
 /// # Returns
 ///
 /// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
-pub async fn list_buckets_handler(
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}/aliases",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The aliases of the bucket.", body = BucketAliasesResponse),
+    )
+)]
+pub async fn list_bucket_aliases_handler(
     s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
 ) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
     let result = {
         let s3 = s3_service.lock().await;
-        s3.list_buckets().await
+        s3.list_bucket_aliases(&bucket).await
     };
     match result {
-        Ok(buckets) => Ok(HttpResponse::Ok().json(ListResponse { items: buckets })),
-        Err(e) => Err(e),
+        Ok(aliases) => Ok(HttpResponse::Ok().negotiated(BucketAliasesResponse {
+            bucket: bucket_name,
+            aliases,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to list bucket aliases");
+            Err(e)
+        }
     }
 }
 
-// --- Object handlers ---
+/// Handles PUT /buckets/{bucket_name}/aliases/{alias}
+/// Registers an alternate name that object requests can address the bucket by.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket and the alias.
+/// * `namespace` - The namespace the bucket and the alias belong to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}/aliases/{alias}",
+    tag = "buckets",
+    params(AliasPath),
+    responses(
+        (status = 201, description = "The alias was registered.", body = BucketAliasesResponse),
+    )
+)]
+pub async fn put_bucket_alias_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, alias_name) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let alias = namespace.bucket(&alias_name)?;
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        match s3.create_bucket_alias(&bucket, &alias).await {
+            Ok(_) => s3.list_bucket_aliases(&bucket).await,
+            Err(e) => Err(e),
+        }
+    };
+    match result {
+        Ok(aliases) => {
+            info!("Alias '{}' added to bucket '{}'.", alias_name, bucket_name);
+            Ok(HttpResponse::Created().negotiated(BucketAliasesResponse {
+                bucket: bucket_name,
+                aliases,
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to create bucket alias");
+            Err(e)
+        }
+    }
+}
 
-/// Handles GET /buckets/{bucket_name}/objects/{object_key}
-/// Retrieves an object from a bucket.
+/// Handles DELETE /buckets/{bucket_name}/aliases/{alias}
+/// Removes an alternate name of a bucket. The bucket itself is untouched.
 ///
 /// # Arguments
 ///
 /// * `s3_service` - A reference to the S3Service instance.
-/// * `path` - The path to the object to retrieve.
+/// * `path` - The path to the bucket and the alias.
+/// * `namespace` - The namespace the bucket and the alias belong to.
 ///
 /// # Returns
 ///
 /// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
-#[tracing::instrument(
-    name = "Get object",
-    skip(s3_service),
-    fields(
-        bucket = %path.0,
-        object_key = %path.1
+#[utoipa::path(
+    delete,
+    path = "/buckets/{bucket_name}/aliases/{alias}",
+    tag = "buckets",
+    params(AliasPath),
+    responses(
+        (status = 204, description = "The alias was removed."),
     )
 )]
-pub async fn get_object_handler(
+pub async fn delete_bucket_alias_handler(
     s3_service: web::Data<Arc<Mutex<S3Service>>>,
     path: web::Path<(String, String)>,
+    namespace: Namespace,
 ) -> Result<HttpResponse, S3Error> {
-    let (bucket_name, object_key) = path.into_inner();
+    let (bucket_name, alias_name) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let alias = namespace.bucket(&alias_name)?;
     let result = {
-        let s3 = s3_service.lock().await;
-        s3.get_object(&bucket_name, &object_key).await
+        let mut s3 = s3_service.lock().await;
+        s3.delete_bucket_alias(&bucket, &alias).await
     };
     match result {
-        Ok(object) => {
+        Ok(_) => {
             info!(
-                "Object '{}' retrieved from bucket '{}'.",
-                object_key, bucket_name
+                "Alias '{}' removed from bucket '{}'.",
+                alias_name, bucket_name
             );
-            let mut response = HttpResponse::Ok();
-            if let Some(content_type) = &object.content_type {
-                response.insert_header((CONTENT_TYPE, content_type.as_str()));
-            }
-            Ok(response.body(object.data))
+            Ok(HttpResponse::NoContent().finish())
         }
         Err(e) => {
-            error!(error = %e, "Failed to retrieve object");
+            error!(error = %e, "Failed to delete bucket alias");
             Err(e)
         }
     }
 }
 
-/// Handles PUT /buckets/{bucket_name}/objects/{object_key}
-/// Puts an object into a bucket. The object data is taken from the request body.
+/// Handles GET /buckets/{bucket_name}?versioning
+/// Reports the versioning status of a bucket.
 ///
 /// # Arguments
 ///
-/// * `req` - The HTTP request.
 /// * `s3_service` - A reference to the S3Service instance.
-/// * `path` - The path to the object to put.
-/// * `body` - The body of the request.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
 ///
 /// # Returns
 ///
 /// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
-#[tracing::instrument(
-    name = "Put object",
-    skip(s3_service, body, req),
-    fields(
-        bucket = %path.0,
-        object_key = %path.1,
-        object_size = body.len()
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}?versioning",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The versioning configuration of the bucket.", body = BucketVersioningResponse),
     )
 )]
-pub async fn put_object_handler(
-    req: HttpRequest,
+pub async fn get_bucket_versioning_handler(
     s3_service: web::Data<Arc<Mutex<S3Service>>>,
-    path: web::Path<(String, String)>,
-    body: Bytes, // Raw bytes from the request body
+    path: web::Path<String>,
+    namespace: Namespace,
 ) -> Result<HttpResponse, S3Error> {
-    let content_type = req
-        .headers()
-        .get(CONTENT_TYPE)
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.to_string());
-
-    let user_metadata = req
-        .headers()
-        .iter()
-        .filter(|(k, _)| k.as_str().starts_with("x-user-meta-"))
-        .filter_map(|(k, v)| {
-            v.to_str().ok().map(|val_str| {
-                (
-                    k.as_str()
-                        .strip_prefix("x-user-meta-")
-                        .unwrap_or(k.as_str())
-                        .to_string(),
-                    val_str.to_string(),
-                )
-            })
-        })
-        .collect::<HashMap<_, _>>();
-
-    let (bucket_name, object_key) = path.into_inner();
-
-    // Create the Object before acquiring the lock
-    let object = Object::new(
-        object_key.clone(),
-        body.to_vec(),
-        content_type,
-        Some(user_metadata),
-    )?;
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_bucket_versioning(&bucket).await
+    };
+    match result {
+        Ok(status) => Ok(HttpResponse::Ok().negotiated(BucketVersioningResponse {
+            bucket: bucket_name,
+            status,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to get bucket versioning");
+            Err(e)
+        }
+    }
+}
 
-    // Acquire the lock, call put_object, and release the lock immediately
+/// Handles PUT /buckets/{bucket_name}?versioning
+/// Enables or suspends versioning on a bucket.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `body` - The requested versioning configuration.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}?versioning",
+    tag = "buckets",
+    params(BucketPath),
+    request_body = VersioningConfiguration,
+    responses(
+        (status = 200, description = "The versioning configuration was applied.", body = BucketVersioningResponse),
+    )
+)]
+pub async fn put_bucket_versioning_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+    body: web::Json<VersioningConfiguration>,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let status = body.into_inner().status;
     let result = {
         let mut s3 = s3_service.lock().await;
-        s3.put_object(&bucket_name, object).await
+        s3.put_bucket_versioning(&bucket, status).await
     };
-
     match result {
-        Ok(returned_object) => {
+        Ok(_) => {
             info!(
-                "Object '{}' put into bucket '{}'.",
-                returned_object.key, bucket_name
+                "Versioning for bucket '{}' set to {}.",
+                bucket_name,
+                status.as_str()
             );
-            Ok(HttpResponse::Created().json(ObjectCreatedResponse {
-                name: returned_object.key.clone(),
+            Ok(HttpResponse::Ok().negotiated(BucketVersioningResponse {
                 bucket: bucket_name,
-                metadata: &returned_object,
-                message: "Object created successfully".to_string(),
+                status: Some(status),
             }))
         }
         Err(e) => {
-            error!(error = %e, "Failed to store object");
+            error!(error = %e, "Failed to set bucket versioning");
             Err(e)
         }
     }
 }
 
-/// Handles DELETE /buckets/{bucket_name}/objects/{object_key}
-/// Deletes an object from a bucket.
+/// Handles GET /buckets/{bucket_name}?worm
+/// Reports whether a bucket is write-once.
 ///
 /// # Arguments
 ///
 /// * `s3_service` - A reference to the S3Service instance.
-/// * `path` - The path to the object to delete.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
 ///
 /// # Returns
 ///
 /// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
-#[tracing::instrument(
-    name = "Delete object",
-    skip(s3_service),
-    fields(
-        bucket = %path.0,
-        object_key = %path.1
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}?worm",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The worm configuration of the bucket.", body = BucketWormResponse),
     )
 )]
-pub async fn delete_object_handler(
+pub async fn get_bucket_worm_handler(
     s3_service: web::Data<Arc<Mutex<S3Service>>>,
-    path: web::Path<(String, String)>,
+    path: web::Path<String>,
+    namespace: Namespace,
 ) -> Result<HttpResponse, S3Error> {
-    let (bucket_name, object_key) = path.into_inner();
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_bucket_worm(&bucket).await
+    };
+    match result {
+        Ok(worm) => Ok(HttpResponse::Ok().negotiated(BucketWormResponse {
+            bucket: bucket_name,
+            worm,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to get bucket write-once mode");
+            Err(e)
+        }
+    }
+}
 
+/// Handles PUT /buckets/{bucket_name}?worm
+/// Makes a bucket write-once. The mode cannot be turned off again.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `body` - The requested mode.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}?worm",
+    tag = "buckets",
+    params(BucketPath),
+    request_body = WormConfiguration,
+    responses(
+        (status = 200, description = "The worm configuration was applied.", body = BucketWormResponse),
+    )
+)]
+pub async fn put_bucket_worm_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+    body: web::Json<WormConfiguration>,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let worm = body.into_inner().worm;
     let result = {
         let mut s3 = s3_service.lock().await;
-        s3.delete_object(&bucket_name, &object_key).await
+        s3.put_bucket_worm(&bucket, worm).await
     };
-
     match result {
         Ok(_) => {
             info!(
-                "Object '{}' deleted from bucket '{}'.",
-                object_key, bucket_name
+                "Write-once mode for bucket '{}' set to {}.",
+                bucket_name, worm
             );
-            Ok(HttpResponse::NoContent().json(ObjectDeletedResponse {
-                name: object_key,
+            Ok(HttpResponse::Ok().negotiated(BucketWormResponse {
                 bucket: bucket_name,
-                message: "Object deleted successfully".to_string(),
+                worm,
             }))
         }
         Err(e) => {
-            error!(error = %e, "Failed to delete object");
+            error!(error = %e, "Failed to set bucket write-once mode");
             Err(e)
         }
     }
 }
 
-/// Handles GET /buckets/{bucket_name}/objects
-/// Lists all objects in a specific bucket.
+/// Handles GET /buckets/{bucket_name}?logging
+/// Reports whether the operations on a bucket are logged in detail.
 ///
 /// # Arguments
 ///
 /// * `s3_service` - A reference to the S3Service instance.
-/// * `path` - The path to the bucket to list objects from.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
 ///
 /// # Returns
 ///
 /// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
-pub async fn list_objects_handler(
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}?logging",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The logging configuration of the bucket.", body = BucketLoggingResponse),
+    )
+)]
+pub async fn get_bucket_logging_handler(
     s3_service: web::Data<Arc<Mutex<S3Service>>>,
     path: web::Path<String>,
+    namespace: Namespace,
 ) -> Result<HttpResponse, S3Error> {
     let bucket_name = path.into_inner();
-    let s3 = s3_service.lock().await;
-    match s3.list_objects(&bucket_name).await {
-        Ok(objects) => {
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_bucket_verbose_logging(&bucket).await
+    };
+    match result {
+        Ok(verbose) => Ok(HttpResponse::Ok().negotiated(BucketLoggingResponse {
+            bucket: bucket_name,
+            verbose,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to get bucket logging");
+            Err(e)
+        }
+    }
+}
+
+/// Handles PUT /buckets/{bucket_name}?logging
+/// Turns detailed logging of the operations on a bucket on or off.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `verbose_buckets` - The buckets logged in detail.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `body` - The requested verbosity.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}?logging",
+    tag = "buckets",
+    params(BucketPath),
+    request_body = BucketLoggingConfiguration,
+    responses(
+        (status = 200, description = "The logging configuration was applied.", body = BucketLoggingResponse),
+    )
+)]
+pub async fn put_bucket_logging_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    verbose_buckets: web::Data<Arc<VerboseBuckets>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+    body: web::Json<BucketLoggingConfiguration>,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let verbose = body.into_inner().verbose;
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.put_bucket_verbose_logging(&bucket, verbose).await
+    };
+    match result {
+        Ok(_) => {
+            verbose_buckets.set(&bucket, verbose);
             info!(
-                "Listed {} objects in bucket '{}'.",
-                objects.len(),
-                bucket_name
+                "Verbose logging for bucket '{}' set to {}.",
+                bucket_name, verbose
             );
-            Ok(HttpResponse::Ok().json(ObjectListResponse {
+            Ok(HttpResponse::Ok().negotiated(BucketLoggingResponse {
                 bucket: bucket_name,
-                items: objects,
+                verbose,
             }))
         }
         Err(e) => {
-            error!(error = %e, "Failed to list objects");
+            error!(error = %e, "Failed to set bucket logging");
             Err(e)
         }
     }
 }
+
+/// Handles GET /buckets/{bucket_name}?replication
+/// Reports the replication destination of a bucket and how far behind it is.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}?replication",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The replication configuration of the bucket.", body = BucketReplicationResponse),
+    )
+)]
+pub async fn get_bucket_replication_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_bucket_replication(&bucket).await
+    };
+    match result {
+        Ok(report) => Ok(HttpResponse::Ok().negotiated(BucketReplicationResponse {
+            bucket: bucket_name,
+            report,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to get bucket replication");
+            Err(e)
+        }
+    }
+}
+
+/// Handles PUT /buckets/{bucket_name}?replication
+/// Sets or clears the peer that new objects in a bucket are replicated to.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `body` - The requested replication configuration.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}?replication",
+    tag = "buckets",
+    params(BucketPath),
+    request_body = ReplicationConfiguration,
+    responses(
+        (status = 200, description = "The replication configuration was applied.", body = BucketReplicationResponse),
+    )
+)]
+pub async fn put_bucket_replication_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+    body: web::Json<ReplicationConfiguration>,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let destination = body.into_inner().destination;
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        match s3
+            .put_bucket_replication(&bucket, destination.as_deref())
+            .await
+        {
+            Ok(_) => s3.get_bucket_replication(&bucket).await,
+            Err(e) => Err(e),
+        }
+    };
+    match result {
+        Ok(report) => {
+            info!(
+                "Replication destination for bucket '{}' set to {:?}.",
+                bucket_name, destination
+            );
+            Ok(HttpResponse::Ok().negotiated(BucketReplicationResponse {
+                bucket: bucket_name,
+                report,
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to set bucket replication");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets/{bucket_name}?lifecycle
+/// Returns the lifecycle transition rules of a bucket.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}?lifecycle",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The lifecycle configuration of the bucket.", body = LifecycleConfiguration),
+    )
+)]
+pub async fn get_bucket_lifecycle_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_bucket_lifecycle(&bucket).await
+    };
+    match result {
+        Ok(rules) => Ok(HttpResponse::Ok().negotiated(LifecycleConfiguration { rules })),
+        Err(e) => {
+            error!(error = %e, "Failed to get bucket lifecycle");
+            Err(e)
+        }
+    }
+}
+
+/// Handles PUT /buckets/{bucket_name}?lifecycle
+/// Replaces the lifecycle transition rules of a bucket.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `body` - The new lifecycle configuration.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}?lifecycle",
+    tag = "buckets",
+    params(BucketPath),
+    request_body = LifecycleConfiguration,
+    responses(
+        (status = 200, description = "The lifecycle configuration was applied.", body = LifecycleConfiguration),
+    )
+)]
+pub async fn put_bucket_lifecycle_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+    body: web::Json<LifecycleConfiguration>,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let configuration = body.into_inner();
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.put_bucket_lifecycle(&bucket, &configuration.rules).await
+    };
+    match result {
+        Ok(_) => {
+            info!(
+                "Set {} lifecycle rules on bucket '{}'.",
+                configuration.rules.len(),
+                bucket_name
+            );
+            Ok(HttpResponse::Ok().negotiated(configuration))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to set bucket lifecycle");
+            Err(e)
+        }
+    }
+}
+
+/// Handles HEAD /buckets/{bucket_name}
+/// Checks that a bucket exists, returning its object count and the bytes it
+/// uses, from its usage counters, as headers.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    head,
+    path = "/buckets/{bucket_name}",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The bucket exists.", headers(("x-bucket-object-count" = u64, description = "Current objects in the bucket."), ("x-bucket-bytes-used" = u64, description = "Bytes used by the bucket's objects, versions and uploads."))),
+    )
+)]
+pub async fn head_bucket_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let bucket = namespace.bucket(&path.into_inner())?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_bucket_usage(&bucket).await
+    };
+    match result {
+        Ok(usage) => Ok(HttpResponse::Ok()
+            .insert_header((BUCKET_OBJECT_COUNT_HEADER, usage.objects))
+            .insert_header((BUCKET_BYTES_USED_HEADER, usage.total_bytes))
+            .finish()),
+        Err(e) => {
+            error!(error = %e, "Failed to head bucket");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets/{bucket_name}?content-type
+/// Returns the content type given to objects uploaded to a bucket without one.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}?content-type",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The content-type configuration of the bucket.", body = BucketContentTypeResponse),
+    )
+)]
+pub async fn get_bucket_content_type_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_bucket_default_content_type(&bucket).await
+    };
+    match result {
+        Ok(content_type) => Ok(HttpResponse::Ok().negotiated(BucketContentTypeResponse {
+            bucket: bucket_name,
+            content_type,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to get bucket default content type");
+            Err(e)
+        }
+    }
+}
+
+/// Handles PUT /buckets/{bucket_name}?content-type
+/// Sets the content type given to objects uploaded to a bucket without a
+/// Content-Type header, or clears it with `null`. It takes precedence over
+/// the content type inferred from the data or key.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `body` - The requested default content type.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}?content-type",
+    tag = "buckets",
+    params(BucketPath),
+    request_body = BucketContentTypeConfiguration,
+    responses(
+        (status = 200, description = "The content-type configuration was applied.", body = BucketContentTypeResponse),
+    )
+)]
+pub async fn put_bucket_content_type_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+    body: web::Json<BucketContentTypeConfiguration>,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let content_type = body.into_inner().content_type;
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.put_bucket_default_content_type(&bucket, content_type.as_deref())
+            .await
+    };
+    match result {
+        Ok(_) => {
+            info!(
+                "Default content type of bucket '{}' set to {:?}.",
+                bucket_name, content_type
+            );
+            Ok(HttpResponse::Ok().negotiated(BucketContentTypeResponse {
+                bucket: bucket_name,
+                content_type,
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to set bucket default content type");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets/{bucket_name}?metadata
+/// Returns the user metadata merged into every object uploaded to a bucket.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}?metadata",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The metadata configuration of the bucket.", body = BucketMetadataResponse),
+    )
+)]
+pub async fn get_bucket_metadata_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_bucket_default_metadata(&bucket).await
+    };
+    match result {
+        Ok(metadata) => Ok(HttpResponse::Ok().negotiated(BucketMetadataResponse {
+            bucket: bucket_name,
+            metadata,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to get bucket default metadata");
+            Err(e)
+        }
+    }
+}
+
+/// Handles PUT /buckets/{bucket_name}?metadata
+/// Sets the user metadata, e.g. `{"team": "payments"}`, merged into every
+/// object uploaded to a bucket unless its `x-user-meta-*` headers set the same
+/// names. An empty map clears the defaults.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `body` - The requested default metadata.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}?metadata",
+    tag = "buckets",
+    params(BucketPath),
+    request_body = BucketMetadataConfiguration,
+    responses(
+        (status = 200, description = "The metadata configuration was applied.", body = BucketMetadataResponse),
+    )
+)]
+pub async fn put_bucket_metadata_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+    body: web::Json<BucketMetadataConfiguration>,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.put_bucket_default_metadata(&bucket, body.into_inner().metadata)
+            .await
+    };
+    match result {
+        Ok(metadata) => {
+            info!(
+                "Default metadata of bucket '{}' set to {} entries.",
+                bucket_name,
+                metadata.len()
+            );
+            Ok(HttpResponse::Ok().negotiated(BucketMetadataResponse {
+                bucket: bucket_name,
+                metadata,
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to set bucket default metadata");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets/{bucket_name}?roles
+/// Returns the roles principals hold on a bucket.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}?roles",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The roles configuration of the bucket.", body = BucketRolesResponse),
+    )
+)]
+pub async fn get_bucket_roles_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_bucket_roles(&bucket).await
+    };
+    match result {
+        Ok(roles) => Ok(HttpResponse::Ok().negotiated(BucketRolesResponse {
+            bucket: bucket_name,
+            roles,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to get bucket roles");
+            Err(e)
+        }
+    }
+}
+
+/// Handles PUT /buckets/{bucket_name}?roles
+/// Replaces the roles principals hold on a bucket, e.g.
+/// `{"roles": {"alice": "writer", "bob": "reader"}}`. An empty map opens the
+/// bucket to every request again.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `bucket_roles` - The roles and grants of the buckets that have any.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `body` - The requested roles.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}?roles",
+    tag = "buckets",
+    params(BucketPath),
+    request_body = BucketRolesConfiguration,
+    responses(
+        (status = 200, description = "The roles configuration was applied.", body = BucketRolesResponse),
+    )
+)]
+pub async fn put_bucket_roles_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    bucket_roles: web::Data<Arc<BucketRoles>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+    body: web::Json<BucketRolesConfiguration>,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let roles = body.into_inner().roles;
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.put_bucket_roles(&bucket, &roles).await
+    };
+    match result {
+        Ok(_) => {
+            bucket_roles.set(&bucket, roles.clone());
+            info!(
+                "Roles of bucket '{}' set for {} principals.",
+                bucket_name,
+                roles.len()
+            );
+            Ok(HttpResponse::Ok().negotiated(BucketRolesResponse {
+                bucket: bucket_name,
+                roles,
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to set bucket roles");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets/{bucket_name}?acl
+/// Returns the access a bucket grants to other principals.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}?acl",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The acl configuration of the bucket.", body = BucketAclResponse),
+    )
+)]
+pub async fn get_bucket_acl_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_bucket_acl(&bucket).await
+    };
+    match result {
+        Ok(grants) => Ok(HttpResponse::Ok().negotiated(BucketAclResponse {
+            bucket: bucket_name,
+            grants,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to get bucket ACL");
+            Err(e)
+        }
+    }
+}
+
+/// Handles PUT /buckets/{bucket_name}?acl
+/// Replaces the access a bucket grants to other principals, e.g.
+/// `{"grants": [{"grantee": "bob", "permission": "read"}]}`.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `bucket_roles` - The roles and grants of the buckets that have any.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `body` - The requested grants.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}?acl",
+    tag = "buckets",
+    params(BucketPath),
+    request_body = BucketAclConfiguration,
+    responses(
+        (status = 200, description = "The acl configuration was applied.", body = BucketAclResponse),
+    )
+)]
+pub async fn put_bucket_acl_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    bucket_roles: web::Data<Arc<BucketRoles>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+    body: web::Json<BucketAclConfiguration>,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.put_bucket_acl(&bucket, body.into_inner().grants).await
+    };
+    match result {
+        Ok(grants) => {
+            bucket_roles.set_grants(&bucket, grants.clone());
+            info!(
+                "ACL of bucket '{}' set to {} grants.",
+                bucket_name,
+                grants.len()
+            );
+            Ok(HttpResponse::Ok().negotiated(BucketAclResponse {
+                bucket: bucket_name,
+                grants,
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to set bucket ACL");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets/{bucket_name}?access-stats
+/// Reports the most read and the never-read objects of a bucket.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `query` - The optional `limit` on entries per list.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}?access-stats",
+    tag = "buckets",
+    params(BucketPath, AccessReportQuery),
+    responses(
+        (status = 200, description = "The most read and never-read objects.", body = BucketAccessReportResponse),
+    )
+)]
+pub async fn get_bucket_access_report_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+    query: web::Query<AccessReportQuery>,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let limit = query.limit.unwrap_or(DEFAULT_ACCESS_REPORT_LIMIT);
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_bucket_access_report(&bucket, limit).await
+    };
+    match result {
+        Ok(report) => Ok(HttpResponse::Ok().negotiated(BucketAccessReportResponse {
+            bucket: bucket_name,
+            report,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to get bucket access statistics");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets/{bucket_name}?metrics
+/// Returns the request counters of a bucket since the server started.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `metrics` - A reference to the shared Metrics instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}?metrics",
+    tag = "buckets",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The request counters of the bucket.", body = BucketMetricsResponse),
+    )
+)]
+pub async fn get_bucket_metrics_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    metrics: web::Data<Arc<Metrics>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.head_bucket(&bucket).await
+    };
+    match result {
+        Ok(_) => Ok(HttpResponse::Ok().negotiated(BucketMetricsResponse {
+            metrics: metrics.bucket_snapshot(&bucket),
+            bucket: bucket_name,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to get bucket metrics");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets
+/// Lists all existing buckets of the namespace.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `namespace` - The namespace to list.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets",
+    tag = "buckets",
+    responses(
+        (status = 200, description = "The names of the buckets.", body = ListResponse, headers(("x-total-count" = usize, description = "Number of buckets."))),
+    )
+)]
+pub async fn list_buckets_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.list_buckets(namespace.0.as_deref()).await
+    };
+    match result {
+        Ok(buckets) => Ok(HttpResponse::Ok()
+            .insert_header((TOTAL_COUNT_HEADER, buckets.len()))
+            .negotiated(ListResponse { items: buckets })),
+        Err(e) => Err(e),
+    }
+}
+
+// --- Object handlers ---
+
+/// Handles GET /buckets/{bucket_name}/objects/{object_key}
+/// Retrieves an object from a bucket.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `access_tracker` - The buffer of object reads for access statistics.
+/// * `bandwidth` - The download rate limits the body is sent under.
+/// * `path` - The path to the object to retrieve.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}/objects/{object_key}",
+    tag = "objects",
+    params(ObjectPath),
+    responses(
+        (status = 200, description = "The object's data.", content_type = "application/octet-stream", body = ObjectData, headers(("ETag" = String), ("Last-Modified" = String), ("x-amz-version-id" = String, description = "Set in versioned buckets."), ("x-amz-storage-class" = String), ("x-amz-replication-status" = String), ("x-immutable" = bool))),
+        (status = 206, description = "The requested range of the object's data.", content_type = "application/octet-stream", body = ObjectData),
+        (status = 304, description = "The object was not modified."),
+    )
+)]
+#[tracing::instrument(
+    name = "Get object",
+    skip(s3_service, access_tracker, bandwidth),
+    fields(
+        bucket = %path.0,
+        object_key = %path.1
+    )
+)]
+pub async fn get_object_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    access_tracker: web::Data<Arc<AccessTracker>>,
+    bandwidth: web::Data<Arc<Bandwidth>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, object_key) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.download_object(&bucket, &object_key).await
+    };
+    match result {
+        Ok(download) => {
+            info!(
+                "Object '{}' retrieved from bucket '{}'.",
+                object_key, bucket_name
+            );
+            access_tracker.record(&bucket, &object_key);
+            let (content_type, version_id, immutable) = match &download {
+                ObjectDownload::Buffered(object) => {
+                    (&object.content_type, &object.version_id, object.immutable)
+                }
+                ObjectDownload::Streamed(reader) => (
+                    &reader.info.content_type,
+                    &reader.info.version_id,
+                    reader.info.immutable,
+                ),
+            };
+            let mut response = HttpResponse::Ok();
+            if let Some(content_type) = content_type {
+                response.insert_header((CONTENT_TYPE, content_type.as_str()));
+            }
+            if let Some(version_id) = version_id {
+                response.insert_header((VERSION_ID_HEADER, version_id.as_str()));
+            }
+            if immutable {
+                response.insert_header((IMMUTABLE_HEADER, "true"));
+            }
+            Ok(match download {
+                ObjectDownload::Buffered(object) => {
+                    response.body(paced_body(&bandwidth, &bucket, object.data))
+                }
+                ObjectDownload::Streamed(reader) => {
+                    response.body(streamed_body(&bandwidth, &bucket, reader))
+                }
+            })
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to retrieve object");
+            Err(e)
+        }
+    }
+}
+
+/// The body of a download, sent in chunks paced by the download limits of
+/// its bucket.
+fn paced_body(
+    bandwidth: &Bandwidth,
+    bucket: &str,
+    data: Vec<u8>,
+) -> SizedStream<impl futures::Stream<Item = Result<Bytes, actix_web::Error>> + use<>> {
+    let pacer = bandwidth.download_pacer(bucket);
+    let data = Bytes::from(data);
+    let size = data.len() as u64;
+    let chunks = stream::unfold((data, pacer), |(mut data, pacer)| async move {
+        if data.is_empty() {
+            return None;
+        }
+        let chunk = data.split_to(DOWNLOAD_CHUNK_SIZE.min(data.len()));
+        pacer.pace(chunk.len() as u64).await;
+        Some((Ok::<_, actix_web::Error>(chunk), (data, pacer)))
+    });
+    SizedStream::new(size, chunks)
+}
+
+/// The body of a download read from the object's file as it is sent,
+/// paced like `paced_body`. Bytes not matching the ETag end the body with
+/// an error in place of its last chunk, so the client cannot take it for
+/// complete.
+fn streamed_body(
+    bandwidth: &Bandwidth,
+    bucket: &str,
+    reader: ObjectReader,
+) -> SizedStream<impl futures::Stream<Item = Result<Bytes, actix_web::Error>> + use<>> {
+    let pacer = bandwidth.download_pacer(bucket);
+    let size = reader.info.size;
+    let object = format!("{}/{}", bucket, reader.info.key);
+    let file = tokio::fs::File::from_std(reader.file);
+    let state = (file, reader.check, size, pacer, object);
+    let chunks = stream::unfold(Some(state), |state| async move {
+        let (mut file, mut check, remaining, pacer, object) = state?;
+        if remaining == 0 {
+            return None;
+        }
+        let mut chunk = vec![0; remaining.min(DOWNLOAD_CHUNK_SIZE as u64) as usize];
+        let read = match file.read(&mut chunk).await {
+            Ok(0) => Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof)),
+            read => read,
+        };
+        let read = match read {
+            Ok(read) => read,
+            Err(e) => {
+                error!(error = %e, object = %object, "Failed to read object while sending it");
+                return Some((Err(e.into()), None));
+            }
+        };
+        chunk.truncate(read);
+        if let Some(check) = &mut check {
+            check.update(&chunk);
+        }
+        let remaining = remaining - read as u64;
+        if remaining == 0
+            && let Some(check) = check.take()
+            && !check.matches()
+        {
+            error!(object = %object, "ETag mismatch while sending object - possible data corruption");
+            return Some((
+                Err(actix_web::error::ErrorInternalServerError(
+                    "ETag mismatch - possible data corruption",
+                )),
+                None,
+            ));
+        }
+        pacer.pace(read as u64).await;
+        let state = (file, check, remaining, pacer, object);
+        Some((Ok(Bytes::from(chunk)), Some(state)))
+    });
+    SizedStream::new(size, chunks)
+}
+
+/// Handles POST /buckets/{bucket_name}/objects/{object_key}?compose
+/// Stores the concatenation of other objects of the bucket under the key,
+/// without the client sending their bytes.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the composed object.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `body` - The keys of the sources, in order, and the content type.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    post,
+    path = "/buckets/{bucket_name}/objects/{object_key}?compose",
+    tag = "objects",
+    params(ObjectPath),
+    request_body = ComposeConfiguration,
+    responses(
+        (status = 201, description = "The composed object was stored.", body = ObjectComposedResponse),
+    )
+)]
+#[tracing::instrument(
+    name = "Compose object",
+    skip(s3_service, body),
+    fields(bucket = %path.0, key = %path.1)
+)]
+pub async fn compose_object_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+    body: web::Json<ComposeConfiguration>,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, object_key) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let ComposeConfiguration {
+        sources,
+        content_type,
+    } = body.into_inner();
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.compose_object(&bucket, &object_key, &sources, content_type)
+            .await
+    };
+    match result {
+        Ok(object) => {
+            info!(
+                "Object '{}' composed from {} objects in bucket '{}'.",
+                object.key,
+                sources.len(),
+                bucket_name
+            );
+            let mut response = HttpResponse::Created();
+            if let Some(version_id) = &object.version_id {
+                response.insert_header((VERSION_ID_HEADER, version_id.as_str()));
+            }
+            Ok(response.negotiated(ObjectComposedResponse {
+                name: object.key.clone(),
+                bucket: bucket_name,
+                metadata: &object,
+                message: "Object composed successfully".to_string(),
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to compose object");
+            Err(e)
+        }
+    }
+}
+
+/// Handles POST /buckets/{bucket_name}/objects/{object_key}?share
+/// Issues a link anyone can download the object from, without credentials,
+/// until `max_downloads` downloads or `expires_in_secs` seconds have passed.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the object.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `body` - The limits of the link.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    post,
+    path = "/buckets/{bucket_name}/objects/{object_key}?share",
+    tag = "objects",
+    params(ObjectPath),
+    request_body = ShareConfiguration,
+    responses(
+        (status = 201, description = "The share link was issued.", body = ShareCreatedResponse),
+    )
+)]
+pub async fn create_share_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+    body: web::Json<ShareConfiguration>,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, object_key) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let ShareConfiguration {
+        max_downloads,
+        expires_in_secs,
+    } = body.into_inner();
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.create_share(
+            &bucket,
+            &object_key,
+            max_downloads,
+            expires_in_secs.map(Duration::from_secs),
+        )
+        .await
+    };
+    match result {
+        Ok(share) => {
+            info!(
+                "Share of object '{}' in bucket '{}' created.",
+                object_key, bucket_name
+            );
+            Ok(HttpResponse::Created()
+                .insert_header((LOCATION, share.url()))
+                .negotiated(ShareCreatedResponse {
+                    url: share.url(),
+                    id: share.id,
+                    token: share.token,
+                    bucket: bucket_name,
+                    key: object_key,
+                    max_downloads: share.max_downloads,
+                    expires_at: share.expires_at,
+                }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to create share");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets/{bucket_name}/shares
+/// Lists the share links of a bucket that can still be downloaded, without
+/// their tokens.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}/shares",
+    tag = "objects",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The share links of the bucket.", body = BucketSharesResponse),
+    )
+)]
+pub async fn list_shares_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.list_shares(&bucket).await
+    };
+    match result {
+        Ok(shares) => Ok(HttpResponse::Ok().negotiated(BucketSharesResponse {
+            bucket: bucket_name,
+            shares,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to list shares");
+            Err(e)
+        }
+    }
+}
+
+/// Handles DELETE /buckets/{bucket_name}/shares/{share_id}
+/// Revokes a share link, e.g. one that leaked, before its limits are reached.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket and the ID of the share.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    delete,
+    path = "/buckets/{bucket_name}/shares/{share_id}",
+    tag = "objects",
+    params(SharePath),
+    responses(
+        (status = 204, description = "The share link was revoked."),
+    )
+)]
+pub async fn revoke_share_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, share_id) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.revoke_share(&bucket, &share_id).await
+    };
+    match result {
+        Ok(_) => {
+            info!("Share '{}' of bucket '{}' revoked.", share_id, bucket_name);
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to revoke share");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /share/{token}
+/// Downloads the object a share link points to, counting the download.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `access_tracker` - The buffer of object reads for access statistics.
+/// * `bandwidth` - The download rate limits the body is sent under.
+/// * `path` - The link's token.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/share/{token}",
+    tag = "objects",
+    params(ShareTokenPath),
+    responses(
+        (status = 200, description = "The shared object's data.", content_type = "application/octet-stream", body = ObjectData),
+    )
+)]
+pub async fn get_share_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    access_tracker: web::Data<Arc<AccessTracker>>,
+    bandwidth: web::Data<Arc<Bandwidth>>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, S3Error> {
+    let token = path.into_inner();
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_shared_object(&token).await
+    };
+    match result {
+        Ok((share, object)) => {
+            info!(
+                "Object '{}' of bucket '{}' downloaded through a share ({} downloads).",
+                share.key, share.bucket, share.downloads
+            );
+            access_tracker.record(&share.bucket, &share.key);
+            let mut response = HttpResponse::Ok();
+            if let Some(content_type) = &object.content_type {
+                response.insert_header((CONTENT_TYPE, content_type.as_str()));
+            }
+            // Shared downloads are saved under the key's last segment
+            let file_name = share.key.rsplit('/').next().unwrap_or(&share.key);
+            response.insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(file_name.to_string())],
+            });
+            response.insert_header((CACHE_CONTROL, "no-store"));
+            Ok(response.body(paced_body(&bandwidth, &share.bucket, object.data)))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to download share");
+            Err(e)
+        }
+    }
+}
+
+/// Handles HEAD /buckets/{bucket_name}/objects/{object_key}
+/// Returns an object's metadata as headers without its data.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the object.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    head,
+    path = "/buckets/{bucket_name}/objects/{object_key}",
+    tag = "objects",
+    params(ObjectPath),
+    responses(
+        (status = 200, description = "The object's metadata.", headers(("ETag" = String), ("Last-Modified" = String), ("x-amz-version-id" = String, description = "Set in versioned buckets."), ("x-amz-storage-class" = String), ("x-amz-replication-status" = String), ("x-immutable" = bool))),
+    )
+)]
+#[tracing::instrument(
+    name = "Head object",
+    skip(s3_service),
+    fields(
+        bucket = %path.0,
+        object_key = %path.1
+    )
+)]
+pub async fn head_object_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, object_key) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.head_object(&bucket, &object_key).await
+    };
+    match result {
+        Ok(info) => {
+            let mut response = HttpResponse::Ok();
+            if let Some(content_type) = &info.content_type {
+                response.insert_header((CONTENT_TYPE, content_type.as_str()));
+            }
+            if let Some(etag) = &info.etag {
+                response.insert_header((ETAG, format!("\"{}\"", etag)));
+            }
+            if let Some(last_modified) =
+                UNIX_EPOCH.checked_add(Duration::from_secs(info.last_modified.max(0) as u64))
+            {
+                response.insert_header((LAST_MODIFIED, HttpDate::from(last_modified)));
+            }
+            if let Some(version_id) = &info.version_id {
+                response.insert_header((VERSION_ID_HEADER, version_id.as_str()));
+            }
+            if let Some(status) = &info.replication_status {
+                response.insert_header((REPLICATION_STATUS_HEADER, status.as_str()));
+            }
+            response.insert_header((STORAGE_CLASS_HEADER, info.storage_class.as_str()));
+            if info.immutable {
+                response.insert_header((IMMUTABLE_HEADER, "true"));
+            }
+            // HEAD responses carry the object's length but never its bytes
+            let body: SizedStream<Empty<Result<Bytes, actix_web::Error>>> =
+                SizedStream::new(info.size, stream::empty());
+            Ok(response.body(body))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to head object");
+            Err(e)
+        }
+    }
+}
+
+/// Handles PUT /buckets/{bucket_name}/objects/{object_key}
+/// Puts an object into a bucket. The object data is taken from the request body.
+/// With `x-immutable: true` the object can never be overwritten or deleted.
+///
+/// # Arguments
+///
+/// * `req` - The HTTP request.
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `bandwidth` - The upload rate limits the body is read under.
+/// * `memory` - The memory budget the body is held under.
+/// * `path` - The path to the object to put.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `payload` - The streamed body of the request.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}/objects/{object_key}",
+    tag = "objects",
+    params(ObjectPath),
+    request_body(content = ObjectData, content_type = "application/octet-stream"),
+    responses(
+        (status = 201, description = "The object was stored.", body = ObjectCreatedResponse),
+    )
+)]
+#[tracing::instrument(
+    name = "Put object",
+    skip(s3_service, bandwidth, memory, payload, req),
+    fields(
+        bucket = %path.0,
+        object_key = %path.1,
+        object_size = tracing::field::Empty
+    )
+)]
+pub async fn put_object_handler(
+    req: HttpRequest,
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    bandwidth: web::Data<Arc<Bandwidth>>,
+    memory: web::Data<Arc<MemoryBudget>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, S3Error> {
+    let _slot = admit_upload(&req).await?;
+    let mut reservation = admit_body(&req, &memory)?;
+    let content_type = req
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|s| s.to_string());
+
+    let user_metadata = req
+        .headers()
+        .iter()
+        .filter(|(k, _)| k.as_str().starts_with("x-user-meta-"))
+        .filter_map(|(k, v)| {
+            v.to_str().ok().map(|val_str| {
+                (
+                    k.as_str()
+                        .strip_prefix("x-user-meta-")
+                        .unwrap_or(k.as_str())
+                        .to_string(),
+                    val_str.to_string(),
+                )
+            })
+        })
+        .collect::<HashMap<_, _>>();
+    // Refused before the body is read
+    metadata::validate(&user_metadata)?;
+    let immutable = immutable_flag(&req)?;
+
+    let (bucket_name, object_key) = path.into_inner();
+
+    let bucket = namespace.bucket(&bucket_name)?;
+
+    let body = read_body(&req, &mut payload, &bandwidth, &mut reservation).await?;
+    Span::current().record("object_size", body.len());
+
+    // Create the Object before acquiring the lock
+    let object = Object::new(object_key.clone(), body, content_type, Some(user_metadata))?
+        .with_immutable(immutable);
+
+    // Acquire the lock, call put_object, and release the lock immediately
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.put_object(&bucket, object).await
+    };
+
+    match result {
+        Ok(returned_object) => {
+            info!(
+                "Object '{}' put into bucket '{}'.",
+                returned_object.key, bucket_name
+            );
+            let mut response = HttpResponse::Created();
+            if let Some(version_id) = &returned_object.version_id {
+                response.insert_header((VERSION_ID_HEADER, version_id.as_str()));
+            }
+            Ok(response.negotiated(ObjectCreatedResponse {
+                name: returned_object.key.clone(),
+                bucket: bucket_name,
+                metadata: &returned_object,
+                message: "Object created successfully".to_string(),
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to store object");
+            Err(e)
+        }
+    }
+}
+
+/// Handles PATCH /buckets/{bucket_name}/objects/{object_key}
+/// Rewrites the byte span of an object given by the `Content-Range` header
+/// with the request body, e.g. `Content-Range: bytes 0-15/*`. The span may
+/// extend the object past its end but must not leave a gap.
+///
+/// # Arguments
+///
+/// * `req` - The HTTP request, for its `Content-Range` header.
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `bandwidth` - The upload rate limits the body is read under.
+/// * `memory` - The memory budget the body is held under.
+/// * `path` - The path to the object to patch.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `payload` - The streamed body of the request.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    patch,
+    path = "/buckets/{bucket_name}/objects/{object_key}",
+    tag = "objects",
+    params(ObjectPath),
+    request_body(content = ObjectData, content_type = "application/octet-stream"),
+    responses(
+        (status = 200, description = "The span was written.", body = ObjectInfo),
+    )
+)]
+pub async fn patch_object_handler(
+    req: HttpRequest,
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    bandwidth: web::Data<Arc<Bandwidth>>,
+    memory: web::Data<Arc<MemoryBudget>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, S3Error> {
+    let range = header_str(&req, "Content-Range")
+        .ok_or_else(|| S3Error::InvalidRequest("Missing Content-Range header".to_string()))?;
+    let range = ContentRange::parse(range)
+        .ok_or_else(|| S3Error::InvalidRequest(format!("Invalid Content-Range '{}'", range)))?;
+    let _slot = admit_upload(&req).await?;
+    let mut reservation = admit_body(&req, &memory)?;
+    let (bucket_name, object_key) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+
+    let body = read_body(&req, &mut payload, &bandwidth, &mut reservation).await?;
+    if body.len() as u64 != range.byte_count() {
+        return Err(S3Error::InvalidRequest(format!(
+            "Body of {} bytes does not match the {} bytes of the Content-Range",
+            body.len(),
+            range.byte_count()
+        )));
+    }
+
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.patch_object(&bucket, &object_key, &range, &body).await
+    };
+    match result {
+        Ok(info) => {
+            info!(
+                first = range.first,
+                last = range.last,
+                "Object '{}' in bucket '{}' patched.",
+                object_key,
+                bucket_name
+            );
+            let mut response = HttpResponse::Ok();
+            if let Some(etag) = &info.etag {
+                response.insert_header((ETAG, format!("\"{}\"", etag)));
+            }
+            if let Some(version_id) = &info.version_id {
+                response.insert_header((VERSION_ID_HEADER, version_id.as_str()));
+            }
+            Ok(response.negotiated(info))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to patch object");
+            Err(e)
+        }
+    }
+}
+
+/// Handles POST /buckets/{bucket_name}
+/// Stores an object uploaded from a browser form signed with a POST policy.
+/// The form fields must precede the `file` field; fields after it are ignored.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `req` - The HTTP request.
+/// * `bandwidth` - The upload rate limits the file is read under.
+/// * `memory` - The memory budget the form is held under.
+/// * `credentials` - The secret keys policies are verified against.
+/// * `path` - The path to the bucket.
+/// * `form` - The multipart form.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    post,
+    path = "/buckets/{bucket_name}",
+    tag = "objects",
+    params(BucketPath),
+    request_body(content = PostObjectForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "The uploaded file was stored.", body = ObjectCreatedResponse),
+    )
+)]
+#[tracing::instrument(
+    name = "Post object",
+    skip(req, s3_service, bandwidth, memory, credentials, form),
+    fields(bucket = %path, object_key = tracing::field::Empty)
+)]
+pub async fn post_object_handler(
+    req: HttpRequest,
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    bandwidth: web::Data<Arc<Bandwidth>>,
+    memory: web::Data<Arc<MemoryBudget>>,
+    credentials: web::Data<Arc<Credentials>>,
+    path: web::Path<String>,
+    mut form: Multipart,
+) -> Result<HttpResponse, S3Error> {
+    let _slot = admit_upload(&req).await?;
+    let mut reservation = admit_body(&req, &memory)?;
+    let bucket_name = path.into_inner();
+    let bucket = Namespace::of(&req).bucket(&bucket_name)?;
+    let malformed = |e: actix_multipart::MultipartError| {
+        S3Error::InvalidRequest(format!("Malformed form data: {}", e))
+    };
+
+    let idle = timeout::body_idle_limit(&req);
+    let mut fields = HashMap::from([("bucket".to_string(), bucket_name.clone())]);
+    let mut file = None;
+    while let Some(field) = timeout::next_within(&mut form, idle).await? {
+        let mut field = field.map_err(malformed)?;
+        let name = field.name().unwrap_or_default().to_ascii_lowercase();
+
+        if name != "file" {
+            let mut value = Vec::new();
+            while let Some(chunk) = timeout::next_within(&mut field, idle).await? {
+                value.extend_from_slice(&chunk.map_err(malformed)?);
+                if value.len() > MAX_FORM_FIELD_SIZE {
+                    return Err(S3Error::InvalidRequest(format!(
+                        "Form field '{}' is too large",
+                        name
+                    )));
+                }
+            }
+            let value = String::from_utf8(value).map_err(|_| {
+                S3Error::InvalidRequest(format!("Form field '{}' is not valid UTF-8", name))
+            })?;
+            fields.insert(name, value);
+            continue;
+        }
+
+        // The policy is checked before any of the file is accepted
+        let filename = field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .unwrap_or_default()
+            .to_string();
+        if let Some(key) = fields.get_mut("key") {
+            *key = key.replace("${filename}", &filename);
+        }
+        let now = std::time::SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        let policy = post_policy::authorize(&fields, &credentials, now)
+            .map_err(|e| S3Error::AccessDenied(e.to_string()))?;
+        let (min_size, max_size) = policy.content_length_range().unwrap_or((0, u64::MAX));
+
+        let pacer = bandwidth.upload_pacer();
+        let mut body = Vec::new();
+        while let Some(chunk) = timeout::next_within(&mut field, idle).await? {
+            let chunk = chunk.map_err(malformed)?;
+            pacer.pace(chunk.len() as u64).await;
+            body.extend_from_slice(&chunk);
+            reservation.grow_to(body.len() as u64);
+            if body.len() as u64 > max_size {
+                return Err(S3Error::InvalidRequest(
+                    "Your proposed upload exceeds the maximum allowed size".to_string(),
+                ));
+            }
+        }
+        if (body.len() as u64) < min_size {
+            return Err(S3Error::InvalidRequest(
+                "Your proposed upload is smaller than the minimum allowed size".to_string(),
+            ));
+        }
+        file = Some((body, field.content_type().map(|m| m.to_string())));
+        break;
+    }
+
+    let Some((body, file_content_type)) = file else {
+        return Err(S3Error::InvalidRequest(
+            "POST requires exactly one file upload per request".to_string(),
+        ));
+    };
+    let key = fields
+        .get("key")
+        .filter(|key| !key.is_empty())
+        .cloned()
+        .ok_or_else(|| S3Error::InvalidRequest("Missing form field 'key'".to_string()))?;
+    Span::current().record("object_key", key.as_str());
+
+    let content_type = fields.get("content-type").cloned().or(file_content_type);
+    let user_metadata = fields
+        .iter()
+        .filter_map(|(name, value)| {
+            name.strip_prefix("x-amz-meta-")
+                .map(|meta| (meta.to_string(), value.clone()))
+        })
+        .collect::<HashMap<_, _>>();
+    let object = Object::new(key, body, content_type, Some(user_metadata))?;
+
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.put_object(&bucket, object).await
+    };
+
+    match result {
+        Ok(returned_object) => {
+            info!(
+                "Object '{}' posted to bucket '{}'.",
+                returned_object.key, bucket_name
+            );
+            // Browsers get an empty response unless the form asks for a status
+            let status = match fields.get("success_action_status").map(String::as_str) {
+                Some("200") => StatusCode::OK,
+                Some("201") => StatusCode::CREATED,
+                _ => StatusCode::NO_CONTENT,
+            };
+            let mut response = HttpResponse::build(status);
+            if let Some(version_id) = &returned_object.version_id {
+                response.insert_header((VERSION_ID_HEADER, version_id.as_str()));
+            }
+            if status != StatusCode::CREATED {
+                return Ok(response.finish());
+            }
+            Ok(response.negotiated(ObjectCreatedResponse {
+                name: returned_object.key.clone(),
+                bucket: bucket_name,
+                metadata: &returned_object,
+                message: "Object created successfully".to_string(),
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to store posted object");
+            Err(e)
+        }
+    }
+}
+
+/// Handles DELETE /buckets/{bucket_name}/objects/{object_key}
+/// Deletes an object from a bucket. With an `If-Match` header, the object is
+/// only deleted if its ETag still matches, and 412 is returned otherwise.
+///
+/// # Arguments
+///
+/// * `req` - The HTTP request, for its `If-Match` header.
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the object to delete.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    delete,
+    path = "/buckets/{bucket_name}/objects/{object_key}",
+    tag = "objects",
+    params(ObjectPath),
+    responses(
+        (status = 204, description = "The object was deleted."),
+    )
+)]
+#[tracing::instrument(
+    name = "Delete object",
+    skip(req, s3_service),
+    fields(
+        bucket = %path.0,
+        object_key = %path.1
+    )
+)]
+pub async fn delete_object_handler(
+    req: HttpRequest,
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, object_key) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.delete_object(&bucket, &object_key, header_str(&req, "If-Match"))
+            .await
+    };
+
+    match result {
+        Ok(_) => {
+            info!(
+                "Object '{}' deleted from bucket '{}'.",
+                object_key, bucket_name
+            );
+            Ok(HttpResponse::NoContent().negotiated(ObjectDeletedResponse {
+                name: object_key,
+                bucket: bucket_name,
+                message: "Object deleted successfully".to_string(),
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to delete object");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets/{bucket_name}/objects/{object_key}?legal-hold
+/// Reports whether an object is under legal hold.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the object.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}/objects/{object_key}?legal-hold",
+    tag = "objects",
+    params(ObjectPath),
+    responses(
+        (status = 200, description = "The legal hold state of the object.", body = ObjectLegalHoldResponse),
+    )
+)]
+pub async fn get_object_legal_hold_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, object_key) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_object_legal_hold(&bucket, &object_key).await
+    };
+    match result {
+        Ok(legal_hold) => Ok(HttpResponse::Ok().negotiated(ObjectLegalHoldResponse {
+            bucket: bucket_name,
+            key: object_key,
+            legal_hold,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to get object legal hold");
+            Err(e)
+        }
+    }
+}
+
+/// Handles PUT /buckets/{bucket_name}/objects/{object_key}?legal-hold
+/// Places or releases a legal hold on an object.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the object.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `body` - The requested legal hold state.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}/objects/{object_key}?legal-hold",
+    tag = "objects",
+    params(ObjectPath),
+    request_body = LegalHoldConfiguration,
+    responses(
+        (status = 200, description = "The legal hold state was applied.", body = ObjectLegalHoldResponse),
+    )
+)]
+pub async fn put_object_legal_hold_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+    body: web::Json<LegalHoldConfiguration>,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, object_key) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let legal_hold = body.into_inner().legal_hold;
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.put_object_legal_hold(&bucket, &object_key, legal_hold)
+            .await
+    };
+    match result {
+        Ok(_) => {
+            info!(
+                "Legal hold on object '{}' in bucket '{}' set to {}.",
+                object_key, bucket_name, legal_hold
+            );
+            Ok(HttpResponse::Ok().negotiated(ObjectLegalHoldResponse {
+                bucket: bucket_name,
+                key: object_key,
+                legal_hold,
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to set object legal hold");
+            Err(e)
+        }
+    }
+}
+
+/// Handles POST /buckets/{bucket_name}/objects/{object_key}?verify
+/// Re-hashes an object's file and compares it with the stored ETag. With
+/// `repair=true`, a mismatching stored ETag is replaced with the actual one.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the object.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `query` - Whether to repair the stored ETag.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    post,
+    path = "/buckets/{bucket_name}/objects/{object_key}?verify",
+    tag = "objects",
+    params(ObjectPath, VerifyQuery),
+    responses(
+        (status = 200, description = "The result of the verification.", body = ObjectVerificationResponse),
+    )
+)]
+pub async fn verify_object_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+    query: web::Query<VerifyQuery>,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, object_key) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.verify_object(&bucket, &object_key, query.repair).await
+    };
+    match result {
+        Ok(verification) => {
+            if !verification.valid {
+                warn!(
+                    repaired = verification.repaired,
+                    "ETag mismatch for '{}' in bucket '{}'.", object_key, bucket_name
+                );
+            }
+            Ok(HttpResponse::Ok().negotiated(ObjectVerificationResponse {
+                bucket: bucket_name,
+                verification,
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to verify object");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets/{bucket_name}/objects
+/// Lists all objects in a specific bucket. By default only their keys are
+/// listed; `?detail=full` adds each object's size, ETag, content type and
+/// last modification time, and `&checksum=true` its checksum in the
+/// configured hash algorithm. The `x-total-count` header carries the number
+/// of objects in the bucket, from its usage counters.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket to list objects from.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `query` - The level of detail to list.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}/objects",
+    tag = "objects",
+    params(BucketPath, ListObjectsQuery),
+    responses(
+        (status = 200, description = "The objects of the bucket, by key.", body = ObjectListing, headers(("x-total-count" = u64, description = "Number of objects in the bucket."))),
+    )
+)]
+pub async fn list_objects_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+    query: web::Query<ListObjectsQuery>,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let s3 = s3_service.lock().await;
+    let total = match s3.get_bucket_usage(&bucket).await {
+        Ok(usage) => usage.objects,
+        Err(e) => {
+            error!(error = %e, "Failed to list objects");
+            return Err(e);
+        }
+    };
+    let mut response = HttpResponse::Ok();
+    response.insert_header((TOTAL_COUNT_HEADER, total));
+    let query = query.into_inner();
+    let result = match query.detail {
+        ListDetail::Full if query.checksum => {
+            s3.list_object_checksums(&bucket)
+                .await
+                .map(|(checksum_algorithm, objects)| {
+                    (
+                        objects.len(),
+                        response.negotiated(ObjectChecksumListResponse {
+                            bucket: bucket_name.clone(),
+                            checksum_algorithm,
+                            items: objects
+                                .into_iter()
+                                .map(|(info, checksum)| ObjectChecksumEntry { info, checksum })
+                                .collect(),
+                        }),
+                    )
+                })
+        }
+        ListDetail::Keys => s3.list_objects(&bucket).await.map(|objects| {
+            (
+                objects.len(),
+                response.negotiated(ObjectListResponse {
+                    bucket: bucket_name.clone(),
+                    items: objects,
+                }),
+            )
+        }),
+        ListDetail::Full => s3.list_objects_detailed(&bucket).await.map(|objects| {
+            (
+                objects.len(),
+                response.negotiated(ObjectDetailListResponse {
+                    bucket: bucket_name.clone(),
+                    items: objects,
+                }),
+            )
+        }),
+    };
+    match result {
+        Ok((count, response)) => {
+            info!("Listed {} objects in bucket '{}'.", count, bucket_name);
+            Ok(response)
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to list objects");
+            Err(e)
+        }
+    }
+}
+
+/// Handles DELETE /buckets/{bucket_name}/objects?prefix=...
+/// Deletes every object whose key starts with the prefix, e.g. to clear out
+/// a folder. Objects under legal hold or in a write-once bucket are skipped.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `query` - The key prefix of the objects to delete.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    delete,
+    path = "/buckets/{bucket_name}/objects?prefix",
+    tag = "objects",
+    params(BucketPath, PrefixQuery),
+    responses(
+        (status = 200, description = "The objects under the prefix were deleted.", body = PrefixDeletedResponse),
+    )
+)]
+#[tracing::instrument(
+    name = "Delete prefix",
+    skip(s3_service, namespace, query),
+    fields(bucket = %path, prefix = %query.prefix)
+)]
+pub async fn delete_prefix_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+    query: web::Query<PrefixQuery>,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let prefix = query.into_inner().prefix;
+    // Clearing a whole bucket this way is too easy to do by accident
+    if prefix.is_empty() {
+        return Err(S3Error::InvalidRequest(
+            "The prefix of a recursive delete must not be empty".to_string(),
+        ));
+    }
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.delete_prefix(&bucket, &prefix).await
+    };
+    match result {
+        Ok(report) => {
+            info!(
+                deleted = report.deleted,
+                skipped = report.skipped.len(),
+                "Deleted objects with prefix '{}' from bucket '{}'.",
+                prefix,
+                bucket_name
+            );
+            Ok(HttpResponse::Ok().negotiated(PrefixDeletedResponse {
+                bucket: bucket_name,
+                prefix,
+                report,
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to delete objects by prefix");
+            Err(e)
+        }
+    }
+}
+
+// --- Folder handlers ---
+
+/// Handles PUT /buckets/{bucket_name}/folders/{prefix}
+/// Creates the zero-byte marker object of a folder, `{prefix}/`, so the
+/// folder shows up before any object is stored in it.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket and the folder.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/buckets/{bucket_name}/folders/{prefix}",
+    tag = "objects",
+    params(FolderPath),
+    responses(
+        (status = 201, description = "The folder marker was stored.", body = ObjectCreatedResponse),
+    )
+)]
+pub async fn create_folder_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, prefix) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let key = marker_key(&prefix)
+        .ok_or_else(|| S3Error::InvalidRequest(format!("Invalid folder '{}'", prefix)))?;
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.create_folder(&bucket, &key).await
+    };
+    match result {
+        Ok(returned_object) => {
+            info!("Folder '{}' created in bucket '{}'.", key, bucket_name);
+            Ok(HttpResponse::Created().negotiated(ObjectCreatedResponse {
+                name: returned_object.key.clone(),
+                bucket: bucket_name,
+                metadata: &returned_object,
+                message: "Folder created successfully".to_string(),
+            }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to create folder");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets/{bucket_name}/folders/{prefix}
+/// Lists the objects and subfolders directly in a folder; an empty prefix
+/// lists the bucket's root.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket and the folder.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}/folders/{prefix}",
+    tag = "objects",
+    params(FolderPath),
+    responses(
+        (status = 200, description = "The contents of the folder.", body = FolderListResponse),
+    )
+)]
+pub async fn list_folder_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, prefix) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let prefix = if prefix.is_empty() {
+        prefix
+    } else {
+        marker_key(&prefix)
+            .ok_or_else(|| S3Error::InvalidRequest(format!("Invalid folder '{}'", prefix)))?
+    };
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.list_folder(&bucket, &prefix).await
+    };
+    match result {
+        Ok(listing) => Ok(HttpResponse::Ok().negotiated(FolderListResponse {
+            bucket: bucket_name,
+            listing,
+        })),
+        Err(e) => {
+            error!(error = %e, "Failed to list folder");
+            Err(e)
+        }
+    }
+}
+
+// --- Resumable upload (tus) handlers ---
+
+/// Reads a header as a string, if present and valid.
+fn header_str<'a>(req: &'a HttpRequest, name: &str) -> Option<&'a str> {
+    req.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Returns a 412 response if the request does not speak the supported tus version.
+fn tus_version_mismatch(req: &HttpRequest) -> Option<HttpResponse> {
+    if header_str(req, TUS_RESUMABLE_HEADER) == Some(TUS_VERSION) {
+        return None;
+    }
+    Some(
+        HttpResponse::PreconditionFailed()
+            .insert_header((TUS_VERSION_HEADER, TUS_VERSION))
+            .finish(),
+    )
+}
+
+/// Parses a numeric tus header such as `Upload-Length`.
+fn parse_length_header(req: &HttpRequest, name: &str) -> Result<u64, S3Error> {
+    header_str(req, name)
+        .ok_or_else(|| S3Error::InvalidRequest(format!("Missing {} header", name)))?
+        .parse()
+        .map_err(|_| S3Error::InvalidRequest(format!("Invalid {} header", name)))
+}
+
+/// Handles OPTIONS /buckets/{bucket_name}/uploads
+/// Advertises the supported tus version and extensions.
+///
+/// # Returns
+///
+/// * `HttpResponse` - The HTTP response.
+#[utoipa::path(
+    options,
+    path = "/buckets/{bucket_name}/uploads",
+    tag = "uploads",
+    params(BucketPath),
+    responses(
+        (status = 204, description = "The supported tus version and extensions.", headers(("Tus-Resumable" = String), ("Tus-Version" = String), ("Tus-Extension" = String))),
+    )
+)]
+pub async fn tus_options_handler() -> HttpResponse {
+    HttpResponse::NoContent()
+        .insert_header((TUS_RESUMABLE_HEADER, TUS_VERSION))
+        .insert_header((TUS_VERSION_HEADER, TUS_VERSION))
+        .insert_header((TUS_EXTENSION_HEADER, TUS_EXTENSIONS))
+        .finish()
+}
+
+/// Handles GET /buckets/{bucket_name}/uploads
+/// Lists the resumable uploads in progress in a bucket, e.g. for a client
+/// resuming after a crash.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}/uploads",
+    tag = "uploads",
+    params(BucketPath),
+    responses(
+        (status = 200, description = "The uploads in progress in the bucket.", body = BucketUploadsResponse),
+    )
+)]
+pub async fn list_uploads_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.list_uploads(&bucket).await
+    };
+    match result {
+        Ok(uploads) => Ok(HttpResponse::Ok()
+            .insert_header((CACHE_CONTROL, "no-store"))
+            .negotiated(BucketUploadsResponse {
+                bucket: bucket_name,
+                uploads,
+            })),
+        Err(e) => {
+            error!(error = %e, "Failed to list uploads");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets/{bucket_name}/uploads/{upload_id}/parts
+/// Lists the parts of a resumable upload with their sizes and ETags.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the upload.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}/uploads/{upload_id}/parts",
+    tag = "uploads",
+    params(UploadPath),
+    responses(
+        (status = 200, description = "The parts of the upload.", body = UploadPartsResponse),
+    )
+)]
+pub async fn list_upload_parts_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, upload_id) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.list_upload_parts(&bucket, &upload_id).await
+    };
+    match result {
+        Ok((upload, parts)) => Ok(HttpResponse::Ok()
+            .insert_header((CACHE_CONTROL, "no-store"))
+            .negotiated(UploadPartsResponse {
+                bucket: bucket_name,
+                upload,
+                parts,
+            })),
+        Err(e) => {
+            error!(error = %e, "Failed to list upload parts");
+            Err(e)
+        }
+    }
+}
+
+/// Handles POST /buckets/{bucket_name}/uploads
+/// Starts a resumable upload. The object key is taken from the `key` (or
+/// `filename`) entry of `Upload-Metadata`, the content type from `filetype`;
+/// all other entries become user metadata. An `Upload-Length` above the
+/// largest accepted object body is refused with 413 EntityTooLarge.
+///
+/// # Arguments
+///
+/// * `req` - The HTTP request carrying the tus headers.
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the bucket.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    post,
+    path = "/buckets/{bucket_name}/uploads",
+    tag = "uploads",
+    params(BucketPath),
+    responses(
+        (status = 201, description = "The upload was started.", headers(("Location" = String, description = "The URL of the upload."))),
+    )
+)]
+#[tracing::instrument(name = "Create upload", skip(s3_service, req), fields(bucket = %path))]
+pub async fn create_upload_handler(
+    req: HttpRequest,
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    if let Some(response) = tus_version_mismatch(&req) {
+        return Ok(response);
+    }
+    let bucket_name = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let length = parse_length_header(&req, UPLOAD_LENGTH_HEADER)?;
+    if let Some(limit) = body_limit(&req)
+        && length > limit
+    {
+        return Err(S3Error::EntityTooLarge(limit));
+    }
+    let mut metadata = parse_metadata(header_str(&req, UPLOAD_METADATA_HEADER).unwrap_or(""))
+        .map_err(S3Error::InvalidRequest)?;
+    let key = metadata
+        .remove("key")
+        .or_else(|| metadata.remove("filename"))
+        .filter(|key| !key.is_empty())
+        .ok_or_else(|| {
+            S3Error::InvalidRequest("Upload-Metadata must name the object key".to_string())
+        })?;
+    let content_type = metadata.remove("filetype");
+
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.create_upload(&bucket, &key, length, content_type, metadata)
+            .await
+    };
+    match result {
+        Ok(upload) => {
+            info!(
+                "Upload '{}' of object '{}' started in bucket '{}'.",
+                upload.id, key, bucket_name
+            );
+            Ok(HttpResponse::Created()
+                .insert_header((TUS_RESUMABLE_HEADER, TUS_VERSION))
+                .insert_header((
+                    LOCATION,
+                    format!(
+                        "{}/buckets/{}/uploads/{}",
+                        namespace.path_prefix(),
+                        bucket_name,
+                        upload.id
+                    ),
+                ))
+                .insert_header((UPLOAD_OFFSET_HEADER, "0"))
+                .finish())
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to create upload");
+            Err(e)
+        }
+    }
+}
+
+/// Handles HEAD /buckets/{bucket_name}/uploads/{upload_id}
+/// Reports how many bytes of an upload have been received.
+///
+/// # Arguments
+///
+/// * `req` - The HTTP request carrying the tus headers.
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `path` - The path to the upload.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    head,
+    path = "/buckets/{bucket_name}/uploads/{upload_id}",
+    tag = "uploads",
+    params(UploadPath),
+    responses(
+        (status = 200, description = "The bytes of the upload received so far.", headers(("Upload-Offset" = u64), ("Upload-Length" = u64))),
+    )
+)]
+pub async fn head_upload_handler(
+    req: HttpRequest,
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    if let Some(response) = tus_version_mismatch(&req) {
+        return Ok(response);
+    }
+    let (bucket_name, upload_id) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_upload(&bucket, &upload_id).await
+    };
+    match result {
+        Ok(upload) => Ok(HttpResponse::Ok()
+            .insert_header((TUS_RESUMABLE_HEADER, TUS_VERSION))
+            .insert_header((UPLOAD_OFFSET_HEADER, upload.offset.to_string()))
+            .insert_header((UPLOAD_LENGTH_HEADER, upload.length.to_string()))
+            .insert_header((CACHE_CONTROL, "no-store"))
+            .finish()),
+        Err(e) => {
+            error!(error = %e, "Failed to get upload");
+            Err(e)
+        }
+    }
+}
+
+/// Handles PATCH /buckets/{bucket_name}/uploads/{upload_id}
+/// Appends the request body to an upload at `Upload-Offset`. The request that
+/// delivers the last byte stores the object.
+///
+/// # Arguments
+///
+/// * `req` - The HTTP request carrying the tus headers.
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `bandwidth` - The upload rate limits the body is read under.
+/// * `memory` - The memory budget the body is held under.
+/// * `progress` - The progress counters the arriving bytes are counted in.
+/// * `path` - The path to the upload.
+/// * `namespace` - The namespace the bucket belongs to.
+/// * `payload` - The bytes to append.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    patch,
+    path = "/buckets/{bucket_name}/uploads/{upload_id}",
+    tag = "uploads",
+    params(UploadPath),
+    request_body(content = ObjectData, content_type = "application/offset+octet-stream"),
+    responses(
+        (status = 204, description = "The bytes were appended.", headers(("Upload-Offset" = u64))),
+    )
+)]
+#[tracing::instrument(
+    name = "Patch upload",
+    skip(s3_service, bandwidth, memory, progress, payload, req),
+    fields(bucket = %path.0, upload_id = %path.1)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn patch_upload_handler(
+    req: HttpRequest,
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    bandwidth: web::Data<Arc<Bandwidth>>,
+    memory: web::Data<Arc<MemoryBudget>>,
+    progress: web::Data<Arc<UploadProgress>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+    mut payload: web::Payload,
+) -> Result<HttpResponse, S3Error> {
+    if let Some(response) = tus_version_mismatch(&req) {
+        return Ok(response);
+    }
+    let _slot = admit_upload(&req).await?;
+    let mut reservation = admit_body(&req, &memory)?;
+    if header_str(&req, CONTENT_TYPE.as_str()) != Some(OFFSET_CONTENT_TYPE) {
+        return Ok(HttpResponse::UnsupportedMediaType()
+            .insert_header((TUS_RESUMABLE_HEADER, TUS_VERSION))
+            .finish());
+    }
+    let (bucket_name, upload_id) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let offset = parse_length_header(&req, UPLOAD_OFFSET_HEADER)?;
+
+    // Keep whatever arrived before a broken connection so the client can resume after it
+    let pacer = bandwidth.upload_pacer();
+    let mut body = Vec::new();
+    let mut read_error = None;
+    let idle = timeout::body_idle_limit(&req);
+    loop {
+        let chunk = match timeout::next_within(&mut payload, idle).await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            // A stalled client is treated like a broken connection
+            Err(e) => {
+                read_error = Some(e);
+                break;
+            }
+        };
+        match chunk {
+            Ok(chunk) => {
+                pacer.pace(chunk.len() as u64).await;
+                body.extend_from_slice(&chunk);
+                reservation.grow_to(body.len() as u64);
+                progress.receive(&upload_id, chunk.len() as u64, Instant::now());
+            }
+            Err(e) => {
+                read_error = Some(S3Error::InvalidRequest(format!(
+                    "Failed to read request body: {}",
+                    e
+                )));
+                break;
+            }
+        }
+    }
+
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.append_upload(&bucket, &upload_id, offset, &body).await
+    };
+    match &result {
+        Ok((_, Some(_))) => progress.remove(&upload_id),
+        _ => progress.finish_part(&upload_id, result.is_ok() && read_error.is_none()),
+    }
+    if let Some(e) = read_error {
+        error!(error = %e, "Upload interrupted");
+        return Err(e);
+    }
+    match result {
+        Ok((upload, object)) => {
+            let mut response = HttpResponse::NoContent();
+            response
+                .insert_header((TUS_RESUMABLE_HEADER, TUS_VERSION))
+                .insert_header((UPLOAD_OFFSET_HEADER, upload.offset.to_string()));
+            if let Some(object) = object {
+                info!(
+                    "Upload '{}' completed as object '{}' in bucket '{}'.",
+                    upload_id, object.key, bucket_name
+                );
+                if let Some(version_id) = &object.version_id {
+                    response.insert_header((VERSION_ID_HEADER, version_id.as_str()));
+                }
+            }
+            Ok(response.finish())
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to append to upload");
+            Err(e)
+        }
+    }
+}
+
+/// Handles DELETE /buckets/{bucket_name}/uploads/{upload_id}
+/// Abandons an upload and discards the bytes received so far.
+///
+/// # Arguments
+///
+/// * `req` - The HTTP request carrying the tus headers.
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `progress` - The progress counters the upload is dropped from.
+/// * `path` - The path to the upload.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    delete,
+    path = "/buckets/{bucket_name}/uploads/{upload_id}",
+    tag = "uploads",
+    params(UploadPath),
+    responses(
+        (status = 204, description = "The upload was abandoned."),
+    )
+)]
+pub async fn delete_upload_handler(
+    req: HttpRequest,
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    progress: web::Data<Arc<UploadProgress>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    if let Some(response) = tus_version_mismatch(&req) {
+        return Ok(response);
+    }
+    let (bucket_name, upload_id) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.delete_upload(&bucket, &upload_id).await
+    };
+    match result {
+        Ok(()) => {
+            progress.remove(&upload_id);
+            info!(
+                "Upload '{}' in bucket '{}' terminated.",
+                upload_id, bucket_name
+            );
+            Ok(HttpResponse::NoContent()
+                .insert_header((TUS_RESUMABLE_HEADER, TUS_VERSION))
+                .finish())
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to delete upload");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /buckets/{bucket_name}/uploads/{upload_id}/progress
+/// Reports the bytes of an upload received so far, including those of a PATCH
+/// request still arriving, the parts completed and the estimated time left.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `progress` - The progress counters of the uploads being received.
+/// * `path` - The path to the upload.
+/// * `namespace` - The namespace the bucket belongs to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/buckets/{bucket_name}/uploads/{upload_id}/progress",
+    tag = "uploads",
+    params(UploadPath),
+    responses(
+        (status = 200, description = "The progress of the upload.", body = Progress),
+    )
+)]
+pub async fn upload_progress_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    progress: web::Data<Arc<UploadProgress>>,
+    path: web::Path<(String, String)>,
+    namespace: Namespace,
+) -> Result<HttpResponse, S3Error> {
+    let (bucket_name, upload_id) = path.into_inner();
+    let bucket = namespace.bucket(&bucket_name)?;
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.get_upload(&bucket, &upload_id).await
+    };
+    match result {
+        Ok(upload) => Ok(HttpResponse::Ok()
+            .insert_header((CACHE_CONTROL, "no-store"))
+            .negotiated(progress.progress(&upload, Instant::now()))),
+        Err(e) => {
+            error!(error = %e, "Failed to get upload progress");
+            Err(e)
+        }
+    }
+}
+
+// --- Operational handlers ---
+
+/// Handles GET /metrics
+/// Exposes the service counters in the Prometheus text format.
+///
+/// # Arguments
+///
+/// * `metrics` - A reference to the shared Metrics instance.
+///
+/// # Returns
+///
+/// * `HttpResponse` - The HTTP response.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The counters in the Prometheus text format.", content_type = "text/plain", body = String),
+    )
+)]
+pub async fn metrics_handler(metrics: web::Data<Arc<Metrics>>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((CONTENT_TYPE, "text/plain; version=0.0.4"))
+        .body(metrics.render())
+}
+
+// --- Admin handlers ---
+
+/// Handles POST /admin/cache/warm
+/// Loads the objects of a bucket matching a key prefix into the object cache,
+/// e.g. ahead of an expected traffic spike.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `request` - The bucket and key prefix to load.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    post,
+    path = "/admin/cache/warm",
+    tag = "admin",
+    request_body = CacheWarmRequest,
+    responses(
+        (status = 200, description = "The objects loaded into the cache.", body = CacheWarmReport),
+    )
+)]
+pub async fn warm_cache_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    request: web::Json<CacheWarmRequest>,
+) -> Result<HttpResponse, S3Error> {
+    let request = request.into_inner();
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.warm_cache(&request.bucket, &request.prefix).await
+    };
+    match result {
+        Ok(report) => {
+            info!(
+                objects = report.objects,
+                bytes = report.bytes,
+                skipped = report.skipped,
+                "Warmed cache from bucket '{}' with prefix '{}'.",
+                request.bucket,
+                request.prefix
+            );
+            Ok(HttpResponse::Ok().negotiated(report))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to warm cache");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /admin/cache/pins
+/// Lists the keys and prefixes pinned in the object cache.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/admin/cache/pins",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The pinned keys and prefixes.", body = Vec<CachePin>),
+    )
+)]
+pub async fn list_cache_pins_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+) -> Result<HttpResponse, S3Error> {
+    let result = {
+        let s3 = s3_service.lock().await;
+        s3.list_cache_pins().await
+    };
+    match result {
+        Ok(pins) => Ok(HttpResponse::Ok().negotiated(pins)),
+        Err(e) => {
+            error!(error = %e, "Failed to list cache pins");
+            Err(e)
+        }
+    }
+}
+
+/// Handles PUT /admin/cache/pins
+/// Pins a key (or, with `"prefix": true`, all keys starting with it) so its
+/// objects are loaded and never evicted from the object cache. Pins survive
+/// restarts.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `pin` - The key or prefix to pin.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/admin/cache/pins",
+    tag = "admin",
+    request_body = CachePin,
+    responses(
+        (status = 200, description = "The objects loaded for the pin.", body = CacheWarmReport),
+    )
+)]
+pub async fn put_cache_pin_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    pin: web::Json<CachePin>,
+) -> Result<HttpResponse, S3Error> {
+    let pin = pin.into_inner();
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.pin_cache(pin.clone()).await
+    };
+    match result {
+        Ok(report) => {
+            info!(
+                objects = report.objects,
+                "Pinned '{}' in bucket '{}' in the cache.", pin.key, pin.bucket
+            );
+            Ok(HttpResponse::Ok().negotiated(report))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to pin cache entry");
+            Err(e)
+        }
+    }
+}
+
+/// Handles DELETE /admin/cache/pins
+/// Unpins a key or prefix; its objects become evictable again.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `pin` - The key or prefix to unpin.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    delete,
+    path = "/admin/cache/pins",
+    tag = "admin",
+    request_body = CachePin,
+    responses(
+        (status = 204, description = "The pin was removed."),
+    )
+)]
+pub async fn delete_cache_pin_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    pin: web::Json<CachePin>,
+) -> Result<HttpResponse, S3Error> {
+    let pin = pin.into_inner();
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.unpin_cache(pin.clone()).await
+    };
+    match result {
+        Ok(()) => {
+            info!(
+                "Unpinned '{}' in bucket '{}' from the cache.",
+                pin.key, pin.bucket
+            );
+            Ok(HttpResponse::NoContent().finish())
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to unpin cache entry");
+            Err(e)
+        }
+    }
+}
+
+/// Handles GET /admin/cache/stats
+/// Returns the hit ratio and occupancy of the object cache.
+///
+/// # Arguments
+///
+/// * `cache` - A reference to the shared ObjectCache instance.
+///
+/// # Returns
+///
+/// * `HttpResponse` - The HTTP response.
+#[utoipa::path(
+    get,
+    path = "/admin/cache/stats",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The counters of the cache.", body = CacheStats),
+    )
+)]
+pub async fn cache_stats_handler(cache: web::Data<Arc<ObjectCache>>) -> HttpResponse {
+    HttpResponse::Ok().negotiated(cache.stats())
+}
+
+/// Handles POST /graphql
+/// Runs a read-only query over buckets, objects, their tags and versions,
+/// and the space they use. The verified token's principal, if any, decides
+/// which buckets the query sees.
+///
+/// # Arguments
+///
+/// * `schema` - The schema queries run against.
+/// * `req` - The HTTP request, carrying the principal.
+/// * `body` - The GraphQL request.
+///
+/// # Returns
+///
+/// * `HttpResponse` - The GraphQL response, errors included.
+#[utoipa::path(
+    post,
+    path = "/graphql",
+    tag = "metadata",
+    request_body = GraphqlRequest,
+    responses(
+        (status = 200, description = "The result of the query.", body = GraphqlResponse),
+    )
+)]
+pub async fn graphql_handler(
+    schema: web::Data<MetadataSchema>,
+    req: HttpRequest,
+    body: web::Json<async_graphql::Request>,
+) -> HttpResponse {
+    let mut request = body.into_inner();
+    if let Some(principal) = req.extensions().get::<Principal>() {
+        request = request.data(principal.clone());
+    }
+    let response = schema.execute(request).await;
+    HttpResponse::Ok().negotiated(&response)
+}
+
+/// Handles GET /admin/stats
+/// Returns the space used by every bucket, or by the one named in `bucket`,
+/// from the maintained counters.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `query` - The optional bucket to report on.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/admin/stats",
+    tag = "admin",
+    params(StatsQuery),
+    responses(
+        (status = 200, description = "The space used.", body = UsageStats),
+    )
+)]
+pub async fn usage_stats_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    query: web::Query<StatsQuery>,
+) -> Result<HttpResponse, S3Error> {
+    let s3 = s3_service.lock().await;
+    let result = match query.into_inner().bucket {
+        Some(bucket) => s3
+            .get_bucket_usage(&bucket)
+            .await
+            .map(|usage| HttpResponse::Ok().negotiated(NamedUsage { bucket, usage })),
+        None => s3
+            .get_usage_report()
+            .await
+            .map(|report| HttpResponse::Ok().negotiated(report)),
+    };
+    result.map_err(|e| {
+        error!(error = %e, "Failed to get usage statistics");
+        e
+    })
+}
+
+/// Handles GET /admin/read-only
+/// Reports whether mutating requests are refused.
+///
+/// # Arguments
+///
+/// * `mode` - A reference to the shared ReadOnlyMode instance.
+///
+/// # Returns
+///
+/// * `HttpResponse` - The HTTP response.
+#[utoipa::path(
+    get,
+    path = "/admin/read-only",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Whether read-only mode is on.", body = ReadOnlyStatus),
+    )
+)]
+pub async fn get_read_only_handler(mode: web::Data<Arc<ReadOnlyMode>>) -> HttpResponse {
+    HttpResponse::Ok().negotiated(ReadOnlyStatus {
+        read_only: mode.is_enabled(),
+    })
+}
+
+/// Handles POST /admin/read-only
+/// Switches read-only mode on or off.
+///
+/// # Arguments
+///
+/// * `mode` - A reference to the shared ReadOnlyMode instance.
+/// * `request` - The requested mode.
+///
+/// # Returns
+///
+/// * `HttpResponse` - The HTTP response.
+#[utoipa::path(
+    post,
+    path = "/admin/read-only",
+    tag = "admin",
+    request_body = ReadOnlyStatus,
+    responses(
+        (status = 200, description = "The mode was switched.", body = ReadOnlyStatus),
+    )
+)]
+pub async fn set_read_only_handler(
+    mode: web::Data<Arc<ReadOnlyMode>>,
+    request: web::Json<ReadOnlyStatus>,
+) -> HttpResponse {
+    mode.set(request.read_only);
+    if request.read_only {
+        warn!("Read-only mode enabled, refusing mutating requests");
+    } else {
+        info!("Read-only mode disabled, accepting mutating requests");
+    }
+    HttpResponse::Ok().negotiated(ReadOnlyStatus {
+        read_only: mode.is_enabled(),
+    })
+}
+
+/// Handles GET /admin/maintenance
+/// Reports whether data path requests are refused for maintenance.
+///
+/// # Arguments
+///
+/// * `mode` - A reference to the shared MaintenanceMode instance.
+///
+/// # Returns
+///
+/// * `HttpResponse` - The HTTP response.
+#[utoipa::path(
+    get,
+    path = "/admin/maintenance",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Whether maintenance mode is on.", body = MaintenanceStatus),
+    )
+)]
+pub async fn get_maintenance_handler(mode: web::Data<Arc<MaintenanceMode>>) -> HttpResponse {
+    HttpResponse::Ok().negotiated(mode.status())
+}
+
+/// Handles POST /admin/maintenance
+/// Switches maintenance mode on or off, with the reason and Retry-After
+/// given to refused clients.
+///
+/// # Arguments
+///
+/// * `mode` - A reference to the shared MaintenanceMode instance.
+/// * `request` - The requested mode.
+///
+/// # Returns
+///
+/// * `HttpResponse` - The HTTP response.
+#[utoipa::path(
+    post,
+    path = "/admin/maintenance",
+    tag = "admin",
+    request_body = MaintenanceStatus,
+    responses(
+        (status = 200, description = "The mode was switched.", body = MaintenanceStatus),
+    )
+)]
+pub async fn set_maintenance_handler(
+    mode: web::Data<Arc<MaintenanceMode>>,
+    request: web::Json<MaintenanceStatus>,
+) -> HttpResponse {
+    let status = request.into_inner();
+    if status.enabled {
+        warn!(
+            reason = status.reason.as_deref().unwrap_or("-"),
+            retry_after_secs = status.retry_after_secs,
+            "Maintenance mode enabled, refusing data path requests"
+        );
+    } else {
+        info!("Maintenance mode disabled, serving data path requests");
+    }
+    mode.set(status);
+    HttpResponse::Ok().negotiated(mode.status())
+}
+
+/// Handles GET /admin/log-level
+/// Reports the current log filter directives.
+///
+/// # Arguments
+///
+/// * `log_control` - A reference to the shared LogControl instance.
+///
+/// # Returns
+///
+/// * `HttpResponse` - The HTTP response.
+#[utoipa::path(
+    get,
+    path = "/admin/log-level",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The log filter directives.", body = LogLevel),
+    )
+)]
+pub async fn get_log_level_handler(log_control: web::Data<Arc<LogControl>>) -> HttpResponse {
+    HttpResponse::Ok().negotiated(LogLevel {
+        level: log_control.level(),
+    })
+}
+
+/// Handles PUT /admin/log-level
+/// Replaces the log filter directives of the running server.
+///
+/// # Arguments
+///
+/// * `log_control` - A reference to the shared LogControl instance.
+/// * `request` - The new directives.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    put,
+    path = "/admin/log-level",
+    tag = "admin",
+    request_body = LogLevel,
+    responses(
+        (status = 200, description = "The directives were applied.", body = LogLevel),
+    )
+)]
+pub async fn set_log_level_handler(
+    log_control: web::Data<Arc<LogControl>>,
+    request: web::Json<LogLevel>,
+) -> Result<HttpResponse, S3Error> {
+    log_control
+        .set_level(&request.level)
+        .map_err(|e| S3Error::InvalidRequest(e.to_string()))?;
+    warn!(level = request.level, "Log level changed");
+    Ok(HttpResponse::Ok().negotiated(LogLevel {
+        level: log_control.level(),
+    }))
+}
+
+/// Handles POST /admin/reload
+/// Reads the configuration file again and applies the reloadable settings.
+///
+/// # Arguments
+///
+/// * `reloader` - A reference to the shared ConfigReloader instance.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    post,
+    path = "/admin/reload",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The settings reloaded.", body = ReloadReport),
+    )
+)]
+pub async fn reload_config_handler(
+    reloader: web::Data<Arc<ConfigReloader>>,
+) -> Result<HttpResponse, S3Error> {
+    let report = reloader.reload().map_err(|e| {
+        error!("Failed to reload configuration: {}", e);
+        S3Error::InvalidRequest(format!("Configuration not reloaded: {}", e))
+    })?;
+    Ok(HttpResponse::Ok().negotiated(report))
+}
+
+/// Handles POST /admin/db/backup?dest=...
+/// Starts an online backup of the metadata database to `dest`.
+///
+/// # Arguments
+///
+/// * `backup` - A reference to the shared DbBackup instance.
+/// * `query` - The path to write the backup to.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    post,
+    path = "/admin/db/backup",
+    tag = "admin",
+    params(BackupQuery),
+    responses(
+        (status = 202, description = "The backup was started.", body = BackupStatus),
+    )
+)]
+pub async fn start_db_backup_handler(
+    backup: web::Data<Arc<DbBackup>>,
+    query: web::Query<BackupQuery>,
+) -> Result<HttpResponse, S3Error> {
+    match backup.start(&query.dest) {
+        Ok(status) => {
+            info!("Started database backup to '{}'.", query.dest);
+            Ok(HttpResponse::Accepted().negotiated(status))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to start database backup");
+            Err(S3Error::BackupConflict(e.to_string()))
+        }
+    }
+}
+
+/// Handles GET /admin/db/backup
+/// Reports the progress of the running or most recent database backup.
+///
+/// # Arguments
+///
+/// * `backup` - A reference to the shared DbBackup instance.
+///
+/// # Returns
+///
+/// * `HttpResponse` - The HTTP response.
+#[utoipa::path(
+    get,
+    path = "/admin/db/backup",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The progress of the backup.", body = BackupStatus),
+    )
+)]
+pub async fn db_backup_status_handler(backup: web::Data<Arc<DbBackup>>) -> HttpResponse {
+    HttpResponse::Ok().negotiated(backup.status())
+}
+
+/// Handles POST /admin/restore?bucket=...&at=...
+/// Restores a versioned bucket to its state at a point in time.
+///
+/// # Arguments
+///
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `query` - The bucket and the point in time, in Unix seconds.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    post,
+    path = "/admin/restore",
+    tag = "admin",
+    params(RestoreQuery),
+    responses(
+        (status = 200, description = "The bucket was restored.", body = BucketRestoreResponse),
+    )
+)]
+pub async fn restore_bucket_handler(
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    query: web::Query<RestoreQuery>,
+) -> Result<HttpResponse, S3Error> {
+    let RestoreQuery { bucket, at } = query.into_inner();
+    let result = {
+        let mut s3 = s3_service.lock().await;
+        s3.restore_bucket(&bucket, at).await
+    };
+    match result {
+        Ok(report) => {
+            info!(
+                restored = report.restored.len(),
+                deleted = report.deleted.len(),
+                skipped = report.skipped.len(),
+                "Restored bucket '{}' to {}.",
+                bucket,
+                at
+            );
+            Ok(HttpResponse::Ok().negotiated(BucketRestoreResponse { bucket, at, report }))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to restore bucket");
+            Err(e)
+        }
+    }
+}
+
+/// Handles POST /buckets/{bucket_name}?copy-from={source}&prefix=...
+/// Starts copying every object under the prefix from the source bucket into
+/// this one in the background. Keys keep their names; existing objects are
+/// overwritten.
+///
+/// # Arguments
+///
+/// * `req` - The request, carrying the caller's principal.
+/// * `s3_service` - A reference to the S3Service instance.
+/// * `jobs` - A reference to the shared CopyJobs instance.
+/// * `bucket_roles` - The roles and grants of the buckets that have any.
+/// * `path` - The path to the destination bucket.
+/// * `namespace` - The namespace both buckets belong to.
+/// * `query` - The source bucket and the key prefix.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    post,
+    path = "/buckets/{bucket_name}?copy-from",
+    tag = "buckets",
+    params(BucketPath, CopyQuery),
+    responses(
+        (status = 202, description = "The copy job was started.", body = CopyStatus, headers(("Location" = String, description = "Where the job's progress is reported."))),
+    )
+)]
+pub async fn start_copy_handler(
+    req: HttpRequest,
+    s3_service: web::Data<Arc<Mutex<S3Service>>>,
+    jobs: web::Data<Arc<CopyJobs>>,
+    bucket_roles: web::Data<Arc<BucketRoles>>,
+    path: web::Path<String>,
+    namespace: Namespace,
+    query: web::Query<CopyQuery>,
+) -> Result<HttpResponse, S3Error> {
+    let bucket_name = path.into_inner();
+    let destination = namespace.bucket(&bucket_name)?;
+    let CopyQuery { copy_from, prefix } = query.into_inner();
+    let source = namespace.bucket(&copy_from)?;
+
+    // The roles middleware checked the destination; the source is read
+    let resolved = s3_service.lock().await.resolve_bucket(&source).await?;
+    let principal = req.extensions().get::<Principal>().cloned();
+    if !bucket_roles.allows(
+        resolved.as_deref().unwrap_or(&source),
+        principal.as_ref(),
+        Access::Read,
+    ) {
+        return Err(S3Error::AccessDenied(
+            "No role on the source bucket grants reading it.".to_string(),
+        ));
+    }
+    match jobs.start(&source, &destination, &prefix).await {
+        Ok(status) => {
+            info!(
+                objects = status.total_objects,
+                "Started copy job '{}' from bucket '{}' to bucket '{}' with prefix '{}'.",
+                status.id,
+                copy_from,
+                bucket_name,
+                prefix
+            );
+            Ok(HttpResponse::Accepted()
+                .insert_header((LOCATION, format!("/admin/copy/{}", status.id)))
+                .negotiated(status))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to start copy job");
+            Err(match e {
+                CopyError::BucketNotFound(bucket) => S3Error::BucketNotFound(bucket),
+                CopyError::SameBucket(_) => S3Error::InvalidRequest(e.to_string()),
+                CopyError::Storage(e) => storage_error(e, "Failed to start copy job"),
+            })
+        }
+    }
+}
+
+/// Handles GET /admin/copy/{id}
+/// Reports the progress of a copy job.
+///
+/// # Arguments
+///
+/// * `jobs` - A reference to the shared CopyJobs instance.
+/// * `path` - The id of the job.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    get,
+    path = "/admin/copy/{id}",
+    tag = "admin",
+    params(CopyJobPath),
+    responses(
+        (status = 200, description = "The progress of the copy job.", body = CopyStatus),
+    )
+)]
+pub async fn copy_status_handler(
+    jobs: web::Data<Arc<CopyJobs>>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, S3Error> {
+    let id = path.into_inner();
+    match jobs.status(&id) {
+        Some(status) => Ok(HttpResponse::Ok().negotiated(status)),
+        None => Err(S3Error::CopyJobNotFound(id)),
+    }
+}
+
+/// Handles POST /admin/rehash
+/// Starts converting the checksums of all objects to the configured algorithm.
+///
+/// # Arguments
+///
+/// * `job` - A reference to the shared RehashJob instance.
+///
+/// # Returns
+///
+/// * `Result<HttpResponse, S3Error>` - The HTTP response, or an error.
+#[utoipa::path(
+    post,
+    path = "/admin/rehash",
+    tag = "admin",
+    responses(
+        (status = 202, description = "The re-hash job was started.", body = RehashStatus),
+    )
+)]
+pub async fn start_rehash_handler(job: web::Data<Arc<RehashJob>>) -> Result<HttpResponse, S3Error> {
+    match job.start() {
+        Ok(status) => {
+            info!("Started re-hash job.");
+            Ok(HttpResponse::Accepted().negotiated(status))
+        }
+        Err(e) => {
+            error!(error = %e, "Failed to start re-hash job");
+            Err(S3Error::RehashConflict(e.to_string()))
+        }
+    }
+}
+
+/// Handles GET /admin/rehash
+/// Reports the progress and estimated completion of the re-hash job.
+///
+/// # Arguments
+///
+/// * `job` - A reference to the shared RehashJob instance.
+///
+/// # Returns
+///
+/// * `HttpResponse` - The HTTP response.
+#[utoipa::path(
+    get,
+    path = "/admin/rehash",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The progress of the re-hash job.", body = RehashStatus),
+    )
+)]
+pub async fn rehash_status_handler(job: web::Data<Arc<RehashJob>>) -> HttpResponse {
+    HttpResponse::Ok().negotiated(job.status())
+}
@@ -0,0 +1,269 @@
+// http_client.rs
+// A minimal HTTP/1.1 client over a plain TCP connection, one connection per
+// request, for webhook deliveries, replication, fetching secrets and keys,
+// the embedded test server and the benchmark. It needs no HTTP client
+// dependency; it reads responses up to the end of the connection and decodes
+// chunked bodies. Connecting, sending and reading are each bounded in time,
+// so a peer that hangs fails the request instead of stalling the caller.
+
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time;
+
+/// How long a request may take, step by step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    /// Establishing the connection.
+    pub connect: Duration,
+    /// Sending the request, and then reading the response, each.
+    pub io: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(5),
+            io: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A response, read in full.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    /// Header names in lower case, in the order received.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// The first value of the header `name`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        serde_json::from_slice(&self.body)
+    }
+}
+
+/// Sends a request and reads the whole response, within the default
+/// `Timeouts`.
+///
+/// # Arguments
+///
+/// * `authority` - The `host:port` to connect to.
+/// * `method` - The request method, e.g. `PUT`.
+/// * `path` - The path and query, percent-encoded.
+/// * `headers` - Headers besides Host, Content-Length and Connection.
+/// * `body` - The request body, possibly empty.
+///
+/// # Returns
+///
+/// * `io::Result<Response>` - The response, or the error exchanging it.
+pub async fn send(
+    authority: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> io::Result<Response> {
+    send_within(Timeouts::default(), authority, method, path, headers, body).await
+}
+
+/// Sends a request and reads the whole response, as `send` does, failing
+/// with `TimedOut` once a step takes longer than `timeouts` allow.
+pub async fn send_within(
+    timeouts: Timeouts,
+    authority: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> io::Result<Response> {
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        method,
+        path,
+        authority,
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+
+    let mut stream = within(
+        timeouts.connect,
+        "connecting",
+        TcpStream::connect(authority),
+    )
+    .await?;
+    within(timeouts.io, "sending the request", async {
+        stream.write_all(request.as_bytes()).await?;
+        stream.write_all(body).await
+    })
+    .await?;
+    let mut response = Vec::new();
+    within(
+        timeouts.io,
+        "reading the response",
+        stream.read_to_end(&mut response),
+    )
+    .await?;
+    parse_response(&response)
+}
+
+/// Runs `step`, failing with `TimedOut` if it takes longer than `limit`.
+async fn within<T>(
+    limit: Duration,
+    what: &str,
+    step: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    time::timeout(limit, step).await.map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("Timed out {} after {:?}", what, limit),
+        )
+    })?
+}
+
+/// Splits an `http://` URL into its authority and path.
+pub fn split_url(url: &str) -> Option<(&str, &str)> {
+    let rest = url.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    (!authority.is_empty()).then_some((authority, path))
+}
+
+/// Whether the host of an authority (`host:port`) is the loopback
+/// interface, where plain HTTP never leaves the machine.
+pub fn is_loopback(authority: &str) -> bool {
+    let host = match authority.rsplit_once(':') {
+        // A bracketed IPv6 address, with or without a port
+        _ if authority.starts_with('[') => authority[1..].split(']').next().unwrap_or(""),
+        Some((host, port)) if port.chars().all(|c| c.is_ascii_digit()) => host,
+        _ => authority,
+    };
+    host.eq_ignore_ascii_case("localhost")
+        || host
+            .parse::<std::net::IpAddr>()
+            .is_ok_and(|ip| ip.is_loopback())
+}
+
+/// Parses a response read up to the end of its connection.
+fn parse_response(response: &[u8]) -> io::Result<Response> {
+    let invalid = |what: &str| io::Error::new(io::ErrorKind::InvalidData, what.to_string());
+    let head_end = response
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| invalid("Response without an end of headers"))?;
+    let head = String::from_utf8_lossy(&response[..head_end]);
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| invalid("Response without a status"))?;
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let body = &response[head_end + 4..];
+    let chunked = headers
+        .iter()
+        .any(|(name, value)| name == "transfer-encoding" && value.eq_ignore_ascii_case("chunked"));
+    let body = match chunked {
+        true => decode_chunked(body).ok_or_else(|| invalid("Malformed chunked body"))?,
+        false => body.to_vec(),
+    };
+    Ok(Response {
+        status,
+        headers,
+        body,
+    })
+}
+
+fn decode_chunked(mut body: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body.windows(2).position(|w| w == b"\r\n")?;
+        let size = std::str::from_utf8(&body[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Some(decoded);
+        }
+        decoded.extend_from_slice(body.get(..size)?);
+        body = body.get(size + 2..)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_chunked() {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\n\r\n";
+        let response = parse_response(response).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.header("Transfer-Encoding"), Some("chunked"));
+        assert_eq!(response.body, b"abcde");
+    }
+
+    #[test]
+    fn test_split_url() {
+        assert_eq!(
+            split_url("http://hooks.local:9000/s3/events"),
+            Some(("hooks.local:9000", "/s3/events"))
+        );
+        assert_eq!(
+            split_url("http://hooks.local:9000"),
+            Some(("hooks.local:9000", "/"))
+        );
+        assert_eq!(split_url("https://hooks.local/events"), None);
+        assert_eq!(split_url("http:///events"), None);
+    }
+
+    #[test]
+    fn test_is_loopback() {
+        assert!(is_loopback("127.0.0.1:8200"));
+        assert!(is_loopback("localhost:8200"));
+        assert!(is_loopback("[::1]:8200"));
+        assert!(is_loopback("127.0.0.2"));
+        assert!(!is_loopback("vault.local:8200"));
+        assert!(!is_loopback("10.0.0.1:8200"));
+        assert!(!is_loopback("[2001:db8::1]:8200"));
+    }
+
+    #[tokio::test]
+    async fn test_send_times_out() {
+        // A peer that accepts the request and never answers
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let authority = listener.local_addr().unwrap().to_string();
+        let peer = tokio::spawn(async move { listener.accept().await });
+        let timeouts = Timeouts {
+            connect: Duration::from_secs(5),
+            io: Duration::from_millis(50),
+        };
+        let result = send_within(timeouts, &authority, "GET", "/", &[], b"").await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        drop(peer);
+    }
+}
@@ -1,17 +1,90 @@
+pub mod access;
+pub mod access_log;
+pub mod acl;
+pub mod app;
+pub mod aws_chunked;
 pub mod background;
+pub mod backpressure;
+pub mod backup;
+pub mod bandwidth;
+pub mod bench;
 pub mod bucket;
+pub mod bucket_logging;
+pub mod cache;
+pub mod circuit;
+pub mod clock;
+pub mod config;
+pub mod content_type;
+pub mod copy;
+pub mod disk;
+pub mod error_code;
+pub mod expect;
+#[cfg(feature = "faults")]
+pub mod faults;
+pub mod folder;
+pub mod generators;
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod guards;
 pub mod handlers;
+pub mod http_client;
+pub mod jwt;
+pub mod log_control;
+pub mod log_file;
+pub mod maintenance;
+pub mod memory;
+pub mod metadata;
+pub mod metrics;
+pub mod namespace;
+pub mod negotiation;
+pub mod notifications;
 pub mod object;
+pub mod openapi;
+pub mod placement;
+pub mod post_policy;
+pub mod range;
+pub mod read_only;
+pub mod rehash;
+pub mod reload;
+pub mod replication;
+pub mod roles;
 pub mod s3_service;
+pub mod secrets;
+pub mod seed;
+pub mod share;
+pub mod signing;
+pub mod simulation;
 pub mod storage;
+pub mod storage_trace;
 pub mod structs;
+pub mod systemd;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod throttle;
+pub mod timeout;
+pub mod tus;
+pub mod upload_progress;
+pub mod upload_slots;
+pub mod usage;
 
 // re-export the types
+pub use access::AccessTracker;
 pub use background::ConsistencyChecker;
+pub use background::DiskMonitor;
+pub use background::TransitionWorker;
+pub use bandwidth::Bandwidth;
 pub use bucket::Bucket;
 pub use bucket::BucketError;
+pub use bucket::LifecycleRule;
+pub use bucket::VersioningStatus;
+pub use cache::ObjectCache;
+pub use config::Config;
+pub use metrics::Metrics;
 pub use object::Object;
+pub use replication::Replicator;
 pub use s3_service::S3Error;
 pub use s3_service::S3Service;
 pub use storage::Storage;
 pub use storage::StorageError;
+pub use throttle::Throttle;
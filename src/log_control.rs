@@ -0,0 +1,148 @@
+// log_control.rs
+// Set-up of logging and changes to it at runtime. Records are formatted as
+// JSON or as pretty text, to stdout or a rotating file, and filtered by
+// directives like `info,s3_learning_project=debug`. The filter sits behind a
+// reload layer, so operators can turn up logging on a live instance with
+// PUT /admin/log-level or a configuration reload.
+
+use std::io;
+use thiserror::Error;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::filter::ParseError;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{EnvFilter, Registry, fmt, reload};
+
+use crate::config::{LogFormat, LoggingConfig};
+use crate::log_file;
+
+/// Custom error type for changing the log filter.
+#[derive(Debug, Error)]
+pub enum LogLevelError {
+    #[error("Invalid log level '{0}': {1}")]
+    Invalid(String, ParseError),
+    #[error("Failed to change the log level: {0}")]
+    Reload(#[from] reload::Error),
+}
+
+/// The log filter of the running process.
+#[derive(Debug)]
+pub struct LogControl {
+    handle: reload::Handle<EnvFilter, Registry>,
+    /// Whether the filter came from `RUST_LOG`, which then takes precedence
+    /// over the configured level on reloads.
+    from_env: bool,
+    /// Flushes the log file, if any, when the process exits.
+    _file_guard: Option<WorkerGuard>,
+}
+
+impl LogControl {
+    /// Installs the global subscriber. `RUST_LOG` takes precedence over the
+    /// configured level.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The logging settings.
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<Self>` - The control of the filter, or the error opening the log file.
+    pub fn init(config: &LoggingConfig) -> io::Result<Self> {
+        let from_env = std::env::var(EnvFilter::DEFAULT_ENV).is_ok();
+        let filter = match from_env {
+            true => EnvFilter::from_default_env(),
+            false => EnvFilter::try_new(&config.level)
+                .map_err(|e| io::Error::other(format!("Invalid log level: {}", e)))?,
+        };
+        let (filter, handle) = reload::Layer::new(filter);
+
+        let (writer, file_guard) = match &config.file {
+            Some(path) => {
+                let (file, guard) = log_file::open_non_blocking(
+                    path,
+                    config.rotation,
+                    config.max_bytes,
+                    config.max_files,
+                )?;
+                (BoxMakeWriter::new(file), Some(guard))
+            }
+            None => (BoxMakeWriter::new(io::stdout), None),
+        };
+        let layer = fmt::layer().with_target(false).with_writer(writer);
+        // Set after the format, as the pretty one turns source locations on
+        let layer = match config.format {
+            LogFormat::Json => layer
+                .json()
+                .with_file(false)
+                .with_line_number(false)
+                .boxed(),
+            // Colours only where a terminal might show them
+            LogFormat::Pretty => layer
+                .pretty()
+                .with_file(false)
+                .with_line_number(false)
+                .with_ansi(config.file.is_none())
+                .boxed(),
+        };
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(layer)
+            .init();
+        Ok(Self {
+            handle,
+            from_env,
+            _file_guard: file_guard,
+        })
+    }
+
+    /// The current filter directives.
+    pub fn level(&self) -> String {
+        self.handle
+            .with_current(|filter| filter.to_string())
+            .unwrap_or_default()
+    }
+
+    /// Replaces the filter with `directives`, e.g. `debug` or
+    /// `warn,s3_learning_project=trace`.
+    pub fn set_level(&self, directives: &str) -> Result<(), LogLevelError> {
+        let filter = EnvFilter::try_new(directives)
+            .map_err(|e| LogLevelError::Invalid(directives.to_string(), e))?;
+        self.handle.reload(filter)?;
+        Ok(())
+    }
+
+    /// Applies the level of a reloaded configuration, unless the filter
+    /// came from `RUST_LOG`. Returns whether it was applied.
+    pub fn apply_config(&self, config: &LoggingConfig) -> Result<bool, LogLevelError> {
+        if self.from_env {
+            return Ok(false);
+        }
+        self.set_level(&config.level)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_level() {
+        let (_layer, handle) = reload::Layer::new(EnvFilter::new("error"));
+        let control = LogControl {
+            handle,
+            from_env: false,
+            _file_guard: None,
+        };
+        control.set_level("actix_web=info,debug").unwrap();
+        assert_eq!(control.level(), "actix_web=info,debug");
+        assert!(matches!(
+            control.set_level("actix_web=loud"),
+            Err(LogLevelError::Invalid(_, _))
+        ));
+        assert_eq!(control.level(), "actix_web=info,debug");
+
+        assert!(control.apply_config(&LoggingConfig::default()).unwrap());
+        assert_eq!(control.level(), "bucket_activity=info,slow_ops=warn,error");
+    }
+}
@@ -0,0 +1,337 @@
+// log_file.rs
+// Log files, for deployments without a log collector reading stdout.
+// Records go through tracing-appender's non-blocking writer: the thread
+// logging a record only queues it, and a worker thread does the file I/O.
+// Rotation by time alone is tracing-appender's rolling appender, which
+// appends the hour or day (UTC) to the file name, e.g. `s3.log.2026-10-16`,
+// and keeps the configured path as a link to the current file.
+// tracing-appender cannot rotate by size, so a `max_bytes` limit falls back
+// to `RotatingFile` below: it renames the file with the time of rotation
+// appended, e.g. `s3.log.20261016T140533Z`, when the limit is reached or a
+// new hour or day begins. Either way, only the newest `max_files` rotated
+// files are kept.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tracing_appender::non_blocking::{NonBlocking, NonBlockingBuilder, WorkerGuard};
+use tracing_appender::rolling::{self, RollingFileAppender};
+
+use crate::config::Rotation;
+
+/// Opens a log file behind a non-blocking writer.
+///
+/// # Arguments
+///
+/// * `path` - The path of the current log file.
+/// * `rotation` - When the file is rotated regardless of its size.
+/// * `max_bytes` - The size at which the file is rotated, 0 for no limit.
+/// * `max_files` - The rotated files kept, 0 for all.
+///
+/// # Returns
+///
+/// * `io::Result<(NonBlocking, WorkerGuard)>` - The writer and the guard
+///   flushing it when dropped, or the error opening the file.
+pub fn open_non_blocking(
+    path: &Path,
+    rotation: Rotation,
+    max_bytes: u64,
+    max_files: usize,
+) -> io::Result<(NonBlocking, WorkerGuard)> {
+    // Records wait for a disk that falls behind rather than being dropped
+    let writer = NonBlockingBuilder::default()
+        .lossy(false)
+        .thread_name("log-writer");
+    if max_bytes > 0 {
+        let file = RotatingFile::open(path, rotation, max_bytes, max_files)?;
+        return Ok(writer.finish(file));
+    }
+
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| io::Error::other(format!("Invalid log file {}", path.display())))?;
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut appender = RollingFileAppender::builder().filename_prefix(name);
+    appender = match rotation {
+        Rotation::Never => appender.rotation(rolling::Rotation::NEVER),
+        Rotation::Hourly => appender
+            .rotation(rolling::Rotation::HOURLY)
+            .latest_symlink(name),
+        Rotation::Daily => appender
+            .rotation(rolling::Rotation::DAILY)
+            .latest_symlink(name),
+    };
+    if max_files > 0 {
+        // tracing-appender counts the current file too
+        appender = appender.max_log_files(max_files + 1);
+    }
+    let appender = appender.build(dir).map_err(io::Error::other)?;
+    Ok(writer.finish(appender))
+}
+
+/// A log file rotated by size and time, for the limits tracing-appender
+/// lacks.
+#[derive(Debug)]
+struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    max_bytes: u64,
+    max_files: usize,
+    state: Mutex<FileState>,
+}
+
+#[derive(Debug)]
+struct FileState {
+    file: File,
+    size: u64,
+    period: u64,
+}
+
+impl RotatingFile {
+    /// Opens the log file for appending, creating it and its directory.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - The path of the current log file.
+    /// * `rotation` - When the file is rotated regardless of its size.
+    /// * `max_bytes` - The size at which the file is rotated, 0 for no limit.
+    /// * `max_files` - The rotated files kept, 0 for all.
+    ///
+    /// # Returns
+    ///
+    /// * `io::Result<Self>` - The log file, or the error opening it.
+    pub fn open(
+        path: &Path,
+        rotation: Rotation,
+        max_bytes: u64,
+        max_files: usize,
+    ) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+        let file = open_append(path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            rotation,
+            max_bytes,
+            max_files,
+            state: Mutex::new(FileState {
+                file,
+                size,
+                period: rotation.period(now_secs()),
+            }),
+        })
+    }
+
+    /// Whether the file is due for rotation before `len` more bytes.
+    fn due(&self, state: &FileState, len: u64, now: u64) -> bool {
+        let full = self.max_bytes > 0 && state.size > 0 && state.size + len > self.max_bytes;
+        full || self.rotation.period(now) != state.period
+    }
+
+    /// Renames the current file out of the way, starts a new one and drops
+    /// the rotated files beyond `max_files`.
+    fn rotate(&self, state: &mut FileState, now: u64) -> io::Result<()> {
+        state.file.flush()?;
+        let stamped = |n: u32| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{}", utc_stamp(now)));
+            if n > 0 {
+                name.push(format!(".{}", n));
+            }
+            PathBuf::from(name)
+        };
+        let rotated = (0..).map(stamped).find(|path| !path.exists());
+        if let Some(rotated) = rotated {
+            fs::rename(&self.path, rotated)?;
+        }
+        state.file = open_append(&self.path)?;
+        state.size = 0;
+        state.period = self.rotation.period(now);
+        self.remove_old_files()
+    }
+
+    fn remove_old_files(&self) -> io::Result<()> {
+        if self.max_files == 0 {
+            return Ok(());
+        }
+        let Some(name) = self.path.file_name().and_then(|n| n.to_str()) else {
+            return Ok(());
+        };
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let prefix = format!("{}.", name);
+        let mut rotated: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|entry| entry.path())
+            .collect();
+        // Oldest first: by stamp, then by the counter of files rotated
+        // within the same second
+        rotated.sort_by_key(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            let suffix = &name[prefix.len()..];
+            let (stamp, counter) = suffix.split_once('.').unwrap_or((suffix, "0"));
+            (stamp.to_string(), counter.parse::<u32>().unwrap_or(0))
+        });
+        let excess = rotated.len().saturating_sub(self.max_files);
+        for path in &rotated[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for &RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = now_secs();
+        if self.due(&state, buf.len() as u64, now) {
+            self.rotate(&mut state, now)?;
+        }
+        let written = state.file.write(buf)?;
+        state.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .file
+            .flush()
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self).flush()
+    }
+}
+
+impl Rotation {
+    /// The rotation period `secs` since the epoch falls into.
+    fn period(self, secs: u64) -> u64 {
+        match self {
+            Rotation::Never => 0,
+            Rotation::Hourly => secs / 3600,
+            Rotation::Daily => secs / 86400,
+        }
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// A point in time split into its UTC calendar fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UtcTime {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+    pub hour: u32,
+    pub minute: u32,
+    pub second: u32,
+}
+
+impl UtcTime {
+    pub fn from_secs(secs: u64) -> Self {
+        // Civil date from days since the epoch, after Howard Hinnant's algorithm
+        let days = (secs / 86400) as i64 + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days.rem_euclid(146_097);
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let time = secs % 86400;
+        Self {
+            year: year_of_era + era * 400 + i64::from(month <= 2),
+            month: month as u32,
+            day: day as u32,
+            hour: (time / 3600) as u32,
+            minute: (time / 60 % 60) as u32,
+            second: (time % 60) as u32,
+        }
+    }
+}
+
+/// Formats seconds since the epoch as a compact UTC timestamp.
+fn utc_stamp(secs: u64) -> String {
+    let t = UtcTime::from_secs(secs);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        t.year, t.month, t.day, t.hour, t.minute, t.second
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_utc_stamp() {
+        assert_eq!(utc_stamp(0), "19700101T000000Z");
+        assert_eq!(utc_stamp(951_782_400), "20000229T000000Z");
+        assert_eq!(utc_stamp(1_792_109_633), "20261016T001353Z");
+    }
+
+    #[test]
+    fn test_rotates_by_size_and_keeps_max_files() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("s3.log");
+        let log = RotatingFile::open(&path, Rotation::Never, 10, 2).unwrap();
+
+        for _ in 0..5 {
+            (&log).write_all(b"12345678\n").unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "12345678\n");
+        let files = fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(files, 3);
+    }
+
+    #[test]
+    fn test_non_blocking_daily_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("s3.log");
+        let (mut writer, guard) = open_non_blocking(&path, Rotation::Daily, 0, 2).unwrap();
+        writer.write_all(b"first\n").unwrap();
+        writer.write_all(b"second\n").unwrap();
+        // Dropping the guard waits for the queued records
+        drop(guard);
+
+        // The configured path links to the dated file
+        assert_eq!(fs::read_to_string(&path).unwrap(), "first\nsecond\n");
+        assert!(fs::symlink_metadata(&path).unwrap().is_symlink());
+        let dated: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.starts_with("s3.log."))
+            .collect();
+        assert_eq!(dated.len(), 1, "{:?}", dated);
+    }
+}
@@ -1,93 +1,317 @@
 // main.rs
 // This file now sets up an HTTP server to expose the S3-like service.
 
+mod access;
+mod access_log;
+mod acl;
+mod app;
+mod aws_chunked;
 mod background;
+mod backpressure;
+mod backup;
+mod bandwidth;
+mod bench;
 mod bucket; // Declare the bucket module
+mod bucket_logging;
+mod cache;
+mod circuit;
+mod clock;
+mod config;
+mod content_type;
+mod copy;
+mod disk;
+mod error_code;
+mod expect;
+#[cfg(feature = "faults")]
+#[allow(dead_code)] // Driven by tests, through the library
+mod faults;
+mod folder;
+mod generators;
+mod graphql;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod guards;
 mod handlers;
+#[allow(dead_code)] // Partly used by the embedded test server only
+mod http_client;
+mod jwt;
+mod log_control;
+mod log_file;
+mod maintenance;
+mod memory;
+mod metadata;
+mod metrics;
+mod namespace;
+mod negotiation;
+mod notifications;
 mod object;
+mod openapi;
+mod placement;
+mod post_policy;
+mod range;
+mod read_only;
+mod rehash;
+mod reload;
+mod replication;
+mod roles;
 mod s3_service; // Declare the s3_service module
+mod secrets;
+mod seed;
+mod share;
+mod signing;
+mod simulation;
 mod storage;
+mod storage_trace;
 mod structs;
+mod systemd;
+mod throttle;
+mod timeout;
+mod tus;
+mod upload_progress;
+mod upload_slots;
+mod usage;
 
-use actix_web::http::StatusCode;
-use actix_web::http::header::ContentType;
-use actix_web::web;
-use actix_web::{App, HttpResponse, HttpServer, error::ResponseError};
-use handlers::{
-    create_bucket_handler, delete_bucket_handler, delete_object_handler, get_object_handler,
-    list_buckets_handler, list_objects_handler, put_object_handler,
-};
+use actix_http::{HttpServiceBuilder, Protocol, Request, Response};
+use actix_server::Server;
+use actix_service::{Service, ServiceFactory, ServiceFactoryExt, fn_service, map_config};
+use actix_web::body::BoxBody;
+use actix_web::dev::AppConfig;
+use app::{AppState, build_app};
 use s3_service::{S3Error, S3Service};
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 use storage::Storage;
 use tokio::sync::Mutex;
-use tracing::{error, info};
-use tracing_actix_web::TracingLogger;
-use tracing_subscriber::{EnvFilter, fmt};
+use tracing::{error, info, warn};
 
 // Import the ConsistencyChecker
-use crate::background::ConsistencyChecker;
-
-// Initialize tracing
-fn init_logging() {
-    // Initialize tracing with JSON formatter
-    fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .json()
-        .with_file(false)
-        .with_line_number(false)
-        .with_target(false)
-        .init();
-}
+use crate::access::AccessStatsFlusher;
+use crate::background::{
+    ConsistencyChecker, DiskMonitor, StorageHealthProbe, StorageLockProbe, TransitionWorker,
+    UploadJanitor,
+};
+use crate::config::{
+    BenchOptions, Command, Config, FsckOptions, ListenerConfig, ServerConfig, StorageConfig,
+    TimeoutConfig,
+};
+use crate::replication::Replicator;
+use log_control::LogControl;
+use systemd::ActivatedSocket;
+use tokio::net::UnixStream;
+
+/// Database file, relative to the working directory.
+const DB_PATH: &str = "s3_storage.db";
+
+/// Runs `fsck` against the store in `options.dir` without starting the
+/// server. Findings go to stdout; the process exits with status 1 if
+/// problems remain, so cron jobs can alert on it.
+fn run_fsck(options: FsckOptions, config: StorageConfig) -> std::io::Result<()> {
+    if let Some(dir) = &options.dir {
+        std::env::set_current_dir(dir)?;
+    }
+    // Opening the storage would create an empty store in the wrong directory
+    if !std::path::Path::new(DB_PATH).exists() {
+        return Err(std::io::Error::other(format!(
+            "No database '{}' in {}",
+            DB_PATH,
+            std::env::current_dir()?.display()
+        )));
+    }
+    let data_dir = options.data_dir.clone().unwrap_or(config.data_dir);
+    for dir in std::iter::once(&data_dir).chain(&config.extra_data_dirs) {
+        storage::check_data_dir(dir, Path::new(DB_PATH), false).map_err(std::io::Error::other)?;
+    }
+    let mut storage = Storage::open_for_fsck(DB_PATH, data_dir)
+        .and_then(|s| s.with_extra_data_dirs(&config.extra_data_dirs, config.placement))
+        .map_err(std::io::Error::other)?;
+    let report = storage
+        .fsck(options.repair)
+        .map_err(std::io::Error::other)?;
 
-// --- Helper function to map S3Error to Actix Web HTTP responses ---
-impl ResponseError for S3Error {
-    fn error_response(&self) -> HttpResponse {
-        let status = self.status_code();
-        let error_message = self.to_string();
+    let action = |repaired| if repaired { " (repaired)" } else { "" };
+    for name in &report.missing_files {
+        println!("missing file: {}{}", name, action(report.repaired));
+    }
+    for name in &report.corrupt_objects {
+        println!("corrupt object: {}", name);
+    }
+    for path in &report.orphaned_files {
+        println!("orphaned file: {}{}", path, action(report.repaired));
+    }
+    for row in &report.dangling_rows {
+        println!("dangling row: {}{}", row, action(report.repaired));
+    }
+    println!(
+        "fsck: {} missing, {} corrupt, {} orphaned, {} dangling",
+        report.missing_files.len(),
+        report.corrupt_objects.len(),
+        report.orphaned_files.len(),
+        report.dangling_rows.len()
+    );
 
-        HttpResponse::build(status)
-            .insert_header(ContentType::json())
-            .json(serde_json::json!({
-                "error": error_message,
-                "code": status.as_u16()
-            }))
+    if !report.is_clean() {
+        std::process::exit(1);
     }
+    Ok(())
+}
+
+/// Runs the benchmark described by `options` and prints a line per phase.
+/// The process exits with status 1 if operations failed.
+async fn run_bench(options: BenchOptions, config: Config) -> std::io::Result<()> {
+    let target = options.url.as_deref().unwrap_or("in-process store");
+    println!(
+        "bench: {} objects of {} bytes, {} in flight, against {}",
+        options.objects, options.size, options.concurrency, target
+    );
+    let reports = bench::run(&options, &config)
+        .await
+        .map_err(std::io::Error::other)?;
 
-    fn status_code(&self) -> StatusCode {
-        match self {
-            S3Error::BucketAlreadyExists(_) => StatusCode::CONFLICT,
-            S3Error::BucketNotFound(_) => StatusCode::NOT_FOUND,
-            S3Error::ObjectNotFound(_, _) => StatusCode::NOT_FOUND,
-            S3Error::ObjectCreationFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            S3Error::BucketOperationFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            S3Error::InternalStorageError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    let mut failed = false;
+    for report in &reports {
+        println!("{}", report);
+        if let Some(error) = &report.first_error {
+            println!("{}: first error: {}", report.operation, error);
+            failed = true;
         }
     }
+    if failed {
+        std::process::exit(1);
+    }
+    Ok(())
 }
 
 // The main function is now asynchronous and sets up the Actix Web server.
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Initialize logging
-    init_logging();
+    // Logging is set up from the configuration, so load it first and
+    // report a failure once logging is in place
+    let config = Config::load();
+    let logging = config
+        .as_ref()
+        .map(|config| config.logging.clone())
+        .unwrap_or_default();
+    let log_control = match LogControl::init(&logging) {
+        Ok(log_control) => Arc::new(log_control),
+        Err(e) => {
+            eprintln!("Failed to set up logging: {}", e);
+            return Err(e);
+        }
+    };
 
-    info!("Starting S3-like Storage HTTP API on http://127.0.0.1:8080");
+    let options = match Command::from_args(std::env::args().skip(1)) {
+        Ok(Command::Serve(options)) => options,
+        Ok(Command::Fsck(options)) => {
+            let storage_config = match &config {
+                Ok(config) => config.storage.clone(),
+                Err(_) => StorageConfig::default(),
+            };
+            return run_fsck(options, storage_config);
+        }
+        Ok(Command::Bench(options)) => {
+            let config = config.map_err(|e| {
+                std::io::Error::other(format!("Failed to load configuration: {}", e))
+            })?;
+            return run_bench(options, config).await;
+        }
+        Err(e) => {
+            error!("{}", e);
+            return Err(std::io::Error::other(e.to_string()));
+        }
+    };
 
-    // Initialize Storage
-    let db_path = "s3_storage.db";
-    let storage = match Storage::new(db_path) {
-        Ok(s) => Arc::new(Mutex::new(s)),
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            error!("Failed to load configuration: {}", e);
+            return Err(std::io::Error::other(format!(
+                "Failed to load configuration: {}",
+                e
+            )));
+        }
+    };
+
+    // Initialize Storage, in the data directory the database was created with
+    let data_dir = options
+        .data_dir
+        .clone()
+        .unwrap_or_else(|| config.storage.data_dir.clone());
+    let new_store = !Path::new(DB_PATH).exists();
+    for dir in std::iter::once(&data_dir).chain(&config.storage.extra_data_dirs) {
+        if let Err(e) = storage::check_data_dir(dir, Path::new(DB_PATH), new_store) {
+            error!("{}", e);
+            return Err(std::io::Error::other(e.to_string()));
+        }
+    }
+    let storage = match Storage::open(DB_PATH, &data_dir).and_then(|s| {
+        s.with_extra_data_dirs(&config.storage.extra_data_dirs, config.storage.placement)?
+            .with_busy(config.storage.busy)
+    }) {
+        Ok(s) => Arc::new(Mutex::new(
+            s.with_hash_algorithm(config.storage.hash_algorithm)
+                .with_slow_ops(config.storage.slow_ops),
+        )),
         Err(e) => {
             error!("Failed to initialize storage: {}", e);
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("Failed to initialize storage: {}", e),
-            ));
+            return Err(std::io::Error::other(format!(
+                "Failed to initialize storage: {}",
+                e
+            )));
         }
     };
 
+    // Check the stored objects before accepting any requests
+    if let Some(mode) = options.verify_on_start {
+        info!("Verifying stored objects ({:?})", mode);
+        let problems = match storage.lock().await.verify_integrity(mode) {
+            Ok(problems) => problems,
+            Err(e) => {
+                error!("Failed to verify storage: {}", e);
+                return Err(std::io::Error::other(format!(
+                    "Failed to verify storage: {}",
+                    e
+                )));
+            }
+        };
+        for problem in &problems {
+            error!("Integrity check: {}", problem);
+        }
+        if problems.is_empty() {
+            info!("Integrity check found no problems");
+        } else if options.force {
+            warn!(
+                "Integrity check found {} problems; serving anyway because of --force",
+                problems.len()
+            );
+        } else {
+            return Err(std::io::Error::other(format!(
+                "Integrity check found {} problems; pass --force to serve anyway",
+                problems.len()
+            )));
+        }
+    }
+
+    // Fixtures are in place before the first request is served
+    if let Some(dir) = &options.seed {
+        match seed::seed_from_dir(&mut *storage.lock().await, dir) {
+            Ok(report) => info!(
+                "Seeded {} objects ({} bytes) and {} new buckets from {}",
+                report.objects,
+                report.bytes,
+                report.buckets,
+                dir.display()
+            ),
+            Err(e) => {
+                error!("Failed to seed storage: {}", e);
+                return Err(std::io::Error::other(format!(
+                    "Failed to seed storage: {}",
+                    e
+                )));
+            }
+        }
+    }
+
     // Create and start the background consistency checker
     let storage_for_checker = storage.clone();
     let _checker_handle = ConsistencyChecker::new(
@@ -98,35 +322,279 @@ async fn main() -> std::io::Result<()> {
 
     info!("Started background consistency checker");
 
-    // Create S3Service with the storage
-    let s3_service = Arc::new(Mutex::new(S3Service::new(storage)));
-
-    // Start the HTTP server
-    HttpServer::new(move || {
-        // Only provide s3_service_data to the app_data.
-        // Handlers will interact with S3Service, which internally manages Storage.
-        let s3_service_data = web::Data::new(s3_service.clone());
-
-        App::new()
-            .wrap(TracingLogger::default())
-            .app_data(s3_service_data.clone())
-            .service(
-                web::resource("/buckets/{bucket_name}")
-                    .put(create_bucket_handler) // create_bucket_handler no longer needs 'storage' directly
-                    .delete(delete_bucket_handler),
-            )
-            .service(web::resource("/buckets").get(list_buckets_handler))
-            .service(
-                web::resource("/buckets/{bucket_name}/objects/{object_key}")
-                    .put(put_object_handler)
-                    .get(get_object_handler)
-                    .delete(delete_object_handler),
-            )
-            .service(web::resource("/buckets/{bucket_name}/objects").get(list_objects_handler))
-            .default_service(web::to(|| async { HttpResponse::NotFound().finish() }))
-    })
-    .bind(("127.0.0.1", 8080))?
-    .workers(5)
-    .run()
-    .await
+    // Ship objects in replicated buckets to their peers
+    let _replicator_handle = Replicator::new(storage.clone(), Duration::from_secs(10)).start();
+
+    // The state shared by the handlers, partly driven by the workers below
+    let state = match AppState::new(&config, storage.clone(), DB_PATH, Some(log_control)).await {
+        Ok(state) => state,
+        Err(e) => {
+            error!("Failed to set up the server: {}", e);
+            return Err(e);
+        }
+    };
+    if config.read_only {
+        info!("Starting in read-only mode");
+    }
+    if config.simulation.is_enabled() {
+        warn!(
+            latency_ms = config.simulation.latency_ms,
+            jitter_ms = config.simulation.jitter_ms,
+            error_rate = config.simulation.error_rate,
+            "Simulating a slow and flaky data path"
+        );
+    }
+
+    // Move aged objects between storage classes according to lifecycle rules
+    let _transition_handle = TransitionWorker::new(
+        storage.clone(),
+        state.metrics.clone(),
+        Duration::from_secs(3600), // Run every hour
+    )
+    .start();
+
+    // Refuse writes while the data volume is nearly full
+    let _disk_monitor_handle = DiskMonitor::new(
+        data_dir.clone(),
+        state.disk_state.clone(),
+        state.metrics.clone(),
+        Duration::from_secs(config.disk.check_interval_secs.max(1)),
+    )
+    .start();
+
+    // Writes are shed while the storage layer is saturated
+    let _lock_probe_handle = StorageLockProbe::new(
+        storage.clone(),
+        state.backpressure.clone(),
+        state.metrics.clone(),
+        Duration::from_millis(250),
+    )
+    .start();
+
+    // Requests fail fast while the storage keeps failing, until a probe
+    // finds it working again
+    let _health_probe_handle = StorageHealthProbe::new(
+        storage.clone(),
+        state.circuit_breaker.clone(),
+        Duration::from_secs(5),
+    )
+    .start();
+
+    // Persist the object reads buffered in memory once a minute
+    let _access_flush_handle = AccessStatsFlusher::new(
+        storage.clone(),
+        state.access_tracker.clone(),
+        Duration::from_secs(60),
+    )
+    .start();
+
+    // Abort resumable uploads abandoned by their clients
+    let _upload_janitor_handle = (config.uploads.stale_after_secs > 0).then(|| {
+        UploadJanitor::new(
+            storage.clone(),
+            state.upload_progress.clone(),
+            Duration::from_secs(config.uploads.stale_after_secs),
+            Duration::from_secs(config.uploads.cleanup_interval_secs.max(1)),
+        )
+        .start()
+    });
+
+    // Settings applied again on SIGHUP or POST /admin/reload
+    let _reload_handle = match state.reloader.clone().start_on_hangup() {
+        Ok(handle) => handle,
+        Err(e) => {
+            error!("Failed to listen for SIGHUP: {}", e);
+            return Err(e);
+        }
+    };
+
+    // Secrets fetched again from the secret manager, if there is one
+    let _secrets_handle = state.secrets.clone().start_refresh(config.secrets.clone());
+    let _jwks_handle = state.jwt.clone().start_refresh();
+
+    // Pinned objects are loaded up front rather than on their first read
+    match state.s3_service.lock().await.restore_cache_pins().await {
+        Ok(report) if report.objects > 0 => {
+            info!("Loaded {} pinned objects into the cache", report.objects)
+        }
+        Ok(_) => {}
+        Err(e) => error!("Failed to restore cache pins: {}", e),
+    }
+
+    // The gRPC service runs next to the HTTP API and stops with it
+    #[cfg(feature = "grpc")]
+    let grpc = match start_grpc(&config, &state).await {
+        Ok(grpc) => grpc,
+        Err(e) => {
+            error!("Failed to start the gRPC service: {}", e);
+            return Err(e);
+        }
+    };
+    #[cfg(not(feature = "grpc"))]
+    if config.grpc.is_enabled() {
+        warn!("A gRPC port is configured, but this build has no gRPC service");
+    }
+
+    // Start the HTTP server. It is assembled from its parts rather than with
+    // HttpServer so the `Expect` handling can be replaced.
+    let server = state.server.clone();
+    let timeouts = state.timeouts.clone();
+    let listeners = server.listeners();
+    let activated = match systemd::activated_sockets() {
+        Ok(activated) => activated,
+        Err(e) => {
+            error!("Failed to take over sockets passed by systemd: {}", e);
+            return Err(e);
+        }
+    };
+    let mut builder = Server::build()
+        .workers(server.worker_count())
+        .backlog(server.backlog)
+        .max_concurrent_connections(server.max_connections_per_worker);
+
+    // The app and `Expect` handling of a connection, the same for every
+    // listener
+    let connection_parts = move || {
+        let app = build_app(&state);
+        let expect_check = state.expect_check.clone();
+        let expect = fn_service(move |req: Request| {
+            let expect_check = expect_check.clone();
+            async move {
+                match expect_check.check(&req).await {
+                    Some(response) => Err(response),
+                    None => Ok(req),
+                }
+            }
+        });
+        (map_config(app, |_| AppConfig::default()), expect)
+    };
+
+    let tcp_service = {
+        let (connection_parts, server, timeouts) =
+            (connection_parts.clone(), server.clone(), timeouts.clone());
+        move || {
+            let (app, expect) = connection_parts();
+            http_service_builder(&server, &timeouts)
+                .expect(expect)
+                .finish(app)
+                .tcp()
+        }
+    };
+    let unix_service = move || {
+        let (app, expect) = connection_parts();
+        fn_service(|io: UnixStream| async { Ok((io, Protocol::Http1, None)) }).and_then(
+            http_service_builder(&server, &timeouts)
+                .expect(expect)
+                .finish(app),
+        )
+    };
+
+    // Sockets passed by systemd take the place of the configured listeners
+    if activated.is_empty() {
+        for (index, listener) in listeners.into_iter().enumerate() {
+            info!("Starting S3-like Storage HTTP API on {}", listener);
+            let name = format!("s3-{}", index);
+            builder = match listener {
+                ListenerConfig::Tcp { bind, port } => {
+                    builder.bind(name, (bind, port), tcp_service.clone())?
+                }
+                ListenerConfig::Unix { unix } => {
+                    builder.bind_uds(name, unix, unix_service.clone())?
+                }
+            };
+        }
+    }
+    for (index, socket) in activated.into_iter().enumerate() {
+        let name = format!("s3-activated-{}", index);
+        builder = match socket {
+            ActivatedSocket::Tcp(listener) => {
+                info!(
+                    "Starting S3-like Storage HTTP API on socket-activated {:?}",
+                    listener.local_addr()?
+                );
+                builder.listen(name, listener, tcp_service.clone())?
+            }
+            ActivatedSocket::Unix(listener) => {
+                info!(
+                    "Starting S3-like Storage HTTP API on socket-activated {:?}",
+                    listener.local_addr()?
+                );
+                builder.listen_uds(name, listener, unix_service.clone())?
+            }
+        };
+    }
+    let result = builder.run().await;
+    #[cfg(feature = "grpc")]
+    if let Some((stop, handle)) = grpc {
+        let _ = stop.send(());
+        let _ = handle.await;
+    }
+    result
+}
+
+/// Starts the gRPC service if a port is configured for it.
+///
+/// # Arguments
+///
+/// * `config` - The configuration, for the `[grpc]` listener and the body limit.
+/// * `state` - The state shared with the HTTP handlers.
+///
+/// # Returns
+///
+/// * `std::io::Result<Option<(oneshot::Sender<()>, JoinHandle<()>)>>` - The sender stopping
+///   the service and its task, `None` if it is off, or the error binding its listener.
+#[cfg(feature = "grpc")]
+async fn start_grpc(
+    config: &Config,
+    state: &AppState,
+) -> std::io::Result<
+    Option<(
+        tokio::sync::oneshot::Sender<()>,
+        tokio::task::JoinHandle<()>,
+    )>,
+> {
+    if !config.grpc.is_enabled() {
+        return Ok(None);
+    }
+    let listener =
+        tokio::net::TcpListener::bind((config.grpc.bind.as_str(), config.grpc.port)).await?;
+    let service = grpc::GrpcService::new(
+        state.s3_service.clone(),
+        state.jwt.clone(),
+        state.read_only.clone(),
+        config.server.max_body(),
+    );
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    info!(
+        "Starting S3-like Storage gRPC API on {}",
+        listener.local_addr()?
+    );
+    let handle = tokio::spawn(async move {
+        let shutdown = async {
+            let _ = stopped.await;
+        };
+        if let Err(e) = grpc::serve(service, listener, shutdown).await {
+            error!("gRPC service failed: {}", e);
+        }
+    });
+    Ok(Some((stop, handle)))
+}
+
+/// Starts the HTTP service of a connection, over the stream type of its
+/// listener, with the configured connection settings.
+fn http_service_builder<T, S>(
+    server: &ServerConfig,
+    timeouts: &TimeoutConfig,
+) -> HttpServiceBuilder<T, S>
+where
+    S: ServiceFactory<Request, Config = ()>,
+    S::Error: Into<Response<BoxBody>> + 'static,
+    S::InitError: std::fmt::Debug,
+    <S::Service as Service<Request>>::Future: 'static,
+{
+    HttpServiceBuilder::default()
+        // Zero disables the limit
+        .client_request_timeout(timeouts.header_read().unwrap_or(Duration::ZERO))
+        .client_disconnect_timeout(server.client_disconnect())
+        .keep_alive(server.keep_alive())
 }
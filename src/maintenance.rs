@@ -0,0 +1,129 @@
+// maintenance.rs
+// Maintenance mode for migrations, restores and large repairs. While it is
+// on, every request to the data path (`/buckets`, also under a namespace) is
+// answered with 503, a Retry-After header and a body naming the reason, so
+// clients can tell planned work from an outage. Admin endpoints and metrics
+// keep being served, so the work can be driven and maintenance switched off
+// again with POST /admin/maintenance.
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, web};
+use std::sync::{Arc, RwLock};
+
+use crate::error_code::{ErrorCode, error_response};
+use crate::namespace::split_path;
+use crate::structs::MaintenanceStatus;
+
+/// Retry-After sent when maintenance is switched on without one.
+pub const DEFAULT_RETRY_AFTER_SECS: u64 = 60;
+
+/// Whether the server is under maintenance, and what clients are told.
+#[derive(Debug)]
+pub struct MaintenanceMode {
+    status: RwLock<MaintenanceStatus>,
+}
+
+impl Default for MaintenanceMode {
+    fn default() -> Self {
+        Self {
+            status: RwLock::new(MaintenanceStatus {
+                enabled: false,
+                reason: None,
+                retry_after_secs: DEFAULT_RETRY_AFTER_SECS,
+            }),
+        }
+    }
+}
+
+impl MaintenanceMode {
+    pub fn status(&self) -> MaintenanceStatus {
+        self.status
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set(&self, status: MaintenanceStatus) {
+        *self.status.write().unwrap_or_else(|e| e.into_inner()) = status;
+    }
+
+    /// The response refusing `path`, if maintenance is on and it is a data
+    /// path request.
+    pub fn refusal_for(&self, path: &str) -> Option<HttpResponse> {
+        let status = self.status.read().unwrap_or_else(|e| e.into_inner());
+        (status.enabled && is_data_path(path)).then(|| refusal(&status))
+    }
+}
+
+/// The response refusing a request during maintenance.
+pub fn refusal(status: &MaintenanceStatus) -> HttpResponse {
+    let message = match &status.reason {
+        Some(reason) => format!(
+            "ServiceUnavailable: The server is under maintenance ({}). Please retry in {} seconds.",
+            reason, status.retry_after_secs
+        ),
+        None => format!(
+            "ServiceUnavailable: The server is under maintenance. Please retry in {} seconds.",
+            status.retry_after_secs
+        ),
+    };
+    let mut response = error_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::ServiceUnavailable,
+        &message,
+    );
+    response
+        .headers_mut()
+        .insert(RETRY_AFTER, HeaderValue::from(status.retry_after_secs));
+    response
+}
+
+/// Whether a request goes to buckets and objects rather than to the admin
+/// or metrics endpoints.
+pub fn is_data_path(path: &str) -> bool {
+    let path = split_path(path).map_or(path, |(_, rest)| rest);
+    path == "/buckets" || path.starts_with("/buckets/")
+}
+
+/// Middleware refusing data path requests while maintenance is on.
+pub async fn reject_requests_during_maintenance(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let refusal = req
+        .app_data::<web::Data<Arc<MaintenanceMode>>>()
+        .and_then(|mode| mode.refusal_for(req.path()));
+
+    if let Some(refusal) = refusal {
+        return Ok(req.into_response(refusal).map_into_right_body());
+    }
+
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_refuses_data_paths_only() {
+        let mode = MaintenanceMode::default();
+        assert!(mode.refusal_for("/buckets/b/objects/k").is_none());
+
+        mode.set(MaintenanceStatus {
+            enabled: true,
+            reason: Some("restoring a backup".to_string()),
+            retry_after_secs: 300,
+        });
+        let refusal = mode.refusal_for("/ns/team/buckets/b").unwrap();
+        assert_eq!(refusal.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(refusal.headers().get(RETRY_AFTER).unwrap(), "300");
+        assert!(mode.refusal_for("/buckets").is_some());
+        assert!(mode.refusal_for("/admin/maintenance").is_none());
+        assert!(mode.refusal_for("/metrics").is_none());
+    }
+}
@@ -0,0 +1,112 @@
+// memory.rs
+// Global memory budget shared by in-flight request bodies and the object
+// cache. Uploads are admitted only while the budget has room for their
+// declared size, so a burst of large uploads is answered with 503 SlowDown
+// instead of growing the process until it is OOM-killed.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Bytes held in memory by request bodies and the object cache.
+#[derive(Debug, Default)]
+pub struct MemoryBudget {
+    /// Limit on the bytes held; 0 means unlimited.
+    max_bytes: u64,
+    request_bytes: AtomicU64,
+    cache_bytes: AtomicU64,
+}
+
+impl MemoryBudget {
+    pub fn new(max_bytes: u64) -> Self {
+        Self {
+            max_bytes,
+            ..Self::default()
+        }
+    }
+
+    /// Bytes currently held by request bodies and the cache.
+    pub fn used(&self) -> u64 {
+        self.request_bytes.load(Ordering::Relaxed) + self.cache_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Whether `bytes` more may be cached without exceeding the budget, given
+    /// the cache currently holds `cached` bytes.
+    pub fn allows_cache(&self, cached: u64, bytes: u64) -> bool {
+        self.max_bytes == 0
+            || self.request_bytes.load(Ordering::Relaxed) + cached + bytes <= self.max_bytes
+    }
+
+    /// Records the number of bytes held by the object cache.
+    pub fn set_cache_bytes(&self, bytes: u64) {
+        self.cache_bytes.store(bytes, Ordering::Relaxed);
+    }
+
+    /// Whether a request body of `expected` bytes would currently be admitted.
+    pub fn has_room(&self, expected: u64) -> bool {
+        self.max_bytes == 0 || self.used() + expected <= self.max_bytes
+    }
+
+    /// Admits a request body expected to be `expected` bytes long, or returns
+    /// `None` if it does not fit in the budget. The returned reservation
+    /// releases its bytes when dropped.
+    pub fn admit(self: &Arc<Self>, expected: u64) -> Option<Reservation> {
+        if !self.has_room(expected) {
+            return None;
+        }
+        self.request_bytes.fetch_add(expected, Ordering::Relaxed);
+        Some(Reservation {
+            budget: self.clone(),
+            bytes: expected,
+        })
+    }
+}
+
+/// Memory held by one request body.
+#[derive(Debug)]
+pub struct Reservation {
+    budget: Arc<MemoryBudget>,
+    bytes: u64,
+}
+
+impl Reservation {
+    /// Accounts for a body that has grown to `total` bytes. Bodies already
+    /// admitted are never cut off, so this may exceed the budget.
+    pub fn grow_to(&mut self, total: u64) {
+        if total > self.bytes {
+            self.budget
+                .request_bytes
+                .fetch_add(total - self.bytes, Ordering::Relaxed);
+            self.bytes = total;
+        }
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        self.budget
+            .request_bytes
+            .fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reservations_are_released_on_drop() {
+        let budget = Arc::new(MemoryBudget::new(100));
+        budget.set_cache_bytes(40);
+
+        let mut first = budget.admit(50).unwrap();
+        assert!(budget.admit(20).is_none());
+
+        // Growing past the budget is allowed for admitted bodies
+        first.grow_to(70);
+        assert_eq!(budget.used(), 110);
+
+        drop(first);
+        assert_eq!(budget.used(), 40);
+        assert!(budget.admit(60).is_some());
+    }
+}
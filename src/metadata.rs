@@ -0,0 +1,119 @@
+// metadata.rs
+// Limits on the user metadata of objects (`x-user-meta-*` headers), after
+// those of S3: at most 2 KB in total, counting names and values, under
+// ASCII names. A bucket can define defaults, merged into the metadata of
+// every object uploaded to it unless the upload sets the same names.
+
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Combined size of all metadata names and values, in bytes.
+pub const MAX_METADATA_BYTES: usize = 2 * 1024;
+/// Number of metadata entries of an object.
+pub const MAX_METADATA_ENTRIES: usize = 64;
+
+/// Custom error type for user metadata over the limits.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MetadataError {
+    #[error("User metadata is {0} bytes, over the limit of {MAX_METADATA_BYTES} bytes")]
+    TooLarge(usize),
+    #[error("User metadata has {0} entries, over the limit of {MAX_METADATA_ENTRIES}")]
+    TooManyEntries(usize),
+    #[error("User metadata name '{0}' is not ASCII")]
+    InvalidName(String),
+}
+
+/// Checks user metadata against the limits.
+pub fn validate(metadata: &HashMap<String, String>) -> Result<(), MetadataError> {
+    if let Some(name) = metadata.keys().find(|name| !name.is_ascii()) {
+        return Err(MetadataError::InvalidName(name.clone()));
+    }
+    if metadata.len() > MAX_METADATA_ENTRIES {
+        return Err(MetadataError::TooManyEntries(metadata.len()));
+    }
+    let size: usize = metadata
+        .iter()
+        .map(|(name, value)| name.len() + value.len())
+        .sum();
+    if size > MAX_METADATA_BYTES {
+        return Err(MetadataError::TooLarge(size));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let metadata = HashMap::from([("owner".to_string(), "alice".to_string())]);
+        assert_eq!(validate(&metadata), Ok(()));
+        assert_eq!(validate(&HashMap::new()), Ok(()));
+
+        let large = HashMap::from([("blob".to_string(), "x".repeat(MAX_METADATA_BYTES))]);
+        assert_eq!(
+            validate(&large),
+            Err(MetadataError::TooLarge(MAX_METADATA_BYTES + 4))
+        );
+
+        let many = (0..=MAX_METADATA_ENTRIES)
+            .map(|i| (format!("k{}", i), String::new()))
+            .collect();
+        assert_eq!(
+            validate(&many),
+            Err(MetadataError::TooManyEntries(MAX_METADATA_ENTRIES + 1))
+        );
+
+        let non_ascii = HashMap::from([("größe".to_string(), "1".to_string())]);
+        assert!(matches!(
+            validate(&non_ascii),
+            Err(MetadataError::InvalidName(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_bucket_defaults() {
+        use crate::object::Object;
+        use crate::s3_service::S3Service;
+        use crate::storage::Storage;
+        use std::sync::Arc;
+        use tokio::sync::Mutex;
+
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let storage = Storage::open(&db_path.to_string_lossy(), dir.path().join("data")).unwrap();
+        let mut s3 = S3Service::new(Arc::new(Mutex::new(storage)));
+        s3.create_bucket("b").await.unwrap();
+        let defaults = HashMap::from([
+            ("Team".to_string(), "payments".to_string()),
+            ("tier".to_string(), "gold".to_string()),
+        ]);
+        let stored = s3.put_bucket_default_metadata("b", defaults).await.unwrap();
+        assert_eq!(stored.get("team").map(String::as_str), Some("payments"));
+
+        let own = HashMap::from([("tier".to_string(), "silver".to_string())]);
+        let object = Object::new("k".to_string(), b"x".to_vec(), None, Some(own)).unwrap();
+        let metadata = s3.put_object("b", object).await.unwrap().user_metadata;
+        assert_eq!(
+            metadata,
+            Some(HashMap::from([
+                ("team".to_string(), "payments".to_string()),
+                ("tier".to_string(), "silver".to_string()),
+            ]))
+        );
+
+        s3.put_bucket_default_metadata("b", HashMap::new())
+            .await
+            .unwrap();
+        assert!(
+            s3.get_bucket_default_metadata("b")
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let object = Object::new("k".to_string(), b"x".to_vec(), None, None).unwrap();
+        let metadata = s3.put_object("b", object).await.unwrap().user_metadata;
+        assert!(metadata.is_none_or(|metadata| metadata.is_empty()));
+    }
+}
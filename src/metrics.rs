@@ -0,0 +1,417 @@
+// metrics.rs
+// Process-wide counters exposed in the Prometheus text format at /metrics.
+
+use actix_web::Error;
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::http::header::CONTENT_LENGTH;
+use actix_web::middleware::Next;
+use actix_web::web;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use utoipa::ToSchema;
+
+use crate::backpressure::Pressure;
+use crate::disk::DiskUsage;
+use crate::namespace::split_path;
+
+/// Label under which failed requests to buckets not yet tracked are counted,
+/// so requests naming made-up buckets cannot add a series each.
+pub const UNKNOWN_BUCKET: &str = "(unknown)";
+
+/// Request counters of a single bucket.
+#[derive(Debug, Default, Clone, Serialize, ToSchema)]
+pub struct BucketMetrics {
+    /// Requests per operation, e.g. `GetObject` or `PutBucketVersioning`.
+    pub requests: BTreeMap<String, u64>,
+    pub errors_4xx: u64,
+    pub errors_5xx: u64,
+    pub bytes_uploaded: u64,
+    pub bytes_downloaded: u64,
+}
+
+/// Counters shared between the HTTP handlers and background jobs.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    objects_transitioned: AtomicU64,
+    bytes_transitioned: AtomicU64,
+    disk_total_bytes: AtomicU64,
+    disk_available_bytes: AtomicU64,
+    disk_read_only: AtomicBool,
+    queued_writes: AtomicU64,
+    storage_lock_wait_micros: AtomicU64,
+    requests_shed: AtomicU64,
+    webhook_events_dropped: AtomicU64,
+    buckets: std::sync::Mutex<HashMap<String, BucketMetrics>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records objects moved to another storage tier by a lifecycle rule.
+    pub fn record_transition(&self, objects: u64, bytes: u64) {
+        self.objects_transitioned
+            .fetch_add(objects, Ordering::Relaxed);
+        self.bytes_transitioned.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    /// Records the latest reading of the data volume.
+    pub fn record_disk_usage(&self, usage: DiskUsage, read_only: bool) {
+        self.disk_total_bytes
+            .store(usage.total_bytes, Ordering::Relaxed);
+        self.disk_available_bytes
+            .store(usage.available_bytes, Ordering::Relaxed);
+        self.disk_read_only.store(read_only, Ordering::Relaxed);
+    }
+
+    /// Records the latest load on the storage layer.
+    pub fn record_pressure(&self, pressure: Pressure) {
+        self.queued_writes
+            .store(pressure.queued_writes, Ordering::Relaxed);
+        self.storage_lock_wait_micros
+            .store(pressure.lock_wait.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Records a write refused under storage pressure.
+    pub fn record_shed_request(&self) {
+        self.requests_shed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records an object event dropped because its webhook's queue was full.
+    pub fn record_dropped_event(&self) {
+        self.webhook_events_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records a completed request against a bucket. A bucket is tracked from
+    /// its first successful request; failures before that, such as requests
+    /// to buckets that do not exist, are counted under `UNKNOWN_BUCKET`.
+    pub fn record_bucket_request(
+        &self,
+        bucket: &str,
+        operation: &str,
+        status: u16,
+        bytes_uploaded: u64,
+        bytes_downloaded: u64,
+    ) {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = match status < 400 || buckets.contains_key(bucket) {
+            true => bucket,
+            false => UNKNOWN_BUCKET,
+        };
+        let metrics = buckets.entry(bucket.to_string()).or_default();
+        *metrics.requests.entry(operation.to_string()).or_default() += 1;
+        match status {
+            400..=499 => metrics.errors_4xx += 1,
+            500..=599 => metrics.errors_5xx += 1,
+            _ => {}
+        }
+        metrics.bytes_uploaded += bytes_uploaded;
+        metrics.bytes_downloaded += bytes_downloaded;
+    }
+
+    /// Returns a copy of the counters of a bucket; zeroes if it has seen no requests.
+    pub fn bucket_snapshot(&self, bucket: &str) -> BucketMetrics {
+        let buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        buckets.get(bucket).cloned().unwrap_or_default()
+    }
+
+    /// Renders all counters in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        write_counter(
+            &mut out,
+            "s3_lifecycle_objects_transitioned_total",
+            "Objects moved to another storage class by lifecycle rules",
+            self.objects_transitioned.load(Ordering::Relaxed),
+        );
+        write_counter(
+            &mut out,
+            "s3_lifecycle_bytes_transitioned_total",
+            "Bytes moved to another storage class by lifecycle rules",
+            self.bytes_transitioned.load(Ordering::Relaxed),
+        );
+        write_gauge(
+            &mut out,
+            "s3_disk_total_bytes",
+            "Size of the data volume",
+            self.disk_total_bytes.load(Ordering::Relaxed),
+        );
+        write_gauge(
+            &mut out,
+            "s3_disk_available_bytes",
+            "Free space of the data volume",
+            self.disk_available_bytes.load(Ordering::Relaxed),
+        );
+        write_gauge(
+            &mut out,
+            "s3_disk_read_only",
+            "1 while writes are refused because the data volume is above its high watermark",
+            self.disk_read_only.load(Ordering::Relaxed) as u64,
+        );
+        write_gauge(
+            &mut out,
+            "s3_storage_queued_writes",
+            "Writes admitted and not yet answered",
+            self.queued_writes.load(Ordering::Relaxed),
+        );
+        write_gauge(
+            &mut out,
+            "s3_storage_lock_wait_microseconds",
+            "Time the latest probe waited for the storage lock",
+            self.storage_lock_wait_micros.load(Ordering::Relaxed),
+        );
+        write_counter(
+            &mut out,
+            "s3_requests_shed_total",
+            "Writes refused with 503 SlowDown under storage pressure",
+            self.requests_shed.load(Ordering::Relaxed),
+        );
+        write_counter(
+            &mut out,
+            "s3_webhook_events_dropped_total",
+            "Object events dropped because a webhook's delivery queue was full",
+            self.webhook_events_dropped.load(Ordering::Relaxed),
+        );
+        self.render_buckets(&mut out);
+        out
+    }
+
+    fn render_buckets(&self, out: &mut String) {
+        let buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let mut names: Vec<&String> = buckets.keys().collect();
+        names.sort();
+
+        let _ = writeln!(
+            out,
+            "# HELP s3_bucket_requests_total Requests per bucket and operation"
+        );
+        let _ = writeln!(out, "# TYPE s3_bucket_requests_total counter");
+        for name in &names {
+            for (operation, count) in &buckets[*name].requests {
+                let _ = writeln!(
+                    out,
+                    "s3_bucket_requests_total{{bucket=\"{}\",operation=\"{}\"}} {}",
+                    escape_label(name),
+                    escape_label(operation),
+                    count
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP s3_bucket_errors_total Failed requests per bucket and status class"
+        );
+        let _ = writeln!(out, "# TYPE s3_bucket_errors_total counter");
+        for name in &names {
+            let metrics = &buckets[*name];
+            for (class, count) in [("4xx", metrics.errors_4xx), ("5xx", metrics.errors_5xx)] {
+                let _ = writeln!(
+                    out,
+                    "s3_bucket_errors_total{{bucket=\"{}\",class=\"{}\"}} {}",
+                    escape_label(name),
+                    class,
+                    count
+                );
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP s3_bucket_bytes_uploaded_total Request body bytes received per bucket"
+        );
+        let _ = writeln!(out, "# TYPE s3_bucket_bytes_uploaded_total counter");
+        for name in &names {
+            let _ = writeln!(
+                out,
+                "s3_bucket_bytes_uploaded_total{{bucket=\"{}\"}} {}",
+                escape_label(name),
+                buckets[*name].bytes_uploaded
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP s3_bucket_bytes_downloaded_total Response body bytes sent per bucket"
+        );
+        let _ = writeln!(out, "# TYPE s3_bucket_bytes_downloaded_total counter");
+        for name in &names {
+            let _ = writeln!(
+                out,
+                "s3_bucket_bytes_downloaded_total{{bucket=\"{}\"}} {}",
+                escape_label(name),
+                buckets[*name].bytes_downloaded
+            );
+        }
+    }
+}
+
+/// Middleware recording per-bucket request counters for every request under
+/// `/buckets/{bucket}`.
+pub async fn track_bucket_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let metrics = req.app_data::<web::Data<Arc<Metrics>>>().cloned();
+    let is_head = req.method() == Method::HEAD;
+    let target = bucket_operation(req.method().as_str(), req.path(), req.query_string());
+    let bytes_uploaded = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    let res = next.call(req).await?;
+
+    if let (Some(metrics), Some((bucket, operation))) = (metrics, target) {
+        // HEAD responses declare a length but send no body
+        let bytes_downloaded = match res.response().body().size() {
+            BodySize::Sized(n) if !is_head => n,
+            _ => 0,
+        };
+        metrics.record_bucket_request(
+            &bucket,
+            &operation,
+            res.status().as_u16(),
+            bytes_uploaded,
+            bytes_downloaded,
+        );
+    }
+    Ok(res)
+}
+
+/// Maps a request to its bucket and an S3-style operation name, or `None`
+/// for requests outside `/buckets/{bucket}`.
+pub fn bucket_operation(method: &str, path: &str, query: &str) -> Option<(String, String)> {
+    // Buckets of a namespace are tracked under their storage name
+    let (namespace, path) = match split_path(path) {
+        Some((namespace, rest)) => (Some(namespace), rest),
+        None => (None, path),
+    };
+    let mut segments = path.trim_start_matches('/').split('/');
+    if segments.next() != Some("buckets") {
+        return None;
+    }
+    let bucket = segments.next().filter(|b| !b.is_empty())?;
+    let bucket = match namespace {
+        Some(namespace) => format!("{}/{}", namespace, bucket),
+        None => bucket.to_string(),
+    };
+    let noun = match (segments.next(), segments.next()) {
+        (None, _) => "Bucket",
+        (Some("objects"), None) => "Objects",
+        (Some("objects"), Some(_)) => "Object",
+        (Some("uploads"), None) => "Uploads",
+        (Some("uploads"), Some(_)) => "Upload",
+        (Some("shares"), None) => "Shares",
+        (Some("shares"), Some(_)) => "Share",
+        _ => return None,
+    };
+    let verb = match method {
+        "GET" => "Get",
+        "PUT" => "Put",
+        "POST" => "Post",
+        "DELETE" => "Delete",
+        "HEAD" => "Head",
+        "PATCH" => "Patch",
+        "OPTIONS" => "Options",
+        _ => "Other",
+    };
+    // The first query parameter selects the sub-resource, e.g. `?versioning`
+    let subresource: String = query
+        .split('&')
+        .next()
+        .and_then(|pair| pair.split('=').next())
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            name.split('-')
+                .map(|word| {
+                    let mut chars = word.chars();
+                    chars
+                        .next()
+                        .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                        .unwrap_or_default()
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let operation = match (verb, noun, subresource.as_str()) {
+        ("Put", "Bucket", "") => "CreateBucket".to_string(),
+        ("Post", "Bucket", "") => "PostObject".to_string(),
+        ("Get", "Objects", _) => "ListObjects".to_string(),
+        (verb, noun, subresource) => format!("{}{}{}", verb, noun, subresource),
+    };
+    Some((bucket, operation))
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn write_counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn write_gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_operation_names() {
+        let op = |method, path, query| bucket_operation(method, path, query).map(|(_, op)| op);
+
+        assert_eq!(
+            op("PUT", "/buckets/b", ""),
+            Some("CreateBucket".to_string())
+        );
+        assert_eq!(
+            op("GET", "/buckets/b/objects", ""),
+            Some("ListObjects".to_string())
+        );
+        assert_eq!(
+            op("GET", "/buckets/b/objects/k", ""),
+            Some("GetObject".to_string())
+        );
+        assert_eq!(
+            op("PUT", "/buckets/b/objects/k", "legal-hold"),
+            Some("PutObjectLegalHold".to_string())
+        );
+        assert_eq!(
+            op("GET", "/buckets/b", "versioning"),
+            Some("GetBucketVersioning".to_string())
+        );
+        assert_eq!(op("GET", "/metrics", ""), None);
+    }
+
+    #[test]
+    fn test_unknown_buckets_share_a_label() {
+        let metrics = Metrics::new();
+        metrics.record_bucket_request("missing-1", "GetObject", 404, 0, 0);
+        metrics.record_bucket_request("missing-2", "ListObjects", 404, 0, 0);
+        metrics.record_bucket_request("b", "CreateBucket", 200, 0, 0);
+        metrics.record_bucket_request("b", "GetObject", 404, 0, 0);
+
+        let unknown = metrics.bucket_snapshot(UNKNOWN_BUCKET);
+        assert_eq!(unknown.errors_4xx, 2);
+        assert_eq!(metrics.bucket_snapshot("missing-1").requests.len(), 0);
+        // Once a bucket is tracked its failures are its own
+        assert_eq!(metrics.bucket_snapshot("b").errors_4xx, 1);
+        assert!(!metrics.render().contains("missing"));
+    }
+}
@@ -0,0 +1,151 @@
+// namespace.rs
+// Optional grouping above buckets. Requests under `/ns/{namespace}/buckets/...`
+// are routed like `/buckets/...` with the namespace attached to the request,
+// so separate teams can reuse bucket names. Storage keys a namespaced bucket
+// as `{namespace}/{bucket}`; buckets outside any namespace keep their name.
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::http::uri::PathAndQuery;
+use actix_web::http::{StatusCode, Uri};
+use actix_web::middleware::Next;
+use actix_web::{Error, FromRequest, HttpMessage, HttpRequest};
+use std::convert::Infallible;
+use std::future::{Ready, ready};
+
+use crate::S3Error;
+use crate::error_code::{ErrorCode, error_response};
+
+const NAMESPACE_PREFIX: &str = "/ns/";
+
+/// The namespace a request addresses; `None` for the default namespace.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Namespace(pub Option<String>);
+
+impl Namespace {
+    /// Returns the namespace a request addresses.
+    pub fn of(req: &HttpRequest) -> Self {
+        req.extensions()
+            .get::<Namespace>()
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns the storage name of `bucket` within this namespace.
+    pub fn bucket(&self, bucket: &str) -> Result<String, S3Error> {
+        // A slash would let a bucket name reach into another namespace
+        if bucket.contains('/') {
+            return Err(S3Error::InvalidRequest(format!(
+                "Bucket name '{}' must not contain '/'",
+                bucket
+            )));
+        }
+        Ok(match &self.0 {
+            Some(namespace) => format!("{}/{}", namespace, bucket),
+            None => bucket.to_string(),
+        })
+    }
+
+    /// Returns the URL path prefix addressing this namespace.
+    pub fn path_prefix(&self) -> String {
+        match &self.0 {
+            Some(namespace) => format!("{}{}", NAMESPACE_PREFIX, namespace),
+            None => String::new(),
+        }
+    }
+}
+
+impl FromRequest for Namespace {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(Namespace::of(req)))
+    }
+}
+
+/// Splits a storage bucket name into its namespace (empty for the default
+/// namespace) and the bucket name clients use.
+pub fn split_bucket(bucket: &str) -> (&str, &str) {
+    bucket.split_once('/').unwrap_or(("", bucket))
+}
+
+/// Splits `/ns/{namespace}/buckets...` into the namespace and the path
+/// below it.
+pub fn split_path(path: &str) -> Option<(&str, &str)> {
+    let rest = path.strip_prefix(NAMESPACE_PREFIX)?;
+    let (namespace, rest) = rest.split_at(rest.find('/')?);
+    (rest == "/buckets" || rest.starts_with("/buckets/")).then_some((namespace, rest))
+}
+
+/// Namespaces use the characters of DNS labels: 1-63 lowercase letters,
+/// digits and inner hyphens.
+pub fn is_valid_namespace(namespace: &str) -> bool {
+    (1..=63).contains(&namespace.len())
+        && namespace
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        && !namespace.starts_with('-')
+        && !namespace.ends_with('-')
+}
+
+/// Middleware routing namespaced requests to the bucket routes. Registered
+/// innermost so the other middlewares see the path the client sent.
+pub async fn strip_namespace_prefix(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    if let Some((namespace, rest)) = split_path(req.path()) {
+        if !is_valid_namespace(namespace) {
+            let response = error_response(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidRequest,
+                &format!("Invalid request: Invalid namespace '{}'", namespace),
+            );
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+        let namespace = namespace.to_string();
+        let path_and_query = match req.query_string() {
+            "" => rest.to_string(),
+            query => format!("{}?{}", rest, query),
+        };
+        let mut parts = req.head().uri.clone().into_parts();
+        parts.path_and_query = PathAndQuery::try_from(path_and_query).ok();
+        if let Ok(uri) = Uri::from_parts(parts) {
+            req.match_info_mut().get_mut().update(&uri);
+            req.head_mut().uri = uri;
+        }
+        req.extensions_mut().insert(Namespace(Some(namespace)));
+    }
+
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespaced_paths() {
+        assert_eq!(
+            split_path("/ns/team-a/buckets/photos/objects/k"),
+            Some(("team-a", "/buckets/photos/objects/k"))
+        );
+        assert_eq!(
+            split_path("/ns/team-a/buckets"),
+            Some(("team-a", "/buckets"))
+        );
+        assert_eq!(split_path("/ns/team-a/metrics"), None);
+        assert_eq!(split_path("/buckets/photos"), None);
+
+        assert!(is_valid_namespace("team-a"));
+        assert!(!is_valid_namespace("Team"));
+        assert!(!is_valid_namespace("-team"));
+
+        let namespace = Namespace(Some("team-a".to_string()));
+        assert_eq!(namespace.bucket("photos").unwrap(), "team-a/photos");
+        assert_eq!(split_bucket("team-a/photos"), ("team-a", "photos"));
+        assert_eq!(split_bucket("photos"), ("", "photos"));
+        assert!(Namespace::default().bucket("a/photos").is_err());
+    }
+}
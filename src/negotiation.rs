@@ -0,0 +1,318 @@
+// negotiation.rs
+// Compact binary renderings of JSON responses for high-volume machine
+// consumers. A request preferring `application/msgpack` or
+// `application/cbor` to JSON gets its listings, metadata and errors
+// serialized as MessagePack or CBOR instead: the same response structs with
+// the same field names, so clients decode the types they already know.
+// Handlers build those bodies with `Negotiate::negotiated`; other responses,
+// object data among them, pass through untouched.
+
+use std::cmp::Reverse;
+
+use actix_web::body::{BoxBody, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, HeaderValue, VARY};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, HttpResponseBuilder};
+use serde::Serialize;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::error_code::{ErrorBody, ErrorCode, error_response};
+
+pub const MSGPACK: &str = "application/msgpack";
+pub const CBOR: &str = "application/cbor";
+
+tokio::task_local! {
+    /// The binary format the request being handled is answered in, set by
+    /// `encode_binary_responses` around the handlers.
+    static RESPONSE_FORMAT: Option<Format>;
+}
+
+/// A binary format a response may be rendered in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    MessagePack,
+    Cbor,
+}
+
+/// Errors serializing a response body in a binary format.
+#[derive(Debug, Error)]
+pub enum EncodeError {
+    #[error("MessagePack encoding failed: {0}")]
+    MessagePack(#[from] rmp_serde::encode::Error),
+    #[error("CBOR encoding failed: {0}")]
+    Cbor(#[from] ciborium::ser::Error<std::io::Error>),
+}
+
+impl Format {
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Format::MessagePack => MSGPACK,
+            Format::Cbor => CBOR,
+        }
+    }
+
+    /// Serializes a value in the format, structs as maps keyed by their
+    /// field names.
+    pub fn encode<T: Serialize + ?Sized>(&self, value: &T) -> Result<Vec<u8>, EncodeError> {
+        match self {
+            Format::MessagePack => Ok(rmp_serde::to_vec_named(value)?),
+            Format::Cbor => {
+                let mut out = Vec::new();
+                ciborium::into_writer(value, &mut out)?;
+                Ok(out)
+            }
+        }
+    }
+}
+
+/// A media range of an `Accept` header, with its quality.
+struct MediaRange<'a> {
+    range: &'a str,
+    q: f32,
+}
+
+impl MediaRange<'_> {
+    /// How specifically the range names a media type: 2 for the type
+    /// itself, 1 for `type/*`, 0 for `*/*`, or `None` if it does not match.
+    fn specificity(&self, media_type: &str) -> Option<u8> {
+        if self.range.eq_ignore_ascii_case(media_type) {
+            return Some(2);
+        }
+        let (range_type, range_subtype) = self.range.split_once('/')?;
+        let (main_type, _) = media_type.split_once('/')?;
+        match (range_type, range_subtype) {
+            ("*", "*") => Some(0),
+            (t, "*") if t.eq_ignore_ascii_case(main_type) => Some(1),
+            _ => None,
+        }
+    }
+}
+
+/// Parses the media ranges of an `Accept` header, skipping malformed ones.
+fn media_ranges(accept: &str) -> Vec<MediaRange<'_>> {
+    accept
+        .split(',')
+        .filter_map(|range| {
+            let mut params = range.split(';');
+            let range = params.next()?.trim();
+            if !range.contains('/') {
+                return None;
+            }
+            let mut q = 1.0;
+            for param in params {
+                if let Some((name, value)) = param.split_once('=')
+                    && name.trim().eq_ignore_ascii_case("q")
+                {
+                    q = value.trim().parse::<f32>().ok()?;
+                }
+            }
+            (0.0..=1.0).contains(&q).then_some(MediaRange { range, q })
+        })
+        .collect()
+}
+
+/// The binary format an `Accept` header prefers to JSON, if any.
+///
+/// Each candidate takes the quality of the most specific range matching it,
+/// so `application/msgpack;q=0` refuses MessagePack even next to `*/*`. The
+/// highest quality wins; ties go to the more specific range, then to the one
+/// listed first, and then to JSON. JSON stays the answer to headers naming
+/// neither binary format.
+pub fn accepted_format(accept: &str) -> Option<Format> {
+    let ranges = media_ranges(accept);
+    let candidates: [(Option<Format>, &[&str]); 3] = [
+        (None, &["application/json"]),
+        (
+            Some(Format::MessagePack),
+            &[MSGPACK, "application/x-msgpack"],
+        ),
+        (Some(Format::Cbor), &[CBOR]),
+    ];
+    candidates
+        .into_iter()
+        .filter_map(|(format, media_types)| {
+            // The most specific matching range, the first of equally specific ones
+            let (specificity, position, q) = ranges
+                .iter()
+                .enumerate()
+                .filter_map(|(position, range)| {
+                    let specificity = media_types
+                        .iter()
+                        .filter_map(|media_type| range.specificity(media_type))
+                        .max()?;
+                    Some((specificity, Reverse(position), range.q))
+                })
+                .max_by_key(|(specificity, position, _)| (*specificity, *position))?;
+            (q > 0.0).then_some((format, (q, specificity, position)))
+        })
+        // On a full tie, as under a lone `*/*`, JSON first
+        .reduce(|best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        })
+        .and_then(|(format, _)| format)
+}
+
+/// Response builders rendering bodies in the format the request accepts.
+pub trait Negotiate {
+    /// Sets a serializable body and completes the response: as MessagePack
+    /// or CBOR if the request prefers one of them, as JSON otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The response struct to serialize.
+    ///
+    /// # Returns
+    ///
+    /// * `HttpResponse` - The response, or a 500 error if the value could not
+    ///   be encoded.
+    fn negotiated(&mut self, value: impl Serialize) -> HttpResponse;
+}
+
+impl Negotiate for HttpResponseBuilder {
+    fn negotiated(&mut self, value: impl Serialize) -> HttpResponse {
+        let format = RESPONSE_FORMAT.try_with(|format| *format).ok().flatten();
+        let Some(format) = format else {
+            return self.json(value);
+        };
+        match format.encode(&value) {
+            Ok(body) => self
+                .content_type(format.media_type())
+                .insert_header((VARY, "Accept"))
+                .body(body),
+            Err(e) => error_response(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::InternalError,
+                &e.to_string(),
+            ),
+        }
+    }
+}
+
+/// Middleware answering requests that prefer MessagePack or CBOR in that
+/// format. Handlers encode their own bodies through `Negotiate`, which reads
+/// the format this middleware sets for them; error bodies, wherever they
+/// were raised, are re-encoded here. Registered outside the error body
+/// middleware, so they are complete by then.
+pub async fn encode_binary_responses(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let format = req
+        .headers()
+        .get(ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .and_then(accepted_format);
+    let mut res = RESPONSE_FORMAT.scope(format, next.call(req)).await?;
+    let Some(format) = format else {
+        return Ok(res.map_into_left_body());
+    };
+    // Problem details stay JSON, as asked for
+    let is_json = res
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    let error = res.response().extensions().get::<ErrorBody>().cloned();
+    let Some(error) = error.filter(|_| is_json) else {
+        return Ok(res.map_into_left_body());
+    };
+    let body = match format.encode(&error) {
+        Ok(body) => body,
+        Err(e) => {
+            warn!(error = %e, "Sending error body as JSON");
+            return Ok(res.map_into_left_body());
+        }
+    };
+    let headers = res.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(format.media_type()));
+    headers.remove(CONTENT_LENGTH);
+    headers.insert(VARY, HeaderValue::from_static("Accept"));
+    Ok(res
+        .map_body(|_, _| BoxBody::new(body))
+        .map_into_right_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    #[test]
+    fn test_accepted_format() {
+        assert_eq!(
+            accepted_format("application/msgpack"),
+            Some(Format::MessagePack)
+        );
+        assert_eq!(
+            accepted_format("text/html, Application/CBOR;q=0.9"),
+            Some(Format::Cbor)
+        );
+        assert_eq!(accepted_format("application/json, */*"), None);
+        assert_eq!(accepted_format("*/*"), None);
+    }
+
+    #[test]
+    fn test_accepted_format_honours_quality() {
+        // The highest quality wins, wherever it is listed
+        assert_eq!(
+            accepted_format("application/msgpack;q=0.5, application/cbor"),
+            Some(Format::Cbor)
+        );
+        assert_eq!(
+            accepted_format("application/msgpack;q=0.5, application/json;q=0.8"),
+            None
+        );
+        // A quality of 0 refuses the type, even where a wildcard allows it
+        assert_eq!(accepted_format("application/msgpack;q=0"), None);
+        assert_eq!(accepted_format("application/*, application/cbor;q=0"), None);
+        // Ties go to the more specific range, then to the first listed
+        assert_eq!(
+            accepted_format("*/*, application/msgpack"),
+            Some(Format::MessagePack)
+        );
+        assert_eq!(
+            accepted_format("application/cbor, application/msgpack"),
+            Some(Format::Cbor)
+        );
+        // Malformed qualities drop the range
+        assert_eq!(accepted_format("application/cbor;q=high"), None);
+    }
+
+    #[derive(Serialize)]
+    struct Listing<'a> {
+        name: &'a str,
+        size: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        etag: Option<String>,
+        tags: Vec<bool>,
+    }
+
+    #[test]
+    fn test_encodes_structs_by_field_name() {
+        let listing = Listing {
+            name: "a",
+            size: 300,
+            etag: None,
+            tags: vec![true],
+        };
+        let expected = json!({"name": "a", "size": 300, "tags": [true]});
+
+        let msgpack = Format::MessagePack.encode(&listing).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Value>(&msgpack).unwrap(), expected);
+        let cbor = Format::Cbor.encode(&listing).unwrap();
+        assert_eq!(
+            ciborium::from_reader::<Value, _>(cbor.as_slice()).unwrap(),
+            expected
+        );
+        // Examples from RFC 8949, appendix A
+        assert_eq!(Format::Cbor.encode(&1000u32).unwrap(), [0x19, 0x03, 0xe8]);
+        assert_eq!(Format::Cbor.encode(&-100i32).unwrap(), [0x38, 0x63]);
+    }
+}
@@ -1,15 +1,19 @@
 // object.rs
 // This module defines the Object structure, representing a stored item within a bucket.
 
+use crate::clock::{Clock, SystemClock};
+use crate::replication::ReplicationStatus;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::str::FromStr;
 use std::time::SystemTimeError;
 use thiserror::Error;
+use utoipa::ToSchema;
 
 /// Represents an object stored within an S3-like bucket.
 /// It contains the object's key (its unique identifier within the bucket)
 /// and the actual binary data.
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 #[allow(dead_code)]
 pub struct Object {
     pub key: String,
@@ -22,6 +26,85 @@ pub struct Object {
     pub last_modified: i64,
     #[serde(skip_serializing)]
     pub user_metadata: Option<HashMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>, // Set when the bucket has versioning enabled
+    /// Whether the object can never be overwritten or deleted.
+    #[serde(default)]
+    pub immutable: bool,
+}
+
+/// Storage tier an object's data lives in. Lifecycle rules move objects
+/// from STANDARD to COLD once they reach a configured age.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum StorageClass {
+    Standard,
+    Cold,
+}
+
+impl StorageClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageClass::Standard => "STANDARD",
+            StorageClass::Cold => "COLD",
+        }
+    }
+}
+
+impl FromStr for StorageClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "STANDARD" => Ok(StorageClass::Standard),
+            "COLD" => Ok(StorageClass::Cold),
+            other => Err(format!("Unknown storage class: {}", other)),
+        }
+    }
+}
+
+/// Metadata of a stored object, as returned by HEAD requests, without the
+/// object's data.
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: u64,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replication_status: Option<ReplicationStatus>,
+    pub storage_class: StorageClass,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub immutable: bool,
+}
+
+/// A version of an object: its current one, or a noncurrent version or
+/// delete marker kept by a versioned bucket.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct ObjectVersion {
+    pub key: String,
+    /// `None` for objects written before versioning was enabled.
+    pub version_id: Option<String>,
+    pub size: u64,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: i64,
+    pub is_latest: bool,
+    pub is_delete_marker: bool,
+}
+
+/// Result of re-hashing an object's file against its stored ETag.
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct ObjectVerification {
+    pub key: String,
+    pub stored_etag: Option<String>,
+    pub actual_etag: String,
+    pub valid: bool,
+    /// Whether the stored ETag was replaced with the actual one.
+    pub repaired: bool,
 }
 
 /// Custom error type for operations within the object module.
@@ -33,7 +116,8 @@ pub enum ObjectError {
 }
 
 impl Object {
-    /// Creates a new Object instance.
+    /// Creates a new Object instance, modified now by the system clock. The
+    /// storage stamps the object with its own clock when storing it.
     ///
     /// # Arguments
     ///
@@ -58,9 +142,7 @@ impl Object {
         content_type: Option<String>,
         user_metadata: Option<HashMap<String, String>>,
     ) -> Result<Self, ObjectError> {
-        let last_modified = std::time::SystemTime::now()
-            .duration_since(std::time::SystemTime::UNIX_EPOCH)? // Use '?' to propagate the error
-            .as_secs() as i64;
+        let last_modified = SystemClock.unix_secs()?;
         Ok(Object {
             key,
             data,
@@ -68,9 +150,17 @@ impl Object {
             etag: None,
             last_modified,
             user_metadata,
+            version_id: None,
+            immutable: false,
         })
     }
 
+    /// Marks the object as never to be overwritten or deleted once stored.
+    pub fn with_immutable(mut self, immutable: bool) -> Self {
+        self.immutable = immutable;
+        self
+    }
+
     /// Returns the size of the object data in bytes.
     ///
     /// # Returns
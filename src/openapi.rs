@@ -0,0 +1,437 @@
+// openapi.rs
+// OpenAPI 3 description of the HTTP API, served at /openapi.json with a
+// Swagger UI under /swagger-ui/ for client developers generating bindings.
+// It is derived from the `#[utoipa::path]` attributes on the handlers and
+// the `ToSchema` types they send and accept, so it follows the code rather
+// than being written next to it. Operations S3 selects by a query flag, such
+// as `GET /buckets/{bucket_name}?versioning`, are listed under paths that
+// include the flag, as S3's own descriptions do, since OpenAPI tells
+// operations apart by path and method alone. Bucket paths also work under
+// `/ns/{namespace}` (see namespace.rs); they are listed once, without it.
+
+#![allow(dead_code)] // Path shapes and bodies are described here, never built
+
+use std::collections::HashMap;
+
+use serde::Serialize;
+use utoipa::openapi::path::Operation;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme};
+use utoipa::openapi::{Content, Ref, RefOr, Response};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+
+use crate::error_code::PROBLEM_JSON;
+use crate::negotiation::{CBOR, MSGPACK};
+use crate::structs::{ObjectChecksumListResponse, ObjectDetailListResponse, ObjectListResponse};
+use crate::usage::{NamedUsage, UsageReport};
+use crate::{
+    access, acl, backup, bucket, cache, config, copy, error_code, folder, handlers, metrics,
+    object, rehash, reload, replication, roles, s3_service, share, storage, structs, tus,
+    upload_progress, usage,
+};
+
+pub const OPENAPI_PATH: &str = "/openapi.json";
+pub const SWAGGER_UI_PATH: &str = "/swagger-ui";
+
+/// Name of the response every operation may fail with.
+const ERROR_RESPONSE: &str = "Error";
+/// Name of the bearer token security scheme.
+const BEARER_SCHEME: &str = "bearer";
+
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        handlers::list_buckets_handler,
+        handlers::create_bucket_handler,
+        handlers::head_bucket_handler,
+        handlers::delete_bucket_handler,
+        handlers::post_object_handler,
+        handlers::start_copy_handler,
+        handlers::get_bucket_versioning_handler,
+        handlers::put_bucket_versioning_handler,
+        handlers::get_bucket_worm_handler,
+        handlers::put_bucket_worm_handler,
+        handlers::get_bucket_logging_handler,
+        handlers::put_bucket_logging_handler,
+        handlers::get_bucket_content_type_handler,
+        handlers::put_bucket_content_type_handler,
+        handlers::get_bucket_metadata_handler,
+        handlers::put_bucket_metadata_handler,
+        handlers::get_bucket_access_report_handler,
+        handlers::get_bucket_metrics_handler,
+        handlers::get_bucket_lifecycle_handler,
+        handlers::put_bucket_lifecycle_handler,
+        handlers::get_bucket_replication_handler,
+        handlers::put_bucket_replication_handler,
+        handlers::get_bucket_roles_handler,
+        handlers::put_bucket_roles_handler,
+        handlers::get_bucket_acl_handler,
+        handlers::put_bucket_acl_handler,
+        handlers::list_bucket_aliases_handler,
+        handlers::put_bucket_alias_handler,
+        handlers::delete_bucket_alias_handler,
+        handlers::list_objects_handler,
+        handlers::delete_prefix_handler,
+        handlers::get_object_handler,
+        handlers::head_object_handler,
+        handlers::put_object_handler,
+        handlers::patch_object_handler,
+        handlers::delete_object_handler,
+        handlers::get_object_legal_hold_handler,
+        handlers::put_object_legal_hold_handler,
+        handlers::verify_object_handler,
+        handlers::compose_object_handler,
+        handlers::create_share_handler,
+        handlers::create_folder_handler,
+        handlers::list_folder_handler,
+        handlers::list_shares_handler,
+        handlers::revoke_share_handler,
+        handlers::get_share_handler,
+        handlers::tus_options_handler,
+        handlers::list_uploads_handler,
+        handlers::create_upload_handler,
+        handlers::head_upload_handler,
+        handlers::patch_upload_handler,
+        handlers::delete_upload_handler,
+        handlers::list_upload_parts_handler,
+        handlers::upload_progress_handler,
+        handlers::graphql_handler,
+        handlers::metrics_handler,
+        handlers::usage_stats_handler,
+        handlers::warm_cache_handler,
+        handlers::cache_stats_handler,
+        handlers::list_cache_pins_handler,
+        handlers::put_cache_pin_handler,
+        handlers::delete_cache_pin_handler,
+        handlers::get_read_only_handler,
+        handlers::set_read_only_handler,
+        handlers::get_maintenance_handler,
+        handlers::set_maintenance_handler,
+        handlers::get_log_level_handler,
+        handlers::set_log_level_handler,
+        handlers::reload_config_handler,
+        handlers::db_backup_status_handler,
+        handlers::start_db_backup_handler,
+        handlers::restore_bucket_handler,
+        handlers::copy_status_handler,
+        handlers::rehash_status_handler,
+        handlers::start_rehash_handler,
+    ),
+    components(schemas(
+        structs::ListResponse,
+        structs::BucketCreatedResponse,
+        structs::BucketDeletedResponse,
+        structs::ObjectCreatedResponse,
+        structs::ObjectComposedResponse,
+        structs::ObjectDeletedResponse,
+        structs::PrefixDeletedResponse,
+        structs::ObjectListResponse,
+        structs::ObjectDetailListResponse,
+        structs::ObjectChecksumEntry,
+        structs::ObjectChecksumListResponse,
+        structs::ListDetail,
+        structs::FolderListResponse,
+        structs::BucketVersioningResponse,
+        structs::VersioningConfiguration,
+        structs::BucketAliasesResponse,
+        structs::BucketWormResponse,
+        structs::WormConfiguration,
+        structs::BucketLoggingResponse,
+        structs::BucketLoggingConfiguration,
+        structs::BucketContentTypeResponse,
+        structs::BucketContentTypeConfiguration,
+        structs::BucketMetadataResponse,
+        structs::BucketMetadataConfiguration,
+        structs::BucketRolesResponse,
+        structs::BucketRolesConfiguration,
+        structs::BucketAclResponse,
+        structs::BucketAclConfiguration,
+        structs::ObjectVerificationResponse,
+        structs::ObjectLegalHoldResponse,
+        structs::LegalHoldConfiguration,
+        structs::ComposeConfiguration,
+        structs::ShareConfiguration,
+        structs::ShareCreatedResponse,
+        structs::BucketSharesResponse,
+        structs::BucketUploadsResponse,
+        structs::UploadPartsResponse,
+        structs::BucketReplicationResponse,
+        structs::ReplicationConfiguration,
+        structs::LifecycleConfiguration,
+        structs::BucketAccessReportResponse,
+        structs::BucketMetricsResponse,
+        structs::CacheWarmRequest,
+        structs::ReadOnlyStatus,
+        structs::MaintenanceStatus,
+        structs::LogLevel,
+        structs::BucketRestoreResponse,
+        access::ObjectAccess,
+        access::AccessReport,
+        acl::Grant,
+        acl::Permission,
+        backup::BackupState,
+        backup::BackupStatus,
+        bucket::LifecycleRule,
+        bucket::VersioningStatus,
+        cache::CachePin,
+        cache::CacheStats,
+        cache::CacheWarmReport,
+        config::EvictionPolicy,
+        copy::CopyState,
+        copy::CopyStatus,
+        error_code::ErrorBody,
+        error_code::ErrorCode,
+        folder::FolderListing,
+        metrics::BucketMetrics,
+        object::Object,
+        object::ObjectInfo,
+        object::ObjectVerification,
+        object::StorageClass,
+        rehash::RehashState,
+        rehash::RehashStatus,
+        reload::ReloadReport,
+        replication::ReplicationReport,
+        replication::ReplicationStatus,
+        roles::Role,
+        s3_service::PrefixDeleteReport,
+        share::Share,
+        storage::RestoreReport,
+        tus::Upload,
+        tus::UploadPart,
+        upload_progress::Progress,
+        usage::BucketUsage,
+        usage::NamedUsage,
+        usage::UsageReport,
+        ObjectData,
+        PostObjectForm,
+        ObjectListing,
+        UsageStats,
+        GraphqlRequest,
+        GraphqlResponse,
+    )),
+    modifiers(&Conventions),
+    tags(
+        (name = "buckets", description = "Buckets and their configuration"),
+        (name = "objects", description = "Objects, folders and share links"),
+        (name = "uploads", description = "Resumable uploads (tus 1.0)"),
+        (name = "admin", description = "Operation of the server"),
+        (name = "metadata", description = "Read-only GraphQL queries over the metadata"),
+    )
+)]
+pub struct ApiDoc;
+
+/// Additions made to every operation after the paths are collected.
+struct Conventions;
+
+impl Modify for Conventions {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        let mut error = Response::new("The request failed; `code` says why.");
+        error.content.insert(
+            "application/json".to_string(),
+            Content::new(Ref::from_schema_name("ErrorBody")),
+        );
+        error.content.insert(
+            PROBLEM_JSON.to_string(),
+            Content::new(Ref::from_schema_name("ErrorBody")),
+        );
+        components
+            .responses
+            .insert(ERROR_RESPONSE.to_string(), RefOr::T(error));
+        components.add_security_scheme(
+            BEARER_SCHEME,
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+        // Tokens are only checked when `[jwt]` is configured
+        openapi.security = Some(vec![
+            SecurityRequirement::new(BEARER_SCHEME, Vec::<String>::new()),
+            SecurityRequirement::default(),
+        ]);
+
+        for item in openapi.paths.paths.values_mut() {
+            for operation in item.operations.values_mut() {
+                tidy_docs(operation);
+                add_binary_formats(operation);
+                operation.responses.responses.insert(
+                    "default".to_string(),
+                    RefOr::Ref(Ref::from_response_name(ERROR_RESPONSE)),
+                );
+            }
+        }
+    }
+}
+
+/// Handler docs name their route, describe it, and then list their Rust
+/// arguments and result. Operations keep the description, with its first
+/// sentence as the summary, and are named after the handler.
+fn tidy_docs(operation: &mut Operation) {
+    let description = operation.description.take().unwrap_or_default();
+    let prose = description
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.starts_with('#'))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let summary = prose
+        .split_once(". ")
+        .map_or(prose.as_str(), |(first, _)| first);
+    operation.summary = Some(summary.trim_end_matches('.').to_string());
+    operation.description = Some(prose.clone());
+
+    if let Some(id) = &mut operation.operation_id
+        && let Some(name) = id.strip_suffix("_handler")
+    {
+        *id = name.to_string();
+    }
+}
+
+/// Lists the MessagePack and CBOR renderings of JSON responses, which
+/// clients get by preferring them in `Accept` (see negotiation.rs).
+fn add_binary_formats(operation: &mut Operation) {
+    for response in operation.responses.responses.values_mut() {
+        let RefOr::T(response) = response else {
+            continue;
+        };
+        if let Some(json) = response.content.get("application/json").cloned() {
+            response.content.insert(MSGPACK.to_string(), json.clone());
+            response.content.insert(CBOR.to_string(), json);
+        }
+    }
+}
+
+#[derive(IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct BucketPath {
+    /// Name or alias of the bucket.
+    bucket_name: String,
+}
+
+#[derive(IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct AliasPath {
+    /// Name of the bucket.
+    bucket_name: String,
+    /// Alternate name of the bucket.
+    alias: String,
+}
+
+#[derive(IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct ObjectPath {
+    /// Name or alias of the bucket.
+    bucket_name: String,
+    /// Key of the object.
+    object_key: String,
+}
+
+#[derive(IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct FolderPath {
+    /// Name or alias of the bucket.
+    bucket_name: String,
+    /// Key prefix of the folder, without the trailing `/`.
+    prefix: String,
+}
+
+#[derive(IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct SharePath {
+    /// Name or alias of the bucket.
+    bucket_name: String,
+    /// ID of the share link.
+    share_id: String,
+}
+
+#[derive(IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct ShareTokenPath {
+    /// Token of the share link.
+    token: String,
+}
+
+#[derive(IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct UploadPath {
+    /// Name or alias of the bucket.
+    bucket_name: String,
+    /// ID of the upload.
+    upload_id: String,
+}
+
+#[derive(IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct CopyJobPath {
+    /// ID of the copy job.
+    id: String,
+}
+
+/// Object data, sent and received as is.
+#[derive(ToSchema)]
+#[schema(value_type = String, format = Binary)]
+pub struct ObjectData(Vec<u8>);
+
+/// The multipart form of a browser upload signed with a POST policy. The
+/// fields must precede `file`; other fields the policy allows, such as
+/// `Content-Type` or `x-amz-meta-*`, may be added.
+#[derive(Serialize, ToSchema)]
+pub struct PostObjectForm {
+    /// Key to store the file under; `${filename}` is replaced with its name.
+    key: String,
+    /// The base64-encoded policy document.
+    policy: String,
+    #[serde(rename = "x-amz-algorithm")]
+    #[schema(example = "AWS4-HMAC-SHA256")]
+    algorithm: String,
+    #[serde(rename = "x-amz-credential")]
+    credential: String,
+    #[serde(rename = "x-amz-date")]
+    date: String,
+    #[serde(rename = "x-amz-signature")]
+    signature: String,
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
+/// The listing of GET /buckets/{bucket_name}/objects, by `detail` and
+/// `checksum`.
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum ObjectListing {
+    Keys(ObjectListResponse),
+    Detailed(ObjectDetailListResponse),
+    Checksums(ObjectChecksumListResponse),
+}
+
+/// The report of GET /admin/stats: one bucket's usage if `bucket` is
+/// given, every bucket's otherwise.
+#[derive(Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum UsageStats {
+    Bucket(NamedUsage),
+    All(UsageReport),
+}
+
+/// A GraphQL request.
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphqlRequest {
+    query: String,
+    operation_name: Option<String>,
+    #[schema(value_type = Option<Object>)]
+    variables: Option<HashMap<String, serde_json::Value>>,
+}
+
+/// A GraphQL response. Errors of the query are reported in `errors`, with
+/// status 200.
+#[derive(Serialize, ToSchema)]
+pub struct GraphqlResponse {
+    #[schema(value_type = Option<Object>)]
+    data: Option<serde_json::Value>,
+    #[schema(value_type = Option<Vec<Object>>)]
+    errors: Option<Vec<serde_json::Value>>,
+}
@@ -0,0 +1,134 @@
+// placement.rs
+// Spreading object files over several data directories, e.g. one per disk,
+// so a store can outgrow a single volume without RAID. The first directory
+// (`[storage] data_dir`) also holds uploads, the cold tier and `lost+found`;
+// new object files go to any of them, picked in turn (`round-robin`) or by
+// the most free space (`most-free`). Each object's recorded file path names
+// the directory it landed in, so reads never depend on the policy.
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::disk::disk_usage;
+
+/// How new object files are spread over the data directories.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Placement {
+    /// Each directory in turn.
+    #[default]
+    RoundRobin,
+    /// The directory on the volume with the most space available.
+    MostFree,
+}
+
+/// The data directories of a store and the policy choosing among them.
+#[derive(Debug)]
+pub struct DataRoots {
+    roots: Vec<PathBuf>,
+    placement: Placement,
+    next: AtomicUsize,
+}
+
+impl DataRoots {
+    /// A single data directory, holding everything.
+    pub fn single(root: PathBuf) -> Self {
+        Self::new(vec![root], Placement::default())
+    }
+
+    /// # Arguments
+    ///
+    /// * `roots` - The data directories, the primary one first; not empty.
+    /// * `placement` - How new object files are spread over them.
+    pub fn new(roots: Vec<PathBuf>, placement: Placement) -> Self {
+        assert!(!roots.is_empty(), "at least one data directory is needed");
+        Self {
+            roots,
+            placement,
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn roots(&self) -> &[PathBuf] {
+        &self.roots
+    }
+
+    /// The directory the next object file goes to. Directories whose free
+    /// space cannot be read count as full.
+    pub fn pick(&self) -> &Path {
+        if self.roots.len() == 1 {
+            return &self.roots[0];
+        }
+        match self.placement {
+            Placement::RoundRobin => {
+                let next = self.next.fetch_add(1, Ordering::Relaxed);
+                &self.roots[next % self.roots.len()]
+            }
+            Placement::MostFree => self
+                .roots
+                .iter()
+                .max_by_key(|root| disk_usage(root).map_or(0, |usage| usage.available_bytes))
+                .unwrap_or(&self.roots[0]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object::Object;
+    use crate::storage::{Storage, StorageError};
+
+    #[test]
+    fn test_round_robin() {
+        let roots = DataRoots::new(
+            vec![PathBuf::from("a"), PathBuf::from("b")],
+            Placement::RoundRobin,
+        );
+        let picked: Vec<&Path> = (0..3).map(|_| roots.pick()).collect();
+        assert_eq!(picked, [Path::new("a"), Path::new("b"), Path::new("a")]);
+
+        let roots = DataRoots::new(vec![PathBuf::from("/")], Placement::MostFree);
+        assert_eq!(roots.pick(), Path::new("/"));
+    }
+
+    #[test]
+    fn test_storage_spreads_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir
+            .path()
+            .join("s3_storage.db")
+            .to_string_lossy()
+            .to_string();
+        let (first, second) = (dir.path().join("disk1"), dir.path().join("disk2"));
+        let mut storage = Storage::open(&db_path, &first)
+            .unwrap()
+            .with_extra_data_dirs(std::slice::from_ref(&second), Placement::RoundRobin)
+            .unwrap();
+        storage.create_bucket("b").unwrap();
+        for key in ["k1", "k2"] {
+            let object = Object::new(key.to_string(), key.as_bytes().to_vec(), None, None);
+            storage.put_object("b", object.unwrap()).unwrap();
+        }
+        for (key, root) in [("k1", &first), ("k2", &second)] {
+            assert_eq!(storage.get_object("b", key).unwrap().data, key.as_bytes());
+            assert!(root.join("buckets").join("b").exists());
+        }
+        assert!(storage.fsck(false).unwrap().is_clean());
+        drop(storage);
+
+        // Dropping a directory that holds object files is refused, as are
+        // nested directories
+        let open = || Storage::open(&db_path, &first).unwrap();
+        assert!(matches!(
+            open().with_extra_data_dirs(&[], Placement::RoundRobin),
+            Err(StorageError::DataDirMissing(_))
+        ));
+        let nested = [second.clone(), first.join("nested")];
+        assert!(matches!(
+            open().with_extra_data_dirs(&nested, Placement::MostFree),
+            Err(StorageError::InvalidDataDir(..))
+        ));
+    }
+}
@@ -0,0 +1,287 @@
+// post_policy.rs
+// Browser-based uploads with S3-style POST policy documents. A web app signs a
+// policy restricting what may be uploaded (key prefix, size, content type, ...)
+// and hands it to the browser, which POSTs it with the file as a multipart form.
+// Only SigV4 (`AWS4-HMAC-SHA256`) signed policies are accepted.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde_json::Value;
+use std::collections::HashMap;
+use thiserror::Error;
+
+use crate::config::Credentials;
+use crate::signing::{self, CredentialScope, SIGV4_ALGORITHM};
+
+/// Form fields that need not be covered by a policy condition.
+const UNCHECKED_FIELDS: [&str; 4] = ["bucket", "policy", "x-amz-signature", "file"];
+
+/// Custom error type for rejected POST policy uploads.
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    #[error("Missing form field '{0}'")]
+    MissingField(&'static str),
+    #[error("Invalid policy document: {0}")]
+    Malformed(String),
+    #[error("Unsupported signature algorithm '{0}'")]
+    UnsupportedAlgorithm(String),
+    #[error("The access key id '{0}' does not exist")]
+    InvalidAccessKeyId(String),
+    #[error("The policy signature does not match")]
+    SignatureMismatch,
+    #[error("Invalid according to Policy: Policy expired")]
+    Expired,
+    #[error("Invalid according to Policy: Policy Condition failed: {0}")]
+    ConditionFailed(String),
+    #[error("Invalid according to Policy: Extra input fields: {0}")]
+    ExtraInputField(String),
+}
+
+/// A single restriction of a policy document. Field names are lowercase and
+/// without the leading `$`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    Eq(String, String),
+    StartsWith(String, String),
+    ContentLengthRange(u64, u64),
+}
+
+impl Condition {
+    fn parse(value: &Value) -> Result<Self, PolicyError> {
+        let malformed = || PolicyError::Malformed(format!("Invalid condition: {}", value));
+        let field = |v: &Value| {
+            v.as_str()
+                .map(|s| s.trim_start_matches('$').to_ascii_lowercase())
+                .ok_or_else(malformed)
+        };
+        match value {
+            // {"bucket": "photos"} is shorthand for ["eq", "$bucket", "photos"]
+            Value::Object(map) if map.len() == 1 => {
+                let (name, expected) = map.iter().next().ok_or_else(malformed)?;
+                let expected = expected.as_str().ok_or_else(malformed)?;
+                Ok(Condition::Eq(
+                    name.trim_start_matches('$').to_ascii_lowercase(),
+                    expected.to_string(),
+                ))
+            }
+            Value::Array(items) if items.len() == 3 => {
+                let op = items[0].as_str().ok_or_else(malformed)?;
+                match op.to_ascii_lowercase().as_str() {
+                    "eq" => Ok(Condition::Eq(
+                        field(&items[1])?,
+                        items[2].as_str().ok_or_else(malformed)?.to_string(),
+                    )),
+                    "starts-with" => Ok(Condition::StartsWith(
+                        field(&items[1])?,
+                        items[2].as_str().ok_or_else(malformed)?.to_string(),
+                    )),
+                    "content-length-range" => Ok(Condition::ContentLengthRange(
+                        items[1].as_u64().ok_or_else(malformed)?,
+                        items[2].as_u64().ok_or_else(malformed)?,
+                    )),
+                    _ => Err(malformed()),
+                }
+            }
+            _ => Err(malformed()),
+        }
+    }
+
+    /// The form field the condition applies to, if any.
+    fn field(&self) -> Option<&str> {
+        match self {
+            Condition::Eq(field, _) | Condition::StartsWith(field, _) => Some(field),
+            Condition::ContentLengthRange(..) => None,
+        }
+    }
+}
+
+/// A decoded POST policy document.
+#[derive(Debug, Clone)]
+pub struct PostPolicy {
+    /// Unix time after which the policy is no longer accepted.
+    pub expiration: i64,
+    pub conditions: Vec<Condition>,
+}
+
+impl PostPolicy {
+    /// Decodes a base64-encoded policy document.
+    pub fn decode(encoded: &str) -> Result<Self, PolicyError> {
+        let json = STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| PolicyError::Malformed(e.to_string()))?;
+        let document: Value =
+            serde_json::from_slice(&json).map_err(|e| PolicyError::Malformed(e.to_string()))?;
+
+        let expiration = document["expiration"]
+            .as_str()
+            .and_then(parse_timestamp)
+            .ok_or_else(|| PolicyError::Malformed("Invalid or missing expiration".to_string()))?;
+        let conditions = document["conditions"]
+            .as_array()
+            .ok_or_else(|| PolicyError::Malformed("Missing conditions".to_string()))?
+            .iter()
+            .map(Condition::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            expiration,
+            conditions,
+        })
+    }
+
+    /// The allowed size range of the uploaded file, if the policy limits it.
+    pub fn content_length_range(&self) -> Option<(u64, u64)> {
+        self.conditions.iter().find_map(|c| match c {
+            Condition::ContentLengthRange(min, max) => Some((*min, *max)),
+            _ => None,
+        })
+    }
+
+    /// Checks the form fields (lowercase names, including `bucket`) against
+    /// the policy at `now`. Every field other than the signature, the policy
+    /// itself and `x-ignore-*` fields must be covered by a condition.
+    pub fn check(&self, fields: &HashMap<String, String>, now: i64) -> Result<(), PolicyError> {
+        if now >= self.expiration {
+            return Err(PolicyError::Expired);
+        }
+        for condition in &self.conditions {
+            let value = condition
+                .field()
+                .map(|f| fields.get(f).map(String::as_str).unwrap_or(""));
+            let satisfied = match (condition, value) {
+                (Condition::Eq(_, expected), Some(value)) => value == expected,
+                (Condition::StartsWith(_, prefix), Some(value)) => value.starts_with(prefix),
+                _ => true,
+            };
+            if !satisfied {
+                return Err(PolicyError::ConditionFailed(format!("{:?}", condition)));
+            }
+        }
+        let extra = fields.keys().find(|name| {
+            !UNCHECKED_FIELDS.contains(&name.as_str())
+                && !name.starts_with("x-ignore-")
+                && !self.conditions.iter().any(|c| c.field() == Some(name))
+        });
+        match extra {
+            Some(name) => Err(PolicyError::ExtraInputField(name.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Verifies the SigV4 signature of the policy in `fields` and checks the
+/// policy against the fields, returning the policy for the size check.
+pub fn authorize(
+    fields: &HashMap<String, String>,
+    credentials: &Credentials,
+    now: i64,
+) -> Result<PostPolicy, PolicyError> {
+    let field = |name: &'static str| {
+        fields
+            .get(name)
+            .map(String::as_str)
+            .ok_or(PolicyError::MissingField(name))
+    };
+    let encoded_policy = field("policy")?;
+    let algorithm = field("x-amz-algorithm")?;
+    if algorithm != SIGV4_ALGORITHM {
+        return Err(PolicyError::UnsupportedAlgorithm(algorithm.to_string()));
+    }
+    let credential = field("x-amz-credential")?;
+    let scope = CredentialScope::parse(credential)
+        .ok_or_else(|| PolicyError::Malformed(format!("Invalid credential '{}'", credential)))?;
+    let secret = credentials
+        .secret_key(&scope.access_key)
+        .ok_or_else(|| PolicyError::InvalidAccessKeyId(scope.access_key.clone()))?;
+
+    let key = signing::signing_key(&secret, &scope.date, &scope.region, &scope.service);
+    let expected = signing::sign(&key, encoded_policy);
+    if !signing::signatures_match(&expected, &field("x-amz-signature")?.to_ascii_lowercase()) {
+        return Err(PolicyError::SignatureMismatch);
+    }
+
+    let policy = PostPolicy::decode(encoded_policy)?;
+    policy.check(fields, now)?;
+    Ok(policy)
+}
+
+/// Parses an ISO 8601 UTC timestamp such as `2026-01-01T12:00:00.000Z` into
+/// Unix seconds.
+fn parse_timestamp(s: &str) -> Option<i64> {
+    let s = s.strip_suffix('Z')?;
+    let (date, time) = s.split_once('T')?;
+    let mut date_parts = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (
+        date_parts.next()??,
+        date_parts.next()??,
+        date_parts.next()??,
+    );
+    let time = time.split('.').next()?;
+    let mut time_parts = time.splitn(3, ':').map(|p| p.parse::<i64>().ok());
+    let (hour, minute, second) = (
+        time_parts.next()??,
+        time_parts.next()??,
+        time_parts.next()??,
+    );
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since the Unix epoch of a proleptic Gregorian date
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_conditions() {
+        let document = r#"{
+            "expiration": "2030-01-01T00:00:00.000Z",
+            "conditions": [
+                {"bucket": "photos"},
+                ["starts-with", "$key", "user/alice/"],
+                ["starts-with", "$Content-Type", "image/"],
+                ["content-length-range", 1, 1024]
+            ]
+        }"#;
+        let policy = PostPolicy::decode(&STANDARD.encode(document)).unwrap();
+        assert_eq!(policy.expiration, 1893456000);
+        assert_eq!(policy.content_length_range(), Some((1, 1024)));
+
+        let mut fields: HashMap<String, String> = [
+            ("bucket", "photos"),
+            ("key", "user/alice/cat.png"),
+            ("content-type", "image/png"),
+            ("policy", "..."),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+        assert!(policy.check(&fields, 0).is_ok());
+        assert!(matches!(
+            policy.check(&fields, 1893456000),
+            Err(PolicyError::Expired)
+        ));
+
+        fields.insert("key".to_string(), "user/bob/cat.png".to_string());
+        assert!(matches!(
+            policy.check(&fields, 0),
+            Err(PolicyError::ConditionFailed(_))
+        ));
+
+        fields.insert("key".to_string(), "user/alice/cat.png".to_string());
+        fields.insert("acl".to_string(), "public-read".to_string());
+        assert!(matches!(
+            policy.check(&fields, 0),
+            Err(PolicyError::ExtraInputField(_))
+        ));
+    }
+}
@@ -0,0 +1,75 @@
+// range.rs
+// The `Content-Range` header of partial writes, `bytes {first}-{last}/{length}`
+// with `*` for an unknown complete length, as PATCH requests send it to
+// rewrite a span of an object.
+
+/// A byte span of an object, with the object's complete length if given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentRange {
+    /// Offset of the first byte of the span.
+    pub first: u64,
+    /// Offset of the last byte of the span, inclusive.
+    pub last: u64,
+    /// The complete length of the object after the write, if known.
+    pub complete_length: Option<u64>,
+}
+
+impl ContentRange {
+    /// Parses a `Content-Range` header value. Spans ending before they start
+    /// or at the largest offset, whose end would overflow, and complete
+    /// lengths the span does not fit in are refused.
+    pub fn parse(value: &str) -> Option<Self> {
+        let (span, length) = value.trim().strip_prefix("bytes ")?.split_once('/')?;
+        let (first, last) = span.split_once('-')?;
+        let first: u64 = first.parse().ok()?;
+        let last: u64 = last.parse().ok()?;
+        let complete_length = match length {
+            "*" => None,
+            length => Some(length.parse().ok()?),
+        };
+        if last < first || last == u64::MAX || complete_length.is_some_and(|length| last >= length)
+        {
+            return None;
+        }
+        Some(Self {
+            first,
+            last,
+            complete_length,
+        })
+    }
+
+    /// Number of bytes in the span.
+    pub fn byte_count(&self) -> u64 {
+        self.last - self.first + 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            ContentRange::parse("bytes 0-9/100"),
+            Some(ContentRange {
+                first: 0,
+                last: 9,
+                complete_length: Some(100)
+            })
+        );
+        let range = ContentRange::parse("bytes 10-10/*").unwrap();
+        assert_eq!(range.complete_length, None);
+        assert_eq!(range.byte_count(), 1);
+    }
+
+    #[test]
+    fn test_parse_refuses_invalid_ranges() {
+        assert_eq!(ContentRange::parse("bytes 9-0/100"), None);
+        assert_eq!(ContentRange::parse("bytes 0-100/100"), None);
+        assert_eq!(ContentRange::parse("bytes 0-18446744073709551615/*"), None);
+        assert_eq!(ContentRange::parse("bytes 0-9"), None);
+        assert_eq!(ContentRange::parse("items 0-9/100"), None);
+        assert_eq!(ContentRange::parse("bytes a-9/100"), None);
+    }
+}
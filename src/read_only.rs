@@ -0,0 +1,88 @@
+// read_only.rs
+// Operator-controlled read-only mode for backups, migrations and disk
+// incidents. While enabled, every mutating request is refused with 503 and
+// reads keep being served. It starts from the `read_only` config flag and is
+// toggled at runtime with POST /admin/read-only.
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, web};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error_code::{ErrorCode, error_response};
+use crate::graphql::GRAPHQL_PATH;
+
+/// Whether mutating requests are currently refused.
+#[derive(Debug, Default)]
+pub struct ReadOnlyMode {
+    enabled: AtomicBool,
+}
+
+impl ReadOnlyMode {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: AtomicBool::new(enabled),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// The response refusing a mutating request in read-only mode.
+pub fn refusal() -> HttpResponse {
+    error_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCode::ServiceUnavailable,
+        "ServiceUnavailable: The server is in read-only mode.",
+    )
+}
+
+/// Whether a request may change stored data. Admin requests are exempt so
+/// read-only mode can be switched off again, and so are GraphQL queries,
+/// which are posted but only read.
+pub fn is_mutation(method: &Method, path: &str) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+        && !path.starts_with("/admin/")
+        && path != GRAPHQL_PATH
+}
+
+/// Middleware refusing mutating requests while read-only mode is enabled.
+pub async fn reject_mutations_when_read_only(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let enabled = req
+        .app_data::<web::Data<Arc<ReadOnlyMode>>>()
+        .is_some_and(|mode| mode.is_enabled());
+
+    if enabled && is_mutation(req.method(), req.path()) {
+        return Ok(req.into_response(refusal()).map_into_right_body());
+    }
+
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mutations() {
+        assert!(is_mutation(&Method::PUT, "/buckets/b/objects/k"));
+        assert!(is_mutation(&Method::DELETE, "/buckets/b"));
+        assert!(is_mutation(&Method::PATCH, "/buckets/b/uploads/u"));
+        assert!(!is_mutation(&Method::GET, "/buckets/b/objects"));
+        assert!(!is_mutation(&Method::HEAD, "/buckets/b/objects/k"));
+        assert!(!is_mutation(&Method::POST, "/admin/read-only"));
+        assert!(!is_mutation(&Method::POST, "/graphql"));
+    }
+}
@@ -0,0 +1,175 @@
+// rehash.rs
+// Background conversion of stored checksums after `[storage] hash_algorithm`
+// changes. Objects are re-hashed in small batches, releasing the storage
+// lock in between, so the server keeps serving while the job runs.
+
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tokio::time;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+use crate::storage::Storage;
+
+/// Objects re-hashed per batch.
+const BATCH_SIZE: usize = 64;
+/// Pause between batches, leaving the storage to the request handlers.
+const BATCH_PAUSE: Duration = Duration::from_millis(10);
+
+/// Custom error type for starting a re-hash job.
+#[derive(Debug, Error)]
+pub enum RehashError {
+    #[error("A re-hash job is already running")]
+    InProgress,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RehashState {
+    #[default]
+    Idle,
+    Running,
+    Completed,
+    Failed,
+}
+
+/// Progress of the running or most recent re-hash job.
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct RehashStatus {
+    pub state: RehashState,
+    pub algorithm: Option<String>,
+    /// Objects and versions to re-hash when the job started.
+    pub total: u64,
+    pub processed: u64,
+    pub rehashed: u64,
+    /// Objects whose file no longer matched their old checksum; left as is.
+    pub mismatched: Vec<String>,
+    /// Objects whose file is gone.
+    pub missing: Vec<String>,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+    /// Estimated seconds until the job completes.
+    pub eta_secs: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Runs at most one re-hash job at a time and tracks its progress.
+pub struct RehashJob {
+    storage: Arc<Mutex<Storage>>,
+    status: std::sync::Mutex<RehashStatus>,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+impl RehashJob {
+    pub fn new(storage: Arc<Mutex<Storage>>) -> Self {
+        Self {
+            storage,
+            status: std::sync::Mutex::new(RehashStatus::default()),
+        }
+    }
+
+    /// Returns the progress of the running or most recent job.
+    pub fn status(&self) -> RehashStatus {
+        self.status
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut RehashStatus)) {
+        f(&mut self.status.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+
+    /// Starts re-hashing in the background and returns the initial status.
+    pub fn start(self: &Arc<Self>) -> Result<RehashStatus, RehashError> {
+        {
+            let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+            if status.state == RehashState::Running {
+                return Err(RehashError::InProgress);
+            }
+            *status = RehashStatus {
+                state: RehashState::Running,
+                started_at: Some(now()),
+                ..RehashStatus::default()
+            };
+        }
+
+        let job = self.clone();
+        tokio::spawn(async move {
+            let result = job.run().await;
+            job.update(|status| {
+                status.finished_at = Some(now());
+                status.eta_secs = None;
+                match &result {
+                    Ok(()) => status.state = RehashState::Completed,
+                    Err(e) => {
+                        status.state = RehashState::Failed;
+                        status.error = Some(e.clone());
+                    }
+                }
+            });
+            let status = job.status();
+            match result {
+                Ok(()) if status.mismatched.is_empty() && status.missing.is_empty() => {
+                    info!(rehashed = status.rehashed, "Re-hash job completed")
+                }
+                Ok(()) => warn!(
+                    rehashed = status.rehashed,
+                    mismatched = status.mismatched.len(),
+                    missing = status.missing.len(),
+                    "Re-hash job completed with objects left unconverted"
+                ),
+                Err(e) => error!(error = %e, "Re-hash job failed"),
+            }
+        });
+        Ok(self.status())
+    }
+
+    async fn run(&self) -> Result<(), String> {
+        let (algorithm, total) = {
+            let storage = self.storage.lock().await;
+            let total = storage.count_rehash_pending().map_err(|e| e.to_string())?;
+            (storage.hash_algorithm().to_string(), total)
+        };
+        self.update(|status| {
+            status.algorithm = Some(algorithm);
+            status.total = total;
+        });
+
+        let started = Instant::now();
+        let mut cursor = (-1, -1);
+        loop {
+            let batch = {
+                let mut storage = self.storage.lock().await;
+                storage
+                    .rehash_batch(cursor, BATCH_SIZE)
+                    .map_err(|e| e.to_string())?
+            };
+            self.update(|status| {
+                status.processed += batch.visited;
+                status.rehashed += batch.rehashed;
+                status.mismatched.extend(batch.mismatched);
+                status.missing.extend(batch.missing);
+                let remaining = status.total.saturating_sub(status.processed);
+                status.eta_secs = (status.processed > 0).then(|| {
+                    (started.elapsed().as_secs_f64() / status.processed as f64 * remaining as f64)
+                        .ceil() as u64
+                });
+            });
+            match batch.cursor {
+                Some(next) => cursor = next,
+                None => return Ok(()),
+            }
+            time::sleep(BATCH_PAUSE).await;
+        }
+    }
+}
@@ -0,0 +1,112 @@
+// reload.rs
+// Reloading the configuration without a restart. On SIGHUP or
+// POST /admin/reload the config file is read again and the settings that can
+// change under a running server, the request and bandwidth limits and the
+// log level, are applied in place. In-flight requests keep going; the other
+// settings only take effect on the next start.
+
+use serde::Serialize;
+use std::io;
+use std::sync::Arc;
+use tokio::signal::unix::{SignalKind, signal};
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::bandwidth::Bandwidth;
+use crate::config::{Config, ConfigError};
+use crate::log_control::LogControl;
+use crate::throttle::Throttle;
+
+/// What a reload applied.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ReloadReport {
+    pub reloaded: Vec<&'static str>,
+}
+
+/// Applies a reloaded configuration to the running server.
+#[derive(Debug)]
+pub struct ConfigReloader {
+    throttle: Arc<Throttle>,
+    bandwidth: Arc<Bandwidth>,
+    log_control: Option<Arc<LogControl>>,
+}
+
+impl ConfigReloader {
+    pub fn new(throttle: Arc<Throttle>, bandwidth: Arc<Bandwidth>) -> Self {
+        Self {
+            throttle,
+            bandwidth,
+            log_control: None,
+        }
+    }
+
+    /// Also apply the configured log level on reloads.
+    pub fn with_log_control(mut self, log_control: Arc<LogControl>) -> Self {
+        self.log_control = Some(log_control);
+        self
+    }
+
+    /// Reads the configuration again, from where it was loaded at startup,
+    /// and applies it. A file that fails to load changes nothing.
+    pub fn reload(&self) -> Result<ReloadReport, ConfigError> {
+        let config = Config::load()?;
+        Ok(self.apply(config))
+    }
+
+    /// Applies the reloadable settings of `config`. An invalid log level
+    /// is reported and leaves the current one in place.
+    pub fn apply(&self, config: Config) -> ReloadReport {
+        self.throttle.reload(config.throttle);
+        self.bandwidth.reload(config.bandwidth);
+        let mut reloaded = vec!["throttle", "bandwidth"];
+        if let Some(log_control) = &self.log_control {
+            match log_control.apply_config(&config.logging) {
+                Ok(true) => reloaded.push("logging.level"),
+                Ok(false) => {}
+                Err(e) => error!("Log level not reloaded: {}", e),
+            }
+        }
+        info!(?reloaded, "Configuration reloaded");
+        ReloadReport { reloaded }
+    }
+
+    /// Start reloading the configuration on every SIGHUP
+    pub fn start_on_hangup(self: Arc<Self>) -> io::Result<tokio::task::JoinHandle<()>> {
+        let mut hangups = signal(SignalKind::hangup())?;
+        Ok(tokio::spawn(async move {
+            while hangups.recv().await.is_some() {
+                if let Err(e) = self.reload() {
+                    error!("Failed to reload configuration: {}", e);
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BandwidthConfig, ThrottleConfig, ThrottleLimits};
+
+    #[test]
+    fn test_apply_replaces_limits() {
+        let throttle = Arc::new(Throttle::new(ThrottleConfig {
+            default: ThrottleLimits {
+                requests_per_second: 1,
+                bytes_per_second: 0,
+            },
+            ..ThrottleConfig::default()
+        }));
+        let reloader = ConfigReloader::new(
+            throttle.clone(),
+            Arc::new(Bandwidth::new(BandwidthConfig::default())),
+        );
+        assert!(throttle.admit("key", 0).is_ok());
+        assert!(throttle.admit("key", 0).is_err());
+
+        let report = reloader.apply(Config::default());
+        assert_eq!(report.reloaded, ["throttle", "bandwidth"]);
+        assert!(throttle.admit("key", 0).is_ok());
+        assert!(throttle.admit("key", 0).is_ok());
+    }
+}
@@ -0,0 +1,349 @@
+// replication.rs
+// Asynchronous replication of objects to a peer instance of this service.
+// Buckets with a replication destination mark every new write as PENDING;
+// the Replicator ships those objects to the peer and records the outcome.
+
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tokio::time;
+use tracing::{error, info, warn};
+use utoipa::ToSchema;
+
+use crate::http_client::{self, Timeouts};
+use crate::object::Object;
+use crate::storage::{Storage, StorageError};
+
+/// Characters left unescaped when building request paths for the peer.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// Replication state of a single object, reported as `x-amz-replication-status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum ReplicationStatus {
+    Pending,
+    Completed,
+    Failed,
+}
+
+impl ReplicationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReplicationStatus::Pending => "PENDING",
+            ReplicationStatus::Completed => "COMPLETED",
+            ReplicationStatus::Failed => "FAILED",
+        }
+    }
+}
+
+impl FromStr for ReplicationStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PENDING" => Ok(ReplicationStatus::Pending),
+            "COMPLETED" => Ok(ReplicationStatus::Completed),
+            "FAILED" => Ok(ReplicationStatus::Failed),
+            other => Err(format!("Unknown replication status: {}", other)),
+        }
+    }
+}
+
+/// How far behind the replication destination of a bucket is.
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct ReplicationReport {
+    pub destination: Option<String>,
+    pub pending: i64,
+    pub completed: i64,
+    pub failed: i64,
+    /// Last-modified time of the oldest object not yet on the peer.
+    pub oldest_unreplicated: Option<i64>,
+}
+
+/// Custom error type for shipping objects to a peer.
+#[derive(Debug, Error)]
+pub enum ReplicationError {
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid replication destination: {0}")]
+    InvalidDestination(String),
+    #[error("Peer rejected object: {0}")]
+    Rejected(String),
+}
+
+/// Background task that ships pending objects to their bucket's destination
+pub struct Replicator {
+    storage: Arc<Mutex<Storage>>,
+    interval: Duration,
+    batch_size: usize,
+    timeouts: Timeouts,
+}
+
+impl Replicator {
+    /// Create a new Replicator
+    pub fn new(storage: Arc<Mutex<Storage>>, interval: Duration) -> Self {
+        Self {
+            storage,
+            interval,
+            batch_size: 100,
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// Limits the time a push may take, so a hung peer fails the object
+    /// instead of stalling the replicator.
+    #[allow(dead_code)] // Driven by tests, through the library
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Start the background replicator
+    pub fn start(self) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = time::interval(self.interval);
+
+            loop {
+                interval.tick().await;
+
+                match self.run_once().await {
+                    Ok(0) => {}
+                    Ok(n) => info!("Replicated {} objects", n),
+                    Err(e) => error!("Replication pass failed: {}", e),
+                }
+            }
+        })
+    }
+
+    /// Ship one batch of pending objects, returning how many succeeded
+    async fn run_once(&self) -> Result<usize, ReplicationError> {
+        let pending = {
+            let storage = self.storage.lock().await;
+            storage.pending_replications(self.batch_size)?
+        };
+
+        let mut replicated = 0;
+        for (bucket, key, destination) in pending {
+            let object = {
+                let storage = self.storage.lock().await;
+                storage.get_object(&bucket, &key)
+            };
+            let object = match object {
+                Ok(object) => object,
+                // Deleted since the batch was read
+                Err(StorageError::ObjectNotFound(_, _)) => continue,
+                Err(e) => return Err(e.into()),
+            };
+            let status = match push_object(self.timeouts, &destination, &bucket, &object).await {
+                Ok(()) => {
+                    replicated += 1;
+                    ReplicationStatus::Completed
+                }
+                Err(e) => {
+                    warn!(bucket = %bucket, key = %key, error = %e, "Failed to replicate object");
+                    ReplicationStatus::Failed
+                }
+            };
+            // An overwrite during the push stays pending
+            let mut storage = self.storage.lock().await;
+            storage.set_replication_status(&bucket, &key, &object, status)?;
+        }
+        Ok(replicated)
+    }
+}
+
+/// PUTs an object to a peer at `destination` (an `http://host:port` base URL)
+/// using the peer's object API.
+async fn push_object(
+    timeouts: Timeouts,
+    destination: &str,
+    bucket: &str,
+    object: &Object,
+) -> Result<(), ReplicationError> {
+    let authority = destination
+        .strip_prefix("http://")
+        .map(|rest| rest.trim_end_matches('/'))
+        .filter(|rest| !rest.is_empty() && !rest.contains('/'))
+        .ok_or_else(|| ReplicationError::InvalidDestination(destination.to_string()))?;
+
+    let path = format!(
+        "/buckets/{}/objects/{}",
+        utf8_percent_encode(bucket, PATH_SEGMENT),
+        utf8_percent_encode(&object.key, PATH_SEGMENT)
+    );
+    let metadata: Vec<(String, &str)> = object
+        .user_metadata
+        .iter()
+        .flatten()
+        .map(|(name, value)| (format!("x-user-meta-{}", name), value.as_str()))
+        .collect();
+    let mut headers: Vec<(&str, &str)> = metadata
+        .iter()
+        .map(|(name, value)| (name.as_str(), *value))
+        .collect();
+    if let Some(content_type) = &object.content_type {
+        headers.push(("Content-Type", content_type));
+    }
+
+    let response =
+        http_client::send_within(timeouts, authority, "PUT", &path, &headers, &object.data).await?;
+    match response.status {
+        200..=299 => Ok(()),
+        status => Err(ReplicationError::Rejected(format!(
+            "{} {}",
+            status,
+            response.text()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// A peer accepting objects whose key does not start with `bad`.
+    async fn spawn_peer() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                // The head, then as much body as it announces
+                let (head_len, body_len) = loop {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&request);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let len = text[..end]
+                            .lines()
+                            .find_map(|line| line.strip_prefix("Content-Length: "))
+                            .and_then(|len| len.trim().parse::<usize>().ok())
+                            .unwrap_or(0);
+                        break (end + 4, len);
+                    }
+                };
+                while request.len() < head_len + body_len {
+                    let n = stream.read(&mut buf).await.unwrap();
+                    request.extend_from_slice(&buf[..n]);
+                }
+                let status = if request.starts_with(b"PUT /buckets/b/objects/bad") {
+                    "500 Internal Server Error"
+                } else {
+                    "200 OK"
+                };
+                let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status);
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn put(storage: &mut Storage, key: &str, data: &[u8]) -> Object {
+        let object = Object::new(key.to_string(), data.to_vec(), None, None).unwrap();
+        storage.put_object("b", object).unwrap();
+        storage.get_object("b", key).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_run_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = Storage::open(
+            &dir.path().join("s3_storage.db").to_string_lossy(),
+            dir.path().join("data"),
+        )
+        .unwrap();
+        storage.create_bucket("b").unwrap();
+        let peer = spawn_peer().await;
+        storage.set_bucket_replication("b", Some(&peer)).unwrap();
+        put(&mut storage, "bad", b"refused");
+        put(&mut storage, "good", b"accepted");
+        let storage = Arc::new(Mutex::new(storage));
+        let replicator = Replicator::new(storage.clone(), Duration::from_secs(60));
+
+        assert_eq!(replicator.run_once().await.unwrap(), 1);
+        let report = storage.lock().await.replication_report("b").unwrap();
+        assert_eq!((report.pending, report.completed, report.failed), (0, 1, 1));
+
+        // New writes go ahead of objects the peer refused
+        put(&mut *storage.lock().await, "new", b"accepted");
+        let pending = storage.lock().await.pending_replications(1).unwrap();
+        assert_eq!(pending[0].1, "new");
+    }
+
+    #[tokio::test]
+    async fn test_hung_peer_fails_the_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = Storage::open(
+            &dir.path().join("s3_storage.db").to_string_lossy(),
+            dir.path().join("data"),
+        )
+        .unwrap();
+        storage.create_bucket("b").unwrap();
+        // A peer accepting connections and never answering
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let peer = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+        storage.set_bucket_replication("b", Some(&peer)).unwrap();
+        put(&mut storage, "k", b"stuck");
+        let storage = Arc::new(Mutex::new(storage));
+        let replicator =
+            Replicator::new(storage.clone(), Duration::from_secs(60)).with_timeouts(Timeouts {
+                connect: Duration::from_secs(5),
+                io: Duration::from_millis(50),
+            });
+
+        assert_eq!(replicator.run_once().await.unwrap(), 0);
+        let report = storage.lock().await.replication_report("b").unwrap();
+        assert_eq!((report.pending, report.failed), (0, 1));
+    }
+
+    #[test]
+    fn test_overwrite_during_push_stays_pending() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = Storage::open(
+            &dir.path().join("s3_storage.db").to_string_lossy(),
+            dir.path().join("data"),
+        )
+        .unwrap();
+        storage.create_bucket("b").unwrap();
+        storage
+            .set_bucket_replication("b", Some("http://127.0.0.1:1"))
+            .unwrap();
+        let shipped = put(&mut storage, "k", b"first");
+        put(&mut storage, "k", b"second");
+
+        assert!(
+            !storage
+                .set_replication_status("b", "k", &shipped, ReplicationStatus::Completed)
+                .unwrap()
+        );
+        assert_eq!(storage.replication_report("b").unwrap().pending, 1);
+
+        let current = storage.get_object("b", "k").unwrap();
+        assert!(
+            storage
+                .set_replication_status("b", "k", &current, ReplicationStatus::Completed)
+                .unwrap()
+        );
+        assert_eq!(storage.replication_report("b").unwrap().completed, 1);
+    }
+}
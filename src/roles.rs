@@ -0,0 +1,293 @@
+// roles.rs
+// Role-based access control per bucket, a simpler alternative to full
+// policies for small deployments. A bucket's admin assigns principals one of
+// three roles with PUT /buckets/{bucket}?roles: readers may read, writers
+// may also change objects and admins may also change the bucket's settings,
+// aliases and roles and delete it. Buckets without roles stay open to every
+// request; once a bucket has any, only principals holding a role, or the
+// `s3:admin` scope, may reach it. Principals are those of verified bearer
+// tokens (see jwt.rs), since access keys are not verified on every request;
+// a token still needs the scope of each request, so roles narrow what it may
+// do rather than widen it. ACL grants (see acl.rs) are checked alongside the
+// roles. Both are kept in memory and checked before any handler runs.
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::{Method, StatusCode};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, web};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use tokio::sync::Mutex;
+use tracing::error;
+use utoipa::ToSchema;
+
+use crate::acl::{self, Grant};
+use crate::error_code::{ErrorCode, error_response};
+use crate::jwt::{Access, Principal};
+use crate::metrics::bucket_operation;
+use crate::namespace::split_path;
+use crate::s3_service::S3Service;
+
+/// A principal's role on a bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Reader,
+    Writer,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Reader => "reader",
+            Role::Writer => "writer",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// Whether the role grants `access` to its bucket.
+    pub fn grants(&self, access: Access) -> bool {
+        let granted = match self {
+            Role::Reader => Access::Read,
+            Role::Writer => Access::Write,
+            Role::Admin => Access::Admin,
+        };
+        granted >= access
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reader" => Ok(Role::Reader),
+            "writer" => Ok(Role::Writer),
+            "admin" => Ok(Role::Admin),
+            other => Err(format!("Unknown role '{}'", other)),
+        }
+    }
+}
+
+/// The roles and ACL grants of one bucket.
+#[derive(Debug, Clone, Default)]
+struct BucketAccess {
+    roles: HashMap<String, Role>,
+    grants: Vec<Grant>,
+}
+
+/// The roles and ACL grants of every bucket that has any, by storage name,
+/// kept in memory so requests need no database lookup.
+#[derive(Debug, Default)]
+pub struct BucketRoles(RwLock<HashMap<String, BucketAccess>>);
+
+impl BucketRoles {
+    pub fn new(
+        roles: HashMap<String, HashMap<String, Role>>,
+        grants: HashMap<String, Vec<Grant>>,
+    ) -> Self {
+        let this = Self::default();
+        for (bucket, roles) in roles {
+            this.set(&bucket, roles);
+        }
+        for (bucket, grants) in grants {
+            this.set_grants(&bucket, grants);
+        }
+        this
+    }
+
+    /// Replaces the roles of a bucket; with neither roles nor grants left, the
+    /// bucket is open again.
+    pub fn set(&self, bucket: &str, roles: HashMap<String, Role>) {
+        self.update(bucket, |access| access.roles = roles);
+    }
+
+    /// Replaces the ACL grants of a bucket.
+    pub fn set_grants(&self, bucket: &str, grants: Vec<Grant>) {
+        self.update(bucket, |access| access.grants = grants);
+    }
+
+    /// Forgets a deleted bucket.
+    pub fn remove(&self, bucket: &str) {
+        self.write().remove(bucket);
+    }
+
+    /// Whether any bucket has roles or grants.
+    pub fn is_empty(&self) -> bool {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).is_empty()
+    }
+
+    /// Whether a request by `principal` needing `access` may reach `bucket`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The storage name of the bucket.
+    /// * `principal` - The request's verified principal, if any.
+    /// * `access` - The access the request needs.
+    ///
+    /// # Returns
+    ///
+    /// * `bool` - Whether the request is allowed.
+    pub fn allows(&self, bucket: &str, principal: Option<&Principal>, access: Access) -> bool {
+        let buckets = self.0.read().unwrap_or_else(|e| e.into_inner());
+        let Some(bucket_access) = buckets.get(bucket) else {
+            return true;
+        };
+        let Some(principal) = principal else {
+            return false;
+        };
+        principal.may(Access::Admin)
+            || bucket_access
+                .roles
+                .get(&principal.subject)
+                .is_some_and(|role| role.grants(access))
+            || acl::grants(&bucket_access.grants, &principal.subject, access)
+    }
+
+    fn update(&self, bucket: &str, change: impl FnOnce(&mut BucketAccess)) {
+        let mut buckets = self.write();
+        let access = buckets.entry(bucket.to_string()).or_default();
+        change(access);
+        if access.roles.is_empty() && access.grants.is_empty() {
+            buckets.remove(bucket);
+        }
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, HashMap<String, BucketAccess>> {
+        self.0.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The access a request to a bucket needs: changing the bucket itself or its
+/// aliases is for its admins, other changes for its writers.
+pub fn bucket_access(method: &Method, path: &str) -> Access {
+    let path = split_path(path).map_or(path, |(_, rest)| rest);
+    let mut segments = path.trim_start_matches('/').split('/').skip(2);
+    let access = Access::of(method, path);
+    match segments.next() {
+        None | Some("aliases") if matches!(*method, Method::PUT | Method::DELETE) => Access::Admin,
+        _ => access,
+    }
+}
+
+/// Middleware refusing requests to buckets with roles unless the request's
+/// principal holds a role granting them. Registered inside the bearer token
+/// authentication, which attaches the principal.
+pub async fn enforce_bucket_roles(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(roles) = req
+        .app_data::<web::Data<Arc<BucketRoles>>>()
+        .filter(|roles| !roles.is_empty())
+        .cloned()
+    else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+    let Some((bucket, _)) = bucket_operation(req.method().as_str(), req.path(), req.query_string())
+    else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+
+    // Aliases are checked against the roles of the bucket they name
+    let resolved = match req.app_data::<web::Data<Arc<Mutex<S3Service>>>>() {
+        Some(s3_service) => s3_service.lock().await.resolve_bucket(&bucket).await,
+        None => Ok(None),
+    };
+    let bucket = match resolved {
+        Ok(resolved) => resolved.unwrap_or(bucket),
+        Err(e) => {
+            error!(error = %e, "Failed to resolve bucket for its roles");
+            return Ok(req.error_response(e).map_into_right_body());
+        }
+    };
+
+    let access = bucket_access(req.method(), req.path());
+    let allowed = roles.allows(&bucket, req.extensions().get::<Principal>(), access);
+    if !allowed {
+        let response = error_response(
+            StatusCode::FORBIDDEN,
+            ErrorCode::AccessDenied,
+            "AccessDenied: No role on this bucket grants this request.",
+        );
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn principal(subject: &str, scopes: &[&str]) -> Principal {
+        Principal {
+            subject: subject.to_string(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_allows() {
+        let roles = BucketRoles::default();
+        let alice = principal("alice", &[]);
+        assert!(roles.allows("photos", None, Access::Write));
+
+        roles.set(
+            "photos",
+            HashMap::from([("alice".to_string(), Role::Writer)]),
+        );
+        assert!(roles.allows("photos", Some(&alice), Access::Write));
+        assert!(!roles.allows("photos", Some(&alice), Access::Admin));
+        assert!(!roles.allows("photos", Some(&principal("bob", &[])), Access::Read));
+        assert!(!roles.allows("photos", None, Access::Read));
+        assert!(roles.allows(
+            "photos",
+            Some(&principal("root", &["s3:admin"])),
+            Access::Admin
+        ));
+        assert!(roles.allows("other", None, Access::Write));
+
+        roles.set("photos", HashMap::new());
+        assert!(roles.is_empty());
+
+        // Grants let principals without a role in
+        roles.set_grants(
+            "photos",
+            vec![Grant {
+                grantee: "bob".to_string(),
+                permission: crate::acl::Permission::Read,
+            }],
+        );
+        assert!(roles.allows("photos", Some(&principal("bob", &[])), Access::Read));
+        assert!(!roles.allows("photos", Some(&principal("bob", &[])), Access::Write));
+        assert!(!roles.allows("photos", Some(&alice), Access::Read));
+        roles.remove("photos");
+        assert!(roles.is_empty());
+    }
+
+    #[test]
+    fn test_bucket_access() {
+        assert_eq!(
+            bucket_access(&Method::GET, "/buckets/b?roles"),
+            Access::Read
+        );
+        assert_eq!(bucket_access(&Method::PUT, "/buckets/b"), Access::Admin);
+        assert_eq!(
+            bucket_access(&Method::DELETE, "/ns/team/buckets/b"),
+            Access::Admin
+        );
+        assert_eq!(
+            bucket_access(&Method::PUT, "/buckets/b/aliases/a"),
+            Access::Admin
+        );
+        assert_eq!(bucket_access(&Method::POST, "/buckets/b"), Access::Write);
+        assert_eq!(
+            bucket_access(&Method::DELETE, "/buckets/b/objects/k"),
+            Access::Write
+        );
+    }
+}
@@ -1,10 +1,46 @@
 // s3_service.rs
-use crate::bucket::{Bucket, BucketError};
-use crate::object::{Object, ObjectError};
-use crate::storage::{Storage, StorageError};
+use crate::access::AccessReport;
+use crate::acl::Grant;
+use crate::bucket::{Bucket, BucketError, LifecycleRule, VersioningStatus};
+use crate::cache::{CachePin, CacheWarmReport, ObjectCache};
+use crate::circuit::StorageFailure;
+use crate::config::ContentTypeConfig;
+use crate::content_type;
+use crate::error_code::{ErrorCode, error_response};
+use crate::folder::{self, FOLDER_CONTENT_TYPE, FolderListing};
+use crate::metadata::{self, MetadataError};
+use crate::notifications::{Event, Notifier};
+use crate::object::{Object, ObjectError, ObjectInfo, ObjectVerification, ObjectVersion};
+use crate::range::ContentRange;
+use crate::replication::ReplicationReport;
+use crate::roles::Role;
+use crate::share::{self, Share};
+use crate::storage::{ObjectReader, RestoreReport, Storage, StorageError, lock_storage};
+use crate::tus::{Upload, UploadPart};
+use crate::usage::{BucketUsage, UsageReport};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::{HttpResponse, ResponseError};
+use serde::Serialize;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, MutexGuard};
+use utoipa::ToSchema;
+
+/// Objects deleted per transaction by a prefix delete.
+const DELETE_BATCH_SIZE: usize = 500;
+/// Most source objects a compose request may concatenate.
+const MAX_COMPOSE_SOURCES: usize = 32;
+
+/// An object to download: read whole if the cache takes objects of its
+/// size, otherwise opened to be streamed from its file.
+#[derive(Debug)]
+pub enum ObjectDownload {
+    Buffered(Object),
+    Streamed(ObjectReader),
+}
 
 /// Represents custom errors that can occur in our S3-like service.
 #[derive(Debug, Error)]
@@ -19,17 +55,225 @@ pub enum S3Error {
     ObjectCreationFailed(#[from] ObjectError),
     #[error("Bucket operation failed: {0}")]
     BucketOperationFailed(#[from] BucketError),
+    #[error("Metadata too large: {0}")]
+    MetadataTooLarge(#[from] MetadataError),
+    #[error("Object '{0}' in bucket '{1}' is under legal hold")]
+    ObjectLocked(String, String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("SlowDown: {0}")]
+    SlowDown(String),
+    #[error("Access denied: {0}")]
+    AccessDenied(String),
+    #[error("Alias '{0}' of bucket '{1}' not found")]
+    AliasNotFound(String, String),
+    #[error("Upload '{0}' not found")]
+    UploadNotFound(String),
+    #[error("Upload conflict: {0}")]
+    UploadConflict(String),
+    #[error("Object '{0}' in bucket '{1}' is write-once and cannot be changed")]
+    ObjectImmutable(String, String),
+    #[error("Write-once conflict: {0}")]
+    WriteOnceConflict(String),
+    #[error("Object '{0}' in bucket '{1}' does not match the given ETag")]
+    PreconditionFailed(String, String),
+    #[error("Incomplete body: expected {0} bytes but received {1}")]
+    IncompleteBody(u64, u64),
+    #[error("Request body exceeds the limit of {0} bytes")]
+    EntityTooLarge(u64),
+    #[error("Range not satisfiable: {0}")]
+    InvalidRange(String),
+    #[error("Copy job '{0}' not found")]
+    CopyJobNotFound(String),
+    // Tokens stay out of messages, which end up in logs
+    #[error("Share not found")]
+    ShareNotFound,
+    #[error("Share has expired or used up its downloads")]
+    ShareExpired,
+    #[error("Re-hash conflict: {0}")]
+    RehashConflict(String),
+    #[error("Backup conflict: {0}")]
+    BackupConflict(String),
     #[error("Internal storage error: {0}")]
     InternalStorageError(String),
+    #[error("Storage timeout: no response from the storage within {0} seconds")]
+    StorageTimeout(u64),
+    #[error("Request timeout: the request was not completed within {0} seconds")]
+    RequestTimeout(u64),
+}
+
+impl S3Error {
+    /// The machine-readable code clients branch on.
+    pub fn error_code(&self) -> ErrorCode {
+        match self {
+            S3Error::BucketAlreadyExists(_) => ErrorCode::BucketAlreadyOwnedByYou,
+            S3Error::BucketNotFound(_) => ErrorCode::NoSuchBucket,
+            S3Error::ObjectNotFound(_, _) => ErrorCode::NoSuchKey,
+            S3Error::MetadataTooLarge(_) => ErrorCode::MetadataTooLarge,
+            S3Error::ObjectLocked(_, _) => ErrorCode::ObjectLocked,
+            S3Error::InvalidRequest(_) => ErrorCode::InvalidRequest,
+            S3Error::SlowDown(_) => ErrorCode::SlowDown,
+            S3Error::AccessDenied(_) => ErrorCode::AccessDenied,
+            S3Error::AliasNotFound(_, _) => ErrorCode::NoSuchAlias,
+            S3Error::UploadNotFound(_) => ErrorCode::NoSuchUpload,
+            S3Error::UploadConflict(_) => ErrorCode::UploadConflict,
+            S3Error::ObjectImmutable(_, _) => ErrorCode::ObjectImmutable,
+            S3Error::WriteOnceConflict(_) => ErrorCode::WriteOnceConflict,
+            S3Error::PreconditionFailed(_, _) => ErrorCode::PreconditionFailed,
+            S3Error::IncompleteBody(_, _) => ErrorCode::IncompleteBody,
+            S3Error::EntityTooLarge(_) => ErrorCode::EntityTooLarge,
+            S3Error::InvalidRange(_) => ErrorCode::InvalidRange,
+            S3Error::CopyJobNotFound(_) => ErrorCode::NoSuchCopyJob,
+            S3Error::ShareNotFound => ErrorCode::NoSuchShare,
+            S3Error::ShareExpired => ErrorCode::ShareExpired,
+            S3Error::RehashConflict(_) | S3Error::BackupConflict(_) => ErrorCode::OperationAborted,
+            S3Error::StorageTimeout(_) => ErrorCode::ServiceUnavailable,
+            S3Error::RequestTimeout(_) => ErrorCode::RequestTimeout,
+            S3Error::BucketOperationFailed(BucketError::Storage(StorageError::LockTimeout(_))) => {
+                ErrorCode::ServiceUnavailable
+            }
+            S3Error::BucketOperationFailed(_) if self.is_busy() => ErrorCode::SlowDown,
+            S3Error::ObjectCreationFailed(_)
+            | S3Error::BucketOperationFailed(_)
+            | S3Error::InternalStorageError(_) => ErrorCode::InternalError,
+        }
+    }
+
+    /// Whether the error comes from the database or the disk failing rather
+    /// than from the request.
+    pub fn is_storage_failure(&self) -> bool {
+        !self.is_busy()
+            && matches!(
+                self,
+                S3Error::BucketOperationFailed(BucketError::Storage(
+                    StorageError::DatabaseError(_)
+                        | StorageError::IoError(_)
+                        | StorageError::TransactionCommitError
+                        | StorageError::LockTimeout(_)
+                )) | S3Error::InternalStorageError(_)
+                    | S3Error::StorageTimeout(_)
+            )
+    }
+
+    /// Whether an object operation found the database locked by another
+    /// connection after the retries, answered like `SlowDown`.
+    pub fn is_busy(&self) -> bool {
+        matches!(self, S3Error::BucketOperationFailed(BucketError::Storage(e)) if e.is_busy())
+    }
+}
+
+// Maps S3Error to Actix Web HTTP responses
+impl ResponseError for S3Error {
+    fn error_response(&self) -> HttpResponse {
+        let mut response = error_response(self.status_code(), self.error_code(), &self.to_string());
+        if matches!(self, S3Error::SlowDown(_)) || self.is_busy() {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from_static("1"));
+        }
+        // Counted by the circuit breaker around the storage layer
+        if self.is_storage_failure() {
+            response.extensions_mut().insert(StorageFailure);
+        }
+        response
+    }
+
+    fn status_code(&self) -> StatusCode {
+        match self {
+            S3Error::BucketAlreadyExists(_) => StatusCode::CONFLICT,
+            S3Error::BucketNotFound(_) => StatusCode::NOT_FOUND,
+            S3Error::ObjectNotFound(_, _) => StatusCode::NOT_FOUND,
+            S3Error::ObjectLocked(_, _) => StatusCode::FORBIDDEN,
+            S3Error::ObjectImmutable(_, _) => StatusCode::FORBIDDEN,
+            S3Error::WriteOnceConflict(_) => StatusCode::CONFLICT,
+            S3Error::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            S3Error::AccessDenied(_) => StatusCode::FORBIDDEN,
+            S3Error::SlowDown(_) => StatusCode::SERVICE_UNAVAILABLE,
+            S3Error::AliasNotFound(_, _) => StatusCode::NOT_FOUND,
+            S3Error::UploadNotFound(_) => StatusCode::NOT_FOUND,
+            S3Error::UploadConflict(_) => StatusCode::CONFLICT,
+            S3Error::BackupConflict(_) => StatusCode::CONFLICT,
+            S3Error::PreconditionFailed(_, _) => StatusCode::PRECONDITION_FAILED,
+            S3Error::MetadataTooLarge(_) => StatusCode::BAD_REQUEST,
+            S3Error::IncompleteBody(_, _) => StatusCode::BAD_REQUEST,
+            S3Error::EntityTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            S3Error::InvalidRange(_) => StatusCode::RANGE_NOT_SATISFIABLE,
+            S3Error::CopyJobNotFound(_) => StatusCode::NOT_FOUND,
+            S3Error::ShareNotFound => StatusCode::NOT_FOUND,
+            S3Error::ShareExpired => StatusCode::GONE,
+            S3Error::RehashConflict(_) => StatusCode::CONFLICT,
+            S3Error::ObjectCreationFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            S3Error::StorageTimeout(_) => StatusCode::SERVICE_UNAVAILABLE,
+            S3Error::RequestTimeout(_) => StatusCode::REQUEST_TIMEOUT,
+            S3Error::BucketOperationFailed(BucketError::Storage(StorageError::LockTimeout(_))) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            S3Error::BucketOperationFailed(_) if self.is_busy() => StatusCode::SERVICE_UNAVAILABLE,
+            S3Error::BucketOperationFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            S3Error::InternalStorageError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Outcome of deleting every object under a key prefix.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct PrefixDeleteReport {
+    pub deleted: u64,
+    /// Keys left alone because they are under legal hold or write-once.
+    pub skipped: Vec<String>,
 }
 
 pub struct S3Service {
     storage: Arc<Mutex<Storage>>,
+    cache: Arc<ObjectCache>,
+    content_types: ContentTypeConfig,
+    notifier: Arc<Notifier>,
+    storage_timeout: Option<Duration>,
 }
 
 impl S3Service {
     pub fn new(storage: Arc<Mutex<Storage>>) -> Self {
-        S3Service { storage }
+        S3Service {
+            storage,
+            cache: Arc::new(ObjectCache::disabled()),
+            content_types: ContentTypeConfig::default(),
+            notifier: Arc::new(Notifier::disabled()),
+            storage_timeout: None,
+        }
+    }
+
+    /// Serves object reads through `cache`.
+    pub fn with_cache(mut self, cache: Arc<ObjectCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Infers the content type of objects stored without one as `config` enables.
+    pub fn with_content_type_inference(mut self, config: ContentTypeConfig) -> Self {
+        self.content_types = config;
+        self
+    }
+
+    /// Fails requests that wait longer than `timeout` for the storage.
+    pub fn with_storage_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.storage_timeout = timeout;
+        self
+    }
+
+    /// Locks the storage within the storage timeout.
+    async fn lock_storage(&self) -> Result<MutexGuard<'_, Storage>, S3Error> {
+        lock_storage(&self.storage, self.storage_timeout)
+            .await
+            .map_err(|e| match e {
+                StorageError::LockTimeout(secs) => S3Error::StorageTimeout(secs),
+                e => storage_error(e, "Failed to lock the storage"),
+            })
+    }
+
+    /// Publishes object events through `notifier`.
+    pub fn with_notifier(mut self, notifier: Arc<Notifier>) -> Self {
+        self.notifier = notifier;
+        self
     }
 
     /// Creates a new bucket.
@@ -43,7 +287,7 @@ impl S3Service {
     /// * `Result<(), S3Error>` - An empty result, or an error.
     pub async fn create_bucket(&mut self, name: &str) -> Result<(), S3Error> {
         let result = {
-            let mut lock = self.storage.lock().await;
+            let mut lock = self.lock_storage().await?;
             lock.create_bucket(name)
         };
 
@@ -52,10 +296,7 @@ impl S3Service {
             Err(StorageError::BucketAlreadyExistsInStorage(bucket_name)) => {
                 Err(S3Error::BucketAlreadyExists(bucket_name))
             }
-            Err(e) => Err(S3Error::InternalStorageError(format!(
-                "Failed to create bucket in storage: {}",
-                e
-            ))),
+            Err(e) => Err(storage_error(e, "Failed to create bucket in storage")),
         }
     }
 
@@ -70,138 +311,1925 @@ impl S3Service {
     /// * `Result<(), S3Error>` - An empty result, or an error.
     pub async fn delete_bucket(&mut self, name: &str) -> Result<(), S3Error> {
         let result = {
-            let mut lock = self.storage.lock().await;
+            let mut lock = self.lock_storage().await?;
             lock._delete_bucket(name)
         };
 
         match result {
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                self.cache.invalidate_bucket(name);
+                Ok(())
+            }
             Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
                 Err(S3Error::BucketNotFound(bucket_name))
             }
-            Err(e) => Err(S3Error::InternalStorageError(format!(
-                "Failed to delete bucket from storage: {}",
-                e
-            ))),
+            Err(e @ StorageError::BucketImmutable(..)) => {
+                Err(S3Error::WriteOnceConflict(e.to_string()))
+            }
+            Err(StorageError::ObjectImmutable(key, bucket)) => {
+                Err(S3Error::ObjectImmutable(key, bucket))
+            }
+            Err(StorageError::ObjectUnderLegalHold(key, bucket)) => {
+                Err(S3Error::ObjectLocked(key, bucket))
+            }
+            Err(e) => Err(storage_error(e, "Failed to delete bucket from storage")),
         }
     }
 
-    /// Lists all buckets.
+    /// Lists the buckets of a namespace.
+    ///
+    /// # Arguments
+    ///
+    /// * `namespace` - The namespace to list, or `None` for the default namespace.
     ///
     /// # Returns
     ///
     /// * `Vec<String>` - A vector of bucket names.
-    pub async fn list_buckets(&self) -> Result<Vec<String>, S3Error> {
+    pub async fn list_buckets(&self, namespace: Option<&str>) -> Result<Vec<String>, S3Error> {
         let result = {
-            let storage_lock = self.storage.lock().await;
-            storage_lock.list_buckets()
+            let storage_lock = self.lock_storage().await?;
+            storage_lock.list_buckets(namespace)
         };
         match result {
             Ok(buckets) => Ok(buckets),
             Err(e) => {
                 eprintln!("Error listing buckets from storage: {}", e);
-                Err(S3Error::InternalStorageError(format!(
-                    "Failed to list buckets from storage: {}",
-                    e
-                )))
+                Err(storage_error(e, "Failed to list buckets from storage"))
             }
         }
     }
 
-    /// Helper to get a Bucket instance on demand
-    async fn get_bucket_instance(&self, bucket_name: &str) -> Result<Bucket, S3Error> {
+    /// Registers an alternate name that resolves to a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    /// * `alias` - The alternate name.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), S3Error>` - An empty result, or an error.
+    pub async fn create_bucket_alias(&mut self, name: &str, alias: &str) -> Result<(), S3Error> {
         let result = {
-            let storage_lock = self.storage.lock().await;
-            storage_lock.bucket_exists(bucket_name)
+            let mut lock = self.lock_storage().await?;
+            lock.create_bucket_alias(name, alias)
         };
+
         match result {
-            Ok(true) => Ok(Bucket::new(bucket_name.to_string(), self.storage.clone())),
-            Ok(false) => Err(S3Error::BucketNotFound(bucket_name.to_string())),
-            Err(e) => Err(S3Error::InternalStorageError(format!(
-                "Error checking bucket existence: {}",
-                e
-            ))),
+            Ok(_) => Ok(()),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(StorageError::BucketAlreadyExistsInStorage(alias)) => {
+                Err(S3Error::BucketAlreadyExists(alias))
+            }
+            Err(e) => Err(storage_error(e, "Failed to create bucket alias in storage")),
         }
     }
 
-    /// Puts an object into a bucket.
+    /// Removes an alternate name of a bucket.
     ///
     /// # Arguments
     ///
-    /// * `bucket_name` - The name of the bucket to put the object into.
-    /// * `object` - The object to put into the bucket.
+    /// * `name` - The name of the bucket.
+    /// * `alias` - The alternate name to remove.
     ///
     /// # Returns
     ///
-    /// * `Result<Object, S3Error>` - The put object, or an error.
-    pub async fn put_object(
+    /// * `Result<(), S3Error>` - An empty result, or an error.
+    pub async fn delete_bucket_alias(&mut self, name: &str, alias: &str) -> Result<(), S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.delete_bucket_alias(name, alias)
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(StorageError::AliasNotFound(alias, bucket_name)) => {
+                Err(S3Error::AliasNotFound(alias, bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to delete bucket alias from storage",
+            )),
+        }
+    }
+
+    /// Lists the alternate names of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<String>, S3Error>` - The bucket's aliases, or an error.
+    pub async fn list_bucket_aliases(&self, name: &str) -> Result<Vec<String>, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.list_bucket_aliases(name)
+        };
+
+        match result {
+            Ok(aliases) => Ok(aliases),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to list bucket aliases from storage",
+            )),
+        }
+    }
+
+    /// Gets the versioning status of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<VersioningStatus>, S3Error>` - The status (`None` if never enabled), or an error.
+    pub async fn get_bucket_versioning(
+        &self,
+        name: &str,
+    ) -> Result<Option<VersioningStatus>, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.get_bucket_versioning(name)
+        };
+
+        match result {
+            Ok(status) => Ok(status),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to get bucket versioning from storage",
+            )),
+        }
+    }
+
+    /// Reports whether a bucket is write-once.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, S3Error>` - Whether the bucket is write-once, or an error.
+    pub async fn get_bucket_worm(&self, name: &str) -> Result<bool, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.get_bucket_worm(name)
+        };
+
+        match result {
+            Ok(worm) => Ok(worm),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to get bucket write-once mode from storage",
+            )),
+        }
+    }
+
+    /// Whether operations on a bucket are logged in detail.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, S3Error>` - Whether verbose logging is on, or an error.
+    pub async fn get_bucket_verbose_logging(&self, name: &str) -> Result<bool, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.get_bucket_verbose_logging(name)
+        };
+
+        match result {
+            Ok(verbose) => Ok(verbose),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to get bucket logging from storage",
+            )),
+        }
+    }
+
+    /// Turns detailed logging of a bucket's operations on or off.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    /// * `verbose` - The requested mode.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), S3Error>` - An empty result, or an error.
+    pub async fn put_bucket_verbose_logging(
         &mut self,
-        bucket_name: &str,
-        object: Object,
-    ) -> Result<Object, S3Error> {
-        let mut bucket = self.get_bucket_instance(bucket_name).await?;
-        let result = bucket.put_object(object);
-        match result.await {
-            Ok(object) => Ok(object),
-            Err(e) => Err(S3Error::BucketOperationFailed(e)),
+        name: &str,
+        verbose: bool,
+    ) -> Result<(), S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.set_bucket_verbose_logging(name, verbose)
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(e, "Failed to set bucket logging in storage")),
         }
     }
 
-    /// Retrieves an object from a bucket.
+    /// Resolves a bucket name or alias to the bucket's name.
     ///
     /// # Arguments
     ///
-    /// * `bucket_name` - The name of the bucket to retrieve the object from.
-    /// * `key` - The key of the object to retrieve.
+    /// * `name` - The name or an alias of the bucket.
     ///
     /// # Returns
     ///
-    /// * `Result<Object, S3Error>` - The retrieved object, or an error.
-    pub async fn get_object(&self, bucket_name: &str, key: &str) -> Result<Object, S3Error> {
-        let bucket = self.get_bucket_instance(bucket_name).await?;
-        match bucket.get_object(key).await {
-            Ok(object) => Ok(object),
-            Err(e) => Err(S3Error::BucketOperationFailed(e)),
+    /// * `Result<Option<String>, S3Error>` - The bucket's name, `None` if there is no
+    ///   such bucket or alias, or an error.
+    pub async fn resolve_bucket(&self, name: &str) -> Result<Option<String>, S3Error> {
+        let lock = self.lock_storage().await?;
+        lock.resolve_bucket(name)
+            .map_err(|e| storage_error(e, "Failed to resolve bucket in storage"))
+    }
+
+    /// Gets the roles principals hold on a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<HashMap<String, Role>, S3Error>` - The roles by principal, or an error.
+    pub async fn get_bucket_roles(&self, name: &str) -> Result<HashMap<String, Role>, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.get_bucket_roles(name)
+        };
+
+        match result {
+            Ok(roles) => Ok(roles),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(e, "Failed to get bucket roles from storage")),
         }
     }
 
-    /// Deletes an object from a bucket.
+    /// Replaces the roles principals hold on a bucket.
     ///
     /// # Arguments
     ///
-    /// * `bucket_name` - The name of the bucket to delete the object from.
-    /// * `key` - The key of the object to delete.
+    /// * `name` - The name of the bucket.
+    /// * `roles` - The roles by principal; empty opens the bucket to everyone.
     ///
     /// # Returns
     ///
     /// * `Result<(), S3Error>` - An empty result, or an error.
-    pub async fn delete_object(&mut self, bucket_name: &str, key: &str) -> Result<(), S3Error> {
-        let mut bucket = self.get_bucket_instance(bucket_name).await?;
-        match bucket.delete_object(key).await {
-            Ok(true) => Ok(()),
-            Ok(false) => Err(S3Error::ObjectNotFound(
-                key.to_string(),
-                bucket_name.to_string(),
-            )),
-            Err(e) => Err(S3Error::BucketOperationFailed(e)),
+    pub async fn put_bucket_roles(
+        &mut self,
+        name: &str,
+        roles: &HashMap<String, Role>,
+    ) -> Result<(), S3Error> {
+        if roles.keys().any(|principal| principal.trim().is_empty()) {
+            return Err(S3Error::InvalidRequest(
+                "Role principals must not be empty".to_string(),
+            ));
+        }
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.set_bucket_roles(name, roles)
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(e, "Failed to set bucket roles in storage")),
         }
     }
 
-    /// Lists all objects in a bucket.
+    /// Gets the ACL grants of a bucket.
     ///
     /// # Arguments
     ///
-    /// * `bucket_name` - The name of the bucket to list objects from.
+    /// * `name` - The name of the bucket.
     ///
     /// # Returns
     ///
-    /// * `Result<Vec<String>, S3Error>` - A vector of object keys in the bucket, or an error.
-    pub async fn list_objects(&self, bucket_name: &str) -> Result<Vec<String>, S3Error> {
-        let bucket = self.get_bucket_instance(bucket_name).await?;
-        let result = bucket.list_objects().await;
+    /// * `Result<Vec<Grant>, S3Error>` - The grants, or an error.
+    pub async fn get_bucket_acl(&self, name: &str) -> Result<Vec<Grant>, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.get_bucket_acl(name)
+        };
+
         match result {
-            Ok(objects) => Ok(objects),
-            Err(e) => Err(S3Error::BucketOperationFailed(e)),
+            Ok(grants) => Ok(grants),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(e, "Failed to get bucket ACL from storage")),
+        }
+    }
+
+    /// Replaces the ACL grants of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    /// * `grants` - The grants; repeated ones are kept once.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Grant>, S3Error>` - The grants as stored, or an error.
+    pub async fn put_bucket_acl(
+        &mut self,
+        name: &str,
+        mut grants: Vec<Grant>,
+    ) -> Result<Vec<Grant>, S3Error> {
+        if grants.iter().any(|grant| grant.grantee.trim().is_empty()) {
+            return Err(S3Error::InvalidRequest(
+                "Grantees must not be empty".to_string(),
+            ));
+        }
+        grants.sort_by(|a, b| {
+            (&a.grantee, a.permission.as_str()).cmp(&(&b.grantee, b.permission.as_str()))
+        });
+        grants.dedup();
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.set_bucket_acl(name, &grants)
+        };
+
+        match result {
+            Ok(_) => Ok(grants),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(e, "Failed to set bucket ACL in storage")),
+        }
+    }
+
+    /// Gets the content type given to objects uploaded to a bucket without one.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<String>, S3Error>` - The default content type, if any, or an error.
+    pub async fn get_bucket_default_content_type(
+        &self,
+        name: &str,
+    ) -> Result<Option<String>, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.get_bucket_default_content_type(name)
+        };
+
+        match result {
+            Ok(content_type) => Ok(content_type),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to get bucket default content type from storage",
+            )),
         }
     }
+
+    /// Sets or clears the content type given to objects uploaded to a bucket
+    /// without one.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    /// * `content_type` - The default content type, a MIME type, or `None` for none.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), S3Error>` - An empty result, or an error.
+    pub async fn put_bucket_default_content_type(
+        &mut self,
+        name: &str,
+        content_type: Option<&str>,
+    ) -> Result<(), S3Error> {
+        if let Some(content_type) = content_type
+            && content_type.parse::<actix_web::mime::Mime>().is_err()
+        {
+            return Err(S3Error::InvalidRequest(format!(
+                "'{}' is not a valid content type",
+                content_type
+            )));
+        }
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.set_bucket_default_content_type(name, content_type)
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to set bucket default content type in storage",
+            )),
+        }
+    }
+
+    /// Gets the user metadata merged into every object uploaded to a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<HashMap<String, String>, S3Error>` - The default metadata, or an error.
+    pub async fn get_bucket_default_metadata(
+        &self,
+        name: &str,
+    ) -> Result<HashMap<String, String>, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.get_bucket_default_metadata(name)
+        };
+
+        match result {
+            Ok(metadata) => Ok(metadata),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to get bucket default metadata from storage",
+            )),
+        }
+    }
+
+    /// Sets the user metadata merged into every object uploaded to a bucket,
+    /// unless the upload sets the same names. Names are lowercased, as those
+    /// of `x-user-meta-*` headers are; an empty map clears the defaults.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    /// * `defaults` - The default metadata.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<HashMap<String, String>, S3Error>` - The defaults as stored, or an error.
+    pub async fn put_bucket_default_metadata(
+        &mut self,
+        name: &str,
+        defaults: HashMap<String, String>,
+    ) -> Result<HashMap<String, String>, S3Error> {
+        let defaults: HashMap<String, String> = defaults
+            .into_iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value))
+            .collect();
+        metadata::validate(&defaults)?;
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.set_bucket_default_metadata(name, &defaults)
+        };
+
+        match result {
+            Ok(_) => Ok(defaults),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to set bucket default metadata in storage",
+            )),
+        }
+    }
+
+    /// Turns write-once mode of a bucket on; it cannot be turned off again.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    /// * `worm` - The requested mode.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), S3Error>` - An empty result, or an error.
+    pub async fn put_bucket_worm(&mut self, name: &str, worm: bool) -> Result<(), S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.set_bucket_worm(name, worm)
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e @ StorageError::BucketImmutable(..)) => {
+                Err(S3Error::WriteOnceConflict(e.to_string()))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to set bucket write-once mode in storage",
+            )),
+        }
+    }
+
+    /// Enables or suspends versioning on a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    /// * `status` - The new versioning status.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), S3Error>` - An empty result, or an error.
+    pub async fn put_bucket_versioning(
+        &mut self,
+        name: &str,
+        status: VersioningStatus,
+    ) -> Result<(), S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.set_bucket_versioning(name, status)
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to set bucket versioning in storage",
+            )),
+        }
+    }
+
+    /// Sets or clears the replication destination of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    /// * `destination` - The peer base URL, or `None` to stop replicating.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), S3Error>` - An empty result, or an error.
+    pub async fn put_bucket_replication(
+        &mut self,
+        name: &str,
+        destination: Option<&str>,
+    ) -> Result<(), S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.set_bucket_replication(name, destination)
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to set bucket replication in storage",
+            )),
+        }
+    }
+
+    /// Reports how far behind the replication destination of a bucket is.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ReplicationReport, S3Error>` - The replication lag report, or an error.
+    pub async fn get_bucket_replication(&self, name: &str) -> Result<ReplicationReport, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.replication_report(name)
+        };
+
+        match result {
+            Ok(report) => Ok(report),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to get bucket replication from storage",
+            )),
+        }
+    }
+
+    /// Gets the lifecycle transition rules of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<LifecycleRule>, S3Error>` - The bucket's rules, or an error.
+    pub async fn get_bucket_lifecycle(&self, name: &str) -> Result<Vec<LifecycleRule>, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.get_bucket_lifecycle(name)
+        };
+
+        match result {
+            Ok(rules) => Ok(rules),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to get bucket lifecycle from storage",
+            )),
+        }
+    }
+
+    /// Replaces the lifecycle transition rules of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    /// * `rules` - The new rules.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), S3Error>` - An empty result, or an error.
+    pub async fn put_bucket_lifecycle(
+        &mut self,
+        name: &str,
+        rules: &[LifecycleRule],
+    ) -> Result<(), S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.set_bucket_lifecycle(name, rules)
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to set bucket lifecycle in storage",
+            )),
+        }
+    }
+
+    /// Reports the space used by every bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<UsageReport, S3Error>` - The usage report, or an error.
+    pub async fn get_usage_report(&self) -> Result<UsageReport, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.usage_report()
+        };
+
+        result.map_err(|e| storage_error(e, "Failed to get usage from storage"))
+    }
+
+    /// Gets the space used by a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name or an alias of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<BucketUsage, S3Error>` - The usage, or an error.
+    pub async fn get_bucket_usage(&self, name: &str) -> Result<BucketUsage, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            match lock.resolve_bucket(name) {
+                Ok(Some(bucket_name)) => lock.bucket_usage(&bucket_name),
+                Ok(None) => Err(StorageError::BucketNotFoundInStorage(name.to_string())),
+                Err(e) => Err(e),
+            }
+        };
+
+        match result {
+            Ok(usage) => Ok(usage),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(e, "Failed to get bucket usage from storage")),
+        }
+    }
+
+    /// Reports the hot and never-read objects of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    /// * `limit` - The maximum number of entries in each list.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<AccessReport, S3Error>` - The access report, or an error.
+    pub async fn get_bucket_access_report(
+        &self,
+        name: &str,
+        limit: usize,
+    ) -> Result<AccessReport, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.access_report(name, limit)
+        };
+
+        match result {
+            Ok(report) => Ok(report),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(
+                e,
+                "Failed to get bucket access statistics from storage",
+            )),
+        }
+    }
+
+    /// Checks that a bucket exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), S3Error>` - An empty result if the bucket exists, or an error.
+    pub async fn head_bucket(&self, name: &str) -> Result<(), S3Error> {
+        self.get_bucket_instance(name).await.map(|_| ())
+    }
+
+    /// Starts a resumable upload of an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket the object will be stored in.
+    /// * `key` - The key the object will be stored under.
+    /// * `length` - The total size of the object in bytes.
+    /// * `content_type` - The content type of the object.
+    /// * `user_metadata` - User metadata stored with the object.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Upload, S3Error>` - The new upload, or an error.
+    pub async fn create_upload(
+        &mut self,
+        bucket_name: &str,
+        key: &str,
+        length: u64,
+        content_type: Option<String>,
+        user_metadata: HashMap<String, String>,
+    ) -> Result<Upload, S3Error> {
+        metadata::validate(&user_metadata)?;
+        let mut lock = self.lock_storage().await?;
+        let created_at = lock.clock().unix_secs().unwrap_or_default();
+        let upload = Upload {
+            id: lock.ids().upload_id(),
+            bucket: bucket_name.to_string(),
+            key: key.to_string(),
+            length,
+            offset: 0,
+            content_type,
+            user_metadata,
+            created_at,
+        };
+        let result = lock.create_upload(&upload);
+        drop(lock);
+
+        match result {
+            Ok(()) => Ok(upload),
+            Err(e) => Err(upload_error(e, "create upload")),
+        }
+    }
+
+    /// Gets the state of a resumable upload.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket the upload targets.
+    /// * `id` - The id of the upload.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Upload, S3Error>` - The upload, or an error.
+    pub async fn get_upload(&self, bucket_name: &str, id: &str) -> Result<Upload, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.get_upload(bucket_name, id)
+        };
+        result.map_err(|e| upload_error(e, "get upload"))
+    }
+
+    /// Lists the resumable uploads in progress in a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket the uploads target.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Upload>, S3Error>` - The uploads, or an error.
+    pub async fn list_uploads(&self, bucket_name: &str) -> Result<Vec<Upload>, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.list_uploads(bucket_name)
+        };
+        result.map_err(|e| upload_error(e, "list uploads"))
+    }
+
+    /// Lists the parts of a resumable upload.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket the upload targets.
+    /// * `id` - The id of the upload.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(Upload, Vec<UploadPart>), S3Error>` - The upload and its parts, or an error.
+    pub async fn list_upload_parts(
+        &self,
+        bucket_name: &str,
+        id: &str,
+    ) -> Result<(Upload, Vec<UploadPart>), S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.list_upload_parts(bucket_name, id)
+        };
+        result.map_err(|e| upload_error(e, "list upload parts"))
+    }
+
+    /// Appends bytes to a resumable upload. When the last byte arrives the
+    /// upload is stored as an object and removed.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket the upload targets.
+    /// * `id` - The id of the upload.
+    /// * `offset` - The offset the client is writing at.
+    /// * `data` - The bytes to append.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(Upload, Option<ObjectInfo>), S3Error>` - The upload with its new offset
+    ///   and, if it completed, the stored object, or an error.
+    pub async fn append_upload(
+        &mut self,
+        bucket_name: &str,
+        id: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<(Upload, Option<ObjectInfo>), S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.append_upload(bucket_name, id, offset, data)
+                .and_then(|upload| {
+                    let head = if upload.is_complete() {
+                        Some(lock.read_upload_head(&upload, content_type::SNIFF_LEN)?)
+                    } else {
+                        None
+                    };
+                    Ok((upload, head))
+                })
+        };
+        let (upload, head) = result.map_err(|e| upload_error(e, "append to upload"))?;
+        let Some(head) = head else {
+            return Ok((upload, None));
+        };
+
+        let bucket = self.get_bucket_instance(bucket_name).await?;
+        let mut content_type = upload.content_type.clone();
+        let mut user_metadata = Some(upload.user_metadata.clone());
+        self.apply_bucket_defaults(
+            &bucket.name,
+            &upload.key,
+            &mut content_type,
+            &mut user_metadata,
+            &head,
+        )
+        .await?;
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.complete_upload(
+                bucket_name,
+                id,
+                content_type.as_deref(),
+                user_metadata.as_ref(),
+            )
+        };
+        let info = result.map_err(|e| upload_error(e, "complete upload"))?;
+        self.cache.invalidate(&bucket.name, &info.key);
+        self.notifier
+            .publish(Event::object_info_created(&bucket.name, &info));
+        Ok((upload, Some(info)))
+    }
+
+    /// Abandons a resumable upload, discarding the bytes received so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket the upload targets.
+    /// * `id` - The id of the upload.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), S3Error>` - An empty result, or an error.
+    pub async fn delete_upload(&mut self, bucket_name: &str, id: &str) -> Result<(), S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.delete_upload(bucket_name, id)
+        };
+        result.map_err(|e| upload_error(e, "delete upload"))
+    }
+
+    /// Helper to get a Bucket instance on demand. Aliases resolve to the
+    /// bucket they name.
+    async fn get_bucket_instance(&self, bucket_name: &str) -> Result<Bucket, S3Error> {
+        let result = {
+            let storage_lock = self.lock_storage().await?;
+            storage_lock.resolve_bucket(bucket_name)
+        };
+        match result {
+            Ok(Some(name)) => {
+                Ok(Bucket::new(name, self.storage.clone()).with_lock_timeout(self.storage_timeout))
+            }
+            Ok(None) => Err(S3Error::BucketNotFound(bucket_name.to_string())),
+            Err(e) => Err(storage_error(e, "Error checking bucket existence")),
+        }
+    }
+
+    /// Puts an object into a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket to put the object into.
+    /// * `object` - The object to put into the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Object, S3Error>` - The put object, or an error.
+    pub async fn put_object(
+        &mut self,
+        bucket_name: &str,
+        mut object: Object,
+    ) -> Result<Object, S3Error> {
+        let mut bucket = self.get_bucket_instance(bucket_name).await?;
+        self.apply_bucket_defaults(
+            &bucket.name,
+            &object.key,
+            &mut object.content_type,
+            &mut object.user_metadata,
+            &object.data,
+        )
+        .await?;
+        let result = bucket.put_object(object);
+        match result.await {
+            Ok(object) => {
+                self.cache.invalidate(&bucket.name, &object.key);
+                self.notifier
+                    .publish(Event::object_created(&bucket.name, &object));
+                Ok(object)
+            }
+            Err(BucketError::Storage(StorageError::ObjectUnderLegalHold(key, bucket))) => {
+                Err(S3Error::ObjectLocked(key, bucket))
+            }
+            Err(BucketError::Storage(StorageError::ObjectImmutable(key, bucket))) => {
+                Err(S3Error::ObjectImmutable(key, bucket))
+            }
+            Err(e) => Err(S3Error::BucketOperationFailed(e)),
+        }
+    }
+
+    /// Fills in what an object stored without them gets from its bucket:
+    /// the bucket's default user metadata, and its default content type or
+    /// one inferred from the key and `head`, the object's leading bytes.
+    async fn apply_bucket_defaults(
+        &self,
+        bucket_name: &str,
+        key: &str,
+        content_type: &mut Option<String>,
+        user_metadata: &mut Option<HashMap<String, String>>,
+        head: &[u8],
+    ) -> Result<(), S3Error> {
+        let defaults = self.get_bucket_default_metadata(bucket_name).await?;
+        if !defaults.is_empty() {
+            let user_metadata = user_metadata.get_or_insert_with(HashMap::new);
+            for (name, value) in defaults {
+                user_metadata.entry(name).or_insert(value);
+            }
+        }
+        if let Some(user_metadata) = user_metadata {
+            metadata::validate(user_metadata)?;
+        }
+        if content_type.is_none() {
+            *content_type = self.get_bucket_default_content_type(bucket_name).await?;
+        }
+        if content_type.is_none() {
+            *content_type = content_type::infer(&self.content_types, key, head).map(str::to_string);
+        }
+        Ok(())
+    }
+
+    /// Retrieves an object from a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket to retrieve the object from.
+    /// * `key` - The key of the object to retrieve.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Object, S3Error>` - The retrieved object, or an error.
+    pub async fn get_object(&self, bucket_name: &str, key: &str) -> Result<Object, S3Error> {
+        let bucket = self.get_bucket_instance(bucket_name).await?;
+        if let Some(object) = self.cache.get(&bucket.name, key) {
+            return Ok(object);
+        }
+        match bucket.get_object(key).await {
+            Ok(object) => {
+                self.cache.insert(&bucket.name, &object);
+                Ok(object)
+            }
+            Err(e) => Err(S3Error::BucketOperationFailed(e)),
+        }
+    }
+
+    /// Gets an object to download. Objects small enough to be cached are
+    /// read whole and cached like by `get_object`; larger ones are opened
+    /// to be sent piece by piece, so they are never held in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket containing the object.
+    /// * `key` - The key of the object to download.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectDownload, S3Error>` - The object or its opened file, or an error.
+    pub async fn download_object(
+        &self,
+        bucket_name: &str,
+        key: &str,
+    ) -> Result<ObjectDownload, S3Error> {
+        let bucket = self.get_bucket_instance(bucket_name).await?;
+        if let Some(object) = self.cache.get(&bucket.name, key) {
+            return Ok(ObjectDownload::Buffered(object));
+        }
+        let info = bucket.head_object(key).await.map_err(download_error)?;
+        if self.cache.accepts(&bucket.name, info.size) {
+            return self
+                .get_object(bucket_name, key)
+                .await
+                .map(ObjectDownload::Buffered);
+        }
+        bucket
+            .open_object(key)
+            .await
+            .map(ObjectDownload::Streamed)
+            .map_err(download_error)
+    }
+
+    /// Concatenates objects of a bucket, in the given order, into a new
+    /// object of the same bucket. A source may appear more than once, and
+    /// the destination may be one of the sources. The sources are copied
+    /// file to file by the storage, never held in memory.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket holding the sources and the destination.
+    /// * `key` - The key of the composed object.
+    /// * `sources` - The keys of the objects to concatenate.
+    /// * `content_type` - The content type of the composed object; that of the first
+    ///   source if absent.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectInfo, S3Error>` - The composed object, or an error.
+    pub async fn compose_object(
+        &mut self,
+        bucket_name: &str,
+        key: &str,
+        sources: &[String],
+        content_type: Option<String>,
+    ) -> Result<ObjectInfo, S3Error> {
+        if sources.is_empty() || sources.len() > MAX_COMPOSE_SOURCES {
+            return Err(S3Error::InvalidRequest(format!(
+                "Compose needs between 1 and {} sources",
+                MAX_COMPOSE_SOURCES
+            )));
+        }
+        let bucket = self.get_bucket_instance(bucket_name).await?;
+        let mut content_type = match content_type {
+            Some(content_type) => Some(content_type),
+            None => {
+                self.head_object(bucket_name, &sources[0])
+                    .await?
+                    .content_type
+            }
+        };
+        let mut user_metadata = None;
+        self.apply_bucket_defaults(
+            &bucket.name,
+            key,
+            &mut content_type,
+            &mut user_metadata,
+            &[],
+        )
+        .await?;
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.compose_object(
+                &bucket.name,
+                key,
+                sources,
+                content_type.as_deref(),
+                user_metadata.as_ref(),
+            )
+        };
+        let info = result.map_err(|e| write_error(e, "compose object"))?;
+        self.cache.invalidate(&bucket.name, &info.key);
+        self.notifier
+            .publish(Event::object_info_created(&bucket.name, &info));
+        Ok(info)
+    }
+
+    /// Issues a share link of an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket containing the object.
+    /// * `key` - The key of the object to share.
+    /// * `max_downloads` - Downloads allowed in all, if limited.
+    /// * `expires_in` - Time until the link stops working, if limited.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Share, S3Error>` - The issued share, or an error.
+    pub async fn create_share(
+        &mut self,
+        bucket_name: &str,
+        key: &str,
+        max_downloads: Option<u64>,
+        expires_in: Option<Duration>,
+    ) -> Result<Share, S3Error> {
+        if max_downloads.is_none() && expires_in.is_none() {
+            return Err(S3Error::InvalidRequest(
+                "A share needs max_downloads, expires_in_secs or both".to_string(),
+            ));
+        }
+        if max_downloads == Some(0) || expires_in.is_some_and(|d| d.is_zero()) {
+            return Err(S3Error::InvalidRequest(
+                "Share limits must be positive".to_string(),
+            ));
+        }
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.create_share(
+                bucket_name,
+                key,
+                &share::new_id(),
+                &share::new_token(),
+                max_downloads,
+                expires_in,
+            )
+        };
+
+        match result {
+            Ok(share) => Ok(share),
+            Err(StorageError::ObjectNotFound(key, bucket)) => {
+                Err(S3Error::ObjectNotFound(key, bucket))
+            }
+            Err(e) => Err(storage_error(e, "Failed to create share in storage")),
+        }
+    }
+
+    /// Lists the share links of a bucket that can still be downloaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Share>, S3Error>` - The shares, or an error.
+    pub async fn list_shares(&self, bucket_name: &str) -> Result<Vec<Share>, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.list_shares(bucket_name)
+        };
+
+        match result {
+            Ok(shares) => Ok(shares),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e) => Err(storage_error(e, "Failed to list shares from storage")),
+        }
+    }
+
+    /// Revokes a share link before its limits are reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket the shared object is in.
+    /// * `id` - The ID of the share.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), S3Error>` - An empty result, or an error.
+    pub async fn revoke_share(&mut self, bucket_name: &str, id: &str) -> Result<(), S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.revoke_share(bucket_name, id)
+        };
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(StorageError::ShareNotFound) => Err(S3Error::ShareNotFound),
+            Err(e) => Err(storage_error(e, "Failed to revoke share in storage")),
+        }
+    }
+
+    /// Gets the object a share link points to, counting the download.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The link's token.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(Share, Object), S3Error>` - The share and its object, or an error.
+    pub async fn get_shared_object(&self, token: &str) -> Result<(Share, Object), S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.claim_share(token)
+        };
+
+        let share = match result {
+            Ok(share) => share,
+            Err(StorageError::ShareNotFound) => return Err(S3Error::ShareNotFound),
+            Err(StorageError::ShareExpired) => return Err(S3Error::ShareExpired),
+            Err(e) => {
+                return Err(storage_error(e, "Failed to claim share in storage"));
+            }
+        };
+        let object = self.get_object(&share.bucket, &share.key).await?;
+        Ok((share, object))
+    }
+
+    /// Rewrites a byte span of an object, e.g. a header of a fixed-layout
+    /// binary file, without uploading the whole object again. The stored
+    /// object is still rewritten in full; see `Storage::patch_object`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket containing the object.
+    /// * `key` - The key of the object to patch.
+    /// * `range` - The span to rewrite.
+    /// * `data` - The new bytes of the span.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectInfo, S3Error>` - The object's new metadata, or an error.
+    pub async fn patch_object(
+        &mut self,
+        bucket_name: &str,
+        key: &str,
+        range: &ContentRange,
+        data: &[u8],
+    ) -> Result<ObjectInfo, S3Error> {
+        let mut bucket = self.get_bucket_instance(bucket_name).await?;
+        match bucket.patch_object(key, range, data).await {
+            Ok(info) => {
+                self.cache.invalidate(&bucket.name, key);
+                Ok(info)
+            }
+            Err(BucketError::Storage(StorageError::ObjectNotFound(key, bucket))) => {
+                Err(S3Error::ObjectNotFound(key, bucket))
+            }
+            Err(BucketError::Storage(StorageError::ObjectUnderLegalHold(key, bucket))) => {
+                Err(S3Error::ObjectLocked(key, bucket))
+            }
+            Err(BucketError::Storage(StorageError::ObjectImmutable(key, bucket))) => {
+                Err(S3Error::ObjectImmutable(key, bucket))
+            }
+            Err(BucketError::Storage(e @ StorageError::RangeNotSatisfiable(..))) => {
+                Err(S3Error::InvalidRange(e.to_string()))
+            }
+            Err(e) => Err(S3Error::BucketOperationFailed(e)),
+        }
+    }
+
+    /// Retrieves the metadata of an object without its data.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket containing the object.
+    /// * `key` - The key of the object.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectInfo, S3Error>` - The object's metadata, or an error.
+    pub async fn head_object(&self, bucket_name: &str, key: &str) -> Result<ObjectInfo, S3Error> {
+        let bucket = self.get_bucket_instance(bucket_name).await?;
+        match bucket.head_object(key).await {
+            Ok(info) => Ok(info),
+            Err(BucketError::Storage(StorageError::ObjectNotFound(key, bucket))) => {
+                Err(S3Error::ObjectNotFound(key, bucket))
+            }
+            Err(e) => Err(S3Error::BucketOperationFailed(e)),
+        }
+    }
+
+    /// Deletes an object from a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket to delete the object from.
+    /// * `key` - The key of the object to delete.
+    /// * `if_match` - The `If-Match` header value the object's ETag must match, if any.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), S3Error>` - An empty result, or an error.
+    pub async fn delete_object(
+        &mut self,
+        bucket_name: &str,
+        key: &str,
+        if_match: Option<&str>,
+    ) -> Result<(), S3Error> {
+        let mut bucket = self.get_bucket_instance(bucket_name).await?;
+        match bucket.delete_object(key, if_match).await {
+            Ok(true) => {
+                self.cache.invalidate(&bucket.name, key);
+                self.notifier
+                    .publish(Event::object_removed(&bucket.name, key));
+                Ok(())
+            }
+            Ok(false) => Err(S3Error::ObjectNotFound(
+                key.to_string(),
+                bucket_name.to_string(),
+            )),
+            Err(BucketError::Storage(StorageError::ObjectUnderLegalHold(key, bucket))) => {
+                Err(S3Error::ObjectLocked(key, bucket))
+            }
+            Err(BucketError::Storage(StorageError::ObjectImmutable(key, bucket))) => {
+                Err(S3Error::ObjectImmutable(key, bucket))
+            }
+            Err(BucketError::Storage(StorageError::ObjectNotFound(key, bucket))) => {
+                Err(S3Error::ObjectNotFound(key, bucket))
+            }
+            Err(BucketError::Storage(StorageError::PreconditionFailed(key, bucket))) => {
+                Err(S3Error::PreconditionFailed(key, bucket))
+            }
+            Err(e) => Err(S3Error::BucketOperationFailed(e)),
+        }
+    }
+
+    /// Deletes every object whose key starts with `prefix`, one batch per
+    /// transaction, releasing the storage between batches.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket to delete from.
+    /// * `prefix` - The key prefix of the objects to delete.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<PrefixDeleteReport, S3Error>` - What was deleted, or an error.
+    pub async fn delete_prefix(
+        &mut self,
+        bucket_name: &str,
+        prefix: &str,
+    ) -> Result<PrefixDeleteReport, S3Error> {
+        let bucket = self.get_bucket_instance(bucket_name).await?;
+        let mut report = PrefixDeleteReport::default();
+        let mut after = String::new();
+        loop {
+            let result = {
+                let mut lock = self.lock_storage().await?;
+                lock.delete_prefix_batch(&bucket.name, prefix, &after, DELETE_BATCH_SIZE)
+            };
+            let batch =
+                result.map_err(|e| storage_error(e, "Failed to delete objects from storage"))?;
+            for key in &batch.deleted {
+                self.cache.invalidate(&bucket.name, key);
+            }
+            report.deleted += batch.deleted.len() as u64;
+            report.skipped.extend(batch.skipped);
+            match batch.cursor {
+                Some(cursor) => after = cursor,
+                None => return Ok(report),
+            }
+        }
+    }
+
+    /// Re-hashes an object's file and compares it with the stored ETag.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket containing the object.
+    /// * `key` - The key of the object.
+    /// * `repair` - Whether to store the actual ETag if they differ.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectVerification, S3Error>` - The verdict, or an error.
+    pub async fn verify_object(
+        &mut self,
+        bucket_name: &str,
+        key: &str,
+        repair: bool,
+    ) -> Result<ObjectVerification, S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.verify_object(bucket_name, key, repair)
+        };
+
+        match result {
+            Ok(verification) => {
+                if verification.repaired {
+                    self.cache.invalidate(bucket_name, key);
+                }
+                Ok(verification)
+            }
+            Err(StorageError::ObjectNotFound(key, bucket)) => {
+                Err(S3Error::ObjectNotFound(key, bucket))
+            }
+            Err(e) => Err(storage_error(e, "Failed to verify object in storage")),
+        }
+    }
+
+    /// Restores a versioned bucket to its state at a point in time.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `at` - The point in time, in Unix seconds.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<RestoreReport, S3Error>` - What was changed, or an error.
+    pub async fn restore_bucket(
+        &mut self,
+        bucket_name: &str,
+        at: i64,
+    ) -> Result<RestoreReport, S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.restore_bucket(bucket_name, at)
+        };
+        // Restored objects may be served from the cache otherwise
+        self.cache.invalidate_bucket(bucket_name);
+
+        match result {
+            Ok(report) => Ok(report),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                Err(S3Error::BucketNotFound(bucket_name))
+            }
+            Err(e @ StorageError::NoVersionHistory(_)) => {
+                Err(S3Error::InvalidRequest(e.to_string()))
+            }
+            Err(e) => Err(storage_error(e, "Failed to restore bucket in storage")),
+        }
+    }
+
+    /// Gets the legal hold flag of an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket containing the object.
+    /// * `key` - The key of the object.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, S3Error>` - Whether the object is under legal hold, or an error.
+    pub async fn get_object_legal_hold(
+        &self,
+        bucket_name: &str,
+        key: &str,
+    ) -> Result<bool, S3Error> {
+        let bucket = self.get_bucket_instance(bucket_name).await?;
+        match bucket.get_legal_hold(key).await {
+            Ok(legal_hold) => Ok(legal_hold),
+            Err(BucketError::Storage(StorageError::ObjectNotFound(key, bucket))) => {
+                Err(S3Error::ObjectNotFound(key, bucket))
+            }
+            Err(e) => Err(S3Error::BucketOperationFailed(e)),
+        }
+    }
+
+    /// Places or releases a legal hold on an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket containing the object.
+    /// * `key` - The key of the object.
+    /// * `legal_hold` - Whether the object should be under legal hold.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), S3Error>` - An empty result, or an error.
+    pub async fn put_object_legal_hold(
+        &mut self,
+        bucket_name: &str,
+        key: &str,
+        legal_hold: bool,
+    ) -> Result<(), S3Error> {
+        let mut bucket = self.get_bucket_instance(bucket_name).await?;
+        match bucket.set_legal_hold(key, legal_hold).await {
+            Ok(_) => Ok(()),
+            Err(BucketError::Storage(StorageError::ObjectNotFound(key, bucket))) => {
+                Err(S3Error::ObjectNotFound(key, bucket))
+            }
+            Err(e) => Err(S3Error::BucketOperationFailed(e)),
+        }
+    }
+
+    /// Lists all objects in a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket to list objects from.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<String>, S3Error>` - A vector of object keys in the bucket, or an error.
+    pub async fn list_objects(&self, bucket_name: &str) -> Result<Vec<String>, S3Error> {
+        let bucket = self.get_bucket_instance(bucket_name).await?;
+        let result = bucket.list_objects().await;
+        match result {
+            Ok(objects) => Ok(objects),
+            Err(e) => Err(S3Error::BucketOperationFailed(e)),
+        }
+    }
+
+    /// Lists all objects in a bucket with their size, ETag, content type and
+    /// last modification time.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket to list objects from.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<ObjectInfo>, S3Error>` - The metadata of the objects, or an error.
+    pub async fn list_objects_detailed(
+        &self,
+        bucket_name: &str,
+    ) -> Result<Vec<ObjectInfo>, S3Error> {
+        let bucket = self.get_bucket_instance(bucket_name).await?;
+        bucket
+            .list_object_infos()
+            .await
+            .map_err(S3Error::BucketOperationFailed)
+    }
+
+    /// Lists the versions of the objects in a bucket, current ones included.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket to list versions from.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<ObjectVersion>, S3Error>` - The versions, ordered by key and then
+    ///   newest first, or an error.
+    pub async fn list_object_versions(
+        &self,
+        bucket_name: &str,
+    ) -> Result<Vec<ObjectVersion>, S3Error> {
+        let bucket = self.get_bucket_instance(bucket_name).await?;
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.list_object_versions(&bucket.name)
+        };
+        result.map_err(|e| storage_error(e, "Failed to list object versions from storage"))
+    }
+
+    /// Lists the user metadata of the objects in a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket to list metadata from.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<HashMap<String, HashMap<String, String>>, S3Error>` - The metadata by
+    ///   object key, for the objects that have any, or an error.
+    pub async fn list_object_metadata(
+        &self,
+        bucket_name: &str,
+    ) -> Result<HashMap<String, HashMap<String, String>>, S3Error> {
+        let bucket = self.get_bucket_instance(bucket_name).await?;
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.list_object_metadata(&bucket.name)
+        };
+        result.map_err(|e| storage_error(e, "Failed to list object metadata from storage"))
+    }
+
+    /// Lists the metadata of all objects in a bucket with their checksums in
+    /// the configured hash algorithm, for sync tools diffing a local tree.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket to list objects from.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(String, Vec<(ObjectInfo, Option<String>)>), S3Error>` - The name of the
+    ///   hash algorithm and the objects with their checksums, or an error.
+    pub async fn list_object_checksums(
+        &self,
+        bucket_name: &str,
+    ) -> Result<(String, Vec<(ObjectInfo, Option<String>)>), S3Error> {
+        let bucket = self.get_bucket_instance(bucket_name).await?;
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.list_object_checksums(&bucket.name)
+                .map(|objects| (lock.hash_algorithm().to_string(), objects))
+        };
+        result.map_err(|e| storage_error(e, "Failed to list checksums in storage"))
+    }
+
+    /// Creates the zero-byte marker object of a folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket to create the folder in.
+    /// * `marker_key` - The key of the marker, ending with `/`.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Object, S3Error>` - The marker object, or an error.
+    pub async fn create_folder(
+        &mut self,
+        bucket_name: &str,
+        marker_key: &str,
+    ) -> Result<Object, S3Error> {
+        let object = Object::new(
+            marker_key.to_string(),
+            Vec::new(),
+            Some(FOLDER_CONTENT_TYPE.to_string()),
+            None,
+        )?;
+        self.put_object(bucket_name, object).await
+    }
+
+    /// Lists the objects and subfolders directly in a folder.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket to list.
+    /// * `prefix` - The folder, ending with `/`, or empty for the bucket's root.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<FolderListing, S3Error>` - The folder's contents, or an error.
+    pub async fn list_folder(
+        &self,
+        bucket_name: &str,
+        prefix: &str,
+    ) -> Result<FolderListing, S3Error> {
+        let keys = self.list_objects(bucket_name).await?;
+        Ok(folder::list_folder(keys.iter().map(String::as_str), prefix))
+    }
+
+    /// Loads the objects of a bucket whose keys start with `prefix` into the
+    /// cache, stopping once it is full.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket to load objects from.
+    /// * `prefix` - The key prefix of the objects to load.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CacheWarmReport, S3Error>` - What was loaded, or an error.
+    pub async fn warm_cache(
+        &self,
+        bucket_name: &str,
+        prefix: &str,
+    ) -> Result<CacheWarmReport, S3Error> {
+        self.load_into_cache(bucket_name, |key| key.starts_with(prefix))
+            .await
+    }
+
+    /// Loads the objects of a bucket selected by `filter` into the cache,
+    /// skipping those that do not fit.
+    async fn load_into_cache(
+        &self,
+        bucket_name: &str,
+        filter: impl Fn(&str) -> bool,
+    ) -> Result<CacheWarmReport, S3Error> {
+        let bucket = self.get_bucket_instance(bucket_name).await?;
+        let keys = bucket
+            .list_objects()
+            .await
+            .map_err(S3Error::BucketOperationFailed)?;
+
+        let mut report = CacheWarmReport::default();
+        for key in keys.iter().filter(|k| filter(k)) {
+            let size = match bucket.head_object(key).await {
+                Ok(info) => info.size,
+                // Deleted since it was listed
+                Err(BucketError::Storage(StorageError::ObjectNotFound(..))) => continue,
+                Err(e) => return Err(S3Error::BucketOperationFailed(e)),
+            };
+            // Loading more than fits would only evict what was just loaded
+            if !self.cache.accepts(&bucket.name, size)
+                || !self.cache.has_room(&bucket.name, key, size)
+            {
+                report.skipped += 1;
+                continue;
+            }
+            let object = bucket
+                .get_object(key)
+                .await
+                .map_err(S3Error::BucketOperationFailed)?;
+            if self.cache.insert(&bucket.name, &object) {
+                report.objects += 1;
+                report.bytes += size;
+            } else {
+                report.skipped += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Lists the keys and prefixes pinned in the object cache.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<CachePin>, S3Error>` - The pins, or an error.
+    pub async fn list_cache_pins(&self) -> Result<Vec<CachePin>, S3Error> {
+        let result = {
+            let lock = self.lock_storage().await?;
+            lock.list_cache_pins()
+        };
+        result.map_err(|e| storage_error(e, "Failed to list cache pins in storage"))
+    }
+
+    /// Pins a key or prefix in the object cache and loads the matching objects.
+    ///
+    /// # Arguments
+    ///
+    /// * `pin` - The key or prefix to pin.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CacheWarmReport, S3Error>` - The objects loaded, or an error.
+    pub async fn pin_cache(&mut self, pin: CachePin) -> Result<CacheWarmReport, S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.add_cache_pin(&pin)
+                .and_then(|_| lock.list_cache_pins())
+        };
+        match result {
+            Ok(pins) => self.cache.set_pins(pins),
+            Err(StorageError::BucketNotFoundInStorage(bucket_name)) => {
+                return Err(S3Error::BucketNotFound(bucket_name));
+            }
+            Err(e) => {
+                return Err(storage_error(e, "Failed to add cache pin in storage"));
+            }
+        }
+        self.load_into_cache(&pin.bucket, |key| pin.matches(&pin.bucket, key))
+            .await
+    }
+
+    /// Unpins a key or prefix. Its objects stay cached until evicted.
+    ///
+    /// # Arguments
+    ///
+    /// * `pin` - The key or prefix to unpin.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), S3Error>` - An empty result, or an error.
+    pub async fn unpin_cache(&mut self, pin: CachePin) -> Result<(), S3Error> {
+        let result = {
+            let mut lock = self.lock_storage().await?;
+            lock.remove_cache_pin(&pin)
+                .and_then(|removed| Ok((removed, lock.list_cache_pins()?)))
+        };
+        match result {
+            Ok((true, pins)) => {
+                self.cache.set_pins(pins);
+                Ok(())
+            }
+            Ok((false, _)) => Err(S3Error::InvalidRequest(format!(
+                "'{}' is not pinned in bucket '{}'",
+                pin.key, pin.bucket
+            ))),
+            Err(e) => Err(storage_error(e, "Failed to remove cache pin from storage")),
+        }
+    }
+
+    /// Restores the pins persisted in storage and loads their objects, e.g.
+    /// after a restart.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CacheWarmReport, S3Error>` - The objects loaded, or an error.
+    pub async fn restore_cache_pins(&self) -> Result<CacheWarmReport, S3Error> {
+        let pins = self.list_cache_pins().await?;
+        self.cache.set_pins(pins.clone());
+
+        let mut report = CacheWarmReport::default();
+        for pin in &pins {
+            let loaded = self
+                .load_into_cache(&pin.bucket, |key| pin.matches(&pin.bucket, key))
+                .await?;
+            report.objects += loaded.objects;
+            report.bytes += loaded.bytes;
+            report.skipped += loaded.skipped;
+        }
+        Ok(report)
+    }
+}
+
+/// Maps storage errors of resumable upload operations to service errors.
+fn upload_error(e: StorageError, action: &str) -> S3Error {
+    match e {
+        StorageError::BucketNotFoundInStorage(bucket_name) => S3Error::BucketNotFound(bucket_name),
+        StorageError::UploadNotFound(id) => S3Error::UploadNotFound(id),
+        StorageError::UploadOffsetMismatch(..) => S3Error::UploadConflict(e.to_string()),
+        StorageError::UploadLengthExceeded(..) => S3Error::InvalidRequest(e.to_string()),
+        e => write_error(e, action),
+    }
+}
+
+/// Maps storage errors of operations writing an object to service errors.
+fn write_error(e: StorageError, action: &str) -> S3Error {
+    match e {
+        StorageError::ObjectNotFound(key, bucket) => S3Error::ObjectNotFound(key, bucket),
+        StorageError::ObjectUnderLegalHold(key, bucket) => S3Error::ObjectLocked(key, bucket),
+        StorageError::ObjectImmutable(key, bucket) => S3Error::ObjectImmutable(key, bucket),
+        e => storage_error(e, &format!("Failed to {} in storage", action)),
+    }
+}
+
+/// Maps an error looking up or opening an object to download.
+fn download_error(e: BucketError) -> S3Error {
+    match e {
+        BucketError::Storage(StorageError::ObjectNotFound(key, bucket)) => {
+            S3Error::ObjectNotFound(key, bucket)
+        }
+        BucketError::Storage(StorageError::LockTimeout(secs)) => S3Error::StorageTimeout(secs),
+        BucketError::Storage(e) => storage_error(e, "Failed to open object in storage"),
+        e => S3Error::BucketOperationFailed(e),
+    }
+}
+
+/// Maps a storage error without a more specific meaning: a database still
+/// locked by another connection after the retries asks the client to slow
+/// down, anything else is an internal error described by `context`.
+pub(crate) fn storage_error(e: StorageError, context: &str) -> S3Error {
+    if e.is_busy() {
+        S3Error::SlowDown("The storage is busy, please retry.".to_string())
+    } else {
+        S3Error::InternalStorageError(format!("{}: {}", context, e))
+    }
 }
@@ -0,0 +1,265 @@
+// secrets.rs
+// Secrets fetched from a secret manager instead of the config file: a
+// HashiCorp Vault KV v2 secret or any HTTP endpoint serving a JSON object of
+// names to values, configured under `[secrets]`. They are fetched once at
+// startup, which fails if they cannot be, and then refreshed in the
+// background; a failed refresh keeps the previous values. Secret managers
+// are reached over plain HTTP, so only on the loopback interface, e.g.
+// through a local Vault Agent listener; the token and the secrets would
+// otherwise cross the network in cleartext.
+
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{info, warn};
+
+use crate::config::{SecretSource, SecretsConfig};
+use crate::http_client::{self, is_loopback, split_url};
+
+/// Prefix of the secrets holding the secret keys of access keys.
+pub const CREDENTIAL_PREFIX: &str = "credentials.";
+/// Header carrying the token of Vault requests.
+const VAULT_TOKEN_HEADER: &str = "X-Vault-Token";
+
+/// Custom error type for fetching secrets.
+#[derive(Debug, Error)]
+pub enum SecretsError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Invalid secrets URL: {0}")]
+    InvalidUrl(String),
+    #[error("Secrets URL {0} is not on the loopback interface; plain HTTP would expose them")]
+    InsecureUrl(String),
+    #[error("Environment variable {0} holding the secrets token is not set")]
+    MissingToken(String),
+    #[error("Secret manager answered with status {0}")]
+    Status(u16),
+    #[error("Malformed secrets: {0}")]
+    Malformed(String),
+}
+
+/// The secrets fetched last, by name.
+#[derive(Default)]
+pub struct SecretStore {
+    values: RwLock<HashMap<String, String>>,
+}
+
+// Values stay out of logs and debug output
+impl fmt::Debug for SecretStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretStore")
+            .field("secrets", &self.read().len())
+            .finish()
+    }
+}
+
+impl SecretStore {
+    pub fn new(values: HashMap<String, String>) -> Self {
+        Self {
+            values: RwLock::new(values),
+        }
+    }
+
+    /// Fetches the secrets `config` points to; without a source, the store
+    /// is empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The `[secrets]` section of the configuration.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, SecretsError>` - The store, or the error fetching the secrets.
+    pub async fn load(config: &SecretsConfig) -> Result<Self, SecretsError> {
+        if config.source == SecretSource::None {
+            return Ok(Self::default());
+        }
+        let values = fetch(config, |name| std::env::var(name).ok()).await?;
+        info!(secrets = values.len(), "Secrets fetched");
+        Ok(Self::new(values))
+    }
+
+    /// The secret named `name`.
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.read().get(name).cloned()
+    }
+
+    /// The secret key of an access key, from `credentials.<access_key>`.
+    pub fn credential(&self, access_key: &str) -> Option<String> {
+        self.get(&format!("{}{}", CREDENTIAL_PREFIX, access_key))
+    }
+
+    /// Replaces every secret with `values`.
+    pub fn replace(&self, values: HashMap<String, String>) {
+        *self.values.write().unwrap_or_else(|e| e.into_inner()) = values;
+    }
+
+    /// Starts fetching the secrets again every `refresh_secs`, unless there
+    /// is no source or refreshes are off.
+    pub fn start_refresh(
+        self: Arc<Self>,
+        config: SecretsConfig,
+    ) -> Option<tokio::task::JoinHandle<()>> {
+        if config.source == SecretSource::None || config.refresh_secs == 0 {
+            return None;
+        }
+        Some(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(config.refresh_secs));
+            // The first tick completes at once, right after the startup fetch
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match fetch(&config, |name| std::env::var(name).ok()).await {
+                    Ok(values) => self.replace(values),
+                    Err(e) => {
+                        warn!(error = %e, "Failed to refresh secrets, keeping the previous ones")
+                    }
+                }
+            }
+        }))
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, HashMap<String, String>> {
+        self.values.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Fetches the secrets `config` points to.
+///
+/// # Arguments
+///
+/// * `config` - The `[secrets]` section of the configuration.
+/// * `var` - Looks up an environment variable by name, for the token.
+///
+/// # Returns
+///
+/// * `Result<HashMap<String, String>, SecretsError>` - The secrets, or an error.
+pub async fn fetch(
+    config: &SecretsConfig,
+    var: impl Fn(&str) -> Option<String>,
+) -> Result<HashMap<String, String>, SecretsError> {
+    let (authority, path) =
+        split_url(&config.url).ok_or_else(|| SecretsError::InvalidUrl(config.url.clone()))?;
+    if !is_loopback(authority) {
+        return Err(SecretsError::InsecureUrl(config.url.clone()));
+    }
+    let token = var(&config.token_env);
+    let header = match (config.source, &token) {
+        (SecretSource::Vault, Some(token)) => Some((VAULT_TOKEN_HEADER, token.clone())),
+        (SecretSource::Vault, None) => {
+            return Err(SecretsError::MissingToken(config.token_env.clone()));
+        }
+        (_, Some(token)) => Some(("Authorization", format!("Bearer {}", token))),
+        (_, None) => None,
+    };
+    let headers: Vec<(&str, &str)> = header
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .collect();
+    let response = http_client::send(authority, "GET", path, &headers, b"").await?;
+    if response.status != 200 {
+        return Err(SecretsError::Status(response.status));
+    }
+    parse(config.source, &response.body)
+}
+
+/// Reads the secrets from a response body: the `data.data` object of a
+/// Vault KV v2 secret, or the top-level object otherwise. Values that are
+/// not strings are skipped.
+fn parse(source: SecretSource, body: &[u8]) -> Result<HashMap<String, String>, SecretsError> {
+    let document: Value =
+        serde_json::from_slice(body).map_err(|e| SecretsError::Malformed(e.to_string()))?;
+    let secrets = match source {
+        SecretSource::Vault => document.pointer("/data/data"),
+        _ => Some(&document),
+    };
+    let secrets = secrets
+        .and_then(Value::as_object)
+        .ok_or_else(|| SecretsError::Malformed("not a JSON object of secrets".to_string()))?;
+    Ok(secrets
+        .iter()
+        .filter_map(|(name, value)| Some((name.clone(), value.as_str()?.to_string())))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Credentials;
+
+    #[test]
+    fn test_parse() {
+        let vault = br#"{"data": {"data": {"credentials.AKID": "s3cr3t", "port": 1},
+                         "metadata": {"version": 3}}}"#;
+        let secrets = parse(SecretSource::Vault, vault).unwrap();
+        assert_eq!(
+            secrets,
+            HashMap::from([("credentials.AKID".to_string(), "s3cr3t".to_string())])
+        );
+        let http = br#"{"hook": "whsec"}"#;
+        assert_eq!(parse(SecretSource::Http, http).unwrap()["hook"], "whsec");
+        assert!(parse(SecretSource::Vault, http).is_err());
+        assert!(parse(SecretSource::Http, b"[]").is_err());
+    }
+
+    #[test]
+    fn test_credentials_from_secrets() {
+        let credentials: Credentials =
+            toml::from_str("AKID = \"from-file\"\nOTHER = \"other\"").unwrap();
+        let secrets = Arc::new(SecretStore::new(HashMap::from([(
+            "credentials.AKID".to_string(),
+            "from-vault".to_string(),
+        )])));
+        let credentials = credentials.with_secrets(secrets.clone());
+        assert_eq!(
+            credentials.secret_key("AKID").as_deref(),
+            Some("from-vault")
+        );
+        assert_eq!(credentials.secret_key("OTHER").as_deref(), Some("other"));
+
+        // Refreshed secrets apply at once
+        secrets.replace(HashMap::from([(
+            "credentials.NEW".to_string(),
+            "rotated".to_string(),
+        )]));
+        assert_eq!(credentials.secret_key("NEW").as_deref(), Some("rotated"));
+        assert_eq!(credentials.secret_key("AKID").as_deref(), Some("from-file"));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_needs_a_vault_token() {
+        let config = SecretsConfig {
+            source: SecretSource::Vault,
+            url: "http://127.0.0.1:1/v1/secret/data/s3".to_string(),
+            ..SecretsConfig::default()
+        };
+        assert!(matches!(
+            fetch(&config, |_| None).await,
+            Err(SecretsError::MissingToken(_))
+        ));
+        let config = SecretsConfig {
+            url: "https://vault.local/v1/secret".to_string(),
+            ..config
+        };
+        assert!(matches!(
+            fetch(&config, |_| Some("token".to_string())).await,
+            Err(SecretsError::InvalidUrl(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_refuses_remote_hosts() {
+        let config = SecretsConfig {
+            source: SecretSource::Http,
+            url: "http://secrets.example.com:8200/v1/secret".to_string(),
+            ..SecretsConfig::default()
+        };
+        assert!(matches!(
+            fetch(&config, |_| Some("token".to_string())).await,
+            Err(SecretsError::InsecureUrl(_))
+        ));
+    }
+}
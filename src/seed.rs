@@ -0,0 +1,118 @@
+// seed.rs
+// Seeding the store from a fixtures directory at startup (`--seed <dir>`),
+// for demos and integration test environments that need known content.
+// Every directory at the top level becomes a bucket and every file below it
+// an object, keyed by its path relative to the bucket directory with `/`
+// separators. Existing buckets are reused and existing objects overwritten,
+// so seeding the same directory again gives the same content.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tracing::warn;
+
+use crate::content_type;
+use crate::object::Object;
+use crate::storage::{Storage, StorageError};
+
+/// Custom error type for seeding
+#[derive(Debug, Error)]
+pub enum SeedError {
+    #[error("Failed to read seed path '{0}': {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Seed path '{0}' is not valid UTF-8")]
+    InvalidName(PathBuf),
+    #[error("Failed to seed '{0}': {1}")]
+    Storage(String, StorageError),
+}
+
+/// What seeding created.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeedReport {
+    pub buckets: u64,
+    pub objects: u64,
+    pub bytes: u64,
+}
+
+/// Creates the buckets and objects found in `dir`.
+///
+/// # Arguments
+///
+/// * `storage` - The storage to seed.
+/// * `dir` - The fixtures directory, one subdirectory per bucket.
+///
+/// # Returns
+///
+/// * `Result<SeedReport, SeedError>` - What was created, or the first error.
+pub fn seed_from_dir(storage: &mut Storage, dir: &Path) -> Result<SeedReport, SeedError> {
+    let mut report = SeedReport::default();
+    for path in sorted_entries(dir)? {
+        if !path.is_dir() {
+            warn!(path = %path.display(), "Skipping seed file outside of a bucket directory");
+            continue;
+        }
+        let bucket = file_name(&path)?;
+        if !storage
+            .bucket_exists(&bucket)
+            .map_err(|e| SeedError::Storage(bucket.clone(), e))?
+        {
+            storage
+                .create_bucket(&bucket)
+                .map_err(|e| SeedError::Storage(bucket.clone(), e))?;
+            report.buckets += 1;
+        }
+        seed_objects(storage, &bucket, &path, "", &mut report)?;
+    }
+    Ok(report)
+}
+
+/// Puts the files under `dir` into `bucket`, with `prefix` before their names.
+fn seed_objects(
+    storage: &mut Storage,
+    bucket: &str,
+    dir: &Path,
+    prefix: &str,
+    report: &mut SeedReport,
+) -> Result<(), SeedError> {
+    for path in sorted_entries(dir)? {
+        let key = format!("{}{}", prefix, file_name(&path)?);
+        if path.is_dir() {
+            seed_objects(storage, bucket, &path, &format!("{}/", key), report)?;
+            continue;
+        }
+        let data = fs::read(&path).map_err(|e| SeedError::Io(path.clone(), e))?;
+        let target = format!("{}/{}", bucket, key);
+        report.objects += 1;
+        report.bytes += data.len() as u64;
+        let object = Object {
+            content_type: content_type::from_extension(&key).map(str::to_string),
+            key,
+            data,
+            etag: None,
+            last_modified: 0,
+            user_metadata: None,
+            version_id: None,
+            immutable: false,
+        };
+        storage
+            .put_object(bucket, object)
+            .map_err(|e| SeedError::Storage(target, e))?;
+    }
+    Ok(())
+}
+
+/// The entries of `dir` in name order, so seeding is deterministic.
+fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>, SeedError> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .and_then(|entries| entries.map(|entry| entry.map(|e| e.path())).collect())
+        .map_err(|e| SeedError::Io(dir.to_path_buf(), e))?;
+    entries.sort_unstable();
+    Ok(entries)
+}
+
+fn file_name(path: &Path) -> Result<String, SeedError> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(str::to_string)
+        .ok_or_else(|| SeedError::InvalidName(path.to_path_buf()))
+}
@@ -0,0 +1,100 @@
+// share.rs
+// Share links handing a single object to someone without credentials.
+// POST /buckets/{bucket}/objects/{key}?share issues a random token, stored in
+// the `shares` table with a download limit, a deadline or both, and anyone
+// holding the token downloads the object from GET /share/{token} until the
+// limit is used up or the deadline passes. Every download is counted in the
+// same storage call that checks the limits, so concurrent downloads never
+// exceed them. Each share also has an ID, under which the bucket's
+// outstanding shares are listed with GET /buckets/{bucket}/shares and a
+// leaked link is revoked with DELETE /buckets/{bucket}/shares/{id}; the
+// tokens themselves are only returned when issued.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Path prefix of share link downloads.
+pub const SHARE_PATH_PREFIX: &str = "/share/";
+
+/// An issued share link.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Share {
+    pub id: String,
+    #[serde(skip_serializing)]
+    pub token: String,
+    #[serde(skip_serializing)]
+    pub bucket: String,
+    pub key: String,
+    /// Downloads allowed in all; unlimited if absent.
+    pub max_downloads: Option<u64>,
+    pub downloads: u64,
+    /// Seconds since the Unix epoch after which the link stops working.
+    pub expires_at: Option<i64>,
+    pub created_at: i64,
+}
+
+impl Share {
+    /// Whether the link may still be downloaded at `now`.
+    pub fn is_usable(&self, now: i64) -> bool {
+        self.max_downloads
+            .is_none_or(|max_downloads| self.downloads < max_downloads)
+            && self.expires_at.is_none_or(|expires_at| now < expires_at)
+    }
+
+    /// The path the object is downloaded from.
+    pub fn url(&self) -> String {
+        format!("{}{}", SHARE_PATH_PREFIX, self.token)
+    }
+}
+
+/// Creates the ID a share is listed and revoked under.
+pub fn new_id() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Creates an unguessable share token.
+pub fn new_token() -> String {
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_usable() {
+        let share = Share {
+            id: new_id(),
+            token: new_token(),
+            bucket: "b".to_string(),
+            key: "k".to_string(),
+            max_downloads: Some(2),
+            downloads: 1,
+            expires_at: Some(100),
+            created_at: 0,
+        };
+        assert_eq!(share.token.len(), 64);
+        assert!(share.is_usable(99));
+        assert!(!share.is_usable(100));
+        assert!(
+            !Share {
+                downloads: 2,
+                ..share.clone()
+            }
+            .is_usable(0)
+        );
+        assert!(
+            Share {
+                max_downloads: None,
+                expires_at: None,
+                downloads: 1000,
+                ..share
+            }
+            .is_usable(1000)
+        );
+    }
+}
@@ -0,0 +1,99 @@
+// simulation.rs
+// Simulated slowness and flakiness of the data path, for developing clients
+// against the behaviour of a remote object store on a local server. With
+// `[simulation]` configured, every request to `/buckets` (also under a
+// namespace) is delayed by `latency_ms` plus a random share of `jitter_ms`,
+// and a fraction `error_rate` of them fails with 500 InternalError instead
+// of reaching its handler. Admin endpoints and metrics are not affected.
+// Off by default; not meant for production.
+
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{Error, web};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::config::SimulationConfig;
+use crate::error_code::{ErrorCode, error_response};
+use crate::maintenance::is_data_path;
+
+impl SimulationConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.latency_ms > 0 || self.jitter_ms > 0 || self.error_rate > 0.0
+    }
+
+    /// The delay of a request, given a random number in `[0, 1)`.
+    pub fn delay(&self, random: f64) -> Duration {
+        let jitter = (self.jitter_ms as f64 * random) as u64;
+        Duration::from_millis(self.latency_ms + jitter)
+    }
+
+    /// Whether a request fails, given a random number in `[0, 1)`.
+    pub fn fails(&self, random: f64) -> bool {
+        random < self.error_rate
+    }
+}
+
+/// A random number in `[0, 1)`, good enough for spreading delays and
+/// failures; each call hashes a counter with a randomly keyed hasher.
+fn random() -> f64 {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let hash = RandomState::new().hash_one(COUNTER.fetch_add(1, Ordering::Relaxed));
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Middleware delaying and failing data path requests as configured.
+pub async fn simulate_flaky_storage(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let simulation = req
+        .app_data::<web::Data<SimulationConfig>>()
+        .filter(|simulation| simulation.is_enabled() && is_data_path(req.path()))
+        .map(|simulation| *simulation.get_ref());
+    let Some(simulation) = simulation else {
+        return Ok(next.call(req).await?.map_into_left_body());
+    };
+
+    tokio::time::sleep(simulation.delay(random())).await;
+    if simulation.fails(random()) {
+        let response = error_response(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::InternalError,
+            "InternalError: Simulated failure (see [simulation] in the configuration)",
+        );
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_and_failures() {
+        let simulation = SimulationConfig {
+            latency_ms: 100,
+            jitter_ms: 50,
+            error_rate: 0.1,
+        };
+        assert!(simulation.is_enabled());
+        assert_eq!(simulation.delay(0.0), Duration::from_millis(100));
+        assert_eq!(simulation.delay(0.5), Duration::from_millis(125));
+        assert!(simulation.fails(0.05));
+        assert!(!simulation.fails(0.1));
+        assert!(!SimulationConfig::default().is_enabled());
+    }
+
+    #[test]
+    fn test_random() {
+        let values: Vec<f64> = (0..100).map(|_| random()).collect();
+        assert!(values.iter().all(|v| (0.0..1.0).contains(v)));
+        assert!(values.iter().any(|v| *v != values[0]));
+    }
+}
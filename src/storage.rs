@@ -1,25 +1,648 @@
 // storage.rs
-use hex;
 use md5::{Digest, Md5};
-use rusqlite::{Connection, OptionalExtension, params};
-use serde_json;
-use std::collections::HashMap;
+use rusqlite::{Connection, OptionalExtension, Transaction, TransactionBehavior, params};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
+use tokio::sync::{Mutex, MutexGuard};
+use tracing::{instrument, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
 
-use crate::object::Object;
+use crate::access::{AccessReport, ObjectAccess, PendingAccess};
+use crate::acl::Grant;
+use crate::bucket::{LifecycleRule, VersioningStatus};
+use crate::cache::CachePin;
+use crate::clock::{Clock, SystemClock};
+use crate::config::{BusyConfig, SlowOpConfig};
+#[cfg(feature = "faults")]
+use crate::faults::{Fault, FaultSchedule};
+use crate::generators::{ETagGenerator, IdGenerator, UuidGenerator};
+use crate::namespace::split_bucket;
+use crate::object::{Object, ObjectInfo, ObjectVerification, ObjectVersion, StorageClass};
+use crate::placement::{DataRoots, Placement};
+use crate::range::ContentRange;
+use crate::replication::{ReplicationReport, ReplicationStatus};
+use crate::roles::Role;
+use crate::share::Share;
+use crate::storage_trace::OpTrace;
+use crate::tus::{Upload, UploadPart};
+use crate::usage::{self, BucketUsage, NamedUsage, USAGE_COLUMNS, UsageReport};
+
+/// Prepared statements kept per connection, enough for the statements of
+/// the request paths, which are prepared once and reused.
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+/// Size of the pieces object files are copied and hashed in.
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
 
 pub struct Storage {
     conn: Connection,
     base_path: PathBuf,
+    /// Data directories new object files are spread over, `base_path` first.
+    roots: DataRoots,
+    /// Generator of the checksums of newly written objects.
+    etags: Arc<dyn ETagGenerator>,
+    /// Generator of version and upload IDs.
+    ids: Arc<dyn IdGenerator>,
+    /// Thresholds of slow operation warnings.
+    slow_ops: SlowOpConfig,
+    /// Waiting and retrying while another connection holds the write lock.
+    busy: BusyConfig,
+    /// Time the current holder of the lock waited for it, taken by the
+    /// first operation it runs.
+    lock_wait: Cell<Duration>,
+    /// Source of the modification times and of the current time of the
+    /// lifecycle transitions.
+    clock: Arc<dyn Clock>,
+    /// Faults injected into the operations, in tests.
+    #[cfg(feature = "faults")]
+    faults: FaultSchedule,
+}
+
+/// Algorithm an object's ETag is computed with. Each row records its own,
+/// so objects written before a change keep verifying until re-hashed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgorithm {
+    #[default]
+    Md5,
+    Sha256,
+}
+
+impl HashAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Md5 => "md5",
+            HashAlgorithm::Sha256 => "sha256",
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = StorageError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "md5" => Ok(HashAlgorithm::Md5),
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            other => Err(StorageError::IntegrityError(format!(
+                "Unknown hash algorithm '{}'",
+                other
+            ))),
+        }
+    }
+}
+
+impl ETagGenerator for HashAlgorithm {
+    fn algorithm(&self) -> &str {
+        self.as_str()
+    }
+
+    fn etag(&self, data: &[u8]) -> String {
+        match self {
+            HashAlgorithm::Md5 => {
+                let mut hasher = Md5::default();
+                hasher.input(data);
+                hex::encode(hasher.result())
+            }
+            HashAlgorithm::Sha256 => hex::encode(<Sha256 as sha2::Digest>::digest(data)),
+        }
+    }
+}
+
+/// Computes the ETag of `data` with the algorithm named `algorithm`, as
+/// recorded with an object: the configured generator's or a built-in one.
+fn calculate_etag(
+    etags: &dyn ETagGenerator,
+    data: &[u8],
+    algorithm: &str,
+) -> Result<String, StorageError> {
+    if algorithm == etags.algorithm() {
+        Ok(etags.etag(data))
+    } else {
+        Ok(algorithm.parse::<HashAlgorithm>()?.etag(data))
+    }
+}
+
+/// An object's file opened for reading, with the metadata sent ahead of
+/// its bytes.
+#[derive(Debug)]
+pub struct ObjectReader {
+    pub info: ObjectInfo,
+    pub file: fs::File,
+    /// Check of the bytes read against the ETag, unless it was computed by
+    /// an algorithm that cannot hash them piece by piece.
+    pub check: Option<ETagCheck>,
+}
+
+/// Hashes an object's bytes as they are read, to compare them with its
+/// ETag once all of them are.
+#[derive(Debug)]
+pub struct ETagCheck {
+    hasher: ETagHasher,
+    expected: String,
+}
+
+/// Hashes bytes piece by piece into an ETag.
+#[derive(Debug)]
+enum ETagHasher {
+    Md5(Md5),
+    Sha256(Sha256),
+}
+
+impl ETagHasher {
+    fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Md5 => ETagHasher::Md5(Md5::default()),
+            HashAlgorithm::Sha256 => ETagHasher::Sha256(<Sha256 as sha2::Digest>::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            ETagHasher::Md5(hasher) => hasher.input(data),
+            ETagHasher::Sha256(hasher) => sha2::Digest::update(hasher, data),
+        }
+    }
+
+    fn finish(self) -> String {
+        match self {
+            ETagHasher::Md5(hasher) => hex::encode(hasher.result()),
+            ETagHasher::Sha256(hasher) => hex::encode(sha2::Digest::finalize(hasher)),
+        }
+    }
+}
+
+impl ETagCheck {
+    /// A check of the bytes against `expected`, an ETag computed with
+    /// `algorithm`.
+    pub fn new(algorithm: HashAlgorithm, expected: String) -> Self {
+        let hasher = ETagHasher::new(algorithm);
+        Self { hasher, expected }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    /// Whether the bytes hashed so far match the ETag.
+    pub fn matches(self) -> bool {
+        self.hasher.finish() == self.expected
+    }
+}
+
+/// Copies `reader` to `writer` in pieces, handing each to `on_chunk` on
+/// the way, e.g. to hash it, and returns the number of bytes copied.
+fn copy_chunks(
+    reader: &mut impl Read,
+    writer: &mut impl Write,
+    mut on_chunk: impl FnMut(&[u8]),
+) -> std::io::Result<u64> {
+    let mut buf = vec![0; COPY_BUFFER_SIZE];
+    let mut copied = 0;
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok(copied),
+            Ok(n) => n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        on_chunk(&buf[..n]);
+        writer.write_all(&buf[..n])?;
+        copied += n as u64;
+    }
+}
+
+/// Computes the ETag of the file at `path` with `etags`, reading it piece
+/// by piece unless the generator only hashes whole buffers.
+fn file_etag(etags: &dyn ETagGenerator, path: &Path) -> Result<String, StorageError> {
+    match etags.algorithm().parse::<HashAlgorithm>() {
+        Ok(algorithm) => {
+            let mut hasher = ETagHasher::new(algorithm);
+            copy_chunks(&mut fs::File::open(path)?, &mut std::io::sink(), |chunk| {
+                hasher.update(chunk)
+            })?;
+            Ok(hasher.finish())
+        }
+        Err(_) => Ok(etags.etag(&fs::read(path)?)),
+    }
+}
+
+/// Returns the file the data stored under the internal ID `id` lives in
+/// below `dir`, fanned out over subdirectories named by its first two
+/// characters. Keys never reach the filesystem, so they may contain any
+/// character; files of objects written before IDs were introduced keep
+/// their key-based paths, as recorded in `file_path`.
+fn object_data_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(id.get(..2).unwrap_or(id)).join(id)
+}
+
+/// Gives every object without an internal ID (written before IDs were
+/// introduced) a random one.
+fn assign_object_ids(conn: &Connection) -> Result<(), StorageError> {
+    let rowids = conn
+        .prepare("SELECT rowid FROM objects WHERE id IS NULL")?
+        .query_map([], |row| row.get::<_, i64>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for rowid in rowids {
+        conn.execute(
+            "UPDATE objects SET id = ?1 WHERE rowid = ?2",
+            params![UuidGenerator.object_id(), rowid],
+        )?;
+    }
+    Ok(())
+}
+
+/// Columns of `objects` read by `object_info_from_row`, in order.
+const OBJECT_INFO_COLUMNS: &str = "key, content_type, etag, size, last_modified, version_id,
+     replication_status, storage_class, immutable";
+
+/// Columns of `shares` read by `share_from_row`.
+const SHARE_COLUMNS: &str =
+    "id, token, bucket_name, key, max_downloads, downloads, expires_at, created_at";
+
+/// Reads a share selected with `SHARE_COLUMNS`.
+fn share_from_row(row: &rusqlite::Row) -> rusqlite::Result<Share> {
+    Ok(Share {
+        id: row.get(0)?,
+        token: row.get(1)?,
+        bucket: row.get(2)?,
+        key: row.get(3)?,
+        max_downloads: row.get::<_, Option<i64>>(4)?.map(|n| n as u64),
+        downloads: row.get::<_, i64>(5)? as u64,
+        expires_at: row.get(6)?,
+        created_at: row.get(7)?,
+    })
+}
+
+/// Reads an object's metadata from a row selecting `OBJECT_INFO_COLUMNS`.
+fn object_info_from_row(row: &rusqlite::Row) -> rusqlite::Result<ObjectInfo> {
+    Ok(ObjectInfo {
+        key: row.get(0)?,
+        content_type: row.get(1)?,
+        etag: row.get(2)?,
+        size: row.get::<_, i64>(3)? as u64,
+        last_modified: row.get(4)?,
+        version_id: row.get(5)?,
+        replication_status: row
+            .get::<_, Option<String>>(6)?
+            .and_then(|s| s.parse().ok()),
+        storage_class: row
+            .get::<_, String>(7)?
+            .parse()
+            .unwrap_or(StorageClass::Standard),
+        immutable: row.get(8)?,
+    })
+}
+
+/// Whether an `If-Match` header value matches an object's ETag: `*` matches
+/// any object, otherwise one of the listed, optionally quoted, ETags must.
+fn etag_matches(if_match: &str, etag: Option<&str>) -> bool {
+    if_match
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || Some(candidate.trim_matches('"')) == etag)
+}
+
+/// Moves a file, falling back to copy and remove when the destination is on
+/// another filesystem (e.g. a cold tier mounted from a different disk).
+fn move_file(from: &Path, to: &Path) -> Result<(), StorageError> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Fails with `ObjectUnderLegalHold` if the current version of an object is
+/// under legal hold. Missing objects are not an error here.
+fn check_legal_hold(conn: &Connection, bucket: &str, key: &str) -> Result<(), StorageError> {
+    let legal_hold: Option<bool> = conn
+        .prepare_cached("SELECT legal_hold FROM objects WHERE bucket_name = ?1 AND key = ?2")?
+        .query_row(params![bucket, key], |row| row.get(0))
+        .optional()?;
+    if legal_hold == Some(true) {
+        return Err(StorageError::ObjectUnderLegalHold(
+            key.to_string(),
+            bucket.to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Fails with `ObjectImmutable` if the object exists and either it was
+/// stored as immutable or the bucket is write-once. Missing objects and
+/// buckets are not an error here.
+fn check_write_once(conn: &Connection, bucket: &str, key: &str) -> Result<(), StorageError> {
+    let immutable: Option<bool> = conn
+        .prepare_cached(
+            "SELECT 1 FROM objects o JOIN buckets b ON b.name = o.bucket_name
+             WHERE o.bucket_name = ?1 AND o.key = ?2 AND (b.worm = 1 OR o.immutable = 1)",
+        )?
+        .query_row(params![bucket, key], |row| row.get(0))
+        .optional()?;
+    if immutable.is_some() {
+        return Err(StorageError::ObjectImmutable(
+            key.to_string(),
+            bucket.to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Adds a column to an existing table if a database created by an older
+/// version of the schema does not have it yet.
+fn ensure_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), StorageError> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+    if !columns.iter().any(|c| c == column) {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(())
+}
+
+/// Runs SQLite's integrity check over the whole database, failing with its
+/// findings if the database is damaged.
+fn check_integrity(conn: &Connection) -> Result<(), StorageError> {
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let findings = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    if findings != ["ok"] {
+        return Err(StorageError::CorruptDatabase(findings.join("; ")));
+    }
+    Ok(())
+}
+
+/// Rows referring to a bucket or upload that no longer exists, as their
+/// table, rowid and the table they refer to. Deletions left them behind
+/// while foreign keys were not enforced.
+fn dangling_rows(conn: &Connection) -> Result<Vec<(String, i64, String)>, StorageError> {
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// Describes dangling rows by table, e.g. `3 in objects, 1 in uploads`.
+fn describe_dangling_rows(rows: &[(String, i64, String)]) -> String {
+    let mut counts = BTreeMap::new();
+    for (table, _, _) in rows {
+        *counts.entry(table.as_str()).or_insert(0) += 1;
+    }
+    counts
+        .iter()
+        .map(|(table, count)| format!("{} in {}", count, table))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Records the data directory in a new database, or checks it against the
+/// recorded one. Databases from before it was recorded adopt the first one
+/// they are opened with.
+fn record_data_dir(conn: &Connection, data_dir: &Path) -> Result<(), StorageError> {
+    let data_dir = fs::canonicalize(data_dir)?;
+    let data_dir = data_dir
+        .to_str()
+        .ok_or_else(|| StorageError::InvalidPath(data_dir.display().to_string()))?;
+    let recorded: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE name = 'data_dir'",
+            [],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match recorded {
+        Some(recorded) if recorded != data_dir => Err(StorageError::DataDirMismatch(
+            data_dir.to_string(),
+            recorded,
+        )),
+        Some(_) => Ok(()),
+        None => {
+            conn.execute(
+                "INSERT INTO settings (name, value) VALUES ('data_dir', ?1)",
+                [data_dir],
+            )?;
+            Ok(())
+        }
+    }
+}
+
+/// Checks the data directory before the storage is opened with it: it
+/// exists (or is created, with `create`), is writable, and neither it nor
+/// the database lies inside the other, where `fsck` would take the database
+/// for an orphaned object file.
+///
+/// # Arguments
+///
+/// * `data_dir` - The directory of the object files.
+/// * `db_path` - The path of the metadata database.
+/// * `create` - Whether to create a missing directory, e.g. for a new store.
+///
+/// # Returns
+///
+/// * `Result<(), StorageError>` - An empty result, or `InvalidDataDir`.
+pub fn check_data_dir(data_dir: &Path, db_path: &Path, create: bool) -> Result<(), StorageError> {
+    let invalid =
+        |reason: String| StorageError::InvalidDataDir(data_dir.display().to_string(), reason);
+    if create {
+        fs::create_dir_all(data_dir).map_err(|e| invalid(e.to_string()))?;
+    }
+    if !data_dir.is_dir() {
+        return Err(invalid("not an existing directory".to_string()));
+    }
+    let probe = data_dir.join(".write-test");
+    fs::write(&probe, b"")
+        .and_then(|_| fs::remove_file(&probe))
+        .map_err(|e| invalid(format!("not writable: {}", e)))?;
+
+    let data_dir = fs::canonicalize(data_dir).map_err(|e| invalid(e.to_string()))?;
+    let db_path = std::path::absolute(db_path).map_err(|e| invalid(e.to_string()))?;
+    let db_path = match (db_path.parent(), db_path.file_name()) {
+        (Some(parent), Some(name)) if parent.exists() => fs::canonicalize(parent)?.join(name),
+        _ => db_path,
+    };
+    if db_path.starts_with(&data_dir) {
+        return Err(invalid(format!(
+            "contains the database {}",
+            db_path.display()
+        )));
+    }
+    if data_dir.starts_with(&db_path) {
+        return Err(invalid(format!(
+            "lies inside the database path {}",
+            db_path.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Removes `dir` and the directories below it that hold no files. Those
+/// still holding some, such as the directories of namespaced buckets below
+/// that of a bucket named like their namespace, are kept.
+fn remove_empty_dirs(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            remove_empty_dirs(&path);
+        }
+    }
+    // Fails, as intended, if anything is left
+    let _ = fs::remove_dir(dir);
+}
+
+/// Appends every file below `dir` to `files`.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), StorageError> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Findings of an offline `fsck` run.
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    /// Objects, versions and uploads whose file is gone, as `bucket/key`.
+    pub missing_files: Vec<String>,
+    /// Objects whose file no longer matches their ETag, as `bucket/key`.
+    pub corrupt_objects: Vec<String>,
+    /// Files in the data directory no record refers to.
+    pub orphaned_files: Vec<String>,
+    /// Rows referring to a bucket or upload that is gone, as `table row N`.
+    pub dangling_rows: Vec<String>,
+    /// Whether the missing, orphaned and dangling entries were repaired.
+    pub repaired: bool,
+}
+
+impl FsckReport {
+    /// Whether problems remain after the run.
+    pub fn is_clean(&self) -> bool {
+        self.corrupt_objects.is_empty()
+            && (self.repaired
+                || self.missing_files.is_empty()
+                    && self.orphaned_files.is_empty()
+                    && self.dangling_rows.is_empty())
+    }
+}
+
+/// Outcome of restoring a bucket to an earlier point in time.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct RestoreReport {
+    /// Keys brought back to an earlier version.
+    pub restored: Vec<String>,
+    /// Keys that did not exist yet, or were deleted, at the time.
+    pub deleted: Vec<String>,
+    pub unchanged: usize,
+    /// Keys left alone because they are under legal hold or write-once.
+    pub skipped: Vec<String>,
+}
+
+/// One entry of an object's history: a version, a delete marker, or the
+/// current version.
+struct HistoryEntry {
+    file_path: Option<String>,
+    content_type: Option<String>,
+    metadata: Option<String>,
+    last_modified: i64,
+    is_delete_marker: bool,
+    is_current: bool,
+}
+
+/// A version moved out of `objects` by `archive_current_version`.
+struct ArchivedVersion {
+    /// File of the version the archived one replaced, no longer referenced
+    /// once the transaction commits.
+    replaced_file: Option<String>,
+}
+
+impl ArchivedVersion {
+    /// Removes the replaced file. Only call this after the commit.
+    fn remove_replaced_file(&self) -> std::io::Result<()> {
+        match &self.replaced_file {
+            Some(path) if Path::new(path).exists() => fs::remove_file(path),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What `store_object` records of an object besides its data.
+struct ObjectFields<'a> {
+    key: &'a str,
+    content_type: Option<&'a str>,
+    user_metadata: Option<&'a HashMap<String, String>>,
+    immutable: bool,
+}
+
+/// Outcome of one `rehash_batch` call.
+#[derive(Debug, Default)]
+pub struct RehashBatch {
+    /// Rows looked at, whether or not they could be re-hashed.
+    pub visited: u64,
+    pub rehashed: u64,
+    /// Objects whose file no longer matches their old checksum; they keep it.
+    pub mismatched: Vec<String>,
+    /// Objects whose file is gone.
+    pub missing: Vec<String>,
+    /// Where the next batch continues, `None` once every row was visited.
+    pub cursor: Option<(i64, i64)>,
+}
+
+/// Outcome of one `delete_prefix_batch` call.
+#[derive(Debug, Default)]
+pub struct PrefixDeleteBatch {
+    pub deleted: Vec<String>,
+    /// Keys left alone because they are under legal hold or write-once.
+    pub skipped: Vec<String>,
+    /// Where the next batch continues, `None` once every key was visited.
+    pub cursor: Option<String>,
+}
+
+/// Outcome of one `copy_prefix_batch` call.
+#[derive(Debug, Default)]
+pub struct PrefixCopyBatch {
+    pub copied: Vec<String>,
+    pub bytes: u64,
+    /// Keys not copied because the destination object is under legal hold
+    /// or write-once.
+    pub skipped: Vec<String>,
+    /// Where the next batch continues, `None` once every key was visited.
+    pub cursor: Option<String>,
 }
 
-fn calculate_etag(data: &[u8]) -> String {
-    let mut hasher = Md5::default();
-    hasher.input(data);
-    hex::encode(hasher.result())
+/// How thoroughly `verify_integrity` checks object files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyMode {
+    /// Only check that every object's file exists.
+    Quick,
+    /// Also re-hash every file and compare it with the stored ETag.
+    Full,
 }
 
 /// Custom error type for operations within the storage module.
@@ -46,28 +669,235 @@ pub enum StorageError {
     BucketNotFoundInStorage(String),
     #[error("Data integrity error: {0}")]
     IntegrityError(String),
+    #[error("Object '{0}' in bucket '{1}' is under legal hold")]
+    ObjectUnderLegalHold(String, String),
     #[error("Consistency check failed: {0}")]
     ConsistencyError(String),
+    #[error("Upload '{0}' not found")]
+    UploadNotFound(String),
+    #[error("Upload offset {1} does not match the current offset {0}")]
+    UploadOffsetMismatch(u64, u64),
+    #[error("Upload '{0}' would exceed its declared length of {1} bytes")]
+    UploadLengthExceeded(String, u64),
+    #[error("Upload '{0}' has received {1} of its {2} bytes")]
+    UploadIncomplete(String, u64, u64),
+    #[error("Bucket '{0}' keeps no version history to restore from")]
+    NoVersionHistory(String),
+    #[error("Object '{0}' in bucket '{1}' is write-once and cannot be changed")]
+    ObjectImmutable(String, String),
+    #[error("Bucket '{0}' is write-once: {1}")]
+    BucketImmutable(String, &'static str),
+    #[error("Alias '{0}' of bucket '{1}' not found")]
+    AliasNotFound(String, String),
+    #[error("Object '{0}' in bucket '{1}' does not match the given ETag")]
+    PreconditionFailed(String, String),
+    #[error("Bytes {0} cannot be written to an object of {1} bytes")]
+    RangeNotSatisfiable(String, u64),
+    #[error("Timed out after {0} seconds waiting for the storage")]
+    LockTimeout(u64),
+    #[error("Invalid data directory '{0}': {1}")]
+    InvalidDataDir(String, String),
+    #[error(
+        "Data directory '{0}' is not '{1}', the one the database was created with and its object paths point into"
+    )]
+    DataDirMismatch(String, String),
+    #[error("Data directory '{0}' holds object files but is no longer configured")]
+    DataDirMissing(String),
+    #[error("Database is damaged: {0}")]
+    CorruptDatabase(String),
+    #[error(
+        "Database holds rows of deleted buckets or uploads ({0}); run `fsck --repair` to remove them"
+    )]
+    DanglingRows(String),
+    // Tokens stay out of messages, which end up in logs
+    #[error("Share not found")]
+    ShareNotFound,
+    #[error("Share has expired or used up its downloads")]
+    ShareExpired,
+}
+
+impl StorageError {
+    /// Whether the database stayed locked by another connection, a
+    /// condition that passes rather than a failure.
+    pub fn is_busy(&self) -> bool {
+        matches!(
+            self,
+            StorageError::DatabaseError(rusqlite::Error::SqliteFailure(e, _))
+                if matches!(
+                    e.code,
+                    rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+                )
+        )
+    }
+}
+
+/// Most time `write_transaction` pauses between retries, in all. The pauses
+/// block the thread holding the storage lock, an executor thread, so they
+/// are kept well below the busy timeout SQLite itself waits.
+const MAX_BUSY_PAUSE: Duration = Duration::from_millis(250);
+
+/// Begins a write transaction. It takes the write lock right away, so
+/// only beginning it can find the database busy; while it does past the
+/// busy timeout, beginning is retried after a random pause of up to
+/// the backoff, doubled on each retry. The pauses add up to at most a
+/// quarter of the busy timeout and never more than `MAX_BUSY_PAUSE`. A
+/// database still busy then fails with the busy error, which
+/// `StorageError::is_busy` recognizes.
+fn write_transaction(
+    conn: &mut Connection,
+    busy: BusyConfig,
+) -> Result<Transaction<'_>, StorageError> {
+    let mut budget = MAX_BUSY_PAUSE.min(Duration::from_millis(busy.timeout_ms / 4));
+    let mut attempt = 0;
+    loop {
+        // Unchecked only as a borrow kept across retries needs to be
+        // shared; `&mut` still rules out a nested transaction
+        match Transaction::new_unchecked(&*conn, TransactionBehavior::Immediate) {
+            Ok(tx) => return Ok(tx),
+            Err(e) => {
+                let e = StorageError::from(e);
+                if !e.is_busy() || attempt >= busy.retries || budget.is_zero() {
+                    return Err(e);
+                }
+            }
+        }
+        let backoff = busy.backoff_ms.saturating_mul(1 << attempt.min(16));
+        let pause = jitter(Duration::from_millis(backoff)).min(budget);
+        std::thread::sleep(pause);
+        budget -= pause;
+        attempt += 1;
+    }
+}
+
+/// A random duration of up to `max`, spreading out retries of writers
+/// that found the database busy at the same moment.
+fn jitter(max: Duration) -> Duration {
+    let max = max.as_millis() as u64;
+    if max == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_millis((Uuid::new_v4().as_u128() as u64) % (max + 1))
+}
+
+/// Locks the storage, giving up after `timeout` if one is given, so requests
+/// do not queue forever behind an operation stuck on a hung disk.
+///
+/// # Arguments
+///
+/// * `storage` - The shared storage.
+/// * `timeout` - How long to wait for the lock, if not indefinitely.
+///
+/// # Returns
+///
+/// * `Result<MutexGuard<Storage>, StorageError>` - The locked storage, or `LockTimeout`.
+pub async fn lock_storage(
+    storage: &Mutex<Storage>,
+    timeout: Option<Duration>,
+) -> Result<MutexGuard<'_, Storage>, StorageError> {
+    let started = Instant::now();
+    let guard = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, storage.lock())
+            .await
+            .map_err(|_| StorageError::LockTimeout(timeout.as_secs()))?,
+        None => storage.lock().await,
+    };
+    guard.lock_wait.set(started.elapsed());
+    Ok(guard)
+}
+
+/// The error of an injected fault.
+fn injected_error(operation: &str) -> std::io::Error {
+    std::io::Error::other(format!("Injected fault in {}", operation))
+}
+
+/// Writes `data`, or with an injected partial write only its first
+/// `partial` bytes before failing.
+fn write_data(writer: &mut impl Write, data: &[u8], partial: Option<usize>) -> std::io::Result<()> {
+    match partial {
+        None => writer.write_all(data),
+        Some(len) => {
+            writer.write_all(&data[..len.min(data.len())])?;
+            Err(injected_error("write"))
+        }
+    }
 }
 
 impl Storage {
-    pub fn new(db_path: &str) -> Result<Self, StorageError> {
+    /// Opens the storage with its metadata in `db_path` and its object files
+    /// in `data_dir`. A new database records the data directory; opening it
+    /// with another one fails with `DataDirMismatch`, as the object paths
+    /// recorded in it point into the first.
+    ///
+    /// # Arguments
+    ///
+    /// * `db_path` - The path of the metadata database.
+    /// * `data_dir` - The directory of the object files, created if missing.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Self, StorageError>` - The storage, or an error.
+    pub fn open(db_path: &str, data_dir: impl Into<PathBuf>) -> Result<Self, StorageError> {
+        Self::open_checked(db_path, data_dir.into(), true)
+    }
+
+    /// Opens the storage like `open`, but with rows of deleted buckets and
+    /// uploads left for `fsck` to report and remove.
+    pub fn open_for_fsck(
+        db_path: &str,
+        data_dir: impl Into<PathBuf>,
+    ) -> Result<Self, StorageError> {
+        Self::open_checked(db_path, data_dir.into(), false)
+    }
+
+    fn open_checked(
+        db_path: &str,
+        base_path: PathBuf,
+        refuse_dangling_rows: bool,
+    ) -> Result<Self, StorageError> {
         let conn = Connection::open(db_path)?;
-        let base_path = Path::new("data").to_path_buf();
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
         conn.pragma_update(None, "journal_mode", "WAL")?;
+        // The cascades of the tables below need foreign keys, which only
+        // some SQLite builds enforce by default
+        conn.pragma_update(None, "foreign_keys", true)?;
+        let busy = BusyConfig::default();
+        conn.busy_timeout(Duration::from_millis(busy.timeout_ms))?;
+
+        // Serving from a broken catalog would spread the damage
+        check_integrity(&conn)?;
 
         fs::create_dir_all(&base_path)?;
 
         conn.execute(
             "CREATE TABLE IF NOT EXISTS buckets (
                 name TEXT PRIMARY KEY NOT NULL UNIQUE,
-                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                versioning TEXT,
+                replication_destination TEXT,
+                worm INTEGER NOT NULL DEFAULT 0,
+                namespace TEXT NOT NULL DEFAULT '',
+                verbose_logging INTEGER NOT NULL DEFAULT 0,
+                default_content_type TEXT,
+                default_metadata TEXT
             )",
             [],
         )?;
+        ensure_column(&conn, "buckets", "versioning", "TEXT")?;
+        ensure_column(&conn, "buckets", "replication_destination", "TEXT")?;
+        ensure_column(&conn, "buckets", "worm", "INTEGER NOT NULL DEFAULT 0")?;
+        ensure_column(&conn, "buckets", "namespace", "TEXT NOT NULL DEFAULT ''")?;
+        ensure_column(
+            &conn,
+            "buckets",
+            "verbose_logging",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        ensure_column(&conn, "buckets", "default_content_type", "TEXT")?;
+        ensure_column(&conn, "buckets", "default_metadata", "TEXT")?;
 
         conn.execute(
             "CREATE TABLE IF NOT EXISTS objects (
+                id TEXT,
                 bucket_name TEXT,
                 key TEXT,
                 file_path TEXT UNIQUE,
@@ -76,332 +906,5309 @@ impl Storage {
                 size INTEGER,
                 last_modified TIMESTAMP,
                 metadata TEXT,
+                version_id TEXT,
+                legal_hold INTEGER NOT NULL DEFAULT 0,
+                replication_status TEXT,
+                storage_class TEXT NOT NULL DEFAULT 'STANDARD',
+                access_count INTEGER NOT NULL DEFAULT 0,
+                last_accessed INTEGER,
+                hash_algorithm TEXT NOT NULL DEFAULT 'md5',
+                namespace TEXT NOT NULL DEFAULT '',
+                immutable INTEGER NOT NULL DEFAULT 0,
                 PRIMARY KEY (bucket_name, key),
                 FOREIGN KEY (bucket_name) REFERENCES buckets(name) ON DELETE CASCADE
             )",
             [],
         )?;
+        ensure_column(&conn, "objects", "version_id", "TEXT")?;
+        ensure_column(&conn, "objects", "legal_hold", "INTEGER NOT NULL DEFAULT 0")?;
+        ensure_column(&conn, "objects", "replication_status", "TEXT")?;
+        ensure_column(
+            &conn,
+            "objects",
+            "storage_class",
+            "TEXT NOT NULL DEFAULT 'STANDARD'",
+        )?;
+        ensure_column(
+            &conn,
+            "objects",
+            "access_count",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+        ensure_column(&conn, "objects", "last_accessed", "INTEGER")?;
+        ensure_column(
+            &conn,
+            "objects",
+            "hash_algorithm",
+            "TEXT NOT NULL DEFAULT 'md5'",
+        )?;
+        ensure_column(&conn, "objects", "namespace", "TEXT NOT NULL DEFAULT ''")?;
+        ensure_column(&conn, "objects", "id", "TEXT")?;
+        ensure_column(&conn, "objects", "immutable", "INTEGER NOT NULL DEFAULT 0")?;
+        assign_object_ids(&conn)?;
+        conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS objects_id ON objects (id)",
+            [],
+        )?;
 
-        Ok(Self { conn, base_path })
-    }
+        // Noncurrent versions and delete markers of objects in versioned buckets
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS object_versions (
+                bucket_name TEXT,
+                key TEXT,
+                version_id TEXT,
+                file_path TEXT,
+                content_type TEXT,
+                etag TEXT,
+                size INTEGER,
+                last_modified TIMESTAMP,
+                metadata TEXT,
+                is_delete_marker INTEGER NOT NULL DEFAULT 0,
+                hash_algorithm TEXT NOT NULL DEFAULT 'md5',
+                object_id TEXT,
+                PRIMARY KEY (bucket_name, key, version_id),
+                FOREIGN KEY (bucket_name) REFERENCES buckets(name) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        ensure_column(
+            &conn,
+            "object_versions",
+            "hash_algorithm",
+            "TEXT NOT NULL DEFAULT 'md5'",
+        )?;
+        ensure_column(&conn, "object_versions", "object_id", "TEXT")?;
 
-    /// Creates a new bucket.
-    ///
-    /// # Arguments
-    ///
-    /// * `bucket_name` - The name of the bucket to create.
-    ///
-    /// # Returns
-    ///
-    /// * `Result<(), StorageError>` - An empty result, or an error.
-    pub fn create_bucket(&mut self, bucket_name: &str) -> Result<(), StorageError> {
-        let tx = self.conn.transaction()?;
-        match tx.execute("INSERT INTO buckets (name) VALUES (?1)", [bucket_name]) {
-            Ok(_) => {
-                tx.commit().map_err(|e| StorageError::DatabaseError(e))?;
-                Ok(())
-            }
-            Err(rusqlite::Error::SqliteFailure(e, Some(msg)))
-                if e.code == rusqlite::ErrorCode::ConstraintViolation
-                    && msg.contains("UNIQUE constraint failed: buckets.name") =>
-            {
-                tx.rollback().map_err(|e| StorageError::DatabaseError(e))?;
-                Err(StorageError::BucketAlreadyExistsInStorage(
-                    bucket_name.to_string(),
-                ))
-            }
-            Err(e) => {
-                tx.rollback()
-                    .map_err(|err| StorageError::DatabaseError(err))?;
-                Err(StorageError::DatabaseError(e))
-            }
-        }
-    }
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS lifecycle_rules (
+                bucket_name TEXT,
+                prefix TEXT NOT NULL DEFAULT '',
+                days INTEGER NOT NULL,
+                storage_class TEXT NOT NULL,
+                FOREIGN KEY (bucket_name) REFERENCES buckets(name) ON DELETE CASCADE
+            )",
+            [],
+        )?;
 
-    pub fn _delete_bucket(&mut self, bucket: &str) -> Result<(), StorageError> {
-        let tx = self.conn.transaction()?;
-        let rows_affected = tx.execute("DELETE FROM buckets WHERE name = ?1", [bucket])?;
-        if rows_affected == 0 {
-            tx.rollback().map_err(|e| StorageError::DatabaseError(e))?;
-            return Err(StorageError::BucketNotFoundInStorage(bucket.to_string()));
-        }
-        tx.commit()
-            .map_err(|_| StorageError::TransactionCommitError)
-    }
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS uploads (
+                id TEXT PRIMARY KEY,
+                bucket_name TEXT NOT NULL,
+                key TEXT NOT NULL,
+                upload_length INTEGER NOT NULL,
+                upload_offset INTEGER NOT NULL DEFAULT 0,
+                content_type TEXT,
+                metadata TEXT,
+                created_at INTEGER NOT NULL,
+                FOREIGN KEY (bucket_name) REFERENCES buckets(name) ON DELETE CASCADE
+            )",
+            [],
+        )?;
 
-    /// Lists all buckets.
-    ///
-    /// # Returns
-    ///
-    /// * `Result<Vec<String>, StorageError>` - A vector of bucket names, or an error.
-    pub fn list_buckets(&self) -> Result<Vec<String>, StorageError> {
-        let mut stmt = self.conn.prepare("SELECT name FROM buckets")?;
-        let mut rows = stmt.query([])?;
-        let mut bucket_names = Vec::new();
-        while let Some(row) = rows.next()? {
-            bucket_names.push(row.get(0)?);
-        }
-        Ok(bucket_names)
-    }
+        // The PATCH requests each upload was filled by, in order
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS upload_parts (
+                upload_id TEXT NOT NULL,
+                part_number INTEGER NOT NULL,
+                part_offset INTEGER NOT NULL,
+                size INTEGER NOT NULL,
+                etag TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (upload_id, part_number),
+                FOREIGN KEY (upload_id) REFERENCES uploads(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        // When an upload was last appended to, for aborting stale ones
+        ensure_column(&conn, "uploads", "updated_at", "INTEGER")?;
 
-    /// Checks if a bucket exists.
-    ///
-    /// # Arguments
-    ///
-    /// * `bucket_name` - The name of the bucket to check.
-    ///
-    /// # Returns
-    ///
-    /// * `Result<bool, StorageError>` - A boolean indicating whether the bucket exists, or an error.
-    pub fn bucket_exists(&self, bucket_name: &str) -> Result<bool, StorageError> {
-        let mut stmt = self.conn.prepare("SELECT 1 FROM buckets WHERE name = ?1")?;
-        let exists: Option<i64> = stmt
-            .query_row(params![bucket_name], |row| row.get(0))
-            .optional()?;
-        Ok(exists.is_some())
-    }
+        // Alternate names of buckets, keyed like bucket names
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS bucket_aliases (
+                alias TEXT PRIMARY KEY NOT NULL,
+                bucket_name TEXT NOT NULL,
+                FOREIGN KEY (bucket_name) REFERENCES buckets(name) ON DELETE CASCADE
+            )",
+            [],
+        )?;
 
-    /// Puts an object into a bucket.
-    ///
-    /// # Arguments
-    ///
-    /// * `bucket` - The name of the bucket to put the object into.
-    /// * `object` - The object to put into the bucket.
-    ///
-    /// # Returns
-    ///
-    /// * `Result<(), StorageError>` - An empty result, or an error.
-    pub fn put_object(&mut self, bucket: &str, object: Object) -> Result<(), StorageError> {
-        let tx = self.conn.transaction()?;
+        // Roles of principals on buckets, see roles.rs
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS bucket_roles (
+                bucket_name TEXT NOT NULL,
+                principal TEXT NOT NULL,
+                role TEXT NOT NULL,
+                PRIMARY KEY (bucket_name, principal),
+                FOREIGN KEY (bucket_name) REFERENCES buckets(name) ON DELETE CASCADE
+            )",
+            [],
+        )?;
 
-        tx.execute("INSERT OR IGNORE INTO buckets (name) VALUES (?1)", [bucket])?;
+        // Permissions granted to principals on buckets, see acl.rs
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS bucket_acl (
+                bucket_name TEXT NOT NULL,
+                grantee TEXT NOT NULL,
+                permission TEXT NOT NULL,
+                PRIMARY KEY (bucket_name, grantee, permission),
+                FOREIGN KEY (bucket_name) REFERENCES buckets(name) ON DELETE CASCADE
+            )",
+            [],
+        )?;
 
-        let bucket_dir = self.base_path.join("buckets").join(bucket);
-        fs::create_dir_all(&bucket_dir)?;
+        // Share links of objects, see share.rs
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS shares (
+                token TEXT PRIMARY KEY NOT NULL,
+                id TEXT,
+                bucket_name TEXT NOT NULL,
+                key TEXT NOT NULL,
+                max_downloads INTEGER,
+                downloads INTEGER NOT NULL DEFAULT 0,
+                expires_at INTEGER,
+                created_at INTEGER NOT NULL,
+                FOREIGN KEY (bucket_name) REFERENCES buckets(name) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        ensure_column(&conn, "shares", "id", "TEXT")?;
+        conn.execute(
+            "UPDATE shares SET id = lower(hex(randomblob(16))) WHERE id IS NULL",
+            [],
+        )?;
+        conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS shares_id ON shares (id)",
+            [],
+        )?;
 
-        let file_path = bucket_dir.join(&object.key);
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS cache_pins (
+                bucket_name TEXT NOT NULL,
+                key TEXT NOT NULL,
+                is_prefix INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (bucket_name, key, is_prefix),
+                FOREIGN KEY (bucket_name) REFERENCES buckets(name) ON DELETE CASCADE
+            )",
+            [],
+        )?;
 
-        let file_path_str = file_path
-            .to_str()
-            .ok_or_else(|| StorageError::InvalidPath(file_path.display().to_string()))?
-            .to_string();
+        // Facts about the store itself, e.g. its data directory
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS settings (
+                name TEXT PRIMARY KEY NOT NULL,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+        record_data_dir(&conn, &base_path)?;
+        usage::create_counters(&conn)?;
+        if refuse_dangling_rows {
+            let dangling = dangling_rows(&conn)?;
+            if !dangling.is_empty() {
+                return Err(StorageError::DanglingRows(describe_dangling_rows(
+                    &dangling,
+                )));
+            }
+        }
 
-        fs::write(&file_path, &object.data)?;
+        Ok(Self {
+            conn,
+            roots: DataRoots::single(base_path.clone()),
+            base_path,
+            etags: Arc::new(HashAlgorithm::default()),
+            ids: Arc::new(UuidGenerator),
+            slow_ops: SlowOpConfig::default(),
+            busy,
+            lock_wait: Cell::new(Duration::ZERO),
+            clock: Arc::new(SystemClock),
+            #[cfg(feature = "faults")]
+            faults: FaultSchedule::default(),
+        })
+    }
 
-        let metadata_json = match &object.user_metadata {
-            Some(map) => Some(serde_json::to_string(map)?),
-            None => None,
-        };
+    /// Computes the checksums of newly written objects with `algorithm`.
+    pub fn with_hash_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.etags = Arc::new(algorithm);
+        self
+    }
 
-        let size = object.data.len() as i64;
-        let etag = calculate_etag(&object.data);
+    /// Computes the checksums of newly written objects with `etags`.
+    #[allow(dead_code)] // Driven by tests, through the library
+    pub fn with_etag_generator(mut self, etags: Arc<dyn ETagGenerator>) -> Self {
+        self.etags = etags;
+        self
+    }
 
-        let last_modified = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)?
-            .as_secs() as i64;
+    /// The name of the algorithm of the checksums of newly written objects.
+    pub fn hash_algorithm(&self) -> &str {
+        self.etags.algorithm()
+    }
 
-        tx.execute(
-            "INSERT OR REPLACE INTO objects
-             (bucket_name, key, file_path, content_type, etag, size, last_modified, metadata)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-            params![
-                bucket,
-                object.key,
-                file_path_str,
-                object.content_type,
-                etag,
-                size,
-                last_modified,
-                metadata_json
-            ],
-        )?;
+    /// Generates version and upload IDs with `ids`.
+    #[allow(dead_code)] // Driven by tests, through the library
+    pub fn with_id_generator(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
+    }
 
-        tx.commit()
-            .map_err(|_| StorageError::TransactionCommitError)?;
-        Ok(())
+    /// The generator of version and upload IDs.
+    pub fn ids(&self) -> &Arc<dyn IdGenerator> {
+        &self.ids
     }
 
-    /// Gets an object from a bucket.
+    /// Waits for and retries writes locked out by another connection as
+    /// `busy` configures.
+    pub fn with_busy(mut self, busy: BusyConfig) -> Result<Self, StorageError> {
+        self.conn
+            .busy_timeout(Duration::from_millis(busy.timeout_ms))?;
+        self.busy = busy;
+        Ok(self)
+    }
+
+    /// Warns about operations slower than `slow_ops`.
+    pub fn with_slow_ops(mut self, slow_ops: SlowOpConfig) -> Self {
+        self.slow_ops = slow_ops;
+        self
+    }
+
+    /// Spreads new object files over `dirs` besides the data directory, as
+    /// `placement` picks. The directories are recorded in the database, and
+    /// leaving out one recorded before fails with `DataDirMissing`, as
+    /// objects may live in it.
     ///
     /// # Arguments
     ///
-    /// * `bucket` - The name of the bucket to get the object from.
-    /// * `key` - The key of the object to get.
+    /// * `dirs` - The further data directories, created if missing.
+    /// * `placement` - How new object files are spread over all of them.
     ///
     /// # Returns
     ///
-    /// * `Result<Object, StorageError>` - The retrieved object, or an error.
-    pub fn get_object(&self, bucket: &str, key: &str) -> Result<Object, StorageError> {
-        let mut stmt = self.conn.prepare(
-            "SELECT file_path, content_type, etag, last_modified, metadata
-             FROM objects WHERE bucket_name = ?1 AND key = ?2",
+    /// * `Result<Self, StorageError>` - The storage, or an error.
+    pub fn with_extra_data_dirs(
+        mut self,
+        dirs: &[PathBuf],
+        placement: Placement,
+    ) -> Result<Self, StorageError> {
+        let mut canonical = Vec::new();
+        for dir in dirs {
+            fs::create_dir_all(dir)?;
+            let dir = fs::canonicalize(dir)?;
+            canonical.push(
+                dir.to_str()
+                    .ok_or_else(|| StorageError::InvalidPath(dir.display().to_string()))?
+                    .to_string(),
+            );
+        }
+        // Files of one directory would count as orphans of the other
+        let primary = fs::canonicalize(&self.base_path)?;
+        let mut all: Vec<&Path> = vec![&primary];
+        all.extend(canonical.iter().map(Path::new));
+        for (i, dir) in all.iter().enumerate() {
+            if let Some(other) = all[..i]
+                .iter()
+                .find(|other| dir.starts_with(other) || other.starts_with(dir))
+            {
+                return Err(StorageError::InvalidDataDir(
+                    dir.display().to_string(),
+                    format!("overlaps the data directory {}", other.display()),
+                ));
+            }
+        }
+        let recorded: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT value FROM settings WHERE name = 'extra_data_dirs'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let recorded: Vec<String> = match recorded {
+            Some(json) => serde_json::from_str(&json)?,
+            None => Vec::new(),
+        };
+        if let Some(missing) = recorded.iter().find(|dir| !canonical.contains(dir)) {
+            return Err(StorageError::DataDirMissing(missing.clone()));
+        }
+        self.conn.execute(
+            "INSERT OR REPLACE INTO settings (name, value) VALUES ('extra_data_dirs', ?1)",
+            [serde_json::to_string(&canonical)?],
         )?;
 
-        let mut rows = stmt.query(params![bucket, key])?;
+        let mut roots = vec![self.base_path.clone()];
+        roots.extend(dirs.iter().cloned());
+        self.roots = DataRoots::new(roots, placement);
+        Ok(self)
+    }
 
-        let row = rows.next()?;
-        if let Some(row) = row {
-            let file_path_str: String = row.get(0)?;
-            let file_path = PathBuf::from(file_path_str);
-            let content_type: Option<String> = row.get(1)?;
-            let etag: Option<String> = Some(row.get(2)?);
-            let last_modified: i64 = row.get(3)?;
-            let metadata_json: Option<String> = row.get(4)?;
+    /// Reads the current time from `clock` instead of the system clock.
+    #[allow(dead_code)] // Driven by tests, through the library
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
 
-            let data = fs::read(&file_path)?;
+    /// The clock of the modification times and lifecycle transitions.
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
 
-            let current_etag = calculate_etag(&data);
+    /// Injects the faults of `faults` into the operations.
+    #[cfg(feature = "faults")]
+    #[allow(dead_code)] // Driven by tests, through the library
+    pub fn with_faults(mut self, faults: FaultSchedule) -> Self {
+        self.faults = faults;
+        self
+    }
 
-            if let Some(ref etag) = etag {
-                if current_etag != *etag {
-                    return Err(StorageError::IntegrityError(format!(
-                        "ETag mismatch for {}/{} - possible data corruption",
-                        bucket, key
-                    )));
-                }
+    /// Applies the fault scheduled for `operation`: waits out latency and
+    /// fails with an I/O error. A partial write is left to the caller.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<usize>, StorageError>` - The number of bytes to write
+    ///   before failing, for a partial write, or the injected error.
+    #[cfg(feature = "faults")]
+    fn inject_fault(&self, operation: &str) -> Result<Option<usize>, StorageError> {
+        match self.faults.next(operation) {
+            None => Ok(None),
+            Some(Fault::Latency(delay)) => {
+                std::thread::sleep(delay);
+                Ok(None)
             }
+            Some(Fault::PartialWrite(len)) => Ok(Some(len)),
+            Some(Fault::IoError) => Err(injected_error(operation).into()),
+        }
+    }
 
-            let user_metadata: Option<HashMap<String, String>> = metadata_json
-                .map(|s| serde_json::from_str(&s))
-                .transpose()?;
+    #[cfg(not(feature = "faults"))]
+    fn inject_fault(&self, _operation: &str) -> Result<Option<usize>, StorageError> {
+        Ok(None)
+    }
 
-            Ok(Object {
-                key: key.to_string(),
-                data,
-                content_type,
-                etag,
-                last_modified,
-                user_metadata,
-            })
-        } else {
-            Err(StorageError::ObjectNotFound(
-                key.to_string(),
-                bucket.to_string(),
-            ))
-        }
+    /// Starts tracing an operation of the current span.
+    fn trace<'a>(&self, operation: &'static str, bucket: &'a str, key: &'a str) -> OpTrace<'a> {
+        OpTrace::start(operation, bucket, key, self.slow_ops, self.lock_wait.take())
     }
 
-    /// Deletes an object from a bucket.
+    /// Creates a new bucket.
     ///
     /// # Arguments
     ///
-    /// * `bucket` - The name of the bucket to delete the object from.
-    /// * `key` - The key of the object to delete.
+    /// * `bucket_name` - The name of the bucket to create, prefixed with its
+    ///   namespace if it has one.
     ///
     /// # Returns
     ///
-    /// * `Result<bool, StorageError>` - A boolean indicating whether the object was deleted, or an error.
-    pub fn delete_object(&mut self, bucket: &str, key: &str) -> Result<bool, StorageError> {
-        let file_path_to_delete_option: Option<String> = self
-            .conn
-            .query_row(
-                "SELECT file_path FROM objects WHERE bucket_name = ?1 AND key = ?2",
-                params![bucket, key],
-                |row| row.get(0),
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    #[instrument(
+        name = "storage.create_bucket",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket_name, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn create_bucket(&mut self, bucket_name: &str) -> Result<(), StorageError> {
+        let mut trace = self.trace("create_bucket", bucket_name, "");
+        self.inject_fault("create_bucket")?;
+        let (namespace, _) = split_bucket(bucket_name);
+        if trace.sql(|| self.alias_exists(bucket_name))? {
+            return Err(StorageError::BucketAlreadyExistsInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        let tx = trace.sql(|| write_transaction(&mut self.conn, self.busy))?;
+        match trace.sql(|| {
+            tx.execute(
+                "INSERT INTO buckets (name, namespace) VALUES (?1, ?2)",
+                [bucket_name, namespace],
             )
-            .optional()?;
+        }) {
+            Ok(rows) => {
+                trace.add_rows(rows);
+                trace
+                    .sql(|| tx.commit())
+                    .map_err(StorageError::DatabaseError)?;
+                Ok(())
+            }
+            Err(rusqlite::Error::SqliteFailure(e, Some(msg)))
+                if e.code == rusqlite::ErrorCode::ConstraintViolation
+                    && msg.contains("UNIQUE constraint failed: buckets.name") =>
+            {
+                tx.rollback().map_err(StorageError::DatabaseError)?;
+                Err(StorageError::BucketAlreadyExistsInStorage(
+                    bucket_name.to_string(),
+                ))
+            }
+            Err(e) => {
+                tx.rollback().map_err(StorageError::DatabaseError)?;
+                Err(StorageError::DatabaseError(e))
+            }
+        }
+    }
 
-        let tx = self.conn.transaction()?;
+    #[instrument(
+        name = "storage.delete_bucket",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn _delete_bucket(&mut self, bucket: &str) -> Result<(), StorageError> {
+        let mut trace = self.trace("delete_bucket", bucket, "");
+        self.inject_fault("delete_bucket")?;
+        if trace.sql(|| self.get_bucket_worm(bucket))? && !trace.sql(|| self._is_empty(bucket))? {
+            return Err(StorageError::BucketImmutable(
+                bucket.to_string(),
+                "it still holds objects",
+            ));
+        }
+        // Deleting the bucket would take its held objects, and their
+        // versions, with it; holds live on the current versions only
+        let held: Option<String> = trace.sql(|| {
+            self.conn
+                .prepare_cached(
+                    "SELECT key FROM objects WHERE bucket_name = ?1 AND legal_hold = 1 LIMIT 1",
+                )?
+                .query_row([bucket], |row| row.get(0))
+                .optional()
+        })?;
+        if let Some(key) = held {
+            return Err(StorageError::ObjectUnderLegalHold(key, bucket.to_string()));
+        }
+        // Deleting the bucket would take its immutable objects with it
+        let immutable: Option<String> = trace.sql(|| {
+            self.conn
+                .prepare_cached(
+                    "SELECT key FROM objects WHERE bucket_name = ?1 AND immutable = 1 LIMIT 1",
+                )?
+                .query_row([bucket], |row| row.get(0))
+                .optional()
+        })?;
+        if let Some(key) = immutable {
+            return Err(StorageError::ObjectImmutable(key, bucket.to_string()));
+        }
+        let upload_dir = self.base_path.join("uploads");
+        let tx = trace.sql(|| write_transaction(&mut self.conn, self.busy))?;
 
-        let rows_affected = tx.execute(
-            "DELETE FROM objects WHERE bucket_name = ?1 AND key = ?2",
-            params![bucket, key],
-        )?;
+        // The bucket's objects, versions and uploads go with it, and so do
+        // their files once the deletion is committed
+        let files = trace.sql(|| -> Result<Vec<PathBuf>, StorageError> {
+            let mut stmt = tx.prepare(
+                "SELECT file_path FROM objects WHERE bucket_name = ?1
+                 UNION ALL
+                 SELECT file_path FROM object_versions
+                 WHERE bucket_name = ?1 AND file_path IS NOT NULL",
+            )?;
+            let mut files = stmt
+                .query_map([bucket], |row| row.get::<_, String>(0))?
+                .map(|path| path.map(PathBuf::from))
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut stmt = tx.prepare("SELECT id FROM uploads WHERE bucket_name = ?1")?;
+            for id in stmt.query_map([bucket], |row| row.get::<_, String>(0))? {
+                files.push(upload_dir.join(id?));
+            }
+            Ok(files)
+        })?;
+        let data_rows = trace.sql(|| -> rusqlite::Result<usize> {
+            let parts = tx.execute(
+                "DELETE FROM upload_parts
+                 WHERE upload_id IN (SELECT id FROM uploads WHERE bucket_name = ?1)",
+                [bucket],
+            )?;
+            let uploads = tx.execute("DELETE FROM uploads WHERE bucket_name = ?1", [bucket])?;
+            let versions = tx.execute(
+                "DELETE FROM object_versions WHERE bucket_name = ?1",
+                [bucket],
+            )?;
+            let objects = tx.execute("DELETE FROM objects WHERE bucket_name = ?1", [bucket])?;
+            Ok(parts + uploads + versions + objects)
+        })?;
+        let rows_affected =
+            trace.sql(|| tx.execute("DELETE FROM buckets WHERE name = ?1", [bucket]))?;
+        if rows_affected == 0 {
+            tx.rollback().map_err(StorageError::DatabaseError)?;
+            return Err(StorageError::BucketNotFoundInStorage(bucket.to_string()));
+        }
+        let aliases = trace.sql(|| {
+            tx.execute(
+                "DELETE FROM bucket_aliases WHERE bucket_name = ?1",
+                [bucket],
+            )
+        })?;
+        let roles = trace
+            .sql(|| tx.execute("DELETE FROM bucket_roles WHERE bucket_name = ?1", [bucket]))?;
+        let grants =
+            trace.sql(|| tx.execute("DELETE FROM bucket_acl WHERE bucket_name = ?1", [bucket]))?;
+        let shares =
+            trace.sql(|| tx.execute("DELETE FROM shares WHERE bucket_name = ?1", [bucket]))?;
+        trace.add_rows(rows_affected + data_rows + aliases + roles + grants + shares);
+        trace
+            .sql(|| tx.commit())
+            .map_err(|_| StorageError::TransactionCommitError)?;
 
-        if rows_affected > 0 {
-            if let Some(file_path_str) = file_path_to_delete_option {
-                let file_path = PathBuf::from(file_path_str);
-                if file_path.exists() {
-                    fs::remove_file(&file_path)?;
+        // The deletion stands even if files can't be removed; fsck moves
+        // whatever is left behind to lost+found
+        trace.file(|| {
+            for file in &files {
+                if let Err(e) = fs::remove_file(file)
+                    && e.kind() != std::io::ErrorKind::NotFound
+                {
+                    warn!(error = %e, file = %file.display(), "Failed to remove file of deleted bucket");
                 }
             }
-            tx.commit()
-                .map_err(|_| StorageError::TransactionCommitError)?;
-            Ok(true)
-        } else {
-            tx.rollback()?;
-            Err(StorageError::ObjectNotFound(
-                key.to_string(),
-                bucket.to_string(),
-            ))
-        }
+            let mut dirs: Vec<PathBuf> = self
+                .roots
+                .roots()
+                .iter()
+                .map(|root| root.join("buckets").join(bucket))
+                .collect();
+            dirs.push(self.tier_dir(StorageClass::Cold, bucket));
+            for dir in dirs {
+                remove_empty_dirs(&dir);
+                // A namespace's directory goes with its last bucket
+                for parent in dir.ancestors().skip(1).take(bucket.matches('/').count()) {
+                    let _ = fs::remove_dir(parent);
+                }
+            }
+        });
+        Ok(())
     }
 
-    /// Lists all objects in a bucket.
+    /// Lists the buckets of a namespace.
     ///
     /// # Arguments
     ///
-    /// * `bucket` - The name of the bucket to list objects from.
+    /// * `namespace` - The namespace to list, or `None` for the default namespace.
     ///
     /// # Returns
     ///
-    /// * `Result<Vec<String>, StorageError>` - A vector of object keys in the bucket, or an error.
-    pub fn list_objects(&self, bucket: &str) -> Result<Vec<String>, StorageError> {
-        let mut stmt = self
+    /// * `Result<Vec<String>, StorageError>` - A vector of bucket names without
+    ///   their namespace, or an error.
+    #[instrument(
+        name = "storage.list_buckets",
+        level = "debug",
+        skip_all,
+        fields(namespace = namespace.unwrap_or(""), sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn list_buckets(&self, namespace: Option<&str>) -> Result<Vec<String>, StorageError> {
+        let mut trace = self.trace("list_buckets", namespace.unwrap_or(""), "");
+        self.inject_fault("list_buckets")?;
+        let bucket_names = trace.sql(|| -> Result<Vec<String>, StorageError> {
+            let mut stmt = self
+                .conn
+                .prepare_cached("SELECT name FROM buckets WHERE namespace = ?1")?;
+            let mut rows = stmt.query([namespace.unwrap_or("")])?;
+            let mut bucket_names = Vec::new();
+            while let Some(row) = rows.next()? {
+                let name: String = row.get(0)?;
+                bucket_names.push(split_bucket(&name).1.to_string());
+            }
+            Ok(bucket_names)
+        })?;
+        trace.add_rows(bucket_names.len());
+        Ok(bucket_names)
+    }
+
+    /// Checks if a bucket exists.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket to check.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, StorageError>` - A boolean indicating whether the bucket exists, or an error.
+    #[instrument(
+        name = "storage.bucket_exists",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket_name, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn bucket_exists(&self, bucket_name: &str) -> Result<bool, StorageError> {
+        let mut trace = self.trace("bucket_exists", bucket_name, "");
+        self.inject_fault("bucket_exists")?;
+        let exists: Option<i64> = trace.sql(|| -> Result<_, StorageError> {
+            let mut stmt = self
+                .conn
+                .prepare_cached("SELECT 1 FROM buckets WHERE name = ?1")?;
+            Ok(stmt
+                .query_row(params![bucket_name], |row| row.get(0))
+                .optional()?)
+        })?;
+        trace.add_rows(exists.iter().count());
+        Ok(exists.is_some())
+    }
+
+    /// Checks that the database answers queries and the data directory
+    /// accepts writes, the two things a failing disk breaks.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or the first failure.
+    pub fn probe(&self) -> Result<(), StorageError> {
+        self.inject_fault("probe")?;
+        self.conn
+            .query_row("SELECT COUNT(*) FROM buckets", [], |row| {
+                row.get::<_, i64>(0)
+            })?;
+        let path = self.base_path.join(".probe");
+        fs::write(&path, b"probe")?;
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    /// Whether `name` is registered as an alias of some bucket.
+    fn alias_exists(&self, name: &str) -> Result<bool, StorageError> {
+        let exists: Option<i64> = self
             .conn
-            .prepare("SELECT key FROM objects WHERE bucket_name = ?1")?;
-        let mut rows = stmt.query(params![bucket])?;
-        let mut object_keys = Vec::new();
-        while let Some(row) = rows.next()? {
-            object_keys.push(row.get(0)?);
-        }
-        Ok(object_keys)
+            .query_row(
+                "SELECT 1 FROM bucket_aliases WHERE alias = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(exists.is_some())
     }
 
-    /// Checks if a bucket is empty.
+    /// Resolves a bucket name or one of its aliases to the bucket's name.
     ///
     /// # Arguments
     ///
-    /// * `bucket` - The name of the bucket to check.
+    /// * `name` - The name or alias of the bucket.
     ///
     /// # Returns
     ///
-    /// * `Result<bool, StorageError>` - A boolean indicating whether the bucket is empty, or an error.
-    pub fn _is_empty(&self, bucket: &str) -> Result<bool, StorageError> {
-        let mut stmt = self
+    /// * `Result<Option<String>, StorageError>` - The bucket's name, `None` if neither a
+    ///   bucket nor an alias has that name, or an error.
+    pub fn resolve_bucket(&self, name: &str) -> Result<Option<String>, StorageError> {
+        Ok(self
             .conn
-            .prepare("SELECT COUNT(*) FROM objects WHERE bucket_name = ?1")?;
-        let count: i64 = stmt.query_row(params![bucket], |row| row.get(0))?;
-        Ok(count == 0)
+            .prepare_cached(
+                "SELECT name FROM buckets WHERE name = ?1
+                 UNION ALL
+                 SELECT bucket_name FROM bucket_aliases WHERE alias = ?1
+                 LIMIT 1",
+            )?
+            .query_row(params![name], |row| row.get(0))
+            .optional()?)
     }
 
-    /// Checks the consistency of the storage.
+    /// Registers an alternate name for a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `alias` - The alternate name, which must not be taken by a bucket or alias.
     ///
     /// # Returns
     ///
     /// * `Result<(), StorageError>` - An empty result, or an error.
-    pub fn check_consistency(&mut self) -> Result<(), StorageError> {
-        let tx = self.conn.transaction()?;
-
-        // Check all objects have corresponding files
-        let mut stmt = tx.prepare("SELECT bucket_name, key, file_path, etag FROM objects")?;
-
-        let mut rows = stmt.query([])?;
-        while let Some(row) = rows.next()? {
-            let bucket: String = row.get(0)?;
-            let key: String = row.get(1)?;
-            let file_path: String = row.get(2)?;
-            let expected_etag: String = row.get(3)?;
+    pub fn create_bucket_alias(
+        &mut self,
+        bucket_name: &str,
+        alias: &str,
+    ) -> Result<(), StorageError> {
+        if !self.bucket_exists(bucket_name)? {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        if self.resolve_bucket(alias)?.is_some() {
+            return Err(StorageError::BucketAlreadyExistsInStorage(
+                alias.to_string(),
+            ));
+        }
+        self.conn.execute(
+            "INSERT INTO bucket_aliases (alias, bucket_name) VALUES (?1, ?2)",
+            params![alias, bucket_name],
+        )?;
+        Ok(())
+    }
 
-            // Verify file exists
-            if !Path::new(&file_path).exists() {
-                return Err(StorageError::ConsistencyError(format!(
+    /// Removes an alternate name of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `alias` - The alternate name to remove.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn delete_bucket_alias(
+        &mut self,
+        bucket_name: &str,
+        alias: &str,
+    ) -> Result<(), StorageError> {
+        let rows_affected = self.conn.execute(
+            "DELETE FROM bucket_aliases WHERE alias = ?1 AND bucket_name = ?2",
+            params![alias, bucket_name],
+        )?;
+        if rows_affected == 0 {
+            return Err(StorageError::AliasNotFound(
+                alias.to_string(),
+                bucket_name.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Lists the alternate names of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<String>, StorageError>` - The aliases without their namespace, or an error.
+    pub fn list_bucket_aliases(&self, bucket_name: &str) -> Result<Vec<String>, StorageError> {
+        if !self.bucket_exists(bucket_name)? {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        let mut stmt = self
+            .conn
+            .prepare("SELECT alias FROM bucket_aliases WHERE bucket_name = ?1 ORDER BY alias")?;
+        let aliases = stmt
+            .query_map(params![bucket_name], |row| row.get::<_, String>(0))?
+            .map(|alias| alias.map(|alias| split_bucket(&alias).1.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(aliases)
+    }
+
+    /// Returns the directory holding a bucket's files in the given storage tier.
+    fn tier_dir(&self, storage_class: StorageClass, bucket: &str) -> PathBuf {
+        match storage_class {
+            StorageClass::Standard => self.base_path.join("buckets").join(bucket),
+            StorageClass::Cold => self.base_path.join("cold").join("buckets").join(bucket),
+        }
+    }
+
+    /// Gets the versioning status of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<VersioningStatus>, StorageError>` - The status, `None` if versioning
+    ///   has never been enabled, or an error.
+    pub fn get_bucket_versioning(
+        &self,
+        bucket_name: &str,
+    ) -> Result<Option<VersioningStatus>, StorageError> {
+        let status: Option<Option<String>> = self
+            .conn
+            .prepare_cached("SELECT versioning FROM buckets WHERE name = ?1")?
+            .query_row(params![bucket_name], |row| row.get(0))
+            .optional()?;
+        match status {
+            Some(status) => Ok(status.and_then(|s| s.parse().ok())),
+            None => Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            )),
+        }
+    }
+
+    /// Whether a bucket is write-once.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, StorageError>` - Whether the bucket is write-once, or an error.
+    pub fn get_bucket_worm(&self, bucket_name: &str) -> Result<bool, StorageError> {
+        self.conn
+            .query_row(
+                "SELECT worm FROM buckets WHERE name = ?1",
+                params![bucket_name],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| StorageError::BucketNotFoundInStorage(bucket_name.to_string()))
+    }
+
+    /// Turns write-once mode of a bucket on. Once on, it stays on for the
+    /// bucket's lifetime; turning it off is refused.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `enabled` - The requested mode.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn set_bucket_worm(
+        &mut self,
+        bucket_name: &str,
+        enabled: bool,
+    ) -> Result<(), StorageError> {
+        if self.get_bucket_worm(bucket_name)? && !enabled {
+            return Err(StorageError::BucketImmutable(
+                bucket_name.to_string(),
+                "write-once mode cannot be turned off",
+            ));
+        }
+        self.conn.execute(
+            "UPDATE buckets SET worm = ?1 WHERE name = ?2",
+            params![enabled, bucket_name],
+        )?;
+        Ok(())
+    }
+
+    /// Whether operations on a bucket are logged in detail.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, StorageError>` - Whether verbose logging is on, or an error.
+    pub fn get_bucket_verbose_logging(&self, bucket_name: &str) -> Result<bool, StorageError> {
+        self.conn
+            .query_row(
+                "SELECT verbose_logging FROM buckets WHERE name = ?1",
+                params![bucket_name],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| StorageError::BucketNotFoundInStorage(bucket_name.to_string()))
+    }
+
+    /// Turns detailed logging of a bucket's operations on or off.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `enabled` - The requested mode.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn set_bucket_verbose_logging(
+        &mut self,
+        bucket_name: &str,
+        enabled: bool,
+    ) -> Result<(), StorageError> {
+        let updated = self.conn.execute(
+            "UPDATE buckets SET verbose_logging = ?1 WHERE name = ?2",
+            params![enabled, bucket_name],
+        )?;
+        if updated == 0 {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The content type given to objects uploaded to a bucket without one.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<String>, StorageError>` - The default content type, if any, or an error.
+    pub fn get_bucket_default_content_type(
+        &self,
+        bucket_name: &str,
+    ) -> Result<Option<String>, StorageError> {
+        self.conn
+            .prepare_cached("SELECT default_content_type FROM buckets WHERE name = ?1")?
+            .query_row(params![bucket_name], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| StorageError::BucketNotFoundInStorage(bucket_name.to_string()))
+    }
+
+    /// Sets or clears the content type given to objects uploaded to a bucket
+    /// without one.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `content_type` - The default content type, or `None` for none.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn set_bucket_default_content_type(
+        &mut self,
+        bucket_name: &str,
+        content_type: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let updated = self.conn.execute(
+            "UPDATE buckets SET default_content_type = ?1 WHERE name = ?2",
+            params![content_type, bucket_name],
+        )?;
+        if updated == 0 {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The user metadata merged into every object uploaded to a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<HashMap<String, String>, StorageError>` - The default metadata, empty if
+    ///   none, or an error.
+    pub fn get_bucket_default_metadata(
+        &self,
+        bucket_name: &str,
+    ) -> Result<HashMap<String, String>, StorageError> {
+        let json: Option<String> = self
+            .conn
+            .prepare_cached("SELECT default_metadata FROM buckets WHERE name = ?1")?
+            .query_row(params![bucket_name], |row| row.get(0))
+            .optional()?
+            .ok_or_else(|| StorageError::BucketNotFoundInStorage(bucket_name.to_string()))?;
+        match json {
+            Some(json) => Ok(serde_json::from_str(&json)?),
+            None => Ok(HashMap::new()),
+        }
+    }
+
+    /// Sets the user metadata merged into every object uploaded to a bucket;
+    /// an empty map clears it.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `metadata` - The default metadata.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn set_bucket_default_metadata(
+        &mut self,
+        bucket_name: &str,
+        metadata: &HashMap<String, String>,
+    ) -> Result<(), StorageError> {
+        let json = if metadata.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_string(metadata)?)
+        };
+        let updated = self.conn.execute(
+            "UPDATE buckets SET default_metadata = ?1 WHERE name = ?2",
+            params![json, bucket_name],
+        )?;
+        if updated == 0 {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The roles principals hold on a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<HashMap<String, Role>, StorageError>` - The roles by principal, or an error.
+    pub fn get_bucket_roles(
+        &self,
+        bucket_name: &str,
+    ) -> Result<HashMap<String, Role>, StorageError> {
+        if !self.bucket_exists(bucket_name)? {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        let mut stmt = self
+            .conn
+            .prepare("SELECT principal, role FROM bucket_roles WHERE bucket_name = ?1")?;
+        let rows = stmt
+            .query_map(params![bucket_name], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(principal, role)| {
+                let role = role.parse().map_err(StorageError::IntegrityError)?;
+                Ok((principal, role))
+            })
+            .collect()
+    }
+
+    /// Replaces the roles principals hold on a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `roles` - The roles by principal; empty removes them all.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn set_bucket_roles(
+        &mut self,
+        bucket_name: &str,
+        roles: &HashMap<String, Role>,
+    ) -> Result<(), StorageError> {
+        if !self.bucket_exists(bucket_name)? {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        let tx = write_transaction(&mut self.conn, self.busy)?;
+        tx.execute(
+            "DELETE FROM bucket_roles WHERE bucket_name = ?1",
+            params![bucket_name],
+        )?;
+        for (principal, role) in roles {
+            tx.execute(
+                "INSERT INTO bucket_roles (bucket_name, principal, role) VALUES (?1, ?2, ?3)",
+                params![bucket_name, principal, role.as_str()],
+            )?;
+        }
+        tx.commit()
+            .map_err(|_| StorageError::TransactionCommitError)
+    }
+
+    /// The roles of every bucket that has any, by bucket and principal.
+    pub fn list_bucket_roles(
+        &self,
+    ) -> Result<HashMap<String, HashMap<String, Role>>, StorageError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT bucket_name, principal, role FROM bucket_roles")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut buckets: HashMap<String, HashMap<String, Role>> = HashMap::new();
+        for (bucket, principal, role) in rows {
+            let role = role.parse().map_err(StorageError::IntegrityError)?;
+            buckets.entry(bucket).or_default().insert(principal, role);
+        }
+        Ok(buckets)
+    }
+
+    /// The ACL grants of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Grant>, StorageError>` - The grants, ordered by grantee, or an error.
+    pub fn get_bucket_acl(&self, bucket_name: &str) -> Result<Vec<Grant>, StorageError> {
+        if !self.bucket_exists(bucket_name)? {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT grantee, permission FROM bucket_acl WHERE bucket_name = ?1
+             ORDER BY grantee, permission",
+        )?;
+        let rows = stmt
+            .query_map(params![bucket_name], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(grantee, permission)| {
+                Ok(Grant {
+                    grantee,
+                    permission: permission.parse().map_err(StorageError::IntegrityError)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Replaces the ACL grants of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `grants` - The grants; empty removes them all.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn set_bucket_acl(
+        &mut self,
+        bucket_name: &str,
+        grants: &[Grant],
+    ) -> Result<(), StorageError> {
+        if !self.bucket_exists(bucket_name)? {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        let tx = write_transaction(&mut self.conn, self.busy)?;
+        tx.execute(
+            "DELETE FROM bucket_acl WHERE bucket_name = ?1",
+            params![bucket_name],
+        )?;
+        for grant in grants {
+            tx.execute(
+                "INSERT OR IGNORE INTO bucket_acl (bucket_name, grantee, permission)
+                 VALUES (?1, ?2, ?3)",
+                params![bucket_name, grant.grantee, grant.permission.as_str()],
+            )?;
+        }
+        tx.commit()
+            .map_err(|_| StorageError::TransactionCommitError)
+    }
+
+    /// The ACL grants of every bucket that has any.
+    pub fn list_bucket_acls(&self) -> Result<HashMap<String, Vec<Grant>>, StorageError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT bucket_name, grantee, permission FROM bucket_acl")?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut buckets: HashMap<String, Vec<Grant>> = HashMap::new();
+        for (bucket, grantee, permission) in rows {
+            let permission = permission.parse().map_err(StorageError::IntegrityError)?;
+            buckets.entry(bucket).or_default().push(Grant {
+                grantee,
+                permission,
+            });
+        }
+        Ok(buckets)
+    }
+
+    /// The buckets whose operations are logged in detail.
+    pub fn list_verbose_logging_buckets(&self) -> Result<Vec<String>, StorageError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name FROM buckets WHERE verbose_logging = 1")?;
+        let names = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(names)
+    }
+
+    /// Sets the versioning status of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `status` - The new versioning status.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn set_bucket_versioning(
+        &mut self,
+        bucket_name: &str,
+        status: VersioningStatus,
+    ) -> Result<(), StorageError> {
+        let rows_affected = self.conn.execute(
+            "UPDATE buckets SET versioning = ?1 WHERE name = ?2",
+            params![status.as_str(), bucket_name],
+        )?;
+        if rows_affected == 0 {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the version id of the current version of an object, if it has one.
+    fn current_version_id(
+        tx: &rusqlite::Transaction,
+        bucket: &str,
+        key: &str,
+    ) -> Result<Option<String>, StorageError> {
+        let version_id: Option<Option<String>> = tx
+            .prepare_cached("SELECT version_id FROM objects WHERE bucket_name = ?1 AND key = ?2")?
+            .query_row(params![bucket, key], |row| row.get(0))
+            .optional()?;
+        Ok(version_id.flatten())
+    }
+
+    /// Moves the current version of an object into `object_versions`. Its
+    /// file stays where it is, now referenced by the version. The file of a
+    /// version it replaces (the "null" version) is left to the caller to
+    /// remove once the transaction commits, so a rollback loses no data.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<ArchivedVersion>, StorageError>` - The archived
+    ///   version, or `None` if there was no current version to archive.
+    fn archive_current_version(
+        tx: &rusqlite::Transaction,
+        bucket: &str,
+        key: &str,
+        trace: &mut OpTrace,
+    ) -> Result<Option<ArchivedVersion>, StorageError> {
+        let current = trace.sql(|| {
+            tx.query_row(
+                "SELECT file_path, content_type, etag, size, last_modified, metadata, version_id,
+                        hash_algorithm, id
+                 FROM objects WHERE bucket_name = ?1 AND key = ?2",
+                params![bucket, key],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, i64>(3)?,
+                        row.get::<_, i64>(4)?,
+                        row.get::<_, Option<String>>(5)?,
+                        row.get::<_, Option<String>>(6)?,
+                        row.get::<_, String>(7)?,
+                        row.get::<_, Option<String>>(8)?,
+                    ))
+                },
+            )
+            .optional()
+        })?;
+
+        let Some((
+            file_path,
+            content_type,
+            etag,
+            size,
+            last_modified,
+            metadata,
+            version_id,
+            hash_algorithm,
+            object_id,
+        )) = current
+        else {
+            return Ok(None);
+        };
+
+        // Objects written before versioning was enabled carry the "null" version
+        let version_id = version_id.unwrap_or_else(|| "null".to_string());
+        let replaced_path: Option<Option<String>> = trace.sql(|| {
+            tx.query_row(
+                "SELECT file_path FROM object_versions
+                 WHERE bucket_name = ?1 AND key = ?2 AND version_id = ?3",
+                params![bucket, key, version_id],
+                |row| row.get(0),
+            )
+            .optional()
+        })?;
+        let replaced_file = replaced_path
+            .flatten()
+            .filter(|replaced_path| *replaced_path != file_path);
+
+        let rows = trace.sql(|| {
+            tx.execute(
+                "INSERT OR REPLACE INTO object_versions
+                 (bucket_name, key, version_id, file_path, content_type, etag, size, last_modified, metadata,
+                  hash_algorithm, object_id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                params![
+                    bucket,
+                    key,
+                    version_id,
+                    file_path,
+                    content_type,
+                    etag,
+                    size,
+                    last_modified,
+                    metadata,
+                    hash_algorithm,
+                    object_id
+                ],
+            )
+        })?;
+        trace.add_rows(rows);
+        Ok(Some(ArchivedVersion { replaced_file }))
+    }
+
+    /// Puts an object into a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to put the object into.
+    /// * `object` - The object to put into the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    #[instrument(
+        name = "storage.put_object",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket, key = %object.key, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn put_object(&mut self, bucket: &str, object: Object) -> Result<(), StorageError> {
+        let mut trace = self.trace("put_object", bucket, &object.key);
+        let partial = self.inject_fault("put_object")?;
+        let fields = ObjectFields {
+            key: &object.key,
+            content_type: object.content_type.as_deref(),
+            user_metadata: object.user_metadata.as_ref(),
+            immutable: object.immutable,
+        };
+        self.store_object(&mut trace, bucket, fields, |_, file_path, etags, trace| {
+            trace.file(|| {
+                fs::File::create(file_path)
+                    .and_then(|mut file| write_data(&mut file, &object.data, partial))
+            })?;
+            trace.add_bytes(object.data.len());
+            Ok((etags.etag(&object.data), object.data.len() as u64))
+        })
+    }
+
+    /// Stores an object whose file `write` fills in, in one transaction:
+    /// `write` gets the transaction, the path of the new file and the ETag
+    /// generator, and returns the ETag and size of what it wrote. Overwrites
+    /// the current object, archiving it if the bucket keeps versions, and
+    /// removes the file it no longer needs after the commit.
+    fn store_object<'t>(
+        &mut self,
+        trace: &mut OpTrace<'t>,
+        bucket: &str,
+        fields: ObjectFields<'_>,
+        write: impl FnOnce(
+            &Transaction<'_>,
+            &Path,
+            &dyn ETagGenerator,
+            &mut OpTrace<'t>,
+        ) -> Result<(String, u64), StorageError>,
+    ) -> Result<(), StorageError> {
+        let key = fields.key;
+        let tx = trace.sql(|| write_transaction(&mut self.conn, self.busy))?;
+
+        trace.sql(|| -> Result<(), StorageError> {
+            check_legal_hold(&tx, bucket, key)?;
+            check_write_once(&tx, bucket, key)?;
+            tx.prepare_cached("INSERT OR IGNORE INTO buckets (name) VALUES (?1)")?
+                .execute([bucket])?;
+            Ok(())
+        })?;
+
+        let id = self.ids.object_id();
+        let bucket_dir = self.roots.pick().join("buckets").join(bucket);
+        let file_path = object_data_path(&bucket_dir, &id);
+        if let Some(parent) = file_path.parent() {
+            trace.file(|| fs::create_dir_all(parent))?;
+        }
+
+        let file_path_str = file_path
+            .to_str()
+            .ok_or_else(|| StorageError::InvalidPath(file_path.display().to_string()))?
+            .to_string();
+
+        let (previous_path, versioning, replication_destination) =
+            trace.sql(|| -> Result<_, StorageError> {
+                let previous_path: Option<String> = tx
+                    .prepare_cached(
+                        "SELECT file_path FROM objects WHERE bucket_name = ?1 AND key = ?2",
+                    )?
+                    .query_row(params![bucket, key], |row| row.get(0))
+                    .optional()?;
+                let (versioning, replication_destination): (Option<String>, Option<String>) = tx
+                    .prepare_cached(
+                        "SELECT versioning, replication_destination FROM buckets WHERE name = ?1",
+                    )?
+                    .query_row([bucket], |row| Ok((row.get(0)?, row.get(1)?)))?;
+                Ok((previous_path, versioning, replication_destination))
+            })?;
+        let (version_id, archived) = match versioning.and_then(|s| s.parse().ok()) {
+            Some(VersioningStatus::Enabled) => {
+                let archived = Self::archive_current_version(&tx, bucket, key, trace)?;
+                (Some(self.ids.version_id()), archived)
+            }
+            Some(VersioningStatus::Suspended) => {
+                // Only the "null" version is overwritten while versioning is suspended
+                let archived = match trace.sql(|| Self::current_version_id(&tx, bucket, key))? {
+                    Some(_) => Self::archive_current_version(&tx, bucket, key, trace)?,
+                    None => None,
+                };
+                (None, archived)
+            }
+            None => (None, None),
+        };
+
+        let (etag, size) = write(&tx, &file_path, &*self.etags, trace)?;
+
+        let metadata_json = match fields.user_metadata {
+            Some(map) => Some(serde_json::to_string(map)?),
+            None => None,
+        };
+
+        let last_modified = self.clock.unix_secs()?;
+
+        // New writes to a replicated bucket wait for the replicator to ship them
+        let replication_status = replication_destination.map(|_| ReplicationStatus::Pending);
+
+        let rows = trace.sql(|| {
+            tx.prepare_cached(
+                "INSERT OR REPLACE INTO objects
+                 (bucket_name, key, file_path, content_type, etag, size, last_modified, metadata, version_id,
+                  replication_status, hash_algorithm, namespace, id, immutable)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+            )?
+            .execute(params![
+                    bucket,
+                    key,
+                    file_path_str,
+                    fields.content_type,
+                    etag,
+                    size as i64,
+                    last_modified,
+                    metadata_json,
+                    version_id,
+                    replication_status.map(|s| s.as_str()),
+                    self.etags.algorithm(),
+                    split_bucket(bucket).0,
+                    id,
+                    fields.immutable
+                ])
+        })?;
+        trace.add_rows(rows);
+
+        trace
+            .sql(|| tx.commit())
+            .map_err(|_| StorageError::TransactionCommitError)?;
+        match archived {
+            Some(archived) => trace.file(|| archived.remove_replaced_file())?,
+            // The data of an overwritten object goes once nothing refers to it
+            None => {
+                if let Some(previous_path) = previous_path.filter(|path| *path != file_path_str) {
+                    trace.file(|| {
+                        let previous_path = Path::new(&previous_path);
+                        match previous_path.exists() {
+                            true => fs::remove_file(previous_path),
+                            false => Ok(()),
+                        }
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Concatenates objects of a bucket, in the given order, into a new
+    /// object of the same bucket without reading them into memory: the
+    /// source files are copied piece by piece into the new object's file,
+    /// hashed on the way and checked against their own ETags, and the
+    /// object is recorded in one transaction. The sources are looked up in
+    /// that transaction, so the destination may be one of them.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket holding the sources and the destination.
+    /// * `key` - The key of the composed object.
+    /// * `sources` - The keys of the objects to concatenate.
+    /// * `content_type` - The content type of the composed object.
+    /// * `user_metadata` - User metadata stored with the composed object.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectInfo, StorageError>` - The composed object, or an error.
+    #[instrument(
+        name = "storage.compose_object",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket, key = %key, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn compose_object(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        sources: &[String],
+        content_type: Option<&str>,
+        user_metadata: Option<&HashMap<String, String>>,
+    ) -> Result<ObjectInfo, StorageError> {
+        let mut trace = self.trace("compose_object", bucket, key);
+        self.inject_fault("compose_object")?;
+        let fields = ObjectFields {
+            key,
+            content_type,
+            user_metadata,
+            immutable: false,
+        };
+        self.store_object(&mut trace, bucket, fields, |tx, file_path, etags, trace| {
+            let found = trace.sql(|| -> Result<Vec<_>, StorageError> {
+                let mut stmt = tx.prepare_cached(
+                    "SELECT file_path, etag, hash_algorithm FROM objects
+                     WHERE bucket_name = ?1 AND key = ?2",
+                )?;
+                sources
+                    .iter()
+                    .map(|source| {
+                        stmt.query_row(params![bucket, source], |row| {
+                            Ok((
+                                row.get::<_, String>(0)?,
+                                row.get::<_, Option<String>>(1)?,
+                                row.get::<_, String>(2)?,
+                            ))
+                        })
+                        .optional()?
+                        .ok_or_else(|| {
+                            StorageError::ObjectNotFound(source.clone(), bucket.to_string())
+                        })
+                    })
+                    .collect()
+            })?;
+            trace.add_rows(found.len());
+
+            let mut hasher = etags.algorithm().parse().ok().map(ETagHasher::new);
+            let size = trace.file(|| -> Result<u64, StorageError> {
+                let mut file = fs::File::create(file_path)?;
+                let mut size = 0;
+                for (source, (path, etag, algorithm)) in sources.iter().zip(&found) {
+                    let mut check = match (algorithm.parse::<HashAlgorithm>(), etag) {
+                        (Ok(algorithm), Some(etag)) => {
+                            Some(ETagCheck::new(algorithm, etag.clone()))
+                        }
+                        _ => None,
+                    };
+                    size += copy_chunks(&mut fs::File::open(path)?, &mut file, |chunk| {
+                        if let Some(hasher) = &mut hasher {
+                            hasher.update(chunk);
+                        }
+                        if let Some(check) = &mut check {
+                            check.update(chunk);
+                        }
+                    })?;
+                    if check.is_some_and(|check| !check.matches()) {
+                        return Err(StorageError::IntegrityError(format!(
+                            "ETag mismatch for {}/{} - possible data corruption",
+                            bucket, source
+                        )));
+                    }
+                }
+                Ok(size)
+            })?;
+            trace.add_bytes(size as usize);
+            let etag = match hasher {
+                Some(hasher) => hasher.finish(),
+                None => trace.file(|| file_etag(etags, file_path))?,
+            };
+            Ok((etag, size))
+        })?;
+        self.head_object(bucket, key)
+    }
+
+    /// Gets an object from a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to get the object from.
+    /// * `key` - The key of the object to get.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Object, StorageError>` - The retrieved object, or an error.
+    #[instrument(
+        name = "storage.get_object",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket, key = %key, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn get_object(&self, bucket: &str, key: &str) -> Result<Object, StorageError> {
+        let mut trace = self.trace("get_object", bucket, key);
+        self.inject_fault("get_object")?;
+        let row = trace.sql(|| -> Result<_, StorageError> {
+            let mut stmt = self.conn.prepare_cached(
+                "SELECT file_path, content_type, etag, last_modified, metadata, version_id,
+                        hash_algorithm, immutable
+                 FROM objects WHERE bucket_name = ?1 AND key = ?2",
+            )?;
+            let row = stmt
+                .query_row(params![bucket, key], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, i64>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                        row.get::<_, Option<String>>(5)?,
+                        row.get::<_, String>(6)?,
+                        row.get::<_, bool>(7)?,
+                    ))
+                })
+                .optional()?;
+            Ok(row)
+        })?;
+
+        if let Some((
+            file_path_str,
+            content_type,
+            etag,
+            last_modified,
+            metadata_json,
+            version_id,
+            hash_algorithm,
+            immutable,
+        )) = row
+        {
+            trace.add_rows(1);
+            let file_path = PathBuf::from(file_path_str);
+            let etag = Some(etag);
+            let data = trace.file(|| fs::read(&file_path))?;
+            trace.add_bytes(data.len());
+
+            let current_etag = calculate_etag(&*self.etags, &data, &hash_algorithm)?;
+
+            if let Some(ref etag) = etag
+                && current_etag != *etag
+            {
+                return Err(StorageError::IntegrityError(format!(
+                    "ETag mismatch for {}/{} - possible data corruption",
+                    bucket, key
+                )));
+            }
+
+            let user_metadata: Option<HashMap<String, String>> = metadata_json
+                .map(|s| serde_json::from_str(&s))
+                .transpose()?;
+
+            Ok(Object {
+                key: key.to_string(),
+                data,
+                content_type,
+                etag,
+                last_modified,
+                user_metadata,
+                version_id,
+                immutable,
+            })
+        } else {
+            Err(StorageError::ObjectNotFound(
+                key.to_string(),
+                bucket.to_string(),
+            ))
+        }
+    }
+
+    /// Opens an object's file for reading it piece by piece, so large
+    /// objects are sent without being held in memory. Its ETag cannot be
+    /// verified before the bytes are sent; the returned check verifies them
+    /// as they are read. A file of another size than recorded fails right
+    /// away.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket containing the object.
+    /// * `key` - The key of the object to open.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectReader, StorageError>` - The object's metadata and file, or an error.
+    #[instrument(
+        name = "storage.open_object",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket, key = %key, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn open_object(&self, bucket: &str, key: &str) -> Result<ObjectReader, StorageError> {
+        let mut trace = self.trace("open_object", bucket, key);
+        self.inject_fault("get_object")?;
+        let row = trace.sql(|| {
+            self.conn
+                .prepare_cached(&format!(
+                    "SELECT {}, file_path, hash_algorithm FROM objects
+                     WHERE bucket_name = ?1 AND key = ?2",
+                    OBJECT_INFO_COLUMNS
+                ))?
+                .query_row(params![bucket, key], |row| {
+                    Ok((
+                        object_info_from_row(row)?,
+                        row.get::<_, String>(9)?,
+                        row.get::<_, String>(10)?,
+                    ))
+                })
+                .optional()
+        })?;
+        let Some((info, file_path, hash_algorithm)) = row else {
+            return Err(StorageError::ObjectNotFound(
+                key.to_string(),
+                bucket.to_string(),
+            ));
+        };
+        trace.add_rows(1);
+
+        let file = trace.file(|| fs::File::open(&file_path))?;
+        if trace.file(|| file.metadata())?.len() != info.size {
+            return Err(StorageError::IntegrityError(format!(
+                "Size mismatch for {}/{} - possible data corruption",
+                bucket, key
+            )));
+        }
+        let check = match (hash_algorithm.parse::<HashAlgorithm>(), &info.etag) {
+            (Ok(algorithm), Some(etag)) => Some(ETagCheck::new(algorithm, etag.clone())),
+            _ => None,
+        };
+        Ok(ObjectReader { info, file, check })
+    }
+
+    /// Re-reads an object's file and compares its hash with the stored ETag.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket containing the object.
+    /// * `key` - The key of the object.
+    /// * `repair` - Whether to store the actual ETag if they differ.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectVerification, StorageError>` - The verdict, or an error.
+    pub fn verify_object(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        repair: bool,
+    ) -> Result<ObjectVerification, StorageError> {
+        let (file_path, stored_etag, hash_algorithm): (String, Option<String>, String) = self
+            .conn
+            .query_row(
+                "SELECT file_path, etag, hash_algorithm FROM objects
+                 WHERE bucket_name = ?1 AND key = ?2",
+                params![bucket, key],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?
+            .ok_or_else(|| StorageError::ObjectNotFound(key.to_string(), bucket.to_string()))?;
+
+        let data = fs::read(&file_path)?;
+        let actual_etag = calculate_etag(&*self.etags, &data, &hash_algorithm)?;
+        let valid = stored_etag.as_deref() == Some(actual_etag.as_str());
+        let repaired = repair && !valid;
+        if repaired {
+            // The recorded size is as stale as the ETag
+            self.conn.execute(
+                "UPDATE objects SET etag = ?1, size = ?2 WHERE bucket_name = ?3 AND key = ?4",
+                params![actual_etag, data.len() as i64, bucket, key],
+            )?;
+        }
+
+        Ok(ObjectVerification {
+            key: key.to_string(),
+            stored_etag,
+            actual_etag,
+            valid,
+            repaired,
+        })
+    }
+
+    /// Gets the metadata of an object without reading its data.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket containing the object.
+    /// * `key` - The key of the object.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectInfo, StorageError>` - The object's metadata, or an error.
+    #[instrument(
+        name = "storage.head_object",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket, key = %key, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn head_object(&self, bucket: &str, key: &str) -> Result<ObjectInfo, StorageError> {
+        let mut trace = self.trace("head_object", bucket, key);
+        self.inject_fault("head_object")?;
+        let info = trace.sql(|| {
+            self.conn
+                .prepare_cached(&format!(
+                    "SELECT {} FROM objects WHERE bucket_name = ?1 AND key = ?2",
+                    OBJECT_INFO_COLUMNS
+                ))?
+                .query_row(params![bucket, key], object_info_from_row)
+                .optional()
+        })?;
+        trace.add_rows(info.iter().count());
+        info.ok_or_else(|| StorageError::ObjectNotFound(key.to_string(), bucket.to_string()))
+    }
+
+    /// Issues a share link of an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket containing the object.
+    /// * `key` - The key of the object to share.
+    /// * `id` - The ID the share is listed and revoked under.
+    /// * `token` - The link's token.
+    /// * `max_downloads` - Downloads allowed in all, if limited.
+    /// * `expires_in` - Time until the link stops working, if limited.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Share, StorageError>` - The issued share, or an error.
+    pub fn create_share(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        id: &str,
+        token: &str,
+        max_downloads: Option<u64>,
+        expires_in: Option<Duration>,
+    ) -> Result<Share, StorageError> {
+        // Only existing objects are shared
+        self.head_object(bucket, key)?;
+        let created_at = self.clock.unix_secs()?;
+        let share = Share {
+            id: id.to_string(),
+            token: token.to_string(),
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            max_downloads,
+            downloads: 0,
+            expires_at: expires_in.map(|expires_in| created_at + expires_in.as_secs() as i64),
+            created_at,
+        };
+        self.conn.execute(
+            "INSERT INTO shares (token, bucket_name, key, max_downloads, expires_at, created_at, id)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                share.token,
+                share.bucket,
+                share.key,
+                share.max_downloads.map(|n| n as i64),
+                share.expires_at,
+                share.created_at,
+                share.id
+            ],
+        )?;
+        Ok(share)
+    }
+
+    /// Counts a download of a share link, if it is still usable.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The link's token.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Share, StorageError>` - The share with the download counted,
+    ///   `ShareNotFound`, `ShareExpired`, or another error.
+    pub fn claim_share(&mut self, token: &str) -> Result<Share, StorageError> {
+        let now = self.clock.unix_secs()?;
+        let mut share = self
+            .conn
+            .query_row(
+                &format!("SELECT {} FROM shares WHERE token = ?1", SHARE_COLUMNS),
+                params![token],
+                share_from_row,
+            )
+            .optional()?
+            .ok_or(StorageError::ShareNotFound)?;
+        if !share.is_usable(now) {
+            return Err(StorageError::ShareExpired);
+        }
+        self.conn.execute(
+            "UPDATE shares SET downloads = downloads + 1 WHERE token = ?1",
+            params![token],
+        )?;
+        share.downloads += 1;
+        Ok(share)
+    }
+
+    /// Lists the share links of a bucket that can still be downloaded.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Share>, StorageError>` - The shares, newest first, or an error.
+    pub fn list_shares(&self, bucket: &str) -> Result<Vec<Share>, StorageError> {
+        if !self.bucket_exists(bucket)? {
+            return Err(StorageError::BucketNotFoundInStorage(bucket.to_string()));
+        }
+        let now = self.clock.unix_secs()?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM shares WHERE bucket_name = ?1 ORDER BY created_at DESC, id",
+            SHARE_COLUMNS
+        ))?;
+        let shares = stmt
+            .query_map(params![bucket], share_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(shares
+            .into_iter()
+            .filter(|share| share.is_usable(now))
+            .collect())
+    }
+
+    /// Revokes a share link before its limits are reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket the shared object is in.
+    /// * `id` - The ID of the share.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, `ShareNotFound`, or an error.
+    pub fn revoke_share(&mut self, bucket: &str, id: &str) -> Result<(), StorageError> {
+        let deleted = self.conn.execute(
+            "DELETE FROM shares WHERE bucket_name = ?1 AND id = ?2",
+            params![bucket, id],
+        )?;
+        if deleted == 0 {
+            return Err(StorageError::ShareNotFound);
+        }
+        Ok(())
+    }
+
+    /// Rewrites a byte span of an object. The span may extend the object but
+    /// must not leave a gap after its end. For crash safety the whole object
+    /// is written to a new file with the span applied, recorded with its new
+    /// size and ETag in one transaction, and the old file is removed only
+    /// after the commit; a crash never leaves a half-patched object behind.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket containing the object.
+    /// * `key` - The key of the object to patch.
+    /// * `range` - The span to rewrite.
+    /// * `data` - The new bytes of the span.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectInfo, StorageError>` - The object's new metadata, or an error.
+    #[instrument(
+        name = "storage.patch_object",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket, key = %key, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn patch_object(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        range: &ContentRange,
+        data: &[u8],
+    ) -> Result<ObjectInfo, StorageError> {
+        let mut trace = self.trace("patch_object", bucket, key);
+        let partial = self.inject_fault("patch_object")?;
+        let tx = trace.sql(|| write_transaction(&mut self.conn, self.busy))?;
+        let row = trace.sql(|| -> Result<_, StorageError> {
+            check_legal_hold(&tx, bucket, key)?;
+            check_write_once(&tx, bucket, key)?;
+            let row = tx
+                .query_row(
+                    "SELECT file_path, etag, hash_algorithm, content_type, metadata
+                     FROM objects WHERE bucket_name = ?1 AND key = ?2",
+                    params![bucket, key],
+                    |row| {
+                        Ok((
+                            row.get::<_, String>(0)?,
+                            row.get::<_, Option<String>>(1)?,
+                            row.get::<_, String>(2)?,
+                            row.get::<_, Option<String>>(3)?,
+                            row.get::<_, Option<String>>(4)?,
+                        ))
+                    },
+                )
+                .optional()?;
+            Ok(row)
+        })?;
+        let (file_path, etag, hash_algorithm, content_type, metadata_json) =
+            row.ok_or_else(|| StorageError::ObjectNotFound(key.to_string(), bucket.to_string()))?;
+
+        let mut contents = trace.file(|| fs::read(&file_path))?;
+        if etag.as_deref()
+            != Some(calculate_etag(&*self.etags, &contents, &hash_algorithm)?.as_str())
+        {
+            return Err(StorageError::IntegrityError(format!(
+                "ETag mismatch for {}/{} - possible data corruption",
+                bucket, key
+            )));
+        }
+        let size = contents.len() as u64;
+        let end = range.last + 1;
+        if range.first > size
+            || range
+                .complete_length
+                .is_some_and(|length| length != end.max(size))
+        {
+            return Err(StorageError::RangeNotSatisfiable(
+                format!("{}-{}", range.first, range.last),
+                size,
+            ));
+        }
+        if end > size {
+            contents.resize(end as usize, 0);
+        }
+        contents[range.first as usize..end as usize].copy_from_slice(data);
+
+        let (keeps_history, replication_destination) =
+            trace.sql(|| -> Result<_, StorageError> {
+                let (versioning, replication_destination): (Option<String>, Option<String>) = tx
+                    .query_row(
+                        "SELECT versioning, replication_destination FROM buckets WHERE name = ?1",
+                        [bucket],
+                        |row| Ok((row.get(0)?, row.get(1)?)),
+                    )?;
+                let keeps_history = match versioning.and_then(|s| s.parse().ok()) {
+                    Some(VersioningStatus::Enabled) => true,
+                    Some(VersioningStatus::Suspended) => {
+                        Self::current_version_id(&tx, bucket, key)?.is_some()
+                    }
+                    None => false,
+                };
+                Ok((keeps_history, replication_destination))
+            })?;
+        if keeps_history {
+            // The previous version must survive, so the whole object is rewritten
+            tx.rollback()?;
+            let object = Object {
+                key: key.to_string(),
+                data: contents,
+                content_type,
+                etag: None,
+                last_modified: 0,
+                user_metadata: metadata_json
+                    .map(|json| serde_json::from_str(&json))
+                    .transpose()?,
+                version_id: None,
+                immutable: false,
+            };
+            self.put_object(bucket, object)?;
+            return self.head_object(bucket, key);
+        }
+
+        // The patched object goes to a new file, so the old one stays intact
+        // until the row points elsewhere
+        let id = self.ids.object_id();
+        let bucket_dir = self.roots.pick().join("buckets").join(bucket);
+        let new_path = object_data_path(&bucket_dir, &id);
+        if let Some(parent) = new_path.parent() {
+            trace.file(|| fs::create_dir_all(parent))?;
+        }
+        let new_path_str = new_path
+            .to_str()
+            .ok_or_else(|| StorageError::InvalidPath(new_path.display().to_string()))?
+            .to_string();
+        trace.file(|| {
+            let mut file = fs::File::create(&new_path)?;
+            write_data(&mut file, &contents, partial)?;
+            file.sync_data()
+        })?;
+        trace.add_bytes(contents.len());
+
+        let last_modified = self.clock.unix_secs()?;
+        let replication_status = replication_destination.map(|_| ReplicationStatus::Pending);
+        let etag = self.etags.etag(&contents);
+        let rows = trace.sql(|| {
+            tx.execute(
+                "UPDATE objects
+                 SET file_path = ?1, id = ?2, storage_class = ?3, etag = ?4, size = ?5,
+                     last_modified = ?6, hash_algorithm = ?7, replication_status = ?8
+                 WHERE bucket_name = ?9 AND key = ?10",
+                params![
+                    new_path_str,
+                    id,
+                    StorageClass::Standard.as_str(),
+                    etag,
+                    contents.len() as i64,
+                    last_modified,
+                    self.etags.algorithm(),
+                    replication_status.map(|s| s.as_str()),
+                    bucket,
+                    key
+                ],
+            )
+        })?;
+        trace.add_rows(rows);
+
+        trace
+            .sql(|| tx.commit())
+            .map_err(|_| StorageError::TransactionCommitError)?;
+        trace.file(|| fs::remove_file(&file_path))?;
+        self.head_object(bucket, key)
+    }
+
+    /// Deletes an object from a bucket only if its ETag still matches an
+    /// `If-Match` header value, so a client does not delete an object that
+    /// was replaced since it last saw it.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to delete the object from.
+    /// * `key` - The key of the object to delete.
+    /// * `if_match` - The value of the `If-Match` header.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, StorageError>` - A boolean indicating whether the object was deleted, or an error.
+    pub fn delete_object_if_match(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        if_match: &str,
+    ) -> Result<bool, StorageError> {
+        let etag: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT etag FROM objects WHERE bucket_name = ?1 AND key = ?2",
+                params![bucket, key],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| StorageError::ObjectNotFound(key.to_string(), bucket.to_string()))?;
+        if !etag_matches(if_match, etag.as_deref()) {
+            return Err(StorageError::PreconditionFailed(
+                key.to_string(),
+                bucket.to_string(),
+            ));
+        }
+        self.delete_object(bucket, key)
+    }
+
+    /// Deletes an object from a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to delete the object from.
+    /// * `key` - The key of the object to delete.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, StorageError>` - A boolean indicating whether the object was deleted, or an error.
+    #[instrument(
+        name = "storage.delete_object",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket, key = %key, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn delete_object(&mut self, bucket: &str, key: &str) -> Result<bool, StorageError> {
+        let mut trace = self.trace("delete_object", bucket, key);
+        self.inject_fault("delete_object")?;
+        let versioning = trace.sql(|| -> Result<_, StorageError> {
+            check_legal_hold(&self.conn, bucket, key)?;
+            check_write_once(&self.conn, bucket, key)?;
+            self.get_bucket_versioning(bucket)
+        })?;
+
+        match versioning {
+            Some(VersioningStatus::Enabled) => {
+                return self.delete_versioned_object(bucket, key, &mut trace);
+            }
+            Some(VersioningStatus::Suspended) => {
+                let has_version = trace.sql(|| -> Result<_, StorageError> {
+                    let tx = write_transaction(&mut self.conn, self.busy)?;
+                    let has_version = Self::current_version_id(&tx, bucket, key)?.is_some();
+                    tx.rollback()?;
+                    Ok(has_version)
+                })?;
+                if has_version {
+                    return self.delete_versioned_object(bucket, key, &mut trace);
+                }
+            }
+            None => {}
+        }
+
+        let file_path_to_delete_option: Option<String> = trace.sql(|| {
+            self.conn
+                .prepare_cached(
+                    "SELECT file_path FROM objects WHERE bucket_name = ?1 AND key = ?2",
+                )?
+                .query_row(params![bucket, key], |row| row.get(0))
+                .optional()
+        })?;
+
+        let tx = trace.sql(|| write_transaction(&mut self.conn, self.busy))?;
+
+        let rows_affected = trace.sql(|| {
+            tx.prepare_cached("DELETE FROM objects WHERE bucket_name = ?1 AND key = ?2")?
+                .execute(params![bucket, key])
+        })?;
+        trace.add_rows(rows_affected);
+
+        if rows_affected > 0 {
+            if let Some(file_path_str) = file_path_to_delete_option {
+                let file_path = PathBuf::from(file_path_str);
+                trace.file(|| match file_path.exists() {
+                    true => fs::remove_file(&file_path),
+                    false => Ok(()),
+                })?;
+            }
+            trace
+                .sql(|| tx.commit())
+                .map_err(|_| StorageError::TransactionCommitError)?;
+            Ok(true)
+        } else {
+            tx.rollback()?;
+            Err(StorageError::ObjectNotFound(
+                key.to_string(),
+                bucket.to_string(),
+            ))
+        }
+    }
+
+    /// Deletes up to `limit` objects whose key starts with `prefix`, in key
+    /// order after `after` (start with an empty string). Unversioned buckets
+    /// delete the whole batch in one transaction; in versioned buckets each
+    /// object gets a delete marker as with `delete_object`.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to delete from.
+    /// * `prefix` - The key prefix of the objects to delete.
+    /// * `after` - The last key visited by the previous batch.
+    /// * `limit` - The maximum number of keys to visit.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<PrefixDeleteBatch, StorageError>` - What was deleted, or an error.
+    pub fn delete_prefix_batch(
+        &mut self,
+        bucket: &str,
+        prefix: &str,
+        after: &str,
+        limit: usize,
+    ) -> Result<PrefixDeleteBatch, StorageError> {
+        let versioning = self.get_bucket_versioning(bucket)?;
+        let rows = {
+            let mut stmt = self.conn.prepare(
+                "SELECT o.key, o.file_path, o.legal_hold OR b.worm OR o.immutable
+                 FROM objects o JOIN buckets b ON b.name = o.bucket_name
+                 WHERE o.bucket_name = ?1 AND substr(o.key, 1, length(?2)) = ?2 AND o.key > ?3
+                 ORDER BY o.key LIMIT ?4",
+            )?;
+            stmt.query_map(params![bucket, prefix, after, limit as i64], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, bool>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+        };
+
+        let mut batch = PrefixDeleteBatch {
+            cursor: (rows.len() == limit)
+                .then(|| rows.last().map(|row| row.0.clone()))
+                .flatten(),
+            ..PrefixDeleteBatch::default()
+        };
+        if versioning.is_some() {
+            for (key, _, _) in rows {
+                match self.delete_object(bucket, &key) {
+                    Ok(_) => batch.deleted.push(key),
+                    Err(
+                        StorageError::ObjectUnderLegalHold(..) | StorageError::ObjectImmutable(..),
+                    ) => batch.skipped.push(key),
+                    Err(e) => return Err(e),
+                }
+            }
+            return Ok(batch);
+        }
+
+        let tx = write_transaction(&mut self.conn, self.busy)?;
+        let mut files = Vec::new();
+        for (key, file_path, protected) in rows {
+            if protected {
+                batch.skipped.push(key);
+                continue;
+            }
+            tx.execute(
+                "DELETE FROM objects WHERE bucket_name = ?1 AND key = ?2",
+                params![bucket, key],
+            )?;
+            files.push(file_path);
+            batch.deleted.push(key);
+        }
+        tx.commit()
+            .map_err(|_| StorageError::TransactionCommitError)?;
+
+        // Files go only once their rows are gone for good
+        for file_path in files {
+            let file_path = PathBuf::from(file_path);
+            if file_path.exists() {
+                fs::remove_file(&file_path)?;
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Counts the objects whose key starts with `prefix` and their total size.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket.
+    /// * `prefix` - The key prefix.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(u64, u64), StorageError>` - The number of objects and bytes, or an error.
+    pub fn count_prefix(&self, bucket: &str, prefix: &str) -> Result<(u64, u64), StorageError> {
+        if !self.bucket_exists(bucket)? {
+            return Err(StorageError::BucketNotFoundInStorage(bucket.to_string()));
+        }
+        let (objects, bytes): (i64, i64) = self.conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM objects
+             WHERE bucket_name = ?1 AND substr(key, 1, length(?2)) = ?2",
+            params![bucket, prefix],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok((objects as u64, bytes as u64))
+    }
+
+    /// Copies up to `limit` objects whose key starts with `prefix` from
+    /// `source` to `destination`, in key order after `after` (start with an
+    /// empty string). Files are copied and their rows duplicated without
+    /// re-hashing; a versioned destination keeps the overwritten versions as
+    /// with `put_object`.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - The name of the bucket to copy from.
+    /// * `destination` - The name of the bucket to copy to.
+    /// * `prefix` - The key prefix of the objects to copy.
+    /// * `after` - The last key visited by the previous batch.
+    /// * `limit` - The maximum number of keys to visit.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<PrefixCopyBatch, StorageError>` - What was copied, or an error.
+    pub fn copy_prefix_batch(
+        &mut self,
+        source: &str,
+        destination: &str,
+        prefix: &str,
+        after: &str,
+        limit: usize,
+    ) -> Result<PrefixCopyBatch, StorageError> {
+        let versioning = self.get_bucket_versioning(destination)?;
+        let replication_status = self
+            .get_bucket_replication(destination)?
+            .map(|_| ReplicationStatus::Pending);
+        let rows = {
+            let mut stmt = self.conn.prepare(
+                "SELECT key, file_path, content_type, etag, size, metadata, hash_algorithm
+                 FROM objects
+                 WHERE bucket_name = ?1 AND substr(key, 1, length(?2)) = ?2 AND key > ?3
+                 ORDER BY key LIMIT ?4",
+            )?;
+            stmt.query_map(params![source, prefix, after, limit as i64], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, i64>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, String>(6)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+        };
+
+        let mut batch = PrefixCopyBatch {
+            cursor: (rows.len() == limit)
+                .then(|| rows.last().map(|row| row.0.clone()))
+                .flatten(),
+            ..PrefixCopyBatch::default()
+        };
+        let last_modified = self.clock.unix_secs()?;
+        for (key, file_path, content_type, etag, size, metadata, hash_algorithm) in rows {
+            if versioning.is_some() {
+                let object = Object {
+                    key: key.clone(),
+                    data: fs::read(&file_path)?,
+                    content_type,
+                    etag: None,
+                    last_modified,
+                    user_metadata: metadata.as_deref().map(serde_json::from_str).transpose()?,
+                    version_id: None,
+                    immutable: false,
+                };
+                match self.put_object(destination, object) {
+                    Ok(()) => {
+                        batch.bytes += size as u64;
+                        batch.copied.push(key);
+                    }
+                    Err(
+                        StorageError::ObjectUnderLegalHold(..) | StorageError::ObjectImmutable(..),
+                    ) => batch.skipped.push(key),
+                    Err(e) => return Err(e),
+                }
+                continue;
+            }
+
+            let tx = write_transaction(&mut self.conn, self.busy)?;
+            match check_legal_hold(&tx, destination, &key)
+                .and_then(|_| check_write_once(&tx, destination, &key))
+            {
+                Ok(()) => {}
+                Err(StorageError::ObjectUnderLegalHold(..) | StorageError::ObjectImmutable(..)) => {
+                    batch.skipped.push(key);
+                    continue;
+                }
+                Err(e) => return Err(e),
+            }
+            let previous_path: Option<String> = tx
+                .query_row(
+                    "SELECT file_path FROM objects WHERE bucket_name = ?1 AND key = ?2",
+                    params![destination, key],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            let id = self.ids.object_id();
+            let bucket_dir = self.roots.pick().join("buckets").join(destination);
+            let target = object_data_path(&bucket_dir, &id);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let target_str = target
+                .to_str()
+                .ok_or_else(|| StorageError::InvalidPath(target.display().to_string()))?
+                .to_string();
+            fs::copy(&file_path, &target)?;
+            tx.execute(
+                "INSERT OR REPLACE INTO objects
+                 (bucket_name, key, file_path, content_type, etag, size, last_modified, metadata,
+                  replication_status, hash_algorithm, namespace, id)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                params![
+                    destination,
+                    key,
+                    target_str,
+                    content_type,
+                    etag,
+                    size,
+                    last_modified,
+                    metadata,
+                    replication_status.map(|s| s.as_str()),
+                    hash_algorithm,
+                    split_bucket(destination).0,
+                    id
+                ],
+            )?;
+            tx.commit()
+                .map_err(|_| StorageError::TransactionCommitError)?;
+            // The overwritten object's data goes once the row no longer refers to it
+            if let Some(previous_path) = previous_path
+                && previous_path != target_str
+                && Path::new(&previous_path).exists()
+            {
+                fs::remove_file(&previous_path)?;
+            }
+            batch.bytes += size as u64;
+            batch.copied.push(key);
+        }
+        Ok(batch)
+    }
+
+    /// Deletes an object from a bucket with versioning enabled. The current
+    /// version is kept as a noncurrent version and a delete marker is recorded.
+    fn delete_versioned_object(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        trace: &mut OpTrace,
+    ) -> Result<bool, StorageError> {
+        let tx = trace.sql(|| write_transaction(&mut self.conn, self.busy))?;
+
+        let Some(archived) = Self::archive_current_version(&tx, bucket, key, trace)? else {
+            tx.rollback()?;
+            return Err(StorageError::ObjectNotFound(
+                key.to_string(),
+                bucket.to_string(),
+            ));
+        };
+
+        let last_modified = self.clock.unix_secs()?;
+        let rows = trace.sql(|| -> Result<_, StorageError> {
+            let deleted = tx.execute(
+                "DELETE FROM objects WHERE bucket_name = ?1 AND key = ?2",
+                params![bucket, key],
+            )?;
+            let marked = tx.execute(
+                "INSERT INTO object_versions
+                 (bucket_name, key, version_id, last_modified, is_delete_marker)
+                 VALUES (?1, ?2, ?3, ?4, 1)",
+                params![bucket, key, self.ids.version_id(), last_modified],
+            )?;
+            Ok(deleted + marked)
+        })?;
+        trace.add_rows(rows);
+
+        trace
+            .sql(|| tx.commit())
+            .map_err(|_| StorageError::TransactionCommitError)?;
+        trace.file(|| archived.remove_replaced_file())?;
+        Ok(true)
+    }
+
+    /// Restores every object of a versioned bucket to its state at `at`
+    /// (Unix seconds), using the version history as the log of changes. The
+    /// restore itself is written as new versions and delete markers, so it
+    /// can be undone the same way.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to restore.
+    /// * `at` - The point in time to restore to.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<RestoreReport, StorageError>` - What was changed, or an error.
+    pub fn restore_bucket(&mut self, bucket: &str, at: i64) -> Result<RestoreReport, StorageError> {
+        if self.get_bucket_versioning(bucket)?.is_none() {
+            return Err(StorageError::NoVersionHistory(bucket.to_string()));
+        }
+
+        let mut history: BTreeMap<String, Vec<HistoryEntry>> = BTreeMap::new();
+        {
+            let mut stmt = self.conn.prepare(
+                "SELECT key, file_path, content_type, metadata, last_modified, is_delete_marker, 0
+                 FROM object_versions WHERE bucket_name = ?1
+                 UNION ALL
+                 SELECT key, file_path, content_type, metadata, last_modified, 0, 1
+                 FROM objects WHERE bucket_name = ?1
+                 ORDER BY 7, 5",
+            )?;
+            let mut rows = stmt.query(params![bucket])?;
+            while let Some(row) = rows.next()? {
+                history.entry(row.get(0)?).or_default().push(HistoryEntry {
+                    file_path: row.get(1)?,
+                    content_type: row.get(2)?,
+                    metadata: row.get(3)?,
+                    last_modified: row.get(4)?,
+                    is_delete_marker: row.get(5)?,
+                    is_current: row.get(6)?,
+                });
+            }
+        }
+
+        let mut report = RestoreReport::default();
+        for (key, mut entries) in history {
+            // Versions in the order they became current, the current one last
+            entries.sort_by_key(|e| (e.is_current, e.last_modified));
+            let has_current = entries.last().is_some_and(|e| e.is_current);
+            let then = entries
+                .iter()
+                .rev()
+                .find(|e| e.last_modified <= at)
+                .filter(|e| !e.is_delete_marker);
+
+            let result = match then {
+                Some(entry) if entry.is_current => {
+                    report.unchanged += 1;
+                    continue;
+                }
+                None if !has_current => {
+                    report.unchanged += 1;
+                    continue;
+                }
+                None => self.delete_object(bucket, &key).map(|_| false),
+                Some(entry) => {
+                    let file_path = entry.file_path.as_deref().unwrap_or_default();
+                    let object = Object {
+                        key: key.clone(),
+                        data: fs::read(file_path)?,
+                        content_type: entry.content_type.clone(),
+                        etag: None,
+                        last_modified: entry.last_modified,
+                        user_metadata: entry
+                            .metadata
+                            .as_deref()
+                            .map(serde_json::from_str)
+                            .transpose()?,
+                        version_id: None,
+                        immutable: false,
+                    };
+                    self.put_object(bucket, object).map(|_| true)
+                }
+            };
+            match result {
+                Ok(true) => report.restored.push(key),
+                Ok(false) => report.deleted.push(key),
+                Err(StorageError::ObjectUnderLegalHold(..) | StorageError::ObjectImmutable(..)) => {
+                    report.skipped.push(key)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Gets the legal hold flag of an object.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket containing the object.
+    /// * `key` - The key of the object.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, StorageError>` - Whether the object is under legal hold, or an error.
+    pub fn get_object_legal_hold(&self, bucket: &str, key: &str) -> Result<bool, StorageError> {
+        self.conn
+            .query_row(
+                "SELECT legal_hold FROM objects WHERE bucket_name = ?1 AND key = ?2",
+                params![bucket, key],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| StorageError::ObjectNotFound(key.to_string(), bucket.to_string()))
+    }
+
+    /// Places or releases a legal hold on an object. While the hold is on,
+    /// the object can be neither overwritten nor deleted.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket containing the object.
+    /// * `key` - The key of the object.
+    /// * `legal_hold` - Whether the object should be under legal hold.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn set_object_legal_hold(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        legal_hold: bool,
+    ) -> Result<(), StorageError> {
+        let rows_affected = self.conn.execute(
+            "UPDATE objects SET legal_hold = ?1 WHERE bucket_name = ?2 AND key = ?3",
+            params![legal_hold, bucket, key],
+        )?;
+        if rows_affected == 0 {
+            return Err(StorageError::ObjectNotFound(
+                key.to_string(),
+                bucket.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Gets the replication destination of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Option<String>, StorageError>` - The peer base URL, `None` if the bucket is
+    ///   not replicated, or an error.
+    pub fn get_bucket_replication(
+        &self,
+        bucket_name: &str,
+    ) -> Result<Option<String>, StorageError> {
+        self.conn
+            .query_row(
+                "SELECT replication_destination FROM buckets WHERE name = ?1",
+                params![bucket_name],
+                |row| row.get(0),
+            )
+            .optional()?
+            .ok_or_else(|| StorageError::BucketNotFoundInStorage(bucket_name.to_string()))
+    }
+
+    /// Sets or clears the replication destination of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `destination` - The peer base URL, or `None` to stop replicating.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn set_bucket_replication(
+        &mut self,
+        bucket_name: &str,
+        destination: Option<&str>,
+    ) -> Result<(), StorageError> {
+        let rows_affected = self.conn.execute(
+            "UPDATE buckets SET replication_destination = ?1 WHERE name = ?2",
+            params![destination, bucket_name],
+        )?;
+        if rows_affected == 0 {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Summarises the replication state of the objects in a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ReplicationReport, StorageError>` - The report, or an error.
+    pub fn replication_report(&self, bucket_name: &str) -> Result<ReplicationReport, StorageError> {
+        let destination = self.get_bucket_replication(bucket_name)?;
+        let mut report = self.conn.query_row(
+            "SELECT
+                COUNT(CASE WHEN replication_status = 'PENDING' THEN 1 END),
+                COUNT(CASE WHEN replication_status = 'COMPLETED' THEN 1 END),
+                COUNT(CASE WHEN replication_status = 'FAILED' THEN 1 END),
+                MIN(CASE WHEN replication_status IN ('PENDING', 'FAILED') THEN last_modified END)
+             FROM objects WHERE bucket_name = ?1",
+            params![bucket_name],
+            |row| {
+                Ok(ReplicationReport {
+                    destination: None,
+                    pending: row.get(0)?,
+                    completed: row.get(1)?,
+                    failed: row.get(2)?,
+                    oldest_unreplicated: row.get(3)?,
+                })
+            },
+        )?;
+        report.destination = destination;
+        Ok(report)
+    }
+
+    /// Lists objects still waiting to be shipped to their bucket's replication
+    /// destination, including ones whose last attempt failed. Those come
+    /// after every pending one, so objects the peer keeps refusing do not
+    /// hold up new writes.
+    ///
+    /// # Arguments
+    ///
+    /// * `limit` - The maximum number of objects to return.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<(String, String, String)>, StorageError>` - Tuples of bucket, key and
+    ///   destination, oldest first, or an error.
+    pub fn pending_replications(
+        &self,
+        limit: usize,
+    ) -> Result<Vec<(String, String, String)>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT o.bucket_name, o.key, b.replication_destination
+             FROM objects o JOIN buckets b ON b.name = o.bucket_name
+             WHERE o.replication_status IN ('PENDING', 'FAILED')
+               AND b.replication_destination IS NOT NULL
+             ORDER BY o.replication_status = 'FAILED', o.last_modified
+             LIMIT ?1",
+        )?;
+        let rows = stmt.query_map(params![limit as i64], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// Records the outcome of a replication attempt for an object, unless
+    /// it was overwritten since the attempt read it: the new data is still
+    /// to be shipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket containing the object.
+    /// * `key` - The key of the object.
+    /// * `shipped` - The object as the attempt read it.
+    /// * `status` - The new replication status.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, StorageError>` - Whether the status was recorded, or an error.
+    pub fn set_replication_status(
+        &mut self,
+        bucket: &str,
+        key: &str,
+        shipped: &Object,
+        status: ReplicationStatus,
+    ) -> Result<bool, StorageError> {
+        let updated = self.conn.execute(
+            "UPDATE objects SET replication_status = ?1
+             WHERE bucket_name = ?2 AND key = ?3 AND etag IS ?4 AND last_modified = ?5",
+            params![
+                status.as_str(),
+                bucket,
+                key,
+                shipped.etag,
+                shipped.last_modified
+            ],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Gets the lifecycle transition rules of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<LifecycleRule>, StorageError>` - The bucket's rules, or an error.
+    pub fn get_bucket_lifecycle(
+        &self,
+        bucket_name: &str,
+    ) -> Result<Vec<LifecycleRule>, StorageError> {
+        if !self.bucket_exists(bucket_name)? {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        let mut stmt = self.conn.prepare(
+            "SELECT prefix, days, storage_class FROM lifecycle_rules
+             WHERE bucket_name = ?1 ORDER BY rowid",
+        )?;
+        let rows = stmt.query_map(params![bucket_name], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, u32>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?;
+        let mut rules = Vec::new();
+        for row in rows {
+            let (prefix, days, storage_class) = row?;
+            if let Ok(storage_class) = storage_class.parse() {
+                rules.push(LifecycleRule {
+                    prefix,
+                    days,
+                    storage_class,
+                });
+            }
+        }
+        Ok(rules)
+    }
+
+    /// Replaces the lifecycle transition rules of a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `rules` - The new rules; an empty list removes all rules.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn set_bucket_lifecycle(
+        &mut self,
+        bucket_name: &str,
+        rules: &[LifecycleRule],
+    ) -> Result<(), StorageError> {
+        if !self.bucket_exists(bucket_name)? {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        let tx = write_transaction(&mut self.conn, self.busy)?;
+        tx.execute(
+            "DELETE FROM lifecycle_rules WHERE bucket_name = ?1",
+            params![bucket_name],
+        )?;
+        for rule in rules {
+            tx.execute(
+                "INSERT INTO lifecycle_rules (bucket_name, prefix, days, storage_class)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![
+                    bucket_name,
+                    rule.prefix,
+                    rule.days,
+                    rule.storage_class.as_str()
+                ],
+            )?;
+        }
+        tx.commit()
+            .map_err(|_| StorageError::TransactionCommitError)
+    }
+
+    /// Applies lifecycle transition rules, moving every object older than its
+    /// rule's age into the rule's storage tier. Where several rules are due
+    /// for an object, the one with the longest prefix decides, then the one
+    /// with the fewest days, then the one set first.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current time in seconds since the Unix epoch.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(u64, u64), StorageError>` - The number of objects and bytes transitioned,
+    ///   or an error.
+    pub fn transition_objects(&mut self, now: i64) -> Result<(u64, u64), StorageError> {
+        let candidates = {
+            let mut stmt = self.conn.prepare(
+                "SELECT bucket_name, key, file_path, size, rule_class, id FROM (
+                     SELECT o.bucket_name, o.key, o.file_path, o.size, o.id,
+                            o.storage_class, r.storage_class AS rule_class,
+                            ROW_NUMBER() OVER (
+                                PARTITION BY o.bucket_name, o.key
+                                ORDER BY length(r.prefix) DESC, r.days, r.rowid
+                            ) AS rank
+                     FROM objects o JOIN lifecycle_rules r ON r.bucket_name = o.bucket_name
+                     WHERE substr(o.key, 1, length(r.prefix)) = r.prefix
+                       AND o.last_modified <= ?1 - r.days * 86400
+                 )
+                 WHERE rank = 1 AND storage_class != rule_class",
+            )?;
+            let rows = stmt.query_map(params![now], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, i64>(3)?,
+                    row.get::<_, String>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            })?;
+            rows.collect::<Result<Vec<_>, _>>()?
+        };
+
+        let mut objects = 0;
+        let mut bytes = 0;
+        for (bucket, key, file_path, size, storage_class, id) in candidates {
+            let Ok(storage_class) = storage_class.parse::<StorageClass>() else {
+                continue;
+            };
+            let target = object_data_path(&self.tier_dir(storage_class, &bucket), &id);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let target_str = target
+                .to_str()
+                .ok_or_else(|| StorageError::InvalidPath(target.display().to_string()))?
+                .to_string();
+
+            // The object's file stays where the row says until the commit;
+            // a link is as good as a copy while sharing its data
+            let tx = write_transaction(&mut self.conn, self.busy)?;
+            if fs::hard_link(&file_path, &target).is_err() {
+                fs::copy(&file_path, &target)?;
+            }
+            let updated = tx
+                .execute(
+                    "UPDATE objects SET file_path = ?1, storage_class = ?2
+                     WHERE bucket_name = ?3 AND key = ?4",
+                    params![target_str, storage_class.as_str(), bucket, key],
+                )
+                .map_err(StorageError::from)
+                .and_then(|_| {
+                    tx.commit()
+                        .map_err(|_| StorageError::TransactionCommitError)
+                });
+            if let Err(e) = updated {
+                let _ = fs::remove_file(&target);
+                return Err(e);
+            }
+            // The transition is done; a leftover file only wastes space
+            if let Err(e) = fs::remove_file(&file_path) {
+                warn!(
+                    bucket = %bucket,
+                    key = %key,
+                    file = %file_path,
+                    error = %e,
+                    "Failed to remove the file of a transitioned object"
+                );
+            }
+
+            objects += 1;
+            bytes += size as u64;
+        }
+        Ok((objects, bytes))
+    }
+
+    /// Adds buffered read counts to the access statistics of objects.
+    /// Objects deleted since they were read are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `accesses` - Reads per (bucket, key) since the last flush.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn record_accesses(
+        &mut self,
+        accesses: &HashMap<(String, String), PendingAccess>,
+    ) -> Result<(), StorageError> {
+        let tx = write_transaction(&mut self.conn, self.busy)?;
+        {
+            let mut stmt = tx.prepare(
+                "UPDATE objects
+                 SET access_count = access_count + ?1,
+                     last_accessed = MAX(COALESCE(last_accessed, 0), ?2)
+                 WHERE bucket_name = ?3 AND key = ?4",
+            )?;
+            for ((bucket, key), access) in accesses {
+                stmt.execute(params![
+                    access.count as i64,
+                    access.last_accessed,
+                    bucket,
+                    key
+                ])?;
+            }
+        }
+        tx.commit()
+            .map_err(|_| StorageError::TransactionCommitError)
+    }
+
+    /// Reports the most read objects of a bucket and the objects that have
+    /// never been read.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    /// * `limit` - The maximum number of entries in each list.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<AccessReport, StorageError>` - The report, or an error.
+    pub fn access_report(
+        &self,
+        bucket_name: &str,
+        limit: usize,
+    ) -> Result<AccessReport, StorageError> {
+        if !self.bucket_exists(bucket_name)? {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT key, access_count, last_accessed FROM objects
+             WHERE bucket_name = ?1 AND access_count > 0
+             ORDER BY access_count DESC, last_accessed DESC
+             LIMIT ?2",
+        )?;
+        let hot = stmt
+            .query_map(params![bucket_name, limit as i64], |row| {
+                Ok(ObjectAccess {
+                    key: row.get(0)?,
+                    access_count: row.get::<_, i64>(1)? as u64,
+                    last_accessed: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT key FROM objects
+             WHERE bucket_name = ?1 AND access_count = 0
+             ORDER BY last_modified
+             LIMIT ?2",
+        )?;
+        let never_read = stmt
+            .query_map(params![bucket_name, limit as i64], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(AccessReport { hot, never_read })
+    }
+
+    /// Gets the space used by a bucket, from its counters.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket_name` - The name of the bucket.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<BucketUsage, StorageError>` - The usage, or an error.
+    pub fn bucket_usage(&self, bucket_name: &str) -> Result<BucketUsage, StorageError> {
+        if !self.bucket_exists(bucket_name)? {
+            return Err(StorageError::BucketNotFoundInStorage(
+                bucket_name.to_string(),
+            ));
+        }
+        let usage = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM bucket_usage WHERE bucket_name = ?1",
+                    USAGE_COLUMNS
+                ),
+                [bucket_name],
+                |row| usage::usage_from_row(row, 0),
+            )
+            .optional()?;
+        Ok(usage.unwrap_or_default())
+    }
+
+    /// Reports the space used by every bucket, from their counters.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<UsageReport, StorageError>` - The report, or an error.
+    pub fn usage_report(&self) -> Result<UsageReport, StorageError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT b.name, {} FROM buckets b
+             LEFT JOIN bucket_usage u ON u.bucket_name = b.name",
+            USAGE_COLUMNS
+        ))?;
+        let buckets = stmt
+            .query_map([], |row| {
+                Ok(NamedUsage {
+                    bucket: row.get(0)?,
+                    usage: usage::usage_from_row(row, 1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(UsageReport::new(buckets))
+    }
+
+    /// Lists the keys and prefixes pinned in the object cache.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<CachePin>, StorageError>` - The pins, or an error.
+    pub fn list_cache_pins(&self) -> Result<Vec<CachePin>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT bucket_name, key, is_prefix FROM cache_pins ORDER BY bucket_name, key",
+        )?;
+        let pins = stmt
+            .query_map([], |row| {
+                Ok(CachePin {
+                    bucket: row.get(0)?,
+                    key: row.get(1)?,
+                    prefix: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(pins)
+    }
+
+    /// Pins a key or prefix in the object cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `pin` - The key or prefix to pin.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn add_cache_pin(&mut self, pin: &CachePin) -> Result<(), StorageError> {
+        if !self.bucket_exists(&pin.bucket)? {
+            return Err(StorageError::BucketNotFoundInStorage(pin.bucket.clone()));
+        }
+        self.conn.execute(
+            "INSERT OR IGNORE INTO cache_pins (bucket_name, key, is_prefix) VALUES (?1, ?2, ?3)",
+            params![pin.bucket, pin.key, pin.prefix],
+        )?;
+        Ok(())
+    }
+
+    /// Removes a pin from the object cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `pin` - The key or prefix to unpin.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, StorageError>` - Whether the pin existed, or an error.
+    pub fn remove_cache_pin(&mut self, pin: &CachePin) -> Result<bool, StorageError> {
+        let rows_affected = self.conn.execute(
+            "DELETE FROM cache_pins WHERE bucket_name = ?1 AND key = ?2 AND is_prefix = ?3",
+            params![pin.bucket, pin.key, pin.prefix],
+        )?;
+        Ok(rows_affected > 0)
+    }
+
+    /// Path of the staging file holding the bytes received for an upload.
+    fn upload_path(&self, id: &str) -> PathBuf {
+        self.base_path.join("uploads").join(id)
+    }
+
+    /// Registers a new resumable upload and creates its empty staging file.
+    ///
+    /// # Arguments
+    ///
+    /// * `upload` - The upload to register.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn create_upload(&mut self, upload: &Upload) -> Result<(), StorageError> {
+        if !self.bucket_exists(&upload.bucket)? {
+            return Err(StorageError::BucketNotFoundInStorage(upload.bucket.clone()));
+        }
+        let path = self.upload_path(&upload.id);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, [])?;
+
+        let metadata_json = serde_json::to_string(&upload.user_metadata)?;
+        self.conn.execute(
+            "INSERT INTO uploads
+             (id, bucket_name, key, upload_length, upload_offset, content_type, metadata, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                upload.id,
+                upload.bucket,
+                upload.key,
+                upload.length as i64,
+                upload.offset as i64,
+                upload.content_type,
+                metadata_json,
+                upload.created_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Gets the state of a resumable upload.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket the upload targets.
+    /// * `id` - The id of the upload.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Upload, StorageError>` - The upload, or an error.
+    pub fn get_upload(&self, bucket: &str, id: &str) -> Result<Upload, StorageError> {
+        let row = self
+            .conn
+            .query_row(
+                "SELECT key, upload_length, upload_offset, content_type, metadata, created_at
+                 FROM uploads WHERE bucket_name = ?1 AND id = ?2",
+                params![bucket, id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, i64>(1)?,
+                        row.get::<_, i64>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                        row.get::<_, i64>(5)?,
+                    ))
+                },
+            )
+            .optional()?;
+        let (key, length, offset, content_type, metadata, created_at) =
+            row.ok_or_else(|| StorageError::UploadNotFound(id.to_string()))?;
+        let user_metadata = match metadata {
+            Some(json) => serde_json::from_str(&json)?,
+            None => HashMap::new(),
+        };
+        Ok(Upload {
+            id: id.to_string(),
+            bucket: bucket.to_string(),
+            key,
+            length: length as u64,
+            offset: offset as u64,
+            content_type,
+            user_metadata,
+            created_at,
+        })
+    }
+
+    /// Lists the resumable uploads in progress in a bucket, oldest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket the uploads target.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<Upload>, StorageError>` - The uploads, or an error.
+    pub fn list_uploads(&self, bucket: &str) -> Result<Vec<Upload>, StorageError> {
+        if !self.bucket_exists(bucket)? {
+            return Err(StorageError::BucketNotFoundInStorage(bucket.to_string()));
+        }
+        let mut stmt = self
+            .conn
+            .prepare("SELECT id FROM uploads WHERE bucket_name = ?1 ORDER BY created_at, id")?;
+        let ids = stmt
+            .query_map(params![bucket], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        ids.iter().map(|id| self.get_upload(bucket, id)).collect()
+    }
+
+    /// Lists the parts of a resumable upload, one per PATCH request that
+    /// appended to it, in order.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket the upload targets.
+    /// * `id` - The id of the upload.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(Upload, Vec<UploadPart>), StorageError>` - The upload and its parts, or an error.
+    pub fn list_upload_parts(
+        &self,
+        bucket: &str,
+        id: &str,
+    ) -> Result<(Upload, Vec<UploadPart>), StorageError> {
+        let upload = self.get_upload(bucket, id)?;
+        let mut stmt = self.conn.prepare(
+            "SELECT part_number, part_offset, size, etag, created_at
+             FROM upload_parts WHERE upload_id = ?1 ORDER BY part_number",
+        )?;
+        let parts = stmt
+            .query_map(params![id], |row| {
+                Ok(UploadPart {
+                    part_number: row.get::<_, i64>(0)? as u64,
+                    offset: row.get::<_, i64>(1)? as u64,
+                    size: row.get::<_, i64>(2)? as u64,
+                    etag: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((upload, parts))
+    }
+
+    /// Appends bytes to a resumable upload at `offset`, which must equal the
+    /// number of bytes received so far.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket the upload targets.
+    /// * `id` - The id of the upload.
+    /// * `offset` - The offset the client is writing at.
+    /// * `data` - The bytes to append.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Upload, StorageError>` - The upload with its new offset, or an error.
+    #[instrument(
+        name = "storage.append_upload",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket, upload_id = %id, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn append_upload(
+        &mut self,
+        bucket: &str,
+        id: &str,
+        offset: u64,
+        data: &[u8],
+    ) -> Result<Upload, StorageError> {
+        let mut trace = self.trace("append_upload", bucket, id);
+        let partial = self.inject_fault("append_upload")?;
+        let mut upload = trace.sql(|| self.get_upload(bucket, id))?;
+        if offset != upload.offset {
+            return Err(StorageError::UploadOffsetMismatch(upload.offset, offset));
+        }
+        if upload.offset + data.len() as u64 > upload.length {
+            return Err(StorageError::UploadLengthExceeded(
+                id.to_string(),
+                upload.length,
+            ));
+        }
+
+        // Drop bytes written after the last recorded offset, e.g. by a write
+        // that was interrupted before the offset was updated
+        trace.file(|| {
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .open(self.upload_path(id))?;
+            file.set_len(upload.offset)?;
+            file.seek(SeekFrom::End(0))?;
+            write_data(&mut file, data, partial)?;
+            file.sync_data()
+        })?;
+        trace.add_bytes(data.len());
+
+        let part_offset = upload.offset;
+        upload.offset += data.len() as u64;
+        let etag = self.etags.etag(data);
+        let now = self.clock.unix_secs()?;
+        let rows = trace.sql(|| {
+            let tx = write_transaction(&mut self.conn, self.busy)?;
+            let mut rows = tx.execute(
+                "UPDATE uploads SET upload_offset = ?1, updated_at = ?2 WHERE id = ?3",
+                params![upload.offset as i64, now, id],
+            )?;
+            // Parts past the offset are those of the interrupted write dropped above
+            rows += tx.execute(
+                "DELETE FROM upload_parts WHERE upload_id = ?1 AND part_offset >= ?2",
+                params![id, part_offset as i64],
+            )?;
+            if !data.is_empty() {
+                rows += tx.execute(
+                    "INSERT INTO upload_parts
+                     (upload_id, part_number, part_offset, size, etag, created_at)
+                     SELECT ?1, COALESCE(MAX(part_number), 0) + 1, ?2, ?3, ?4, ?5
+                     FROM upload_parts WHERE upload_id = ?1",
+                    params![id, part_offset as i64, data.len() as i64, etag, now],
+                )?;
+            }
+            tx.commit()?;
+            Ok::<_, StorageError>(rows)
+        })?;
+        trace.add_rows(rows);
+        Ok(upload)
+    }
+
+    /// Reads the first bytes received for an upload, at most `len`, e.g. to
+    /// infer its content type.
+    ///
+    /// # Arguments
+    ///
+    /// * `upload` - The upload to read.
+    /// * `len` - The most bytes to read.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<u8>, StorageError>` - The leading bytes, or an error.
+    pub fn read_upload_head(&self, upload: &Upload, len: usize) -> Result<Vec<u8>, StorageError> {
+        let file = fs::File::open(self.upload_path(&upload.id))?;
+        let mut head = Vec::with_capacity(len);
+        file.take(upload.offset.min(len as u64))
+            .read_to_end(&mut head)?;
+        Ok(head)
+    }
+
+    /// Stores a complete upload as an object without reading it into
+    /// memory. The staging file is hashed piece by piece, then hard-linked
+    /// to the object's file, or copied where the data root is on another
+    /// file system. The object is recorded and the upload removed in one
+    /// transaction; the staging file goes after the commit.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket the upload targets.
+    /// * `id` - The id of the upload.
+    /// * `content_type` - The content type of the object.
+    /// * `user_metadata` - User metadata stored with the object.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ObjectInfo, StorageError>` - The stored object, or an error.
+    #[instrument(
+        name = "storage.complete_upload",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket, upload_id = %id, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn complete_upload(
+        &mut self,
+        bucket: &str,
+        id: &str,
+        content_type: Option<&str>,
+        user_metadata: Option<&HashMap<String, String>>,
+    ) -> Result<ObjectInfo, StorageError> {
+        let mut trace = self.trace("complete_upload", bucket, id);
+        self.inject_fault("complete_upload")?;
+        let upload = trace.sql(|| self.get_upload(bucket, id))?;
+        if !upload.is_complete() {
+            return Err(StorageError::UploadIncomplete(
+                id.to_string(),
+                upload.offset,
+                upload.length,
+            ));
+        }
+        let staging = self.upload_path(id);
+        // Bytes past the offset are those of an interrupted write; hashing
+        // happens before the write lock is taken
+        let etag = trace.file(|| -> Result<_, StorageError> {
+            fs::OpenOptions::new()
+                .write(true)
+                .open(&staging)?
+                .set_len(upload.offset)?;
+            file_etag(&*self.etags, &staging)
+        })?;
+        trace.add_bytes(upload.offset as usize);
+
+        let fields = ObjectFields {
+            key: &upload.key,
+            content_type,
+            user_metadata,
+            immutable: false,
+        };
+        self.store_object(&mut trace, bucket, fields, |tx, file_path, _, trace| {
+            let rows = trace.sql(|| -> Result<usize, StorageError> {
+                // Gone if the upload was deleted since it was read
+                let rows = tx.execute(
+                    "DELETE FROM uploads WHERE bucket_name = ?1 AND id = ?2",
+                    params![bucket, id],
+                )?;
+                if rows == 0 {
+                    return Err(StorageError::UploadNotFound(id.to_string()));
+                }
+                Ok(rows
+                    + tx.execute("DELETE FROM upload_parts WHERE upload_id = ?1", params![id])?)
+            })?;
+            trace.add_rows(rows);
+            trace.file(|| match fs::hard_link(&staging, file_path) {
+                Ok(()) => Ok(()),
+                Err(_) => fs::copy(&staging, file_path).map(|_| ()),
+            })?;
+            Ok((etag, upload.offset))
+        })?;
+        if let Err(e) = trace.file(|| fs::remove_file(&staging)) {
+            warn!(upload_id = %id, error = %e, "Failed to remove staging file of completed upload");
+        }
+        self.head_object(bucket, &upload.key)
+    }
+
+    /// Removes a resumable upload and its staging file.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket the upload targets.
+    /// * `id` - The id of the upload.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn delete_upload(&mut self, bucket: &str, id: &str) -> Result<(), StorageError> {
+        let tx = write_transaction(&mut self.conn, self.busy)?;
+        let rows_affected = tx.execute(
+            "DELETE FROM uploads WHERE bucket_name = ?1 AND id = ?2",
+            params![bucket, id],
+        )?;
+        if rows_affected == 0 {
+            return Err(StorageError::UploadNotFound(id.to_string()));
+        }
+        tx.execute("DELETE FROM upload_parts WHERE upload_id = ?1", params![id])?;
+        tx.commit()
+            .map_err(|_| StorageError::TransactionCommitError)?;
+        let path = self.upload_path(id);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// Aborts the resumable uploads not appended to since `cutoff`, or whose
+    /// bucket is gone, removing their parts and staging files.
+    ///
+    /// # Arguments
+    ///
+    /// * `cutoff` - Seconds since the Unix epoch; uploads idle since before it are aborted.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(Vec<String>, u64), StorageError>` - The IDs of the aborted uploads and
+    ///   the bytes they held, or an error.
+    pub fn abort_stale_uploads(&mut self, cutoff: i64) -> Result<(Vec<String>, u64), StorageError> {
+        let tx = write_transaction(&mut self.conn, self.busy)?;
+        let stale = {
+            let mut stmt = tx.prepare(
+                "SELECT id, upload_offset FROM uploads
+                 WHERE COALESCE(updated_at, created_at) < ?1
+                    OR bucket_name NOT IN (SELECT name FROM buckets)",
+            )?;
+            stmt.query_map(params![cutoff], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+        };
+        for (id, _) in &stale {
+            tx.execute("DELETE FROM uploads WHERE id = ?1", params![id])?;
+            tx.execute("DELETE FROM upload_parts WHERE upload_id = ?1", params![id])?;
+        }
+        tx.commit()
+            .map_err(|_| StorageError::TransactionCommitError)?;
+
+        let mut bytes = 0;
+        let mut ids = Vec::with_capacity(stale.len());
+        for (id, offset) in stale {
+            let path = self.upload_path(&id);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+            bytes += offset;
+            ids.push(id);
+        }
+        Ok((ids, bytes))
+    }
+
+    /// Lists all objects in a bucket.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to list objects from.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<String>, StorageError>` - A vector of object keys in the bucket, or an error.
+    #[instrument(
+        name = "storage.list_objects",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn list_objects(&self, bucket: &str) -> Result<Vec<String>, StorageError> {
+        let mut trace = self.trace("list_objects", bucket, "");
+        self.inject_fault("list_objects")?;
+        let object_keys = trace.sql(|| -> Result<Vec<String>, StorageError> {
+            let mut stmt = self
+                .conn
+                .prepare_cached("SELECT key FROM objects WHERE bucket_name = ?1")?;
+            let mut rows = stmt.query(params![bucket])?;
+            let mut object_keys = Vec::new();
+            while let Some(row) = rows.next()? {
+                object_keys.push(row.get(0)?);
+            }
+            Ok(object_keys)
+        })?;
+        trace.add_rows(object_keys.len());
+        Ok(object_keys)
+    }
+
+    /// Lists the objects in a bucket with their metadata, ordered by key.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to list objects from.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<ObjectInfo>, StorageError>` - The metadata of the objects, or an error.
+    #[instrument(
+        name = "storage.list_object_infos",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn list_object_infos(&self, bucket: &str) -> Result<Vec<ObjectInfo>, StorageError> {
+        let mut trace = self.trace("list_object_infos", bucket, "");
+        self.inject_fault("list_object_infos")?;
+        let infos = trace.sql(|| -> Result<Vec<ObjectInfo>, StorageError> {
+            let mut stmt = self.conn.prepare_cached(&format!(
+                "SELECT {} FROM objects WHERE bucket_name = ?1 ORDER BY key",
+                OBJECT_INFO_COLUMNS
+            ))?;
+            let infos = stmt
+                .query_map(params![bucket], object_info_from_row)?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(infos)
+        })?;
+        trace.add_rows(infos.len());
+        Ok(infos)
+    }
+
+    /// Lists the versions of the objects in a bucket: the current ones and
+    /// the noncurrent versions and delete markers of versioned buckets.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to list versions from.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<ObjectVersion>, StorageError>` - The versions, ordered by key and
+    ///   then newest first, or an error.
+    #[instrument(
+        name = "storage.list_object_versions",
+        level = "debug",
+        skip_all,
+        fields(bucket = %bucket, sql_ms, file_ms, bytes, rows)
+    )]
+    pub fn list_object_versions(&self, bucket: &str) -> Result<Vec<ObjectVersion>, StorageError> {
+        let mut trace = self.trace("list_object_versions", bucket, "");
+        self.inject_fault("list_object_versions")?;
+        let mut versions = trace.sql(|| -> Result<Vec<ObjectVersion>, StorageError> {
+            let mut stmt = self.conn.prepare_cached(
+                "SELECT key, version_id, size, content_type, etag, last_modified, 1, 0, 0
+                 FROM objects WHERE bucket_name = ?1
+                 UNION ALL
+                 SELECT key, version_id, size, content_type, etag, last_modified, 0,
+                        is_delete_marker, rowid
+                 FROM object_versions WHERE bucket_name = ?1
+                 ORDER BY 1, 7 DESC, 6 DESC, 9 DESC",
+            )?;
+            let versions = stmt
+                .query_map(params![bucket], |row| {
+                    Ok(ObjectVersion {
+                        key: row.get(0)?,
+                        version_id: row.get(1)?,
+                        size: row.get::<_, Option<i64>>(2)?.unwrap_or(0).max(0) as u64,
+                        content_type: row.get(3)?,
+                        etag: row.get(4)?,
+                        last_modified: row.get(5)?,
+                        is_latest: row.get(6)?,
+                        is_delete_marker: row.get(7)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(versions)
+        })?;
+        // A deleted object's newest delete marker is its latest version
+        for i in 0..versions.len() {
+            if i == 0 || versions[i - 1].key != versions[i].key {
+                versions[i].is_latest = true;
+            }
+        }
+        trace.add_rows(versions.len());
+        Ok(versions)
+    }
+
+    /// Lists the user metadata of the objects in a bucket that have any.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to list metadata from.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<HashMap<String, HashMap<String, String>>, StorageError>` - The metadata by
+    ///   object key, or an error.
+    pub fn list_object_metadata(
+        &self,
+        bucket: &str,
+    ) -> Result<HashMap<String, HashMap<String, String>>, StorageError> {
+        let mut trace = self.trace("list_object_metadata", bucket, "");
+        self.inject_fault("list_object_metadata")?;
+        let metadata = trace.sql(|| -> Result<Vec<(String, String)>, StorageError> {
+            let mut stmt = self.conn.prepare_cached(
+                "SELECT key, metadata FROM objects
+                 WHERE bucket_name = ?1 AND metadata IS NOT NULL",
+            )?;
+            let rows = stmt
+                .query_map(params![bucket], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })?;
+        trace.add_rows(metadata.len());
+        metadata
+            .into_iter()
+            .map(|(key, json)| Ok((key, serde_json::from_str(&json)?)))
+            .collect()
+    }
+
+    /// Lists the metadata of all objects in a bucket with their checksums in
+    /// the configured hash algorithm. Stored ETags are reused where they were
+    /// computed with it; objects written before the algorithm changed are
+    /// hashed from their files, and get no checksum if that can't be read.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to list objects from.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<(ObjectInfo, Option<String>)>, StorageError>` - The metadata of the
+    ///   objects, ordered by key, and their checksums, or an error.
+    pub fn list_object_checksums(
+        &self,
+        bucket: &str,
+    ) -> Result<Vec<(ObjectInfo, Option<String>)>, StorageError> {
+        let mut trace = self.trace("list_object_checksums", bucket, "");
+        self.inject_fault("list_object_checksums")?;
+        let rows = trace.sql(|| -> Result<Vec<_>, StorageError> {
+            let mut stmt = self.conn.prepare_cached(&format!(
+                "SELECT {}, file_path, hash_algorithm FROM objects
+                 WHERE bucket_name = ?1 ORDER BY key",
+                OBJECT_INFO_COLUMNS
+            ))?;
+            let rows = stmt
+                .query_map(params![bucket], |row| {
+                    Ok((
+                        object_info_from_row(row)?,
+                        row.get::<_, String>(9)?,
+                        row.get::<_, String>(10)?,
+                    ))
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(rows)
+        })?;
+        trace.add_rows(rows.len());
+
+        let algorithm = self.etags.algorithm();
+        let checksums = rows
+            .into_iter()
+            .map(|(info, file_path, hash_algorithm)| {
+                let checksum = if hash_algorithm == algorithm {
+                    info.etag.clone()
+                } else {
+                    trace
+                        .file(|| fs::read(&file_path))
+                        .ok()
+                        .map(|data| self.etags.etag(&data))
+                };
+                (info, checksum)
+            })
+            .collect();
+        Ok(checksums)
+    }
+
+    /// Checks if a bucket is empty.
+    ///
+    /// # Arguments
+    ///
+    /// * `bucket` - The name of the bucket to check.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<bool, StorageError>` - A boolean indicating whether the bucket is empty, or an error.
+    pub fn _is_empty(&self, bucket: &str) -> Result<bool, StorageError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT COUNT(*) FROM objects WHERE bucket_name = ?1")?;
+        let count: i64 = stmt.query_row(params![bucket], |row| row.get(0))?;
+        Ok(count == 0)
+    }
+
+    /// Checks the records against the data directories, re-hashing every
+    /// object. With `repair`, records of missing files are dropped and
+    /// orphaned files are moved to `lost+found` in their data directory.
+    /// Corrupt objects are only reported, as their data cannot be recovered.
+    pub fn fsck(&mut self, repair: bool) -> Result<FsckReport, StorageError> {
+        let roots = self.roots.roots().to_vec();
+        let uploads_dir = self.base_path.join("uploads");
+        let etags = self.etags.clone();
+        let tx = write_transaction(&mut self.conn, self.busy)?;
+        let mut report = FsckReport {
+            repaired: repair,
+            ..FsckReport::default()
+        };
+        let mut referenced = HashSet::new();
+
+        {
+            let mut stmt = tx.prepare(
+                "SELECT bucket_name, key, file_path, etag, NULL, hash_algorithm FROM objects
+                 UNION ALL
+                 SELECT bucket_name, key, file_path, etag, version_id, hash_algorithm
+                 FROM object_versions WHERE file_path IS NOT NULL",
+            )?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let bucket: String = row.get(0)?;
+                let key: String = row.get(1)?;
+                let file_path: String = row.get(2)?;
+                let etag: Option<String> = row.get(3)?;
+                let version_id: Option<String> = row.get(4)?;
+                let hash_algorithm: String = row.get(5)?;
+
+                let name = match &version_id {
+                    Some(version_id) => format!("{}/{}?versionId={}", bucket, key, version_id),
+                    None => format!("{}/{}", bucket, key),
+                };
+                let path = PathBuf::from(&file_path);
+                if !path.exists() {
+                    if repair {
+                        match &version_id {
+                            Some(version_id) => tx.execute(
+                                "DELETE FROM object_versions
+                                 WHERE bucket_name = ?1 AND key = ?2 AND version_id = ?3",
+                                params![bucket, key, version_id],
+                            )?,
+                            None => tx.execute(
+                                "DELETE FROM objects WHERE bucket_name = ?1 AND key = ?2",
+                                params![bucket, key],
+                            )?,
+                        };
+                    }
+                    report.missing_files.push(name);
+                    continue;
+                }
+                if let Some(etag) = etag
+                    && calculate_etag(&*etags, &fs::read(&path)?, &hash_algorithm)? != etag
+                {
+                    report.corrupt_objects.push(name);
+                }
+                referenced.insert(path);
+            }
+
+            let mut stmt = tx.prepare("SELECT id, bucket_name, key FROM uploads")?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let id: String = row.get(0)?;
+                let path = uploads_dir.join(&id);
+                if !path.exists() {
+                    if repair {
+                        tx.execute("DELETE FROM uploads WHERE id = ?1", params![id])?;
+                        tx.execute("DELETE FROM upload_parts WHERE upload_id = ?1", params![id])?;
+                    }
+                    report.missing_files.push(format!(
+                        "{}/{}?uploadId={}",
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        id
+                    ));
+                    continue;
+                }
+                referenced.insert(path);
+            }
+        }
+
+        for root in &roots {
+            let lost_found = root.join("lost+found");
+            let mut files = Vec::new();
+            collect_files(root, &mut files)?;
+            for file in files {
+                if referenced.contains(&file) || file.starts_with(&lost_found) {
+                    continue;
+                }
+                if repair {
+                    let relative = file.strip_prefix(root).unwrap_or(&file);
+                    let target = lost_found.join(relative);
+                    if let Some(parent) = target.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    move_file(&file, &target)?;
+                }
+                report.orphaned_files.push(file.display().to_string());
+            }
+        }
+
+        for (table, rowid, parent) in dangling_rows(&tx)? {
+            if repair {
+                tx.execute(
+                    &format!("DELETE FROM {} WHERE rowid = ?1", table),
+                    params![rowid],
+                )?;
+            }
+            report
+                .dangling_rows
+                .push(format!("{} row {} (no {})", table, rowid, parent));
+        }
+
+        tx.commit()
+            .map_err(|_| StorageError::TransactionCommitError)?;
+        Ok(report)
+    }
+
+    /// Counts the objects and versions whose checksum uses another algorithm
+    /// than the configured one.
+    pub fn count_rehash_pending(&self) -> Result<u64, StorageError> {
+        let count: i64 = self.conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM objects WHERE hash_algorithm != ?1)
+                  + (SELECT COUNT(*) FROM object_versions
+                     WHERE hash_algorithm != ?1 AND file_path IS NOT NULL)",
+            params![self.etags.algorithm()],
+            |row| row.get(0),
+        )?;
+        Ok(count as u64)
+    }
+
+    /// Re-hashes up to `limit` objects and versions with the configured
+    /// algorithm, continuing after `cursor` (start with `(-1, -1)`). Each file
+    /// is first checked against its old checksum so corruption is not blessed
+    /// with a fresh one.
+    pub fn rehash_batch(
+        &mut self,
+        cursor: (i64, i64),
+        limit: usize,
+    ) -> Result<RehashBatch, StorageError> {
+        let etags = self.etags.clone();
+        let tx = write_transaction(&mut self.conn, self.busy)?;
+        let rows = {
+            let mut stmt = tx.prepare(
+                "SELECT t, id, bucket_name, key, version_id, file_path, etag, hash_algorithm FROM (
+                     SELECT 0 AS t, rowid AS id, bucket_name, key, NULL AS version_id, file_path,
+                            etag, hash_algorithm
+                     FROM objects
+                     UNION ALL
+                     SELECT 1, rowid, bucket_name, key, version_id, file_path, etag, hash_algorithm
+                     FROM object_versions WHERE file_path IS NOT NULL
+                 )
+                 WHERE hash_algorithm != ?1 AND (t, id) > (?2, ?3)
+                 ORDER BY t, id LIMIT ?4",
+            )?;
+            stmt.query_map(
+                params![etags.algorithm(), cursor.0, cursor.1, limit as i64],
+                |row| {
+                    Ok((
+                        (row.get::<_, i64>(0)?, row.get::<_, i64>(1)?),
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, Option<String>>(4)?,
+                        row.get::<_, String>(5)?,
+                        row.get::<_, Option<String>>(6)?,
+                        row.get::<_, String>(7)?,
+                    ))
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()?
+        };
+
+        let mut batch = RehashBatch {
+            visited: rows.len() as u64,
+            cursor: (rows.len() == limit)
+                .then(|| rows.last().map(|row| row.0))
+                .flatten(),
+            ..RehashBatch::default()
+        };
+        for ((table, rowid), bucket, key, version_id, file_path, etag, old_algorithm) in rows {
+            let name = match &version_id {
+                Some(version_id) => format!("{}/{}?versionId={}", bucket, key, version_id),
+                None => format!("{}/{}", bucket, key),
+            };
+            let Ok(data) = fs::read(&file_path) else {
+                batch.missing.push(name);
+                continue;
+            };
+            if let Some(etag) = etag
+                && calculate_etag(&*etags, &data, &old_algorithm)? != etag
+            {
+                batch.mismatched.push(name);
+                continue;
+            }
+            let table = if table == 0 {
+                "objects"
+            } else {
+                "object_versions"
+            };
+            tx.execute(
+                &format!(
+                    "UPDATE {} SET etag = ?1, hash_algorithm = ?2 WHERE rowid = ?3",
+                    table
+                ),
+                params![etags.etag(&data), etags.algorithm(), rowid],
+            )?;
+            batch.rehashed += 1;
+        }
+
+        tx.commit()
+            .map_err(|_| StorageError::TransactionCommitError)?;
+        Ok(batch)
+    }
+
+    /// Checks the consistency of the storage.
+    ///
+    /// # Returns
+    ///
+    /// * `Result<(), StorageError>` - An empty result, or an error.
+    pub fn check_consistency(&mut self) -> Result<(), StorageError> {
+        match self.verify_integrity(VerifyMode::Full)?.into_iter().next() {
+            Some(problem) => Err(StorageError::ConsistencyError(problem)),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks every object against its file and returns a description of
+    /// each problem found; an empty list means the store is consistent.
+    pub fn verify_integrity(&mut self, mode: VerifyMode) -> Result<Vec<String>, StorageError> {
+        let etags = self.etags.clone();
+        let tx = self.conn.transaction()?;
+        let mut problems = Vec::new();
+
+        // Check all objects have corresponding files
+        let mut stmt =
+            tx.prepare("SELECT bucket_name, key, file_path, etag, hash_algorithm FROM objects")?;
+
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let bucket: String = row.get(0)?;
+            let key: String = row.get(1)?;
+            let file_path: String = row.get(2)?;
+            let expected_etag: String = row.get(3)?;
+            let hash_algorithm: String = row.get(4)?;
+
+            // Verify file exists
+            if !Path::new(&file_path).exists() {
+                problems.push(format!(
                     "File not found for {}/{} at path {}",
                     bucket, key, file_path
-                )));
+                ));
+                continue;
             }
 
             // Verify ETag matches
-            let data = fs::read(&file_path)?;
-            let actual_etag = calculate_etag(&data);
-            if actual_etag != expected_etag {
-                return Err(StorageError::ConsistencyError(format!(
-                    "ETag mismatch for {}/{} - possible data corruption",
-                    bucket, key
-                )));
+            if mode == VerifyMode::Full {
+                let data = fs::read(&file_path)?;
+                let actual_etag = calculate_etag(&*etags, &data, &hash_algorithm)?;
+                if actual_etag != expected_etag {
+                    problems.push(format!(
+                        "ETag mismatch for {}/{} - possible data corruption",
+                        bucket, key
+                    ));
+                }
             }
         }
 
-        Ok(())
+        Ok(problems)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::acl::Permission;
+    use crate::clock::ManualClock;
+
+    /// A storage in a temporary directory, removed when the first is dropped.
+    fn temp_storage() -> (tempfile::TempDir, Storage) {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::open(
+            &dir.path().join("s3_storage.db").to_string_lossy(),
+            dir.path().join("data"),
+        )
+        .unwrap();
+        (dir, storage)
+    }
+
+    /// Puts an object with `data` under `key`.
+    fn put(storage: &mut Storage, bucket: &str, key: &str, data: &[u8]) {
+        let object = Object::new(key.to_string(), data.to_vec(), None, None).unwrap();
+        storage.put_object(bucket, object).unwrap();
+    }
+
+    /// Makes the commit of every transaction running `sql` (e.g. `INSERT` or
+    /// `UPDATE`) on `objects` fail, as a deferred constraint it violates is
+    /// only checked then.
+    fn fail_object_commits(storage: &Storage, sql: &str) {
+        storage
+            .conn
+            .execute_batch(&format!(
+                "CREATE TEMP TABLE fail_parent (id INTEGER PRIMARY KEY);
+                 CREATE TEMP TABLE fail_child (parent INTEGER
+                     REFERENCES fail_parent (id) DEFERRABLE INITIALLY DEFERRED);
+                 CREATE TEMP TRIGGER fail_object_commits AFTER {sql} ON objects
+                 BEGIN INSERT INTO fail_child VALUES (1); END;"
+            ))
+            .unwrap();
+    }
+
+    /// Lets commits after writes to `objects` through again.
+    fn allow_object_commits(storage: &Storage) {
+        storage
+            .conn
+            .execute_batch(
+                "DROP TRIGGER temp.fail_object_commits;
+                 DROP TABLE temp.fail_child;
+                 DROP TABLE temp.fail_parent;",
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_data_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let data_dir = dir.path().join("data");
+
+        assert!(check_data_dir(&data_dir, &db_path, false).is_err());
+        check_data_dir(&data_dir, &db_path, true).unwrap();
+        assert!(check_data_dir(dir.path(), &db_path, false).is_err());
+        let other_db = dir.path().join("other.db");
+        assert!(check_data_dir(&other_db.join("data"), &other_db, true).is_err());
+
+        let db_path = db_path.to_string_lossy();
+        drop(Storage::open(&db_path, &data_dir).unwrap());
+        drop(Storage::open(&db_path, dir.path().join(".").join("data")).unwrap());
+        assert!(matches!(
+            Storage::open(&db_path, dir.path().join("other")),
+            Err(StorageError::DataDirMismatch(..))
+        ));
+    }
+
+    #[test]
+    fn test_shares() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let clock = Arc::new(crate::clock::ManualClock::at_unix_secs(1_000));
+        let mut storage = Storage::open(&db_path.to_string_lossy(), dir.path().join("data"))
+            .unwrap()
+            .with_clock(clock.clone());
+        storage.create_bucket("b").unwrap();
+        let object = Object::new("k".to_string(), b"hi".to_vec(), None, None).unwrap();
+        storage.put_object("b", object).unwrap();
+
+        assert!(matches!(
+            storage.create_share("b", "missing", "i0", "t0", Some(1), None),
+            Err(StorageError::ObjectNotFound(_, _))
+        ));
+        storage
+            .create_share("b", "k", "i1", "t1", Some(2), None)
+            .unwrap();
+        assert_eq!(storage.claim_share("t1").unwrap().downloads, 1);
+        assert_eq!(storage.claim_share("t1").unwrap().downloads, 2);
+        assert!(matches!(
+            storage.claim_share("t1"),
+            Err(StorageError::ShareExpired)
+        ));
+        // Used-up shares are no longer outstanding
+        assert!(storage.list_shares("b").unwrap().is_empty());
+
+        let share = storage
+            .create_share("b", "k", "i2", "t2", None, Some(Duration::from_secs(60)))
+            .unwrap();
+        assert_eq!(share.expires_at, Some(1_060));
+        storage.claim_share("t2").unwrap();
+        let listed = storage.list_shares("b").unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].id, "i2");
+
+        // A revoked share stops working at once
+        storage
+            .create_share("b", "k", "i4", "t4", Some(5), None)
+            .unwrap();
+        storage.revoke_share("b", "i4").unwrap();
+        assert!(matches!(
+            storage.claim_share("t4"),
+            Err(StorageError::ShareNotFound)
+        ));
+        assert!(matches!(
+            storage.revoke_share("b", "i4"),
+            Err(StorageError::ShareNotFound)
+        ));
+
+        clock.advance(Duration::from_secs(60));
+        assert!(matches!(
+            storage.claim_share("t2"),
+            Err(StorageError::ShareExpired)
+        ));
+        assert!(matches!(
+            storage.claim_share("t3"),
+            Err(StorageError::ShareNotFound)
+        ));
+    }
+
+    #[test]
+    fn test_dangling_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let db_path = db_path.to_string_lossy();
+        let data = dir.path().join("data");
+        let mut storage = Storage::open(&db_path, data.clone()).unwrap();
+        storage.create_bucket("b").unwrap();
+        drop(storage);
+
+        // As left by deletions while foreign keys were off
+        let conn = Connection::open(&*db_path).unwrap();
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute(
+            "INSERT INTO bucket_roles (bucket_name, principal, role) VALUES ('gone', 'bob', 'reader')",
+            [],
+        )
+        .unwrap();
+        drop(conn);
+        assert!(matches!(
+            Storage::open(&db_path, data.clone()),
+            Err(StorageError::DanglingRows(rows)) if rows == "1 in bucket_roles"
+        ));
+
+        let mut storage = Storage::open_for_fsck(&db_path, data.clone()).unwrap();
+        let report = storage.fsck(true).unwrap();
+        assert_eq!(report.dangling_rows.len(), 1);
+        drop(storage);
+        Storage::open(&db_path, data).unwrap();
+    }
+
+    #[test]
+    fn test_open_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = Storage::open(
+            &dir.path().join("s3_storage.db").to_string_lossy(),
+            dir.path().join("data"),
+        )
+        .unwrap();
+        storage.create_bucket("b").unwrap();
+        let object = Object::new("k".to_string(), b"hello".to_vec(), None, None).unwrap();
+        storage.put_object("b", object).unwrap();
+
+        let read = |storage: &Storage| {
+            let mut reader = storage.open_object("b", "k")?;
+            let mut data = Vec::new();
+            std::io::Read::read_to_end(&mut reader.file, &mut data)?;
+            let mut check = reader.check.take().unwrap();
+            check.update(&data);
+            Ok::<_, StorageError>((data, check.matches()))
+        };
+        assert_eq!(read(&storage).unwrap(), (b"hello".to_vec(), true));
+
+        let file_path: String = storage
+            .conn
+            .query_row("SELECT file_path FROM objects", [], |row| row.get(0))
+            .unwrap();
+        fs::write(&file_path, b"jello").unwrap();
+        assert_eq!(read(&storage).unwrap(), (b"jello".to_vec(), false));
+        fs::write(&file_path, b"hell").unwrap();
+        assert!(matches!(
+            read(&storage),
+            Err(StorageError::IntegrityError(_))
+        ));
+        assert!(matches!(
+            storage.open_object("b", "missing"),
+            Err(StorageError::ObjectNotFound(..))
+        ));
+    }
+
+    #[test]
+    fn test_busy_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let busy = BusyConfig {
+            timeout_ms: 10,
+            retries: 2,
+            backoff_ms: 1,
+        };
+        let mut storage = Storage::open(&db_path.to_string_lossy(), dir.path().join("data"))
+            .unwrap()
+            .with_busy(busy)
+            .unwrap();
+
+        // Another connection, e.g. the CLI, holding the write lock
+        let other = Connection::open(&db_path).unwrap();
+        other.execute_batch("BEGIN IMMEDIATE").unwrap();
+        let err = storage.create_bucket("b").unwrap_err();
+        assert!(err.is_busy(), "{}", err);
+
+        other.execute_batch("ROLLBACK").unwrap();
+        storage.create_bucket("b").unwrap();
+    }
+
+    #[test]
+    fn test_busy_retries_pause_briefly() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        // Pauses of up to minutes, were they not capped
+        let busy = BusyConfig {
+            timeout_ms: 100,
+            retries: 10,
+            backoff_ms: 60_000,
+        };
+        let mut storage = Storage::open(&db_path.to_string_lossy(), dir.path().join("data"))
+            .unwrap()
+            .with_busy(busy)
+            .unwrap();
+
+        let other = Connection::open(&db_path).unwrap();
+        other.execute_batch("BEGIN IMMEDIATE").unwrap();
+        let started = Instant::now();
+        assert!(storage.create_bucket("b").unwrap_err().is_busy());
+        // At most eleven busy timeouts of 100ms and 25ms of pauses
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn test_delete_bucket_removes_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let data = dir.path().join("data");
+        let mut storage = Storage::open(&db_path.to_string_lossy(), data.clone()).unwrap();
+        for bucket in ["team", "team/b"] {
+            storage.create_bucket(bucket).unwrap();
+            let object = Object::new("k".to_string(), b"hi".to_vec(), None, None).unwrap();
+            storage.put_object(bucket, object).unwrap();
+        }
+        let files = |dir: &Path| {
+            let mut files = Vec::new();
+            collect_files(dir, &mut files).unwrap();
+            files.len()
+        };
+        assert_eq!(files(&data.join("buckets")), 2);
+
+        // The files of namespaced buckets below are left alone
+        storage._delete_bucket("team").unwrap();
+        assert_eq!(files(&data.join("buckets")), 1);
+        assert_eq!(files(&data.join("buckets/team/b")), 1);
+        assert!(storage.list_object_infos("team").unwrap().is_empty());
+
+        storage._delete_bucket("team/b").unwrap();
+        assert!(!data.join("buckets/team").exists());
+    }
+
+    #[test]
+    fn test_delete_bucket_keeps_held_objects() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        put(&mut storage, "b", "k", b"hi");
+        storage.set_object_legal_hold("b", "k", true).unwrap();
+
+        assert!(matches!(
+            storage._delete_bucket("b"),
+            Err(StorageError::ObjectUnderLegalHold(key, _)) if key == "k"
+        ));
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"hi");
+
+        storage.set_object_legal_hold("b", "k", false).unwrap();
+        storage._delete_bucket("b").unwrap();
+    }
+
+    #[test]
+    fn test_legal_hold_blocks_changes() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        storage.create_bucket("source").unwrap();
+        put(&mut storage, "b", "k", b"held");
+        put(&mut storage, "source", "k", b"copy");
+        storage.set_object_legal_hold("b", "k", true).unwrap();
+        assert!(storage.get_object_legal_hold("b", "k").unwrap());
+
+        fn held<T>(result: Result<T, StorageError>) -> bool {
+            matches!(result, Err(StorageError::ObjectUnderLegalHold(key, bucket)) if key == "k" && bucket == "b")
+        }
+        let object = || Object::new("k".to_string(), b"new".to_vec(), None, None).unwrap();
+        let range = ContentRange {
+            first: 0,
+            last: 1,
+            complete_length: None,
+        };
+        assert!(held(storage.put_object("b", object())));
+        assert!(held(storage.patch_object("b", "k", &range, b"HE")));
+        assert!(held(storage.delete_object("b", "k")));
+        let batch = storage
+            .copy_prefix_batch("source", "b", "", "", 10)
+            .unwrap();
+        assert_eq!(batch.skipped, vec!["k".to_string()]);
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"held");
+
+        // Releasing the hold lets each of them through again
+        storage.set_object_legal_hold("b", "k", false).unwrap();
+        let batch = storage
+            .copy_prefix_batch("source", "b", "", "", 10)
+            .unwrap();
+        assert_eq!(batch.copied, vec!["k".to_string()]);
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"copy");
+        storage.patch_object("b", "k", &range, b"CO").unwrap();
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"COpy");
+        storage.put_object("b", object()).unwrap();
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"new");
+        assert!(storage.delete_object("b", "k").unwrap());
+    }
+
+    #[test]
+    fn test_failed_patch_keeps_the_object() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        put(&mut storage, "b", "k", b"copy");
+        let range = ContentRange {
+            first: 0,
+            last: 1,
+            complete_length: None,
+        };
+
+        fail_object_commits(&storage, "UPDATE");
+        assert!(matches!(
+            storage.patch_object("b", "k", &range, b"CO"),
+            Err(StorageError::TransactionCommitError)
+        ));
+        allow_object_commits(&storage);
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"copy");
+
+        storage.patch_object("b", "k", &range, b"CO").unwrap();
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"COpy");
+    }
+
+    #[test]
+    fn test_failed_prefix_copy_keeps_the_destination() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("source").unwrap();
+        storage.create_bucket("b").unwrap();
+        put(&mut storage, "source", "k", b"copy");
+        put(&mut storage, "b", "k", b"kept");
+
+        fail_object_commits(&storage, "INSERT");
+        assert!(matches!(
+            storage.copy_prefix_batch("source", "b", "", "", 10),
+            Err(StorageError::TransactionCommitError)
+        ));
+        allow_object_commits(&storage);
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"kept");
+
+        let batch = storage
+            .copy_prefix_batch("source", "b", "", "", 10)
+            .unwrap();
+        assert_eq!(batch.copied, vec!["k".to_string()]);
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"copy");
+    }
+
+    #[test]
+    fn test_failed_transition_keeps_the_object() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        let rule = LifecycleRule {
+            prefix: String::new(),
+            days: 30,
+            storage_class: StorageClass::Cold,
+        };
+        storage.set_bucket_lifecycle("b", &[rule]).unwrap();
+        put(&mut storage, "b", "k", b"hello");
+        let now = storage.clock().unix_secs().unwrap() + 30 * 86400;
+
+        fail_object_commits(&storage, "UPDATE");
+        assert!(matches!(
+            storage.transition_objects(now),
+            Err(StorageError::TransactionCommitError)
+        ));
+        allow_object_commits(&storage);
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"hello");
+        let info = storage.head_object("b", "k").unwrap();
+        assert_eq!(info.storage_class, StorageClass::Standard);
+
+        assert_eq!(storage.transition_objects(now).unwrap(), (1, 5));
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"hello");
+        let info = storage.head_object("b", "k").unwrap();
+        assert_eq!(info.storage_class, StorageClass::Cold);
+    }
+
+    #[test]
+    fn test_most_specific_transition_rule_wins() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        let rule = |prefix: &str, days, storage_class| LifecycleRule {
+            prefix: prefix.to_string(),
+            days,
+            storage_class,
+        };
+        let rules = [
+            rule("", 30, StorageClass::Cold),
+            rule("keep/", 60, StorageClass::Cold),
+            rule("keep/", 1, StorageClass::Standard),
+        ];
+        storage.set_bucket_lifecycle("b", &rules).unwrap();
+        put(&mut storage, "b", "keep/a", b"hot");
+        put(&mut storage, "b", "other", b"cold");
+        let now = storage.clock().unix_secs().unwrap() + 90 * 86400;
+
+        // "keep/" beats "", and of the two "keep/" rules the 1 day one
+        assert_eq!(storage.transition_objects(now).unwrap(), (1, 4));
+        let class = |storage: &Storage, key| storage.head_object("b", key).unwrap().storage_class;
+        assert_eq!(class(&storage, "keep/a"), StorageClass::Standard);
+        assert_eq!(class(&storage, "other"), StorageClass::Cold);
+        assert_eq!(storage.transition_objects(now).unwrap(), (0, 0));
+    }
+
+    /// The files of the noncurrent versions of `key`, `None` for delete markers.
+    fn version_files(storage: &Storage, bucket: &str, key: &str) -> Vec<Option<String>> {
+        let mut stmt = storage
+            .conn
+            .prepare(
+                "SELECT file_path FROM object_versions
+                 WHERE bucket_name = ?1 AND key = ?2 ORDER BY rowid",
+            )
+            .unwrap();
+        stmt.query_map(params![bucket, key], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_failed_overwrite_keeps_the_object() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        put(&mut storage, "b", "k", b"one");
+
+        fail_object_commits(&storage, "INSERT");
+        let object = Object::new("k".to_string(), b"two".to_vec(), None, None).unwrap();
+        assert!(matches!(
+            storage.put_object("b", object),
+            Err(StorageError::TransactionCommitError)
+        ));
+        allow_object_commits(&storage);
+
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"one");
+        put(&mut storage, "b", "k", b"two");
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"two");
+    }
+
+    #[test]
+    fn test_compose_object() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        put(&mut storage, "b", "a", b"ab");
+        put(&mut storage, "b", "c", b"cd");
+        let sources = |keys: &[&str]| keys.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+
+        let file_path = |storage: &Storage, key: &str| -> String {
+            storage
+                .conn
+                .query_row(
+                    "SELECT file_path FROM objects WHERE key = ?1",
+                    [key],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+
+        // The destination may be a source; the old file goes after the commit
+        let old_path = file_path(&storage, "a");
+        let info = storage
+            .compose_object(
+                "b",
+                "a",
+                &sources(&["a", "c", "a"]),
+                Some("text/plain"),
+                None,
+            )
+            .unwrap();
+        assert_eq!(info.size, 6);
+        assert_eq!(info.etag, Some(HashAlgorithm::Md5.etag(b"abcdab")));
+        assert_eq!(storage.get_object("b", "a").unwrap().data, b"abcdab");
+        assert!(!Path::new(&old_path).exists());
+
+        assert!(matches!(
+            storage.compose_object("b", "new", &sources(&["c", "missing"]), None, None),
+            Err(StorageError::ObjectNotFound(key, _)) if key == "missing"
+        ));
+        assert!(storage.head_object("b", "new").is_err());
+
+        // A corrupt source fails the compose instead of spreading
+        fs::write(file_path(&storage, "c"), b"xx").unwrap();
+        assert!(matches!(
+            storage.compose_object("b", "new", &sources(&["c"]), None, None),
+            Err(StorageError::IntegrityError(_))
+        ));
+        assert!(storage.head_object("b", "new").is_err());
+    }
+
+    #[test]
+    fn test_versioned_overwrite_and_delete() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        storage
+            .set_bucket_versioning("b", VersioningStatus::Enabled)
+            .unwrap();
+        put(&mut storage, "b", "k", b"one");
+        put(&mut storage, "b", "k", b"two");
+
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"two");
+        let versions = version_files(&storage, "b", "k");
+        assert_eq!(versions.len(), 1);
+        let archived = versions[0].clone().unwrap();
+        assert_eq!(fs::read(&archived).unwrap(), b"one");
+
+        // Deleting keeps the current version and records a delete marker
+        assert!(storage.delete_object("b", "k").unwrap());
+        assert!(matches!(
+            storage.get_object("b", "k"),
+            Err(StorageError::ObjectNotFound(..))
+        ));
+        let versions = version_files(&storage, "b", "k");
+        assert_eq!(versions.len(), 3);
+        assert_eq!(versions[2], None);
+        for file in versions.iter().flatten() {
+            assert!(Path::new(file).exists());
+        }
+    }
+
+    #[test]
+    fn test_list_object_versions() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        storage
+            .set_bucket_versioning("b", VersioningStatus::Enabled)
+            .unwrap();
+        put(&mut storage, "b", "k", b"one");
+        put(&mut storage, "b", "k", b"three");
+        put(&mut storage, "b", "other", b"");
+
+        let versions = storage.list_object_versions("b").unwrap();
+        let summary: Vec<_> = versions
+            .iter()
+            .map(|v| (v.key.as_str(), v.size, v.is_latest, v.is_delete_marker))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("k", 5, true, false),
+                ("k", 3, false, false),
+                ("other", 0, true, false)
+            ]
+        );
+
+        // Once deleted, the delete marker is the latest version
+        storage.delete_object("b", "k").unwrap();
+        let versions = storage.list_object_versions("b").unwrap();
+        let summary: Vec<_> = versions
+            .iter()
+            .map(|v| (v.key.as_str(), v.size, v.is_latest, v.is_delete_marker))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("k", 0, true, true),
+                ("k", 5, false, false),
+                ("k", 3, false, false),
+                ("other", 0, true, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_archiving_replaces_the_null_version() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        let versioning = |storage: &mut Storage, status| {
+            storage.set_bucket_versioning("b", status).unwrap();
+        };
+        put(&mut storage, "b", "k", b"one");
+        versioning(&mut storage, VersioningStatus::Enabled);
+        put(&mut storage, "b", "k", b"two");
+        let null_version = version_files(&storage, "b", "k")[0].clone().unwrap();
+        assert_eq!(fs::read(&null_version).unwrap(), b"one");
+
+        // Written while suspended, "three" is the "null" version again...
+        versioning(&mut storage, VersioningStatus::Suspended);
+        put(&mut storage, "b", "k", b"three");
+        versioning(&mut storage, VersioningStatus::Enabled);
+        // ...so archiving it replaces the archived "one", whose file goes
+        put(&mut storage, "b", "k", b"four");
+        assert!(!Path::new(&null_version).exists());
+        let data: Vec<Vec<u8>> = version_files(&storage, "b", "k")
+            .iter()
+            .flatten()
+            .map(|file| fs::read(file).unwrap())
+            .collect();
+        assert_eq!(data.len(), 2);
+        assert!(data.contains(&b"two".to_vec()) && data.contains(&b"three".to_vec()));
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"four");
+    }
+
+    #[test]
+    fn test_restore_bucket() {
+        let (_dir, storage) = temp_storage();
+        let clock = Arc::new(ManualClock::at_unix_secs(1000));
+        let mut storage = storage.with_clock(clock.clone());
+        storage.create_bucket("b").unwrap();
+        assert!(matches!(
+            storage.restore_bucket("b", 1000),
+            Err(StorageError::NoVersionHistory(_))
+        ));
+        storage
+            .set_bucket_versioning("b", VersioningStatus::Enabled)
+            .unwrap();
+        put(&mut storage, "b", "kept", b"same");
+        put(&mut storage, "b", "changed", b"one");
+        put(&mut storage, "b", "removed", b"gone");
+
+        clock.advance(Duration::from_secs(1000));
+        put(&mut storage, "b", "changed", b"two");
+        put(&mut storage, "b", "added", b"new");
+        storage.delete_object("b", "removed").unwrap();
+
+        clock.advance(Duration::from_secs(1000));
+        let report = storage.restore_bucket("b", 1500).unwrap();
+        assert_eq!(report.restored, vec!["changed", "removed"]);
+        assert_eq!(report.deleted, vec!["added"]);
+        assert_eq!(report.unchanged, 1);
+        assert_eq!(storage.get_object("b", "changed").unwrap().data, b"one");
+        assert_eq!(storage.get_object("b", "removed").unwrap().data, b"gone");
+        assert!(storage.get_object("b", "added").is_err());
+
+        // The restore is itself history, so it can be undone
+        clock.advance(Duration::from_secs(1000));
+        let report = storage.restore_bucket("b", 2500).unwrap();
+        assert_eq!(report.restored, vec!["added", "changed"]);
+        assert_eq!(report.deleted, vec!["removed"]);
+        assert_eq!(storage.get_object("b", "changed").unwrap().data, b"two");
+    }
+
+    #[test]
+    fn test_write_once_bucket() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        put(&mut storage, "b", "before", b"one");
+        assert!(!storage.get_bucket_worm("b").unwrap());
+        storage.set_bucket_worm("b", true).unwrap();
+        assert!(storage.get_bucket_worm("b").unwrap());
+
+        // New keys are written once, existing ones can no longer change
+        put(&mut storage, "b", "new", b"two");
+        fn immutable<T>(result: Result<T, StorageError>) -> bool {
+            matches!(result, Err(StorageError::ObjectImmutable(..)))
+        }
+        for key in ["before", "new"] {
+            let object = Object::new(key.to_string(), b"x".to_vec(), None, None).unwrap();
+            assert!(immutable(storage.put_object("b", object)));
+            assert!(immutable(storage.delete_object("b", key)));
+        }
+        assert_eq!(storage.get_object("b", "before").unwrap().data, b"one");
+
+        assert!(matches!(
+            storage.set_bucket_worm("b", false),
+            Err(StorageError::BucketImmutable(..))
+        ));
+        storage.set_bucket_worm("b", true).unwrap();
+        assert!(matches!(
+            storage.get_bucket_worm("missing"),
+            Err(StorageError::BucketNotFoundInStorage(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify_object() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        put(&mut storage, "b", "k", b"hello");
+        let verification = storage.verify_object("b", "k", false).unwrap();
+        assert!(verification.valid && !verification.repaired);
+        assert_eq!(verification.stored_etag, Some(verification.actual_etag));
+
+        let file_path: String = storage
+            .conn
+            .query_row(
+                "SELECT file_path FROM objects WHERE bucket_name = 'b' AND key = 'k'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        fs::write(&file_path, b"corrupted").unwrap();
+        let verification = storage.verify_object("b", "k", false).unwrap();
+        assert!(!verification.valid && !verification.repaired);
+        // Without repair the stored ETag stays as it was
+        assert!(!storage.verify_object("b", "k", false).unwrap().valid);
+
+        let verification = storage.verify_object("b", "k", true).unwrap();
+        assert!(!verification.valid && verification.repaired);
+        assert!(storage.verify_object("b", "k", false).unwrap().valid);
+        assert_eq!(storage.head_object("b", "k").unwrap().size, 9);
+
+        assert!(matches!(
+            storage.verify_object("b", "missing", false),
+            Err(StorageError::ObjectNotFound(..))
+        ));
+    }
+
+    #[test]
+    fn test_rehash_batches() {
+        let (dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        for key in ["a", "corrupted", "missing", "z"] {
+            put(&mut storage, "b", key, key.as_bytes());
+        }
+        let file_of = |storage: &Storage, key: &str| -> String {
+            storage
+                .conn
+                .query_row(
+                    "SELECT file_path FROM objects WHERE bucket_name = 'b' AND key = ?1",
+                    [key],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        fs::write(file_of(&storage, "corrupted"), b"changed").unwrap();
+        fs::remove_file(file_of(&storage, "missing")).unwrap();
+        drop(storage);
+
+        let mut storage = Storage::open(
+            &dir.path().join("s3_storage.db").to_string_lossy(),
+            dir.path().join("data"),
+        )
+        .unwrap()
+        .with_hash_algorithm(HashAlgorithm::Sha256);
+        assert_eq!(storage.count_rehash_pending().unwrap(), 4);
+
+        let first = storage.rehash_batch((-1, -1), 3).unwrap();
+        assert_eq!(first.visited, 3);
+        let cursor = first.cursor.unwrap();
+        let last = storage.rehash_batch(cursor, 3).unwrap();
+        assert_eq!(last.visited, 1);
+        assert_eq!(last.cursor, None);
+
+        assert_eq!(first.rehashed + last.rehashed, 2);
+        assert_eq!(first.mismatched, ["b/corrupted"]);
+        assert_eq!(first.missing, ["b/missing"]);
+        // Objects whose file could not be vouched for keep their old checksum
+        assert_eq!(storage.count_rehash_pending().unwrap(), 2);
+        let verification = storage.verify_object("b", "a", false).unwrap();
+        assert!(verification.valid);
+        assert_eq!(verification.actual_etag.len(), 64);
+    }
+
+    #[test]
+    fn test_bucket_aliases() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        storage.create_bucket("other").unwrap();
+        storage.create_bucket_alias("b", "nick").unwrap();
+        storage.create_bucket_alias("b", "another").unwrap();
+
+        assert_eq!(
+            storage.resolve_bucket("nick").unwrap().as_deref(),
+            Some("b")
+        );
+        assert_eq!(storage.resolve_bucket("b").unwrap().as_deref(), Some("b"));
+        assert_eq!(storage.resolve_bucket("missing").unwrap(), None);
+        assert_eq!(
+            storage.list_bucket_aliases("b").unwrap(),
+            ["another", "nick"]
+        );
+
+        // Aliases and bucket names share one namespace
+        let taken = |result| matches!(result, Err(StorageError::BucketAlreadyExistsInStorage(_)));
+        assert!(taken(storage.create_bucket_alias("other", "nick")));
+        assert!(taken(storage.create_bucket_alias("b", "other")));
+        assert!(taken(storage.create_bucket("nick")));
+        assert!(matches!(
+            storage.create_bucket_alias("missing", "x"),
+            Err(StorageError::BucketNotFoundInStorage(_))
+        ));
+
+        assert!(matches!(
+            storage.delete_bucket_alias("other", "nick"),
+            Err(StorageError::AliasNotFound(..))
+        ));
+        storage.delete_bucket_alias("b", "nick").unwrap();
+        assert_eq!(storage.resolve_bucket("nick").unwrap(), None);
+        storage.create_bucket("nick").unwrap();
+    }
+
+    #[test]
+    fn test_delete_prefix_batches() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        for key in [
+            "logs/1",
+            "logs/2",
+            "logs/3",
+            "logs/held",
+            "logsheet",
+            "other",
+        ] {
+            put(&mut storage, "b", key, b"data");
+        }
+        storage
+            .set_object_legal_hold("b", "logs/held", true)
+            .unwrap();
+        assert_eq!(storage.count_prefix("b", "logs/").unwrap(), (4, 16));
+        let file: String = storage
+            .conn
+            .query_row(
+                "SELECT file_path FROM objects WHERE bucket_name = 'b' AND key = 'logs/1'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        let first = storage.delete_prefix_batch("b", "logs/", "", 2).unwrap();
+        assert_eq!(first.deleted, ["logs/1", "logs/2"]);
+        assert_eq!(first.cursor.as_deref(), Some("logs/2"));
+        assert!(!Path::new(&file).exists());
+        let last = storage
+            .delete_prefix_batch("b", "logs/", "logs/2", 2)
+            .unwrap();
+        assert_eq!(last.deleted, ["logs/3"]);
+        assert_eq!(last.skipped, ["logs/held"]);
+        assert_eq!(last.cursor.as_deref(), Some("logs/held"));
+        let done = storage
+            .delete_prefix_batch("b", "logs/", "logs/held", 2)
+            .unwrap();
+        assert!(done.deleted.is_empty() && done.cursor.is_none());
+
+        // Only whole prefix matches go
+        assert_eq!(storage.count_prefix("b", "logs/").unwrap(), (1, 4));
+        assert!(storage.get_object("b", "logsheet").is_ok());
+        assert!(storage.get_object("b", "other").is_ok());
+    }
+
+    #[test]
+    fn test_copy_prefix_batches() {
+        let (_dir, mut storage) = temp_storage();
+        for bucket in ["source", "plain", "versioned"] {
+            storage.create_bucket(bucket).unwrap();
+        }
+        storage
+            .set_bucket_versioning("versioned", VersioningStatus::Enabled)
+            .unwrap();
+        for key in ["img/a", "img/b", "img/c", "txt/d"] {
+            put(&mut storage, "source", key, key.as_bytes());
+        }
+        put(&mut storage, "versioned", "img/a", b"old");
+
+        let first = storage
+            .copy_prefix_batch("source", "plain", "img/", "", 2)
+            .unwrap();
+        assert_eq!(first.copied, ["img/a", "img/b"]);
+        assert_eq!(first.bytes, 10);
+        let last = storage
+            .copy_prefix_batch("source", "plain", "img/", "img/b", 2)
+            .unwrap();
+        assert_eq!(last.copied, ["img/c"]);
+        assert_eq!(last.cursor, None);
+        assert!(storage.get_object("plain", "txt/d").is_err());
+
+        // Copies have their own files
+        put(&mut storage, "source", "img/a", b"changed");
+        storage.delete_object("source", "img/b").unwrap();
+        assert_eq!(storage.get_object("plain", "img/a").unwrap().data, b"img/a");
+        assert_eq!(storage.get_object("plain", "img/b").unwrap().data, b"img/b");
+
+        // Into a versioned bucket, overwritten objects keep their history
+        storage
+            .copy_prefix_batch("source", "versioned", "img/", "", 10)
+            .unwrap();
+        assert_eq!(
+            storage.get_object("versioned", "img/a").unwrap().data,
+            b"changed"
+        );
+        assert_eq!(version_files(&storage, "versioned", "img/a").len(), 1);
+    }
+
+    #[test]
+    fn test_delete_object_if_match() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        put(&mut storage, "b", "k", b"hi");
+        let etag = storage.head_object("b", "k").unwrap().etag.unwrap();
+
+        assert!(matches!(
+            storage.delete_object_if_match("b", "k", "\"stale\""),
+            Err(StorageError::PreconditionFailed(..))
+        ));
+        assert!(storage.get_object("b", "k").is_ok());
+        // Any of a list of quoted ETags matches
+        let candidates = format!("\"stale\", \"{}\"", etag);
+        assert!(
+            storage
+                .delete_object_if_match("b", "k", &candidates)
+                .unwrap()
+        );
+        assert!(matches!(
+            storage.delete_object_if_match("b", "k", "*"),
+            Err(StorageError::ObjectNotFound(..))
+        ));
+
+        put(&mut storage, "b", "k", b"again");
+        assert!(storage.delete_object_if_match("b", "k", "*").unwrap());
+    }
+
+    #[test]
+    fn test_list_object_checksums() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let mut storage =
+            Storage::open(&db_path.to_string_lossy(), dir.path().join("data")).unwrap();
+        storage.create_bucket("b").unwrap();
+        let object = Object::new("old".to_string(), b"hi".to_vec(), None, None).unwrap();
+        storage.put_object("b", object).unwrap();
+        drop(storage);
+
+        // Objects written before the algorithm changed are hashed from their files
+        let mut storage = Storage::open(&db_path.to_string_lossy(), dir.path().join("data"))
+            .unwrap()
+            .with_hash_algorithm(HashAlgorithm::Sha256);
+        let object = Object::new("new".to_string(), b"hi".to_vec(), None, None).unwrap();
+        storage.put_object("b", object).unwrap();
+        let sha256 = HashAlgorithm::Sha256.etag(b"hi");
+        let checksums = storage.list_object_checksums("b").unwrap();
+        let checksums: Vec<_> = checksums
+            .iter()
+            .map(|(info, checksum)| (info.key.as_str(), checksum.as_deref()))
+            .collect();
+        assert_eq!(
+            checksums,
+            vec![
+                ("new", Some(sha256.as_str())),
+                ("old", Some(sha256.as_str()))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_upload_parts() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let mut storage =
+            Storage::open(&db_path.to_string_lossy(), dir.path().join("data")).unwrap();
+        storage.create_bucket("b").unwrap();
+        let upload = Upload {
+            id: "u".to_string(),
+            bucket: "b".to_string(),
+            key: "big".to_string(),
+            length: 10,
+            offset: 0,
+            content_type: None,
+            user_metadata: HashMap::new(),
+            created_at: 0,
+        };
+        storage.create_upload(&upload).unwrap();
+        storage.append_upload("b", "u", 0, b"1234").unwrap();
+        storage.append_upload("b", "u", 4, b"").unwrap();
+        storage.append_upload("b", "u", 4, b"567").unwrap();
+
+        let uploads = storage.list_uploads("b").unwrap();
+        assert_eq!(uploads.len(), 1);
+        assert_eq!(uploads[0].offset, 7);
+        let (_, parts) = storage.list_upload_parts("b", "u").unwrap();
+        let parts: Vec<_> = parts
+            .iter()
+            .map(|p| (p.part_number, p.offset, p.size))
+            .collect();
+        assert_eq!(parts, vec![(1, 0, 4), (2, 4, 3)]);
+
+        storage.delete_upload("b", "u").unwrap();
+        assert!(storage.list_uploads("b").unwrap().is_empty());
+        assert!(matches!(
+            storage.list_upload_parts("b", "u"),
+            Err(StorageError::UploadNotFound(_))
+        ));
+        assert!(matches!(
+            storage.list_uploads("missing"),
+            Err(StorageError::BucketNotFoundInStorage(_))
+        ));
+    }
+
+    #[test]
+    fn test_complete_upload() {
+        let (_dir, mut storage) = temp_storage();
+        storage.create_bucket("b").unwrap();
+        let upload = Upload {
+            id: "u".to_string(),
+            bucket: "b".to_string(),
+            key: "big".to_string(),
+            length: 10,
+            offset: 0,
+            content_type: None,
+            user_metadata: HashMap::new(),
+            created_at: 0,
+        };
+        storage.create_upload(&upload).unwrap();
+        storage.append_upload("b", "u", 0, b"12345").unwrap();
+        assert!(matches!(
+            storage.complete_upload("b", "u", None, None),
+            Err(StorageError::UploadIncomplete(_, 5, 10))
+        ));
+        let upload = storage.append_upload("b", "u", 5, b"67890").unwrap();
+        // Left over from an interrupted write past the offset
+        let staging = storage.upload_path("u");
+        let mut file = fs::OpenOptions::new().append(true).open(&staging).unwrap();
+        file.write_all(b"junk").unwrap();
+        assert_eq!(storage.read_upload_head(&upload, 3).unwrap(), b"123");
+
+        // A failed commit keeps the upload to retry
+        fail_object_commits(&storage, "INSERT");
+        assert!(matches!(
+            storage.complete_upload("b", "u", Some("text/plain"), None),
+            Err(StorageError::TransactionCommitError)
+        ));
+        allow_object_commits(&storage);
+        assert_eq!(storage.list_uploads("b").unwrap().len(), 1);
+
+        let info = storage
+            .complete_upload("b", "u", Some("text/plain"), None)
+            .unwrap();
+        assert_eq!(info.size, 10);
+        let object = storage.get_object("b", "big").unwrap();
+        assert_eq!(object.data, b"1234567890");
+        assert_eq!(object.etag, Some(HashAlgorithm::Md5.etag(b"1234567890")));
+        assert_eq!(object.content_type.as_deref(), Some("text/plain"));
+        assert!(!staging.exists());
+        assert!(storage.list_uploads("b").unwrap().is_empty());
+        assert!(matches!(
+            storage.list_upload_parts("b", "u"),
+            Err(StorageError::UploadNotFound(_))
+        ));
+        assert!(matches!(
+            storage.complete_upload("b", "u", None, None),
+            Err(StorageError::UploadNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_abort_stale_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let clock = Arc::new(crate::clock::ManualClock::at_unix_secs(1_000));
+        let mut storage = Storage::open(&db_path.to_string_lossy(), dir.path().join("data"))
+            .unwrap()
+            .with_clock(clock.clone());
+        storage.create_bucket("b").unwrap();
+        for id in ["idle", "busy"] {
+            let upload = Upload {
+                id: id.to_string(),
+                bucket: "b".to_string(),
+                key: id.to_string(),
+                length: 10,
+                offset: 0,
+                content_type: None,
+                user_metadata: HashMap::new(),
+                created_at: 1_000,
+            };
+            storage.create_upload(&upload).unwrap();
+            storage.append_upload("b", id, 0, b"123").unwrap();
+        }
+        clock.advance(Duration::from_secs(100));
+        storage.append_upload("b", "busy", 3, b"4").unwrap();
+
+        let (ids, bytes) = storage.abort_stale_uploads(1_050).unwrap();
+        assert_eq!((ids, bytes), (vec!["idle".to_string()], 3));
+        assert!(!storage.upload_path("idle").exists());
+        assert!(matches!(
+            storage.list_upload_parts("b", "idle"),
+            Err(StorageError::UploadNotFound(_))
+        ));
+        assert_eq!(storage.list_uploads("b").unwrap().len(), 1);
+        assert!(storage.abort_stale_uploads(1_050).unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn test_bucket_roles() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let mut storage =
+            Storage::open(&db_path.to_string_lossy(), dir.path().join("data")).unwrap();
+        storage.create_bucket("b").unwrap();
+        let roles = HashMap::from([
+            ("alice".to_string(), Role::Admin),
+            ("bob".to_string(), Role::Reader),
+        ]);
+        storage.set_bucket_roles("b", &roles).unwrap();
+        assert_eq!(storage.get_bucket_roles("b").unwrap(), roles);
+        assert_eq!(storage.list_bucket_roles().unwrap()["b"], roles);
+        assert!(matches!(
+            storage.set_bucket_roles("missing", &roles),
+            Err(StorageError::BucketNotFoundInStorage(_))
+        ));
+
+        let grants = vec![
+            Grant {
+                grantee: "carol".to_string(),
+                permission: Permission::Read,
+            },
+            Grant {
+                grantee: "carol".to_string(),
+                permission: Permission::Write,
+            },
+        ];
+        storage.set_bucket_acl("b", &grants).unwrap();
+        assert_eq!(storage.get_bucket_acl("b").unwrap(), grants);
+        assert_eq!(storage.list_bucket_acls().unwrap()["b"], grants);
+
+        // Deleting the bucket drops its roles and grants
+        storage._delete_bucket("b").unwrap();
+        assert!(storage.list_bucket_roles().unwrap().is_empty());
+        assert!(storage.list_bucket_acls().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_immutable_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let mut storage =
+            Storage::open(&db_path.to_string_lossy(), dir.path().join("data")).unwrap();
+        storage.create_bucket("b").unwrap();
+        let object = |key: &str| Object::new(key.to_string(), b"hi".to_vec(), None, None).unwrap();
+        storage
+            .put_object("b", object("k").with_immutable(true))
+            .unwrap();
+        storage.put_object("b", object("other")).unwrap();
+
+        assert!(storage.get_object("b", "k").unwrap().immutable);
+        assert!(storage.head_object("b", "k").unwrap().immutable);
+        assert!(matches!(
+            storage.put_object("b", object("k")),
+            Err(StorageError::ObjectImmutable(..))
+        ));
+        assert!(matches!(
+            storage.delete_object("b", "k"),
+            Err(StorageError::ObjectImmutable(..))
+        ));
+        assert!(matches!(
+            storage._delete_bucket("b"),
+            Err(StorageError::ObjectImmutable(..))
+        ));
+        let batch = storage.delete_prefix_batch("b", "", "", 10).unwrap();
+        assert_eq!(batch.skipped, ["k"]);
+        assert_eq!(storage.get_object("b", "k").unwrap().data, b"hi");
     }
 }
@@ -0,0 +1,178 @@
+// storage_trace.rs
+// Timing of storage operations for their tracing spans. The hot `Storage`
+// methods run in a debug-level span of their own, a child of the handler
+// span, and time their SQL and file I/O separately, so latency
+// investigations can tell database contention apart from slow disks.
+// Enable with e.g. `RUST_LOG=info,s3_learning_project::storage=debug`.
+//
+// Independently of the spans, an operation whose SQL or file I/O time
+// exceeds its threshold is logged as a warning under the `slow_ops` target,
+// with its bucket, key and the time spent waiting for the storage lock, to
+// surface hotspots before users notice them.
+
+use std::time::{Duration, Instant};
+use tracing::{Span, debug, warn};
+
+use crate::config::SlowOpConfig;
+
+/// Log target of the slow operation warnings, for use in filter directives.
+pub const TARGET: &str = "slow_ops";
+
+/// What one storage operation spent its time on, recorded on its span
+/// (`sql_ms`, `file_ms`, `bytes`, `rows`) when dropped.
+#[derive(Debug)]
+pub struct OpTrace<'a> {
+    span: Span,
+    operation: &'static str,
+    bucket: &'a str,
+    key: &'a str,
+    slow: SlowOpConfig,
+    lock_wait: Duration,
+    sql: Duration,
+    file: Duration,
+    bytes: u64,
+    rows: u64,
+}
+
+impl<'a> OpTrace<'a> {
+    /// Starts tracing an operation in the current span.
+    ///
+    /// # Arguments
+    ///
+    /// * `operation` - The name of the operation, for slow operation warnings.
+    /// * `bucket` - The bucket operated on.
+    /// * `key` - The key operated on, empty for bucket operations.
+    /// * `slow` - The thresholds of slow operations.
+    /// * `lock_wait` - The time spent waiting for the storage lock.
+    ///
+    /// # Returns
+    ///
+    /// * `OpTrace` - The trace, reported when dropped.
+    pub fn start(
+        operation: &'static str,
+        bucket: &'a str,
+        key: &'a str,
+        slow: SlowOpConfig,
+        lock_wait: Duration,
+    ) -> Self {
+        Self {
+            span: Span::current(),
+            operation,
+            bucket,
+            key,
+            slow,
+            lock_wait,
+            sql: Duration::ZERO,
+            file: Duration::ZERO,
+            bytes: 0,
+            rows: 0,
+        }
+    }
+
+    /// Runs a database call, counting its time as SQL time.
+    pub fn sql<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.sql += started.elapsed();
+        result
+    }
+
+    /// Runs a file system call, counting its time as file I/O time.
+    pub fn file<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let started = Instant::now();
+        let result = f();
+        self.file += started.elapsed();
+        result
+    }
+
+    /// Counts bytes read or written.
+    pub fn add_bytes(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+
+    /// Counts rows returned or affected.
+    pub fn add_rows(&mut self, rows: usize) {
+        self.rows += rows as u64;
+    }
+
+    /// Whether the SQL or file I/O time exceeded its threshold.
+    fn is_slow(&self) -> bool {
+        let exceeds = |time: Duration, ms: u64| ms > 0 && time > Duration::from_millis(ms);
+        exceeds(self.sql, self.slow.sql_ms) || exceeds(self.file, self.slow.file_ms)
+    }
+}
+
+impl Drop for OpTrace<'_> {
+    fn drop(&mut self) {
+        let sql_ms = millis(self.sql);
+        let file_ms = millis(self.file);
+        if self.is_slow() {
+            warn!(
+                target: TARGET,
+                operation = self.operation,
+                bucket = self.bucket,
+                key = self.key,
+                sql_ms,
+                file_ms,
+                lock_wait_ms = millis(self.lock_wait),
+                bytes = self.bytes,
+                rows = self.rows,
+                "Slow storage operation"
+            );
+        }
+        if self.span.is_disabled() {
+            return;
+        }
+        self.span.record("sql_ms", sql_ms);
+        self.span.record("file_ms", file_ms);
+        self.span.record("bytes", self.bytes);
+        self.span.record("rows", self.rows);
+        debug!(
+            parent: &self.span,
+            sql_ms,
+            file_ms,
+            bytes = self.bytes,
+            rows = self.rows,
+            "Storage operation finished"
+        );
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trace(slow: SlowOpConfig) -> OpTrace<'static> {
+        OpTrace::start("get_object", "b", "k", slow, Duration::ZERO)
+    }
+
+    #[test]
+    fn test_times_sql_and_file_apart() {
+        let mut trace = trace(SlowOpConfig::default());
+        let rows = trace.sql(|| {
+            std::thread::sleep(Duration::from_millis(5));
+            3
+        });
+        trace.add_rows(rows);
+        trace.file(|| ());
+        assert!(trace.sql >= Duration::from_millis(5));
+        assert!(trace.file < trace.sql);
+        assert_eq!(trace.rows, 3);
+    }
+
+    #[test]
+    fn test_is_slow() {
+        let mut slow_sql = trace(SlowOpConfig {
+            sql_ms: 1,
+            file_ms: 0,
+        });
+        slow_sql.file(|| std::thread::sleep(Duration::from_millis(5)));
+        assert!(!slow_sql.is_slow());
+        slow_sql.sql(|| std::thread::sleep(Duration::from_millis(5)));
+        assert!(slow_sql.is_slow());
+    }
+}
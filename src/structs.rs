@@ -1,27 +1,40 @@
 // --- Request/Response Structs (for JSON where applicable) ---
 
-use crate::object::Object;
-use serde::Serialize;
+use crate::access::AccessReport;
+use crate::acl::Grant;
+use crate::bucket::{LifecycleRule, VersioningStatus};
+use crate::folder::FolderListing;
+use crate::metrics::BucketMetrics;
+use crate::object::{Object, ObjectInfo, ObjectVerification};
+use crate::replication::ReplicationReport;
+use crate::roles::Role;
+use crate::s3_service::PrefixDeleteReport;
+use crate::share::Share;
+use crate::storage::RestoreReport;
+use crate::tus::{Upload, UploadPart};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use utoipa::{IntoParams, ToSchema};
 
 // For listing buckets or objects
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct ListResponse {
     pub items: Vec<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct BucketCreatedResponse {
     pub name: String,
     pub message: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct BucketDeletedResponse {
     pub message: String,
     pub bucket: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct ObjectCreatedResponse<'a> {
     pub name: String,
     pub bucket: String,
@@ -29,20 +42,366 @@ pub struct ObjectCreatedResponse<'a> {
     pub message: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
+pub struct ObjectComposedResponse<'a> {
+    pub name: String,
+    pub bucket: String,
+    pub metadata: &'a ObjectInfo,
+    pub message: String,
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct ObjectDeletedResponse {
     pub name: String,
     pub bucket: String,
     pub message: String,
 }
 
-#[derive(Serialize)]
+// Query of DELETE /buckets/{bucket}/objects?prefix=...
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PrefixQuery {
+    pub prefix: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PrefixDeletedResponse {
+    pub bucket: String,
+    pub prefix: String,
+    #[serde(flatten)]
+    pub report: PrefixDeleteReport,
+}
+
+#[derive(Serialize, ToSchema)]
 pub struct ObjectListResponse {
     pub bucket: String,
     pub items: Vec<String>,
 }
 
-#[derive(Serialize)]
-pub struct ErrorResponse {
-    pub message: String,
+#[derive(Serialize, ToSchema)]
+pub struct ObjectDetailListResponse {
+    pub bucket: String,
+    pub items: Vec<ObjectInfo>,
+}
+
+// An entry of GET /buckets/{bucket}/objects?detail=full&checksum=true
+#[derive(Serialize, ToSchema)]
+pub struct ObjectChecksumEntry {
+    #[serde(flatten)]
+    pub info: ObjectInfo,
+    pub checksum: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ObjectChecksumListResponse {
+    pub bucket: String,
+    pub checksum_algorithm: String,
+    pub items: Vec<ObjectChecksumEntry>,
+}
+
+// How much of each object GET /buckets/{bucket}/objects lists
+#[derive(Deserialize, Default, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ListDetail {
+    #[default]
+    Keys,
+    Full,
+}
+
+// Query of GET /buckets/{bucket}/objects?detail=...
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListObjectsQuery {
+    #[serde(default)]
+    pub detail: ListDetail,
+    /// With `detail=full`, also list each object's checksum in the
+    /// configured hash algorithm.
+    #[serde(default)]
+    pub checksum: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct FolderListResponse {
+    pub bucket: String,
+    #[serde(flatten)]
+    pub listing: FolderListing,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketVersioningResponse {
+    pub bucket: String,
+    pub status: Option<VersioningStatus>,
+}
+
+// Body of PUT /buckets/{bucket}?versioning
+#[derive(Deserialize, ToSchema)]
+pub struct VersioningConfiguration {
+    pub status: VersioningStatus,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketAliasesResponse {
+    pub bucket: String,
+    pub aliases: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketWormResponse {
+    pub bucket: String,
+    pub worm: bool,
+}
+
+// Body of PUT /buckets/{bucket}?worm
+#[derive(Deserialize, ToSchema)]
+pub struct WormConfiguration {
+    pub worm: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketLoggingResponse {
+    pub bucket: String,
+    pub verbose: bool,
+}
+
+// Body of PUT /buckets/{bucket}?logging
+#[derive(Deserialize, ToSchema)]
+pub struct BucketLoggingConfiguration {
+    pub verbose: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketContentTypeResponse {
+    pub bucket: String,
+    pub content_type: Option<String>,
+}
+
+// Body of PUT /buckets/{bucket}?content-type
+#[derive(Deserialize, ToSchema)]
+pub struct BucketContentTypeConfiguration {
+    pub content_type: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketMetadataResponse {
+    pub bucket: String,
+    pub metadata: HashMap<String, String>,
+}
+
+// Body of PUT /buckets/{bucket}?metadata
+#[derive(Deserialize, ToSchema)]
+pub struct BucketMetadataConfiguration {
+    pub metadata: HashMap<String, String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketRolesResponse {
+    pub bucket: String,
+    pub roles: HashMap<String, Role>,
+}
+
+// Body of PUT /buckets/{bucket}?roles
+#[derive(Deserialize, ToSchema)]
+pub struct BucketRolesConfiguration {
+    pub roles: HashMap<String, Role>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketAclResponse {
+    pub bucket: String,
+    pub grants: Vec<Grant>,
+}
+
+// Body of PUT /buckets/{bucket}?acl
+#[derive(Deserialize, ToSchema)]
+pub struct BucketAclConfiguration {
+    pub grants: Vec<Grant>,
+}
+
+// Query of POST /buckets/{bucket}/objects/{key}?verify
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct VerifyQuery {
+    #[serde(default)]
+    pub repair: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ObjectVerificationResponse {
+    pub bucket: String,
+    #[serde(flatten)]
+    pub verification: ObjectVerification,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ObjectLegalHoldResponse {
+    pub bucket: String,
+    pub key: String,
+    pub legal_hold: bool,
+}
+
+// Body of PUT /buckets/{bucket}/objects/{key}?legal-hold
+#[derive(Deserialize, ToSchema)]
+pub struct LegalHoldConfiguration {
+    pub legal_hold: bool,
+}
+
+// Body of POST /buckets/{bucket}/objects/{key}?compose
+#[derive(Deserialize, ToSchema)]
+pub struct ComposeConfiguration {
+    pub sources: Vec<String>,
+    pub content_type: Option<String>,
+}
+
+// Body of POST /buckets/{bucket}/objects/{key}?share
+#[derive(Deserialize, ToSchema)]
+pub struct ShareConfiguration {
+    pub max_downloads: Option<u64>,
+    pub expires_in_secs: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ShareCreatedResponse {
+    pub id: String,
+    pub token: String,
+    pub url: String,
+    pub bucket: String,
+    pub key: String,
+    pub max_downloads: Option<u64>,
+    pub expires_at: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketSharesResponse {
+    pub bucket: String,
+    pub shares: Vec<Share>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketUploadsResponse {
+    pub bucket: String,
+    pub uploads: Vec<Upload>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct UploadPartsResponse {
+    pub bucket: String,
+    #[serde(flatten)]
+    pub upload: Upload,
+    pub parts: Vec<UploadPart>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketReplicationResponse {
+    pub bucket: String,
+    #[serde(flatten)]
+    pub report: ReplicationReport,
+}
+
+// Body of PUT /buckets/{bucket}?replication
+#[derive(Deserialize, ToSchema)]
+pub struct ReplicationConfiguration {
+    pub destination: Option<String>,
+}
+
+// Body of GET/PUT /buckets/{bucket}?lifecycle
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct LifecycleConfiguration {
+    pub rules: Vec<LifecycleRule>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketAccessReportResponse {
+    pub bucket: String,
+    #[serde(flatten)]
+    pub report: AccessReport,
+}
+
+// Query of GET /buckets/{bucket}?access-stats
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AccessReportQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketMetricsResponse {
+    pub bucket: String,
+    #[serde(flatten)]
+    pub metrics: BucketMetrics,
+}
+
+// For POST /admin/cache/warm
+#[derive(Deserialize, ToSchema)]
+pub struct CacheWarmRequest {
+    pub bucket: String,
+    #[serde(default)]
+    pub prefix: String,
+}
+
+// For POST /admin/read-only and its response
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct ReadOnlyStatus {
+    pub read_only: bool,
+}
+
+// For POST /admin/maintenance and its response
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    /// Shown to refused clients, e.g. "restoring a backup".
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default = "default_retry_after_secs")]
+    pub retry_after_secs: u64,
+}
+
+fn default_retry_after_secs() -> u64 {
+    crate::maintenance::DEFAULT_RETRY_AFTER_SECS
+}
+
+// For PUT /admin/log-level and the response of GET /admin/log-level
+#[derive(Serialize, Deserialize, ToSchema)]
+pub struct LogLevel {
+    pub level: String,
+}
+
+// Query of POST /admin/db/backup?dest=...
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BackupQuery {
+    pub dest: String,
+}
+
+// Query of POST /buckets/{bucket}?copy-from=...&prefix=...
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CopyQuery {
+    #[serde(rename = "copy-from")]
+    pub copy_from: String,
+    #[serde(default)]
+    pub prefix: String,
+}
+
+// Query of POST /admin/restore?bucket=...&at=...
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RestoreQuery {
+    pub bucket: String,
+    /// Unix seconds.
+    pub at: i64,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BucketRestoreResponse {
+    pub bucket: String,
+    pub at: i64,
+    #[serde(flatten)]
+    pub report: RestoreReport,
+}
+
+// Query of GET /admin/stats?bucket=...
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsQuery {
+    pub bucket: Option<String>,
 }
@@ -0,0 +1,101 @@
+// systemd.rs
+// Socket activation. When systemd starts the server for a `.socket` unit it
+// passes the already listening sockets as file descriptors from 3 on and
+// announces them in `LISTEN_PID` and `LISTEN_FDS`. Serving those instead of
+// binding our own lets systemd start the server on the first connection and
+// restart it without refusing connections, as the sockets outlive it.
+
+use std::io;
+use std::net::TcpListener;
+use std::os::fd::{FromRawFd, RawFd};
+use std::os::unix::net::UnixListener;
+
+/// Environment variable with the PID the sockets are meant for.
+pub const LISTEN_PID_ENV: &str = "LISTEN_PID";
+/// Environment variable with the number of sockets passed.
+pub const LISTEN_FDS_ENV: &str = "LISTEN_FDS";
+/// The first file descriptor passed.
+const LISTEN_FDS_START: RawFd = 3;
+
+/// A listening socket passed by systemd.
+#[derive(Debug)]
+pub enum ActivatedSocket {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// The number of sockets passed to the process with ID `pid`, given the
+/// values of `LISTEN_PID` and `LISTEN_FDS`. Sockets meant for another
+/// process, e.g. a parent that did not clear the variables, are ignored.
+fn passed_count(listen_pid: Option<&str>, listen_fds: Option<&str>, pid: u32) -> usize {
+    match (listen_pid, listen_fds) {
+        (Some(listen_pid), Some(listen_fds)) if listen_pid.parse() == Ok(pid) => {
+            listen_fds.parse().unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+/// Takes over the sockets systemd passed, if it started the server through
+/// socket activation.
+///
+/// # Returns
+///
+/// * `io::Result<Vec<ActivatedSocket>>` - The sockets, empty without socket activation.
+pub fn activated_sockets() -> io::Result<Vec<ActivatedSocket>> {
+    let count = passed_count(
+        std::env::var(LISTEN_PID_ENV).ok().as_deref(),
+        std::env::var(LISTEN_FDS_ENV).ok().as_deref(),
+        std::process::id(),
+    );
+    (0..count as RawFd)
+        .map(|offset| take_socket(LISTEN_FDS_START + offset))
+        .collect()
+}
+
+/// Wraps a passed file descriptor in a listener of its address family.
+fn take_socket(fd: RawFd) -> io::Result<ActivatedSocket> {
+    let mut address: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut length = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    // SAFETY: the address buffer is as large as `length` says
+    if unsafe {
+        libc::getsockname(
+            fd,
+            &mut address as *mut _ as *mut libc::sockaddr,
+            &mut length,
+        )
+    } != 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    // Keep the socket from leaking into processes we might start
+    unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+
+    // SAFETY: systemd hands the descriptor over to us and nothing else
+    // in the process owns it
+    match address.ss_family as libc::c_int {
+        libc::AF_INET | libc::AF_INET6 => Ok(ActivatedSocket::Tcp(unsafe {
+            TcpListener::from_raw_fd(fd)
+        })),
+        libc::AF_UNIX => Ok(ActivatedSocket::Unix(unsafe {
+            UnixListener::from_raw_fd(fd)
+        })),
+        family => Err(io::Error::other(format!(
+            "Socket {} passed by systemd has unsupported address family {}",
+            fd, family
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passed_count() {
+        assert_eq!(passed_count(Some("42"), Some("2"), 42), 2);
+        assert_eq!(passed_count(Some("41"), Some("2"), 42), 0);
+        assert_eq!(passed_count(None, Some("2"), 42), 0);
+        assert_eq!(passed_count(Some("42"), Some("many"), 42), 0);
+    }
+}
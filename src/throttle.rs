@@ -0,0 +1,394 @@
+// throttle.rs
+// Per-client request and bandwidth throttling. Each client draws from two
+// token buckets (requests and bytes) refilled at its configured rate;
+// requests arriving with an empty bucket are rejected with 503 SlowDown, as S3 does.
+//
+// Throttling runs after authentication, so a client is the principal of a
+// verified bearer token (see jwt.rs) or, for any other request, the peer
+// address it connects from. Access keys named in a request are not verified
+// on every request, so they cannot pick the budget: a client could otherwise
+// spread its load over made-up keys or spend another client's budget. Budgets
+// that have refilled completely are dropped once many are tracked: a fresh
+// budget for the same client is identical.
+
+use actix_web::body::{BodySize, BodyStream, EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{AUTHORIZATION, CONTENT_LENGTH, HeaderValue, RETRY_AFTER};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, web};
+use futures::StreamExt;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::warn;
+
+use crate::config::{ThrottleConfig, ThrottleLimits};
+use crate::error_code::{ErrorCode, error_response};
+use crate::jwt::Principal;
+
+/// Principal used for requests that carry no credentials.
+pub const ANONYMOUS: &str = "anonymous";
+
+/// Number of tracked budgets above which full ones are dropped when a new
+/// client is seen.
+const SWEEP_THRESHOLD: usize = 1024;
+
+/// Tokens refilled continuously at a fixed rate, holding at most one second's
+/// worth. The balance may go negative when a charge is only known after the fact.
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, now: Instant) -> Self {
+        Self {
+            tokens: rate,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, rate: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate);
+        self.updated = now;
+    }
+
+    /// Whether the bucket would be full by `now`, so dropping it changes nothing.
+    fn is_full(&self, rate: f64, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        rate == 0.0 || self.tokens + elapsed * rate >= rate
+    }
+
+    /// Seconds until the balance is positive again, rounded up.
+    fn wait_secs(&self, rate: f64) -> u64 {
+        if self.tokens > 0.0 {
+            0
+        } else {
+            ((-self.tokens + 1.0) / rate).ceil().max(1.0) as u64
+        }
+    }
+}
+
+#[derive(Debug)]
+struct KeyBudget {
+    requests: TokenBucket,
+    bytes: TokenBucket,
+}
+
+/// Tracks the request and bandwidth budgets of every client seen.
+#[derive(Debug)]
+pub struct Throttle {
+    config: std::sync::RwLock<ThrottleConfig>,
+    budgets: std::sync::Mutex<HashMap<String, KeyBudget>>,
+}
+
+impl Throttle {
+    pub fn new(config: ThrottleConfig) -> Self {
+        Self {
+            config: std::sync::RwLock::new(config),
+            budgets: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Replaces the limits. Budgets start over at the new rates.
+    pub fn reload(&self, config: ThrottleConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+        self.budgets
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+
+    fn limits(&self, client: &str) -> ThrottleLimits {
+        let config = self.config.read().unwrap_or_else(|e| e.into_inner());
+        config.keys.get(client).copied().unwrap_or(config.default)
+    }
+
+    /// Admits a request for `client` uploading `bytes_in` bytes, or returns
+    /// the number of seconds the client should wait before retrying.
+    pub fn admit(&self, client: &str, bytes_in: u64) -> Result<(), u64> {
+        self.admit_at(client, bytes_in, Instant::now())
+    }
+
+    fn admit_at(&self, client: &str, bytes_in: u64, now: Instant) -> Result<(), u64> {
+        let limits = self.limits(client);
+        if limits.requests_per_second == 0 && limits.bytes_per_second == 0 {
+            return Ok(());
+        }
+        let request_rate = limits.requests_per_second as f64;
+        let byte_rate = limits.bytes_per_second as f64;
+
+        let mut budgets = self.budgets.lock().unwrap_or_else(|e| e.into_inner());
+        if budgets.len() >= SWEEP_THRESHOLD && !budgets.contains_key(client) {
+            self.sweep(&mut budgets, now);
+        }
+        let budget = budgets
+            .entry(client.to_string())
+            .or_insert_with(|| KeyBudget {
+                requests: TokenBucket::new(request_rate, now),
+                bytes: TokenBucket::new(byte_rate, now),
+            });
+
+        if limits.requests_per_second > 0 {
+            budget.requests.refill(request_rate, now);
+            if budget.requests.tokens < 1.0 {
+                return Err(((1.0 - budget.requests.tokens) / request_rate)
+                    .ceil()
+                    .max(1.0) as u64);
+            }
+        }
+        if limits.bytes_per_second > 0 {
+            budget.bytes.refill(byte_rate, now);
+            if budget.bytes.tokens <= 0.0 {
+                return Err(budget.bytes.wait_secs(byte_rate));
+            }
+            budget.bytes.tokens -= bytes_in as f64;
+        }
+        if limits.requests_per_second > 0 {
+            budget.requests.tokens -= 1.0;
+        }
+        Ok(())
+    }
+
+    /// Drops the budgets that have refilled completely.
+    fn sweep(&self, budgets: &mut HashMap<String, KeyBudget>, now: Instant) {
+        budgets.retain(|key, budget| {
+            let limits = self.limits(key);
+            !(budget
+                .requests
+                .is_full(limits.requests_per_second as f64, now)
+                && budget.bytes.is_full(limits.bytes_per_second as f64, now))
+        });
+    }
+
+    /// Charges bytes sent in a response against the bandwidth budget of `client`.
+    pub fn charge_download(&self, client: &str, bytes_out: u64) {
+        if self.limits(client).bytes_per_second == 0 {
+            return;
+        }
+        let mut budgets = self.budgets.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(budget) = budgets.get_mut(client) {
+            budget.bytes.tokens -= bytes_out as f64;
+        }
+    }
+}
+
+/// Extracts the access key id from SigV4 (`Credential=AKID/...`), SigV2
+/// (`AWS AKID:signature`) or presigned (`X-Amz-Credential=AKID/...`) requests.
+pub fn access_key(req: &ServiceRequest) -> String {
+    let from_header = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|auth| {
+            if let Some((_, credential)) = auth.split_once("Credential=") {
+                credential.split('/').next()
+            } else {
+                auth.strip_prefix("AWS ")
+                    .and_then(|rest| rest.split(':').next())
+            }
+        })
+        .map(|key| key.trim().to_string());
+
+    let from_query = || {
+        req.query_string()
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("X-Amz-Credential="))
+            .and_then(|credential| {
+                percent_encoding::percent_decode_str(credential)
+                    .decode_utf8()
+                    .ok()
+                    .and_then(|c| c.split('/').next().map(|key| key.to_string()))
+            })
+    };
+
+    from_header
+        .or_else(from_query)
+        .filter(|key| !key.is_empty())
+        .unwrap_or_else(|| ANONYMOUS.to_string())
+}
+
+/// The client whose budget a request draws from: the subject of its verified
+/// bearer token, or else `anonymous@` followed by the peer's IP address.
+pub fn client(req: &ServiceRequest) -> String {
+    if let Some(principal) = req.extensions().get::<Principal>() {
+        return principal.subject.clone();
+    }
+    match req.peer_addr() {
+        Some(peer) => format!("{}@{}", ANONYMOUS, peer.ip()),
+        None => ANONYMOUS.to_string(),
+    }
+}
+
+/// Middleware enforcing the per-client budgets configured in `[throttle]`.
+/// It runs after `authenticate_bearer_tokens`, which attaches the principal.
+pub async fn throttle_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, Error> {
+    let Some(throttle) = req.app_data::<web::Data<Arc<Throttle>>>().cloned() else {
+        let res = next.call(req).await?;
+        return Ok(res
+            .map_body(|_, body| EitherBody::left(body))
+            .map_into_left_body());
+    };
+
+    let key = client(&req);
+    let bytes_in = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    if let Err(retry_after) = throttle.admit(&key, bytes_in) {
+        warn!(client = %key, retry_after, "Request throttled");
+        let mut response = error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::SlowDown,
+            "SlowDown: Please reduce your request rate.",
+        );
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+        return Ok(req.into_response(response).map_into_right_body());
+    }
+
+    // Bodies of unknown length are charged chunk by chunk as they are sent
+    let res = next.call(req).await?;
+    if let BodySize::Sized(bytes_out) = res.response().body().size() {
+        throttle.charge_download(&key, bytes_out);
+        return Ok(res
+            .map_body(|_, body| EitherBody::left(body))
+            .map_into_left_body());
+    }
+    Ok(res
+        .map_body(|_, body| {
+            let mut body = Box::pin(body);
+            let chunks =
+                futures::stream::poll_fn(move |cx| body.as_mut().poll_next(cx)).map(move |chunk| {
+                    let bytes = chunk.map_err(Into::<Box<dyn std::error::Error>>::into)?;
+                    throttle.charge_download(&key, bytes.len() as u64);
+                    Ok::<_, Box<dyn std::error::Error>>(bytes)
+                });
+            EitherBody::right(BodyStream::new(chunks))
+        })
+        .map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn throttle(requests_per_second: u32, bytes_per_second: u64) -> Throttle {
+        Throttle::new(ThrottleConfig {
+            default: ThrottleLimits {
+                requests_per_second,
+                bytes_per_second,
+            },
+            keys: HashMap::new(),
+        })
+    }
+
+    #[test]
+    fn test_request_budget_refills_over_time() {
+        let throttle = throttle(2, 0);
+        let start = Instant::now();
+
+        assert!(throttle.admit_at("key", 0, start).is_ok());
+        assert!(throttle.admit_at("key", 0, start).is_ok());
+        assert_eq!(throttle.admit_at("key", 0, start), Err(1));
+
+        // Other keys have their own budget
+        assert!(throttle.admit_at("other", 0, start).is_ok());
+
+        let later = start + Duration::from_millis(500);
+        assert!(throttle.admit_at("key", 0, later).is_ok());
+    }
+
+    #[test]
+    fn test_bandwidth_budget_allows_debt_then_blocks() {
+        let throttle = throttle(0, 100);
+        let start = Instant::now();
+
+        // A large upload is admitted while the budget is positive...
+        assert!(throttle.admit_at("key", 250, start).is_ok());
+        // ...and the resulting debt blocks the next request until repaid
+        assert_eq!(throttle.admit_at("key", 1, start), Err(2));
+        assert!(
+            throttle
+                .admit_at("key", 1, start + Duration::from_secs(2))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_full_budgets_are_dropped() {
+        let throttle = throttle(0, 100);
+        let start = Instant::now();
+
+        assert!(throttle.admit_at("debtor", 1000, start).is_ok());
+        for key in 1..SWEEP_THRESHOLD {
+            assert!(throttle.admit_at(&key.to_string(), 0, start).is_ok());
+        }
+        assert_eq!(throttle.budgets.lock().unwrap().len(), SWEEP_THRESHOLD);
+
+        // Once refilled the made-up keys are dropped, the one in debt is kept
+        let later = start + Duration::from_secs(1);
+        assert!(throttle.admit_at("new", 0, later).is_ok());
+        let budgets = throttle.budgets.lock().unwrap();
+        assert_eq!(budgets.len(), 2);
+        assert!(budgets.contains_key("debtor"));
+    }
+
+    #[test]
+    fn test_client_is_the_verified_principal_or_peer() {
+        use actix_web::test::TestRequest;
+
+        // Made-up access keys from one address share its budget
+        let peer = "203.0.113.7:40000".parse().unwrap();
+        for access_key in ["AKID", "OTHER"] {
+            let req = TestRequest::default()
+                .peer_addr(peer)
+                .insert_header((AUTHORIZATION, format!("AWS {}:signature", access_key)))
+                .to_srv_request();
+            assert_eq!(client(&req), "anonymous@203.0.113.7");
+        }
+
+        let req = TestRequest::default().peer_addr(peer).to_srv_request();
+        req.extensions_mut().insert(Principal {
+            subject: "alice".to_string(),
+            scopes: Vec::new(),
+        });
+        assert_eq!(client(&req), "alice");
+    }
+
+    #[actix_web::test]
+    async fn test_charges_bodies_of_unknown_length() {
+        use actix_web::{App, HttpResponse, middleware::from_fn, test};
+
+        let throttle = Arc::new(throttle(0, 100));
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(throttle.clone()))
+                .wrap(from_fn(throttle_requests))
+                .route(
+                    "/",
+                    web::get().to(|| async {
+                        let chunk = Ok::<_, Error>(web::Bytes::from(vec![0; 150]));
+                        HttpResponse::Ok().streaming(futures::stream::iter([chunk]))
+                    }),
+                ),
+        )
+        .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        assert_eq!(test::read_body(res).await.len(), 150);
+        let res = test::call_service(&app, test::TestRequest::get().to_request()).await;
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
@@ -0,0 +1,99 @@
+// timeout.rs
+// Time limits on requests, so a stalled client or a hung disk cannot hold a
+// worker indefinitely. The request head is limited by the HTTP service
+// itself (see main.rs), pauses in request bodies by `next_within`, the whole
+// request by the `limit_request_time` middleware and waits for the storage
+// by `storage::lock_storage`.
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::StatusCode;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpRequest, web};
+use futures::{Stream, StreamExt};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::config::TimeoutConfig;
+use crate::error_code::{ErrorCode, error_response};
+use crate::s3_service::S3Error;
+
+/// The longest pause allowed between two parts of a request's body.
+pub fn body_idle_limit(req: &HttpRequest) -> Option<Duration> {
+    req.app_data::<web::Data<TimeoutConfig>>()
+        .and_then(|config| config.body_read())
+}
+
+/// Waits for the next part of a request body, failing with `RequestTimeout`
+/// if the client sends nothing for `idle`.
+///
+/// # Arguments
+///
+/// * `stream` - The body, or a field of a form.
+/// * `idle` - The longest pause allowed, if any.
+///
+/// # Returns
+///
+/// * `Result<Option<S::Item>, S3Error>` - The next part, `None` at the end, or a timeout.
+pub async fn next_within<S: Stream + Unpin>(
+    stream: &mut S,
+    idle: Option<Duration>,
+) -> Result<Option<S::Item>, S3Error> {
+    match idle {
+        Some(idle) => tokio::time::timeout(idle, stream.next())
+            .await
+            .map_err(|_| S3Error::RequestTimeout(idle.as_secs())),
+        None => Ok(stream.next().await),
+    }
+}
+
+/// Middleware failing requests not done within the configured time with
+/// 408 RequestTimeout. The handler is dropped, releasing what it holds.
+pub async fn limit_request_time(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let Some(limit) = req
+        .app_data::<web::Data<TimeoutConfig>>()
+        .and_then(|config| config.request())
+    else {
+        return next.call(req).await;
+    };
+
+    // The request cannot be kept for a response of our own, as routing needs
+    // it unshared; actix builds the response from the error instead
+    let path = req.path().to_string();
+    match tokio::time::timeout(limit, next.call(req)).await {
+        Ok(res) => res,
+        Err(_) => {
+            warn!(path, limit_secs = limit.as_secs(), "Request timed out");
+            let error = S3Error::RequestTimeout(limit.as_secs());
+            let response = error_response(
+                StatusCode::REQUEST_TIMEOUT,
+                ErrorCode::RequestTimeout,
+                &error.to_string(),
+            );
+            Err(InternalError::from_response(error, response).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream;
+
+    #[tokio::test]
+    async fn test_next_within() {
+        let idle = Some(Duration::from_millis(10));
+        let mut parts = stream::iter([1, 2]);
+        assert_eq!(next_within(&mut parts, idle).await.unwrap(), Some(1));
+
+        let mut stalled = stream::pending::<u8>();
+        assert!(matches!(
+            next_within(&mut stalled, idle).await,
+            Err(S3Error::RequestTimeout(_))
+        ));
+    }
+}
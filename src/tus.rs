@@ -0,0 +1,101 @@
+// tus.rs
+// Resumable uploads following the tus 1.0.0 protocol (core, creation and
+// termination). An upload is created with its final length, filled by PATCH
+// requests appending at the current offset, and stored as a regular object
+// once the last byte arrives. Interrupted clients ask for the offset with
+// HEAD and continue from there. Each PATCH is recorded as a part with its
+// size and ETag; GET /buckets/{bucket}/uploads lists the uploads in progress
+// and GET /buckets/{bucket}/uploads/{id}/parts the parts of one, so a client
+// that crashed finds its uploads again and checks what was stored.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Serialize;
+use std::collections::HashMap;
+use utoipa::ToSchema;
+
+/// Protocol version spoken by the server.
+pub const TUS_VERSION: &str = "1.0.0";
+/// Protocol extensions supported by the server.
+pub const TUS_EXTENSIONS: &str = "creation,termination";
+
+pub const TUS_RESUMABLE_HEADER: &str = "Tus-Resumable";
+pub const TUS_VERSION_HEADER: &str = "Tus-Version";
+pub const TUS_EXTENSION_HEADER: &str = "Tus-Extension";
+pub const UPLOAD_LENGTH_HEADER: &str = "Upload-Length";
+pub const UPLOAD_OFFSET_HEADER: &str = "Upload-Offset";
+pub const UPLOAD_METADATA_HEADER: &str = "Upload-Metadata";
+
+/// Content type required on PATCH requests.
+pub const OFFSET_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+/// An upload in progress.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Upload {
+    pub id: String,
+    #[serde(skip_serializing)]
+    pub bucket: String,
+    /// Key the object is stored under once the upload completes.
+    pub key: String,
+    pub length: u64,
+    pub offset: u64,
+    pub content_type: Option<String>,
+    #[serde(skip_serializing)]
+    pub user_metadata: HashMap<String, String>,
+    pub created_at: i64,
+}
+
+impl Upload {
+    /// Whether every byte of the upload has been received.
+    pub fn is_complete(&self) -> bool {
+        self.offset == self.length
+    }
+}
+
+/// The bytes one PATCH request appended to an upload.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct UploadPart {
+    pub part_number: u64,
+    /// Offset of the part's first byte in the upload.
+    pub offset: u64,
+    pub size: u64,
+    pub etag: String,
+    pub created_at: i64,
+}
+
+/// Parses an `Upload-Metadata` header: comma-separated pairs of a key and an
+/// optional base64-encoded value, e.g. `key b2JqZWN0,private`.
+pub fn parse_metadata(header: &str) -> Result<HashMap<String, String>, String> {
+    let mut metadata = HashMap::new();
+    for pair in header.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = match pair.split_once(' ') {
+            Some((key, encoded)) => {
+                let bytes = STANDARD
+                    .decode(encoded.trim())
+                    .map_err(|e| format!("Invalid base64 value for '{}': {}", key, e))?;
+                let value = String::from_utf8(bytes)
+                    .map_err(|_| format!("Value for '{}' is not valid UTF-8", key))?;
+                (key, value)
+            }
+            None => (pair, String::new()),
+        };
+        metadata.insert(key.to_string(), value);
+    }
+    Ok(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_metadata() {
+        let metadata =
+            parse_metadata("key cmVwb3J0LnBkZg==, filetype YXBwbGljYXRpb24vcGRm,private").unwrap();
+        assert_eq!(metadata["key"], "report.pdf");
+        assert_eq!(metadata["filetype"], "application/pdf");
+        assert_eq!(metadata["private"], "");
+
+        assert!(parse_metadata("key !!!").is_err());
+    }
+}
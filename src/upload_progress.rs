@@ -0,0 +1,158 @@
+// upload_progress.rs
+// Progress of resumable uploads for progress bars. The PATCH handler counts
+// every chunk as it arrives, before the body is appended, so
+// GET /buckets/{bucket}/uploads/{id}/progress reports bytes still in flight
+// alongside those already stored, the number of PATCH requests completed and
+// an estimate of the time left at the rate seen so far. The counters live in
+// memory only; after a restart an upload reports its stored offset until its
+// next chunk arrives.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+use utoipa::ToSchema;
+
+use crate::tus::Upload;
+
+/// Counters of one upload, since this process first saw a chunk of it.
+#[derive(Debug, Clone, Copy)]
+struct Counters {
+    started: Instant,
+    last: Instant,
+    /// Bytes received by this process, stored or not.
+    received: u64,
+    /// Bytes of the current PATCH request not stored yet.
+    in_flight: u64,
+    parts_completed: u64,
+}
+
+/// A progress report of an upload.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Progress {
+    pub upload_id: String,
+    pub key: String,
+    pub length: u64,
+    pub bytes_received: u64,
+    pub parts_completed: u64,
+    /// Average rate since the first chunk, if any arrived yet.
+    pub bytes_per_sec: Option<u64>,
+    /// Estimated seconds until the last byte arrives at that rate.
+    pub eta_secs: Option<u64>,
+}
+
+/// The counters of every upload being received, by upload ID.
+#[derive(Debug, Default)]
+pub struct UploadProgress(Mutex<HashMap<String, Counters>>);
+
+impl UploadProgress {
+    /// Counts a chunk of an upload's body as it arrives.
+    pub fn receive(&self, id: &str, bytes: u64, now: Instant) {
+        let mut uploads = self.lock();
+        let counters = uploads.entry(id.to_string()).or_insert(Counters {
+            started: now,
+            last: now,
+            received: 0,
+            in_flight: 0,
+            parts_completed: 0,
+        });
+        counters.last = now;
+        counters.received += bytes;
+        counters.in_flight += bytes;
+    }
+
+    /// Ends a PATCH request: its bytes are either stored or dropped, and it
+    /// counts as a completed part if `completed`.
+    pub fn finish_part(&self, id: &str, completed: bool) {
+        if let Some(counters) = self.lock().get_mut(id) {
+            counters.in_flight = 0;
+            counters.parts_completed += u64::from(completed);
+        }
+    }
+
+    /// Forgets a finished or abandoned upload.
+    pub fn remove(&self, id: &str) {
+        self.lock().remove(id);
+    }
+
+    /// Reports the progress of an upload.
+    ///
+    /// # Arguments
+    ///
+    /// * `upload` - The upload as stored.
+    /// * `now` - The current time, against which the rate is measured.
+    ///
+    /// # Returns
+    ///
+    /// * `Progress` - The bytes received, parts completed and time left.
+    pub fn progress(&self, upload: &Upload, now: Instant) -> Progress {
+        let counters = self.lock().get(&upload.id).copied();
+        let in_flight = counters.map_or(0, |c| c.in_flight);
+        let bytes_received = (upload.offset + in_flight).min(upload.length);
+        let bytes_per_sec = counters.and_then(|c| {
+            let elapsed = now.max(c.last).duration_since(c.started).as_secs_f64();
+            (elapsed > 0.0).then(|| (c.received as f64 / elapsed) as u64)
+        });
+        let eta_secs = bytes_per_sec
+            .filter(|rate| *rate > 0)
+            .map(|rate| (upload.length - bytes_received).div_ceil(rate));
+        Progress {
+            upload_id: upload.id.clone(),
+            key: upload.key.clone(),
+            length: upload.length,
+            bytes_received,
+            parts_completed: counters.map_or(0, |c| c.parts_completed),
+            bytes_per_sec,
+            eta_secs,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Counters>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_progress() {
+        let upload = Upload {
+            id: "u1".to_string(),
+            bucket: "b".to_string(),
+            key: "k".to_string(),
+            length: 1000,
+            offset: 0,
+            content_type: None,
+            user_metadata: HashMap::new(),
+            created_at: 0,
+        };
+        let progress = UploadProgress::default();
+        let start = Instant::now();
+        let report = progress.progress(&upload, start);
+        assert_eq!(report.bytes_received, 0);
+        assert_eq!(report.eta_secs, None);
+
+        progress.receive("u1", 100, start);
+        progress.receive("u1", 100, start + Duration::from_secs(1));
+        let report = progress.progress(&upload, start + Duration::from_secs(2));
+        assert_eq!(report.bytes_received, 200);
+        assert_eq!(report.parts_completed, 0);
+        assert_eq!(report.bytes_per_sec, Some(100));
+        assert_eq!(report.eta_secs, Some(8));
+
+        progress.finish_part("u1", true);
+        let stored = Upload {
+            offset: 200,
+            ..upload.clone()
+        };
+        let report = progress.progress(&stored, start + Duration::from_secs(2));
+        assert_eq!(report.bytes_received, 200);
+        assert_eq!(report.parts_completed, 1);
+
+        progress.remove("u1");
+        assert_eq!(progress.progress(&stored, start).bytes_per_sec, None);
+    }
+}
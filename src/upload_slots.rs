@@ -0,0 +1,83 @@
+// upload_slots.rs
+// Admission control for upload bodies. Only so many uploads are read at the
+// same time; the others wait a short while for a slot and are refused with
+// 503 SlowDown if none frees up, bounding the memory and disk writes of
+// parallel bulk uploaders.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::UploadConfig;
+
+/// Slots of the uploads whose bodies are being read.
+#[derive(Debug)]
+pub struct UploadSlots {
+    /// `None` when the number of uploads is unlimited.
+    semaphore: Option<Arc<Semaphore>>,
+    queue_timeout: Duration,
+}
+
+/// A slot held by an upload until it is dropped.
+#[derive(Debug)]
+pub struct UploadSlot {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl UploadSlots {
+    pub fn new(config: &UploadConfig) -> Self {
+        Self {
+            semaphore: (config.max_concurrent > 0)
+                .then(|| Arc::new(Semaphore::new(config.max_concurrent))),
+            queue_timeout: Duration::from_millis(config.queue_timeout_ms),
+        }
+    }
+
+    /// Takes a slot for an upload, waiting up to the queue timeout for one
+    /// to free up. Returns `None` if none did.
+    pub async fn acquire(&self) -> Option<UploadSlot> {
+        let Some(semaphore) = &self.semaphore else {
+            return Some(UploadSlot { _permit: None });
+        };
+        let permit = match semaphore.clone().try_acquire_owned() {
+            Ok(permit) => permit,
+            Err(_) if self.queue_timeout.is_zero() => return None,
+            Err(_) => tokio::time::timeout(self.queue_timeout, semaphore.clone().acquire_owned())
+                .await
+                .ok()?
+                .ok()?,
+        };
+        Some(UploadSlot {
+            _permit: Some(permit),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_acquire() {
+        let slots = UploadSlots::new(&UploadConfig {
+            max_concurrent: 1,
+            queue_timeout_ms: 10,
+            ..UploadConfig::default()
+        });
+        let first = slots.acquire().await.unwrap();
+        assert!(slots.acquire().await.is_none());
+        drop(first);
+        assert!(slots.acquire().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_unlimited() {
+        let slots = UploadSlots::new(&UploadConfig {
+            max_concurrent: 0,
+            queue_timeout_ms: 0,
+            ..UploadConfig::default()
+        });
+        let held: Vec<_> = futures::future::join_all((0..100).map(|_| slots.acquire())).await;
+        assert!(held.iter().all(Option::is_some));
+    }
+}
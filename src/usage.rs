@@ -0,0 +1,263 @@
+// usage.rs
+// Space used by each bucket, kept as counters in the `bucket_usage` table so
+// reports never scan the objects. Triggers on `objects`, `object_versions`
+// and `uploads` adjust the counters in the same transaction as every change,
+// whichever operation makes it; a database from before the counters existed
+// is counted once when they are created. Bytes are object sizes as recorded,
+// not blocks allocated on disk.
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Space used by a bucket, or by all of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct BucketUsage {
+    /// Current objects.
+    pub objects: u64,
+    pub object_bytes: u64,
+    /// Noncurrent versions and delete markers.
+    pub versions: u64,
+    pub version_bytes: u64,
+    /// Resumable uploads in progress, with the bytes received so far.
+    pub uploads: u64,
+    pub upload_bytes: u64,
+    pub total_bytes: u64,
+}
+
+impl BucketUsage {
+    fn add(&mut self, other: &BucketUsage) {
+        self.objects += other.objects;
+        self.object_bytes += other.object_bytes;
+        self.versions += other.versions;
+        self.version_bytes += other.version_bytes;
+        self.uploads += other.uploads;
+        self.upload_bytes += other.upload_bytes;
+        self.total_bytes += other.total_bytes;
+    }
+}
+
+/// Usage of one bucket in a `UsageReport`.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct NamedUsage {
+    pub bucket: String,
+    #[serde(flatten)]
+    pub usage: BucketUsage,
+}
+
+/// Usage of every bucket, the largest first, and their sum.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UsageReport {
+    pub buckets: Vec<NamedUsage>,
+    pub total: BucketUsage,
+}
+
+impl UsageReport {
+    pub fn new(mut buckets: Vec<NamedUsage>) -> Self {
+        buckets.sort_by(|a, b| {
+            b.usage
+                .total_bytes
+                .cmp(&a.usage.total_bytes)
+                .then_with(|| a.bucket.cmp(&b.bucket))
+        });
+        let mut total = BucketUsage::default();
+        for bucket in &buckets {
+            total.add(&bucket.usage);
+        }
+        Self { buckets, total }
+    }
+}
+
+/// Columns of `bucket_usage` read by `usage_from_row`, in order.
+pub(crate) const USAGE_COLUMNS: &str =
+    "objects, object_bytes, versions, version_bytes, uploads, upload_bytes";
+
+/// Reads a `BucketUsage` from the `USAGE_COLUMNS` of a row, starting at
+/// column `first`.
+pub(crate) fn usage_from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<BucketUsage> {
+    let get = |i: usize| -> rusqlite::Result<u64> {
+        Ok(row.get::<_, Option<i64>>(first + i)?.unwrap_or(0).max(0) as u64)
+    };
+    let usage = BucketUsage {
+        objects: get(0)?,
+        object_bytes: get(1)?,
+        versions: get(2)?,
+        version_bytes: get(3)?,
+        uploads: get(4)?,
+        upload_bytes: get(5)?,
+        total_bytes: 0,
+    };
+    Ok(BucketUsage {
+        total_bytes: usage.object_bytes + usage.version_bytes + usage.upload_bytes,
+        ..usage
+    })
+}
+
+/// Creates the counters and their triggers, counting the existing rows if
+/// the counters are new. The triggers need `recursive_triggers` on the
+/// connection, so rows replaced by `INSERT OR REPLACE` are taken off.
+pub(crate) fn create_counters(conn: &Connection) -> rusqlite::Result<()> {
+    let exists = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'bucket_usage'",
+            [],
+            |_| Ok(()),
+        )
+        .optional()?
+        .is_some();
+    conn.pragma_update(None, "recursive_triggers", true)?;
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS bucket_usage (
+            bucket_name TEXT PRIMARY KEY NOT NULL,
+            objects INTEGER NOT NULL DEFAULT 0,
+            object_bytes INTEGER NOT NULL DEFAULT 0,
+            versions INTEGER NOT NULL DEFAULT 0,
+            version_bytes INTEGER NOT NULL DEFAULT 0,
+            uploads INTEGER NOT NULL DEFAULT 0,
+            upload_bytes INTEGER NOT NULL DEFAULT 0
+        );
+
+        CREATE TRIGGER IF NOT EXISTS bucket_usage_object_insert AFTER INSERT ON objects
+        BEGIN
+            INSERT INTO bucket_usage (bucket_name) VALUES (NEW.bucket_name)
+                ON CONFLICT (bucket_name) DO NOTHING;
+            UPDATE bucket_usage
+            SET objects = objects + 1, object_bytes = object_bytes + COALESCE(NEW.size, 0)
+            WHERE bucket_name = NEW.bucket_name;
+        END;
+        CREATE TRIGGER IF NOT EXISTS bucket_usage_object_delete AFTER DELETE ON objects
+        BEGIN
+            UPDATE bucket_usage
+            SET objects = objects - 1, object_bytes = object_bytes - COALESCE(OLD.size, 0)
+            WHERE bucket_name = OLD.bucket_name;
+        END;
+        CREATE TRIGGER IF NOT EXISTS bucket_usage_object_update AFTER UPDATE OF size ON objects
+        BEGIN
+            UPDATE bucket_usage
+            SET object_bytes = object_bytes - COALESCE(OLD.size, 0) + COALESCE(NEW.size, 0)
+            WHERE bucket_name = NEW.bucket_name;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS bucket_usage_version_insert AFTER INSERT ON object_versions
+        BEGIN
+            INSERT INTO bucket_usage (bucket_name) VALUES (NEW.bucket_name)
+                ON CONFLICT (bucket_name) DO NOTHING;
+            UPDATE bucket_usage
+            SET versions = versions + 1, version_bytes = version_bytes + COALESCE(NEW.size, 0)
+            WHERE bucket_name = NEW.bucket_name;
+        END;
+        CREATE TRIGGER IF NOT EXISTS bucket_usage_version_delete AFTER DELETE ON object_versions
+        BEGIN
+            UPDATE bucket_usage
+            SET versions = versions - 1, version_bytes = version_bytes - COALESCE(OLD.size, 0)
+            WHERE bucket_name = OLD.bucket_name;
+        END;
+
+        CREATE TRIGGER IF NOT EXISTS bucket_usage_upload_insert AFTER INSERT ON uploads
+        BEGIN
+            INSERT INTO bucket_usage (bucket_name) VALUES (NEW.bucket_name)
+                ON CONFLICT (bucket_name) DO NOTHING;
+            UPDATE bucket_usage
+            SET uploads = uploads + 1, upload_bytes = upload_bytes + NEW.upload_offset
+            WHERE bucket_name = NEW.bucket_name;
+        END;
+        CREATE TRIGGER IF NOT EXISTS bucket_usage_upload_delete AFTER DELETE ON uploads
+        BEGIN
+            UPDATE bucket_usage
+            SET uploads = uploads - 1, upload_bytes = upload_bytes - OLD.upload_offset
+            WHERE bucket_name = OLD.bucket_name;
+        END;
+        CREATE TRIGGER IF NOT EXISTS bucket_usage_upload_update
+        AFTER UPDATE OF upload_offset ON uploads
+        BEGIN
+            UPDATE bucket_usage
+            SET upload_bytes = upload_bytes - OLD.upload_offset + NEW.upload_offset
+            WHERE bucket_name = NEW.bucket_name;
+        END;",
+    )?;
+    if !exists {
+        recount(conn)?;
+    }
+    Ok(())
+}
+
+/// Sets the counters from the rows they count.
+fn recount(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "DELETE FROM bucket_usage;
+        INSERT INTO bucket_usage
+            (bucket_name, objects, object_bytes, versions, version_bytes, uploads, upload_bytes)
+        SELECT bucket_name, SUM(objects), SUM(object_bytes), SUM(versions), SUM(version_bytes),
+               SUM(uploads), SUM(upload_bytes)
+        FROM (
+            SELECT bucket_name, 1 AS objects, COALESCE(size, 0) AS object_bytes,
+                   0 AS versions, 0 AS version_bytes, 0 AS uploads, 0 AS upload_bytes
+            FROM objects
+            UNION ALL
+            SELECT bucket_name, 0, 0, 1, COALESCE(size, 0), 0, 0 FROM object_versions
+            UNION ALL
+            SELECT bucket_name, 0, 0, 0, 0, 1, upload_offset FROM uploads
+        )
+        GROUP BY bucket_name;",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bucket::VersioningStatus;
+    use crate::object::Object;
+    use crate::storage::Storage;
+    use crate::tus::Upload;
+    use std::collections::HashMap;
+
+    fn object(key: &str, data: &[u8]) -> Object {
+        Object::new(key.to_string(), data.to_vec(), None, None).unwrap()
+    }
+
+    #[test]
+    fn test_counters_follow_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let db_path = dir.path().join("s3_storage.db");
+        let mut storage =
+            Storage::open(&db_path.to_string_lossy(), dir.path().join("data")).unwrap();
+        storage.create_bucket("a").unwrap();
+        storage.create_bucket("b").unwrap();
+
+        storage.put_object("a", object("k", b"hello")).unwrap();
+        storage.put_object("a", object("k", b"hi")).unwrap();
+        storage.put_object("a", object("l", b"abc")).unwrap();
+        storage
+            .set_bucket_versioning("b", VersioningStatus::Enabled)
+            .unwrap();
+        storage.put_object("b", object("k", b"hello")).unwrap();
+        storage.put_object("b", object("k", b"world!")).unwrap();
+        storage.delete_object("b", "k").unwrap();
+        let upload = Upload {
+            id: "u".to_string(),
+            bucket: "b".to_string(),
+            key: "big".to_string(),
+            length: 10,
+            offset: 0,
+            content_type: None,
+            user_metadata: HashMap::new(),
+            created_at: 0,
+        };
+        storage.create_upload(&upload).unwrap();
+        storage.append_upload("b", "u", 0, b"1234").unwrap();
+
+        let a = storage.bucket_usage("a").unwrap();
+        assert_eq!((a.objects, a.object_bytes, a.total_bytes), (2, 5, 5));
+        let b = storage.bucket_usage("b").unwrap();
+        assert_eq!((b.objects, b.versions, b.version_bytes), (0, 3, 11));
+        assert_eq!((b.uploads, b.upload_bytes, b.total_bytes), (1, 4, 15));
+
+        // A fresh count agrees with the counters
+        let report = storage.usage_report().unwrap();
+        recount(&Connection::open(&db_path).unwrap()).unwrap();
+        let recounted = storage.usage_report().unwrap();
+        assert_eq!(report.total, recounted.total);
+        assert_eq!(report.total.total_bytes, 20);
+        assert_eq!(report.buckets[0].bucket, "b");
+    }
+}